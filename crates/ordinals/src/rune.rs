@@ -55,13 +55,23 @@ impl Rune {
   }
 
   pub fn minimum_at_height(chain: Network, height: Height) -> Self {
-    let offset = height.0.saturating_add(1);
+    Self::minimum_at_height_from(Self::first_rune_height(chain), height)
+  }
 
-    const INTERVAL: u32 = SUBSIDY_HALVING_INTERVAL / 12;
+  /// Minimum rune length schedule for a chain whose runes unlock at `start`.
+  pub fn minimum_at_height_from(start: u32, height: Height) -> Self {
+    Self::minimum_at_height_with_interval(start, SUBSIDY_HALVING_INTERVAL, height)
+  }
+
+  /// Minimum rune length schedule for a chain whose runes unlock at `start`
+  /// and whose subsidy halves every `halving_interval` blocks, the schedule
+  /// unlocks a length every twelfth of it.
+  pub fn minimum_at_height_with_interval(start: u32, halving_interval: u32, height: Height) -> Self {
+    let offset = height.0.saturating_add(1);
 
-    let start = Self::first_rune_height(chain);
+    let interval = (halving_interval / 12).max(1);
 
-    let end = start + SUBSIDY_HALVING_INTERVAL;
+    let end = start.saturating_add(interval * 12);
 
     if offset < start {
       return Rune(Self::STEPS[12]);
@@ -73,15 +83,15 @@ impl Rune {
 
     let progress = offset.saturating_sub(start);
 
-    let length = 12u32.saturating_sub(progress / INTERVAL);
+    let length = 12u32.saturating_sub(progress / interval);
 
     let end = Self::STEPS[usize::try_from(length - 1).unwrap()];
 
     let start = Self::STEPS[usize::try_from(length).unwrap()];
 
-    let remainder = u128::from(progress % INTERVAL);
+    let remainder = u128::from(progress % interval);
 
-    Rune(start - ((start - end) * remainder / u128::from(INTERVAL)))
+    Rune(start - ((start - end) * remainder / u128::from(interval)))
   }
 
   pub fn is_reserved(self) -> bool {
@@ -353,6 +363,28 @@ mod tests {
     case(Network::Regtest, 1, "ZZXZUDIVTVQA");
   }
 
+  #[test]
+  fn minimum_at_height_with_interval() {
+    #[track_caller]
+    fn case(height: u32, minimum: &str) {
+      assert_eq!(
+        Rune::minimum_at_height_with_interval(100, 1200, Height(height)).to_string(),
+        minimum,
+      );
+    }
+
+    case(99, "AAAAAAAAAAAAA");
+    case(100, "ZTGGGGGGGGGH");
+    case(199, "AAAAAAAAAAAA");
+    case(1298, "B");
+    case(1299, "A");
+
+    assert_eq!(
+      Rune::minimum_at_height_with_interval(0, SUBSIDY_HALVING_INTERVAL, Height(5000)),
+      Rune::minimum_at_height_from(0, Height(5000)),
+    );
+  }
+
   #[test]
   fn serde() {
     let rune = Rune(0);
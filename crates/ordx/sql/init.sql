@@ -1,3 +1,5 @@
+-- etcher: address of the taproot output committing to the rune, null for reserved runes
+-- commit_txid: transaction of that output
 CREATE TABLE IF NOT EXISTS rune_entry
 (
     rune_id      TEXT    NOT NULL PRIMARY KEY,
@@ -22,13 +24,47 @@ CREATE TABLE IF NOT EXISTS rune_entry
     burned       TEXT    NOT NULL DEFAULT '0',
     mintable     BOOLEAN NOT NULL DEFAULT false,
     holders      INTEGER NOT NULL DEFAULT 0,
-    transactions INTEGER NOT NULL DEFAULT 0
+    transactions INTEGER NOT NULL DEFAULT 0,
+    etcher       TEXT,
+    commit_txid  TEXT
 );
 
 CREATE INDEX IF NOT EXISTS idx_rune ON rune_entry (rune);
 CREATE INDEX IF NOT EXISTS idx_spaced_rune ON rune_entry (spaced_rune);
 CREATE INDEX IF NOT EXISTS idx_etching ON rune_entry (etching);
 CREATE INDEX IF NOT EXISTS idx_fairmint ON rune_entry (fairmint);
+CREATE INDEX IF NOT EXISTS idx_height_number ON rune_entry (height, number);
+CREATE INDEX IF NOT EXISTS idx_number ON rune_entry (number);
+CREATE INDEX IF NOT EXISTS idx_holders_number ON rune_entry (holders, number);
+CREATE INDEX IF NOT EXISTS idx_transactions_number ON rune_entry (transactions, number);
+CREATE INDEX IF NOT EXISTS idx_ts_number ON rune_entry (ts, number);
+CREATE INDEX IF NOT EXISTS idx_mints_number ON rune_entry (length(mints), mints, number);
+CREATE INDEX IF NOT EXISTS idx_supply_number ON rune_entry (CAST(premine AS REAL) + CAST(mints AS REAL) * CAST(IFNULL(amount, '0') AS REAL), number);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS rune_entry_fts USING fts5
+(
+    rune,
+    spaced_rune,
+    symbol,
+    content = 'rune_entry',
+    tokenize = 'trigram'
+);
+
+CREATE TRIGGER IF NOT EXISTS rune_entry_fts_insert AFTER INSERT ON rune_entry
+BEGIN
+    INSERT INTO rune_entry_fts (rowid, rune, spaced_rune, symbol) VALUES (new.rowid, new.rune, new.spaced_rune, new.symbol);
+END;
+
+CREATE TRIGGER IF NOT EXISTS rune_entry_fts_delete AFTER DELETE ON rune_entry
+BEGIN
+    INSERT INTO rune_entry_fts (rune_entry_fts, rowid, rune, spaced_rune, symbol) VALUES ('delete', old.rowid, old.rune, old.spaced_rune, old.symbol);
+END;
+
+CREATE TRIGGER IF NOT EXISTS rune_entry_fts_update AFTER UPDATE OF rune, spaced_rune, symbol ON rune_entry
+BEGIN
+    INSERT INTO rune_entry_fts (rune_entry_fts, rowid, rune, spaced_rune, symbol) VALUES ('delete', old.rowid, old.rune, old.spaced_rune, old.symbol);
+    INSERT INTO rune_entry_fts (rowid, rune, spaced_rune, symbol) VALUES (new.rowid, new.rune, new.spaced_rune, new.symbol);
+END;
 
 CREATE TABLE IF NOT EXISTS rune_balance
 (
@@ -54,6 +90,189 @@ CREATE TABLE IF NOT EXISTS rune_balance
 );
 
 CREATE INDEX IF NOT EXISTS idx_address ON rune_balance (address);
+CREATE INDEX IF NOT EXISTS idx_height ON rune_balance (height);
 CREATE INDEX IF NOT EXISTS idx_spent_height ON rune_balance (spent_height);
 CREATE INDEX IF NOT EXISTS idx_spent_txid ON rune_balance (spent_txid);
-CREATE UNIQUE INDEX IF NOT EXISTS idx_unique_txid_vout_rune_id ON rune_balance (txid, vout, rune_id);
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_rune_id_spent_height ON rune_balance (rune_id, spent_height);
+CREATE INDEX IF NOT EXISTS idx_rune_id_height ON rune_balance (rune_id, height);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_unique_txid_vout_rune_id ON rune_balance (txid, vout, rune_id);
+
+CREATE TABLE IF NOT EXISTS api_key
+(
+    id                    INTEGER PRIMARY KEY AUTOINCREMENT,
+    name                  TEXT    NOT NULL,
+    key_hash              TEXT    NOT NULL,
+    key_prefix            TEXT    NOT NULL,
+    tier                  TEXT    NOT NULL,
+    rate_limit_per_minute INTEGER,
+    daily_quota           INTEGER,
+    created_ts            INTEGER NOT NULL,
+    revoked               BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_key_hash ON api_key (key_hash);
+
+-- last block whose rocksdb and sqlite writes both committed, see RunesDB::reconcile_commit
+CREATE TABLE IF NOT EXISTS block_commit
+(
+    height     INTEGER NOT NULL PRIMARY KEY,
+    block_hash TEXT    NOT NULL,
+    ts         INTEGER NOT NULL
+);
+
+-- indexing time per stage in microseconds, kept across reorgs
+CREATE TABLE IF NOT EXISTS block_stats
+(
+    height     INTEGER NOT NULL PRIMARY KEY,
+    txs        INTEGER NOT NULL,
+    runestones INTEGER NOT NULL,
+    fetch_us   INTEGER NOT NULL,
+    update_us  INTEGER NOT NULL,
+    sqlite_us  INTEGER NOT NULL,
+    cache_us   INTEGER NOT NULL,
+    total_us   INTEGER NOT NULL,
+    ts         INTEGER NOT NULL
+);
+
+-- append only, a reorg adds one revert row per dropped block instead of deleting its rows
+CREATE TABLE IF NOT EXISTS rune_event
+(
+    seq        INTEGER PRIMARY KEY AUTOINCREMENT,
+    height     INTEGER NOT NULL,
+    block_hash TEXT    NOT NULL,
+    type       TEXT    NOT NULL,
+    data       TEXT    NOT NULL,
+    ts         INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_rune_event_height ON rune_event (height);
+
+-- event: etching, mint, burn, mempool, transfer:<address> or watch:<address>
+CREATE TABLE IF NOT EXISTS webhook
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    url        TEXT    NOT NULL,
+    event      TEXT    NOT NULL,
+    secret     TEXT    NOT NULL,
+    created_ts INTEGER NOT NULL,
+    deleted    BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE TABLE IF NOT EXISTS webhook_delivery
+(
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id      INTEGER NOT NULL,
+    height          INTEGER NOT NULL,
+    payload         TEXT    NOT NULL,
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    next_attempt_ts INTEGER NOT NULL,
+    status_code     INTEGER,
+    error           TEXT,
+    delivered       BOOLEAN NOT NULL DEFAULT false,
+    failed          BOOLEAN NOT NULL DEFAULT false,
+    created_ts      INTEGER NOT NULL,
+    updated_ts      INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_delivery_webhook_id ON webhook_delivery (webhook_id);
+CREATE INDEX IF NOT EXISTS idx_webhook_delivery_pending ON webhook_delivery (delivered, failed, next_attempt_ts);
+
+-- webhook_id is the webhook created along with the watch, if it was given a url
+-- api_key_id is the key that created it, only that key and admin keys see it
+CREATE TABLE IF NOT EXISTS watch
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    address    TEXT    NOT NULL,
+    webhook_id INTEGER,
+    api_key_id INTEGER,
+    created_ts INTEGER NOT NULL,
+    deleted    BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE INDEX IF NOT EXISTS idx_watch_address ON watch (address);
+
+-- what each block added to rune_entry holders and transactions, kept for the reorg window to undo them
+CREATE TABLE IF NOT EXISTS rune_counter_delta
+(
+    height       INTEGER NOT NULL,
+    rune_id      TEXT    NOT NULL,
+    holders      INTEGER NOT NULL,
+    transactions INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_rune_counter_delta_height ON rune_counter_delta (height);
+
+-- every edict of every runestone in the order of the runestone, output is the output count
+-- of the tx for edicts over all outputs, allocated is what the edict moved of the inputs
+CREATE TABLE IF NOT EXISTS rune_edict
+(
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    txid      TEXT    NOT NULL,
+    edict     INTEGER NOT NULL,
+    rune_id   TEXT    NOT NULL,
+    amount    TEXT    NOT NULL,
+    allocated TEXT    NOT NULL,
+    output    INTEGER NOT NULL,
+    height    INTEGER NOT NULL,
+    idx       INTEGER NOT NULL,
+    ts        INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_rune_edict_txid ON rune_edict (txid);
+CREATE INDEX IF NOT EXISTS idx_rune_edict_height ON rune_edict (height);
+CREATE INDEX IF NOT EXISTS idx_rune_edict_rune_id_height ON rune_edict (rune_id, height);
+
+-- why a transaction is a cenotaph, flaw in kebab-case, etching the rune it etched without
+-- supply, burned a JSON object of the rune ids it destroyed to their amounts
+CREATE TABLE IF NOT EXISTS cenotaph
+(
+    txid        TEXT    NOT NULL PRIMARY KEY,
+    flaw        TEXT,
+    description TEXT,
+    etching     TEXT,
+    mint        TEXT,
+    burned      TEXT    NOT NULL,
+    height      INTEGER NOT NULL,
+    idx         INTEGER NOT NULL,
+    ts          INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_cenotaph_height ON cenotaph (height);
+
+-- inscriptions revealed since inscription indexing was turned on, number in reveal order,
+-- input the input of the envelope
+CREATE TABLE IF NOT EXISTS inscription
+(
+    inscription_id   TEXT    NOT NULL PRIMARY KEY,
+    number           INTEGER NOT NULL,
+    txid             TEXT    NOT NULL,
+    input            INTEGER NOT NULL,
+    content_type     TEXT,
+    content_length   INTEGER NOT NULL,
+    content_encoding TEXT,
+    metaprotocol     TEXT,
+    height           INTEGER NOT NULL,
+    idx              INTEGER NOT NULL,
+    ts               INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_inscription_height ON inscription (height);
+
+-- every output an inscription was moved to, the reveal included, the latest row is where it is,
+-- vout and address are null for inscriptions lost to the fees
+CREATE TABLE IF NOT EXISTS inscription_transfer
+(
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    inscription_id TEXT    NOT NULL,
+    txid           TEXT    NOT NULL,
+    vout           INTEGER,
+    sat_offset     INTEGER NOT NULL,
+    address        TEXT,
+    height         INTEGER NOT NULL,
+    idx            INTEGER NOT NULL,
+    ts             INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_inscription_transfer_inscription_id ON inscription_transfer (inscription_id, id);
+CREATE INDEX IF NOT EXISTS idx_inscription_transfer_address ON inscription_transfer (address);
+CREATE INDEX IF NOT EXISTS idx_inscription_transfer_height ON inscription_transfer (height);
@@ -1,6 +1,3 @@
-PRAGMA journal_mode = WAL;
-PRAGMA synchronous = NORMAL;
 PRAGMA journal_size_limit = 10485760;
-PRAGMA mmap_size = 536870912;
 PRAGMA automatic_index = ON;
 PRAGMA foreign_keys = OFF;
\ No newline at end of file
@@ -0,0 +1,274 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use crate::api::auth::{generate_api_key, hash_api_key, unix_timestamp, ApiKeyStore, ApiKeyTier};
+use crate::api::dto::{AppError, R};
+use crate::api::reload::{self, ReloadableLayers};
+use crate::backup;
+use crate::backup::Backup;
+use crate::cache::{CacheMethod, CacheStats, MokaCache};
+use crate::chain::Chain;
+use crate::db::model::{ApiKeyForInsert, ApiKeyForQuery, WebhookDeliveryForQuery, WebhookForInsert, WebhookForQuery};
+use crate::control::IndexerControl;
+use crate::db::writer::SqliteWriter;
+use crate::db::RunesDB;
+use crate::webhook::{validate_url, WebhookFilter};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyParams {
+    pub name: String,
+    pub tier: ApiKeyTier,
+    pub rate_limit_per_minute: Option<u32>,
+    pub daily_quota: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKeyDTO {
+    pub id: u32,
+    pub name: String,
+    pub tier: ApiKeyTier,
+    pub key: String,
+}
+
+pub async fn create_api_key(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(writer): Extension<Arc<SqliteWriter>>,
+    Extension(store): Extension<Arc<ApiKeyStore>>,
+    Json(params): Json<CreateApiKeyParams>,
+) -> anyhow::Result<Json<R<CreatedApiKeyDTO>>, AppError> {
+    let key = generate_api_key();
+    let insert = ApiKeyForInsert {
+        name: params.name.clone(),
+        key_hash: hash_api_key(&key),
+        key_prefix: key[..12].to_string(),
+        tier: params.tier.to_string(),
+        rate_limit_per_minute: params.rate_limit_per_minute,
+        daily_quota: params.daily_quota,
+        created_ts: unix_timestamp(),
+    };
+    let id = writer.call(move |db| db.sqlite_api_key_insert(&insert)).await?;
+    store.reload(&db)?;
+    Ok(Json(R::with_data(CreatedApiKeyDTO {
+        id,
+        name: params.name,
+        tier: params.tier,
+        key,
+    })))
+}
+
+pub async fn list_api_keys(
+    Extension(db): Extension<Arc<RunesDB>>,
+) -> anyhow::Result<Json<R<Vec<ApiKeyForQuery>>>, AppError> {
+    Ok(Json(R::with_data(db.sqlite_api_key_list()?)))
+}
+
+pub async fn revoke_api_key(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(writer): Extension<Arc<SqliteWriter>>,
+    Extension(store): Extension<Arc<ApiKeyStore>>,
+    Path(id): Path<u32>,
+) -> anyhow::Result<Json<R<bool>>, AppError> {
+    let revoked = writer.call(move |db| db.sqlite_api_key_revoke(id)).await?;
+    store.reload(&db)?;
+    Ok(Json(R::with_data(revoked)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookParams {
+    pub url: String,
+    pub event: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhookDTO {
+    pub id: u32,
+    pub url: String,
+    pub event: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveriesParams {
+    pub limit: Option<u32>,
+}
+
+pub async fn create_webhook(
+    Extension(writer): Extension<Arc<SqliteWriter>>,
+    Json(params): Json<CreateWebhookParams>,
+) -> anyhow::Result<Json<R<CreatedWebhookDTO>>, AppError> {
+    validate_url(&params.url)?;
+    let event = params.event.parse::<WebhookFilter>()?.to_string();
+    let secret = hex::encode(rand::random::<[u8; 32]>());
+    let insert = WebhookForInsert {
+        url: params.url.clone(),
+        event: event.clone(),
+        secret: secret.clone(),
+        created_ts: unix_timestamp(),
+    };
+    let id = writer.call(move |db| db.sqlite_webhook_insert(&insert)).await?;
+    Ok(Json(R::with_data(CreatedWebhookDTO {
+        id,
+        url: params.url,
+        event,
+        secret,
+    })))
+}
+
+pub async fn list_webhooks(
+    Extension(db): Extension<Arc<RunesDB>>,
+) -> anyhow::Result<Json<R<Vec<WebhookForQuery>>>, AppError> {
+    Ok(Json(R::with_data(db.sqlite_webhook_list()?)))
+}
+
+pub async fn delete_webhook(
+    Extension(writer): Extension<Arc<SqliteWriter>>,
+    Path(id): Path<u32>,
+) -> anyhow::Result<Json<R<bool>>, AppError> {
+    Ok(Json(R::with_data(writer.call(move |db| db.sqlite_webhook_delete(id)).await?)))
+}
+
+pub async fn list_webhook_deliveries(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(id): Path<u32>,
+    Query(params): Query<WebhookDeliveriesParams>,
+) -> anyhow::Result<Json<R<Vec<WebhookDeliveryForQuery>>>, AppError> {
+    let limit = params.limit.unwrap_or(100).min(1000);
+    Ok(Json(R::with_data(db.sqlite_webhook_delivery_list(id, limit)?)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReindexParams {
+    pub from: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReindexDTO {
+    pub from: u32,
+    pub indexed_height: u32,
+}
+
+/// Rolls the index back to `from` and indexes again from there, picked up by
+/// the indexer before its next block.
+pub async fn reindex(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(control): Extension<Arc<IndexerControl>>,
+    Query(params): Query<ReindexParams>,
+) -> anyhow::Result<Json<R<ReindexDTO>>, AppError> {
+    let Some(indexed_height) = db.latest_indexed_height() else {
+        return Err(anyhow::anyhow!("nothing is indexed yet").into());
+    };
+    control.request_reindex(params.from, indexed_height)?;
+    Ok(Json(R::with_data(ReindexDTO {
+        from: params.from,
+        indexed_height,
+    })))
+}
+
+/// Flushes the rocksdb WAL and memtables, e.g. before a filesystem snapshot.
+pub async fn flush_db(
+    Extension(db): Extension<Arc<RunesDB>>,
+) -> anyhow::Result<Json<R<bool>>, AppError> {
+    tokio::task::spawn_blocking(move || db.flush_rocksdb()).await.map_err(anyhow::Error::from)??;
+    Ok(Json(R::with_data(true)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackupParams {
+    pub dir: String,
+}
+
+/// Writes a sqlite copy and a rocksdb checkpoint into `dir` on the server,
+/// off the sqlite writer so indexing carries on meanwhile.
+pub async fn backup(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Query(params): Query<BackupParams>,
+) -> anyhow::Result<Json<R<Backup>>, AppError> {
+    let dir = PathBuf::from(params.dir);
+    let backup = tokio::task::spawn_blocking(move || backup::run(&db, &dir)).await.map_err(anyhow::Error::from)??;
+    Ok(Json(R::with_data(backup)))
+}
+
+/// Bytes read from the snapshot per body chunk.
+const SNAPSHOT_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Streams a consistent copy of `sqlite.db`, taken with the sqlite backup API
+/// into a file that is deleted when the response is done.
+pub async fn sqlite_backup(
+    Extension(db): Extension<Arc<RunesDB>>,
+) -> anyhow::Result<Response, AppError> {
+    let file = tokio::task::spawn_blocking(move || backup::sqlite_snapshot(&db)).await.map_err(anyhow::Error::from)??;
+    let len = file.metadata().map_err(anyhow::Error::from)?.len();
+    let chunks = stream::unfold(Some(tokio::fs::File::from_std(file)), |file| async move {
+        let mut file = file?;
+        let mut buffer = vec![0; SNAPSHOT_CHUNK_SIZE];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buffer.truncate(n);
+                Some((Ok(Bytes::from(buffer)), Some(file)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"sqlite.db\"".to_string()),
+            (header::CONTENT_LENGTH, len.to_string()),
+        ],
+        Body::from_stream(chunks),
+    ).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompactParams {
+    pub cf: Option<String>,
+}
+
+pub async fn compact_db(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Query(params): Query<CompactParams>,
+) -> anyhow::Result<Json<R<Vec<String>>>, AppError> {
+    let compacted = tokio::task::spawn_blocking(move || db.compact_rocksdb(params.cf.as_deref())).await.map_err(anyhow::Error::from)??;
+    Ok(Json(R::with_data(compacted)))
+}
+
+pub async fn cache_stats(
+    Extension(cache): Extension<Arc<MokaCache>>,
+) -> anyhow::Result<Json<R<CacheStats>>, AppError> {
+    Ok(Json(R::with_data(cache.stats().await)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CachePurgeParams {
+    pub method: Option<CacheMethod>,
+    pub key: Option<String>,
+}
+
+/// Evicts cached responses of one method and/or about one address, txid or
+/// rune id; without params the whole cache is purged.
+pub async fn purge_cache(
+    Extension(cache): Extension<Arc<MokaCache>>,
+    Query(params): Query<CachePurgeParams>,
+) -> anyhow::Result<Json<R<u64>>, AppError> {
+    Ok(Json(R::with_data(cache.purge(params.method, params.key.as_deref()).await)))
+}
+
+/// Reloads the rate limit, CORS and cache expiration settings, like SIGHUP.
+pub async fn reload_settings(
+    Extension(chain): Extension<Chain>,
+    Extension(layers): Extension<Arc<ReloadableLayers>>,
+    Extension(cache): Extension<Arc<MokaCache>>,
+) -> anyhow::Result<Json<R<bool>>, AppError> {
+    reload::reload(chain, &layers, &cache)?;
+    Ok(Json(R::with_data(true)))
+}
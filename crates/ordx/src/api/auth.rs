@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::Extension;
+use bitcoin::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+use tower_governor::key_extractor::{KeyExtractor, SmartIpKeyExtractor};
+use tower_governor::GovernorError;
+
+use crate::api::dto::R;
+use crate::db::model::ApiKeyForQuery;
+use crate::db::RunesDB;
+use crate::settings::Settings;
+
+pub const X_API_KEY: &str = "x-api-key";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyTier {
+    Free,
+    Standard,
+    Premium,
+    Admin,
+}
+
+impl ApiKeyTier {
+    pub fn rate_limit_per_minute(self) -> Option<u32> {
+        match self {
+            Self::Free => Some(60),
+            Self::Standard => Some(600),
+            Self::Premium => Some(6000),
+            Self::Admin => None,
+        }
+    }
+
+    pub fn daily_quota(self) -> Option<u64> {
+        match self {
+            Self::Free => Some(10_000),
+            Self::Standard => Some(200_000),
+            Self::Premium => None,
+            Self::Admin => None,
+        }
+    }
+}
+
+impl Display for ApiKeyTier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Free => "free",
+                Self::Standard => "standard",
+                Self::Premium => "premium",
+                Self::Admin => "admin",
+            }
+        )
+    }
+}
+
+impl FromStr for ApiKeyTier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "free" => Ok(Self::Free),
+            "standard" => Ok(Self::Standard),
+            "premium" => Ok(Self::Premium),
+            "admin" => Ok(Self::Admin),
+            _ => bail!("invalid api key tier `{s}`"),
+        }
+    }
+}
+
+/// An API key that passed validation, attached to the request extensions.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: u32,
+    pub name: String,
+    pub key_hash: String,
+    pub tier: ApiKeyTier,
+    pub rate_limit_per_minute: Option<u32>,
+    pub daily_quota: Option<u64>,
+}
+
+impl ApiKey {
+    pub fn is_admin(&self) -> bool {
+        self.tier == ApiKeyTier::Admin
+    }
+}
+
+impl TryFrom<ApiKeyForQuery> for ApiKey {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ApiKeyForQuery) -> Result<Self, Self::Error> {
+        let tier = value.tier.parse::<ApiKeyTier>()?;
+        Ok(ApiKey {
+            id: value.id,
+            name: value.name,
+            key_hash: value.key_hash,
+            tier,
+            rate_limit_per_minute: value.rate_limit_per_minute.or(tier.rate_limit_per_minute()),
+            daily_quota: value.daily_quota.or(tier.daily_quota()),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct KeyUsage {
+    minute: u64,
+    minute_count: u32,
+    day: u64,
+    day_count: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    Missing,
+    Invalid,
+    RateLimited,
+    QuotaExceeded,
+}
+
+impl Rejection {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Missing | Self::Invalid => StatusCode::UNAUTHORIZED,
+            Self::RateLimited | Self::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            Self::Missing => "Missing X-API-Key header",
+            Self::Invalid => "Invalid or revoked API key",
+            Self::RateLimited => "API key rate limit exceeded",
+            Self::QuotaExceeded => "API key daily quota exceeded",
+        }
+    }
+}
+
+/// Keys are only stored as sha256 hashes, the plain key is shown once on creation.
+pub struct ApiKeyStore {
+    required: bool,
+    config_admin_key_hash: Option<String>,
+    keys: RwLock<HashMap<String, ApiKey>>,
+    usage: Mutex<HashMap<String, KeyUsage>>,
+}
+
+impl ApiKeyStore {
+    pub fn load(settings: &Settings, runes_db: &RunesDB) -> anyhow::Result<Self> {
+        let store = ApiKeyStore {
+            required: settings.api_key_required,
+            config_admin_key_hash: settings.admin_api_key.as_deref().map(hash_api_key),
+            keys: RwLock::new(HashMap::new()),
+            usage: Mutex::new(HashMap::new()),
+        };
+        store.reload(runes_db)?;
+        Ok(store)
+    }
+
+    pub fn reload(&self, runes_db: &RunesDB) -> anyhow::Result<()> {
+        let mut keys = HashMap::new();
+        for row in runes_db.sqlite_api_key_list()? {
+            if row.revoked {
+                continue;
+            }
+            let key = ApiKey::try_from(row)?;
+            keys.insert(key.key_hash.clone(), key);
+        }
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    fn lookup(&self, key: &str) -> Option<ApiKey> {
+        let key_hash = hash_api_key(key);
+        if self.config_admin_key_hash.as_ref() == Some(&key_hash) {
+            return Some(ApiKey {
+                id: 0,
+                name: "config".to_string(),
+                key_hash,
+                tier: ApiKeyTier::Admin,
+                rate_limit_per_minute: None,
+                daily_quota: None,
+            });
+        }
+        self.keys.read().unwrap().get(&key_hash).cloned()
+    }
+
+    fn check_limits(&self, key: &ApiKey, now: u64) -> Result<(), Rejection> {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(key.key_hash.clone()).or_default();
+        let minute = now / 60;
+        if usage.minute != minute {
+            usage.minute = minute;
+            usage.minute_count = 0;
+        }
+        let day = now / 86400;
+        if usage.day != day {
+            usage.day = day;
+            usage.day_count = 0;
+        }
+        if let Some(limit) = key.rate_limit_per_minute {
+            if usage.minute_count >= limit {
+                return Err(Rejection::RateLimited);
+            }
+        }
+        if let Some(quota) = key.daily_quota {
+            if usage.day_count >= quota {
+                return Err(Rejection::QuotaExceeded);
+            }
+        }
+        usage.minute_count += 1;
+        usage.day_count += 1;
+        Ok(())
+    }
+
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Option<ApiKey>, Rejection> {
+        let Some(value) = headers.get(X_API_KEY).and_then(|hv| hv.to_str().ok()) else {
+            return if self.required {
+                Err(Rejection::Missing)
+            } else {
+                Ok(None)
+            };
+        };
+        let key = self.lookup(value.trim()).ok_or(Rejection::Invalid)?;
+        self.check_limits(&key, unix_timestamp())?;
+        Ok(Some(key))
+    }
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    sha256::Hash::hash(key.as_bytes()).to_string()
+}
+
+pub fn generate_api_key() -> String {
+    format!("ordx_{}", hex::encode(rand::random::<[u8; 24]>()))
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn reject(status: StatusCode, message: &str) -> Response {
+    let body: R<()> = R::error(-1, message.to_string());
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap()
+}
+
+pub async fn api_key_auth(
+    Extension(store): Extension<Arc<ApiKeyStore>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    match store.authenticate(req.headers()) {
+        Ok(Some(key)) => {
+            req.extensions_mut().insert(key);
+            next.run(req).await
+        }
+        Ok(None) => next.run(req).await,
+        Err(rejection) => reject(rejection.status(), rejection.message()),
+    }
+}
+
+pub async fn require_api_key(req: Request, next: Next) -> Response {
+    match req.extensions().get::<ApiKey>() {
+        Some(_) => next.run(req).await,
+        None => reject(StatusCode::UNAUTHORIZED, Rejection::Missing.message()),
+    }
+}
+
+pub async fn require_admin(req: Request, next: Next) -> Response {
+    match req.extensions().get::<ApiKey>() {
+        Some(key) if key.is_admin() => next.run(req).await,
+        Some(_) => reject(StatusCode::FORBIDDEN, "Admin API key required"),
+        None => reject(StatusCode::UNAUTHORIZED, Rejection::Missing.message()),
+    }
+}
+
+/// Throttles anonymous requests per client IP. Authenticated requests skip the
+/// IP limiter, they are held to the limits of their key by [`ApiKeyStore`].
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIpKeyExtractor;
+
+impl KeyExtractor for ClientIpKeyExtractor {
+    type Key = String;
+
+    fn extract<T>(&self, req: &axum::http::Request<T>) -> Result<Self::Key, GovernorError> {
+        // requests over the unix socket have no peer address unless the proxy forwards one
+        Ok(SmartIpKeyExtractor
+            .extract(req)
+            .map(|ip| format!("ip:{}", ip))
+            .unwrap_or_else(|_| "local".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(required: bool) -> ApiKeyStore {
+        let mut keys = HashMap::new();
+        let key = ApiKey {
+            id: 1,
+            name: "test".to_string(),
+            key_hash: hash_api_key("secret"),
+            tier: ApiKeyTier::Free,
+            rate_limit_per_minute: Some(2),
+            daily_quota: Some(3),
+        };
+        keys.insert(key.key_hash.clone(), key);
+        ApiKeyStore {
+            required,
+            config_admin_key_hash: Some(hash_api_key("root")),
+            keys: RwLock::new(keys),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_API_KEY, key.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn tier_from_str() {
+        assert_eq!("admin".parse::<ApiKeyTier>().unwrap(), ApiKeyTier::Admin);
+        assert_eq!(ApiKeyTier::Premium.to_string(), "premium");
+        assert!("gold".parse::<ApiKeyTier>().is_err());
+    }
+
+    #[test]
+    fn missing_key() {
+        assert!(store(false).authenticate(&HeaderMap::new()).unwrap().is_none());
+        assert_eq!(store(true).authenticate(&HeaderMap::new()).unwrap_err(), Rejection::Missing);
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert_eq!(store(false).authenticate(&headers("nope")).unwrap_err(), Rejection::Invalid);
+    }
+
+    #[test]
+    fn config_admin_key() {
+        let key = store(true).authenticate(&headers("root")).unwrap().unwrap();
+        assert!(key.is_admin());
+    }
+
+    #[test]
+    fn limits() {
+        let store = store(false);
+        let key = store.lookup("secret").unwrap();
+        assert!(store.check_limits(&key, 0).is_ok());
+        assert!(store.check_limits(&key, 1).is_ok());
+        assert_eq!(store.check_limits(&key, 2).unwrap_err(), Rejection::RateLimited);
+        assert!(store.check_limits(&key, 60).is_ok());
+        assert_eq!(store.check_limits(&key, 61).unwrap_err(), Rejection::QuotaExceeded);
+        assert!(store.check_limits(&key, 86400).is_ok());
+    }
+}
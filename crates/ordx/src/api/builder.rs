@@ -0,0 +1,579 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::bail;
+use axum::{Extension, Json};
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::{opcodes, script};
+use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bitcoin::psbt::Psbt;
+use bitcoin::taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::transaction::Version;
+use bitcoin::{Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use ordinals::{Etching, Height, Rune, RuneId, Runestone, SpacedRune, Terms};
+
+use crate::api::dto::{AppError, R};
+use crate::api::handler::resolve_rune_id;
+use crate::api::vo::RuneBalanceGroupKey;
+use crate::chain::Chain;
+use crate::db::{RunesDB, Store};
+use crate::fee::FeeEstimator;
+use crate::mempool::MempoolTracker;
+
+const DEFAULT_POSTAGE: u64 = 546;
+
+#[derive(Debug, Deserialize)]
+pub struct SelectUtxosParams {
+    pub address: String,
+    pub rune_id: String,
+    pub amount: String,
+    /// Only use outputs that hold no other rune, so spending them does not
+    /// require routing unrelated balances.
+    #[serde(default)]
+    pub spendable_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuneUtxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    pub amount: String,
+    /// Balances of other runes held by the same output.
+    pub other_runes: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelectedUtxosDTO {
+    pub rune_id: String,
+    pub utxos: Vec<RuneUtxo>,
+    pub amount: String,
+    pub change: String,
+    /// Sats carried by the selected outputs.
+    pub value: u64,
+    /// Balances of other runes that have to be sent back as change.
+    pub other_runes_change: HashMap<String, String>,
+}
+
+/// Selects as few outputs as possible: the smallest output covering the target
+/// on its own, otherwise the largest outputs first until the target is reached.
+fn select(mut candidates: Vec<(u128, RuneUtxo)>, target: u128) -> anyhow::Result<Vec<(u128, RuneUtxo)>> {
+    if target == 0 {
+        bail!("amount must be greater than zero");
+    }
+    candidates.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.value.cmp(&b.1.value)));
+    if let Some(index) = candidates.iter().position(|x| x.0 >= target) {
+        return Ok(vec![candidates.swap_remove(index)]);
+    }
+
+    let mut selected = vec![];
+    let mut total = 0;
+    while total < target {
+        let Some(utxo) = candidates.pop() else {
+            bail!("insufficient balance: {} < {}", total, target);
+        };
+        total += utxo.0;
+        selected.push(utxo);
+    }
+    Ok(selected)
+}
+
+pub async fn select_utxos(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(mempool): Extension<Arc<MempoolTracker>>,
+    Json(params): Json<SelectUtxosParams>,
+) -> anyhow::Result<Json<R<SelectedUtxosDTO>>, AppError> {
+    let Some(rune_id) = resolve_rune_id(&db, &params.rune_id) else {
+        return Err(anyhow::anyhow!("unknown rune: {}", params.rune_id).into());
+    };
+    let rune_id = rune_id.to_string();
+    let target = params.amount.parse::<u128>().map_err(anyhow::Error::from)?;
+
+    let unspent = db.sqlite_rune_balance_list_unspent_by_address_filtered(&params.address, Some(&rune_id), None, None)?;
+    let mut candidates = vec![];
+    for (k, v) in unspent.into_iter().into_group_map_by(|x| RuneBalanceGroupKey { txid: x.txid.clone(), vout: x.vout }) {
+        let mut amount = 0;
+        let mut other_runes = HashMap::new();
+        for e in &v {
+            if e.rune_id == rune_id {
+                amount = e.rune_amount.parse::<u128>().map_err(anyhow::Error::from)?;
+            } else {
+                other_runes.insert(e.rune_id.clone(), e.rune_amount.clone());
+            }
+        }
+        if params.spendable_only && !other_runes.is_empty() {
+            continue;
+        }
+        // already being spent by an unconfirmed transaction
+        let txid = k.txid.parse()?;
+        if mempool.spent_by(&OutPoint { txid, vout: k.vout }).is_some() {
+            continue;
+        }
+        candidates.push((amount, RuneUtxo {
+            txid: k.txid,
+            vout: k.vout,
+            value: v[0].value,
+            amount: amount.to_string(),
+            other_runes,
+        }));
+    }
+
+    let selected = select(candidates, target)?;
+    let total: u128 = selected.iter().map(|x| x.0).sum();
+    let mut other_runes_change: HashMap<String, u128> = HashMap::new();
+    for (_, utxo) in &selected {
+        for (id, amount) in &utxo.other_runes {
+            *other_runes_change.entry(id.clone()).or_default() += amount.parse::<u128>().map_err(anyhow::Error::from)?;
+        }
+    }
+    Ok(Json(R::with_data(SelectedUtxosDTO {
+        rune_id,
+        value: selected.iter().map(|x| x.1.value).sum(),
+        utxos: selected.into_iter().map(|x| x.1).collect(),
+        amount: total.to_string(),
+        change: (total - target).to_string(),
+        other_runes_change: other_runes_change.into_iter().map(|(k, v)| (k, v.to_string())).collect(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuildMintParams {
+    pub rune_id: String,
+    pub address: String,
+    /// Sats sent to the receive address, defaults to 546.
+    pub postage: Option<u64>,
+    /// sat/vB, defaults to the node's estimate.
+    pub fee_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuiltPsbtDTO {
+    pub psbt_hex: String,
+    pub psbt_base64: String,
+    /// sat/vB the wallet should fund the transaction with.
+    pub fee_rate: f64,
+}
+
+/// Outputs of a mint: the receive address first, then the runestone pointing
+/// the minted amount at it. The wallet adds funding inputs and change.
+fn mint_outputs(rune_id: RuneId, receive: &Address, postage: u64) -> Vec<TxOut> {
+    let runestone = Runestone {
+        mint: Some(rune_id),
+        pointer: Some(0),
+        ..Default::default()
+    };
+    vec![
+        TxOut {
+            value: Amount::from_sat(postage),
+            script_pubkey: receive.script_pubkey(),
+        },
+        TxOut {
+            value: Amount::ZERO,
+            script_pubkey: runestone.encipher(),
+        },
+    ]
+}
+
+fn unsigned_psbt(output: Vec<TxOut>, fee_rate: f64) -> anyhow::Result<BuiltPsbtDTO> {
+    let psbt = Psbt::from_unsigned_tx(Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![],
+        output,
+    })?;
+    Ok(BuiltPsbtDTO::new(&psbt, fee_rate))
+}
+
+impl BuiltPsbtDTO {
+    fn new(psbt: &Psbt, fee_rate: f64) -> Self {
+        BuiltPsbtDTO {
+            psbt_hex: psbt.serialize_hex(),
+            psbt_base64: psbt.to_string(),
+            fee_rate,
+        }
+    }
+}
+
+async fn fee_rate(fee_estimator: &FeeEstimator, fee_rate: Option<f64>) -> anyhow::Result<f64> {
+    match fee_rate {
+        Some(fee_rate) if fee_rate.is_nan() || fee_rate <= 0.0 => bail!("fee_rate must be positive"),
+        Some(fee_rate) => Ok(fee_rate),
+        None => Ok(fee_estimator.default_fee_rate().await),
+    }
+}
+
+pub async fn build_mint(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
+    Extension(fee_estimator): Extension<Arc<FeeEstimator>>,
+    Json(params): Json<BuildMintParams>,
+) -> anyhow::Result<Json<R<BuiltPsbtDTO>>, AppError> {
+    let Some(rune_id) = resolve_rune_id(&db, &params.rune_id) else {
+        return Err(anyhow::anyhow!("unknown rune: {}", params.rune_id).into());
+    };
+    let Some(entry) = db.rune_id_to_rune_entry_get(&rune_id) else {
+        return Err(anyhow::anyhow!("unknown rune: {}", params.rune_id).into());
+    };
+    let next_height = db.latest_indexed_height().unwrap_or_default() + 1;
+    if let Err(e) = entry.mintable(next_height.into()) {
+        return Err(anyhow::anyhow!("rune {} is not mintable at height {}: {:?}", rune_id, next_height, e).into());
+    }
+
+    let receive = chain.parse_address(&params.address)?.require_network(chain.network())?;
+    let postage = params.postage.unwrap_or(DEFAULT_POSTAGE);
+    if postage < DEFAULT_POSTAGE {
+        return Err(anyhow::anyhow!("postage must be at least {} sats", DEFAULT_POSTAGE).into());
+    }
+    let fee_rate = fee_rate(&fee_estimator, params.fee_rate).await?;
+    Ok(Json(R::with_data(unsigned_psbt(mint_outputs(rune_id, &receive, postage), fee_rate)?)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EtchingTermsParams {
+    pub amount: Option<String>,
+    pub cap: Option<String>,
+    pub height_start: Option<u64>,
+    pub height_end: Option<u64>,
+    pub offset_start: Option<u64>,
+    pub offset_end: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuildEtchingParams {
+    /// Spaced rune name, e.g. `UNCOMMON•GOODS`.
+    pub rune: String,
+    pub divisibility: Option<u8>,
+    pub symbol: Option<char>,
+    pub premine: Option<String>,
+    pub terms: Option<EtchingTermsParams>,
+    #[serde(default)]
+    pub turbo: bool,
+    /// X-only key that signs the reveal input, hex encoded.
+    pub pubkey: String,
+    /// Receives the premine and the postage of the reveal transaction.
+    pub address: String,
+    pub postage: Option<u64>,
+    /// sat/vB, defaults to the node's estimate.
+    pub fee_rate: Option<f64>,
+    /// Commit output, once funded. Without it the reveal has no inputs yet.
+    pub commit_outpoint: Option<String>,
+    pub commit_value: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuiltEtchingDTO {
+    pub rune: String,
+    pub commitment: String,
+    pub tapscript: String,
+    pub control_block: String,
+    pub commit_address: String,
+    pub commit_script_pubkey: String,
+    /// The reveal input sequence. It is a relative lock time that keeps the
+    /// reveal out of the chain until the commit has enough confirmations.
+    pub reveal_sequence: u32,
+    pub commit_confirmations: u16,
+    /// Fee of the reveal transaction at `reveal.fee_rate`.
+    pub reveal_fee: u64,
+    /// Value the commit output needs to pay for the reveal and its postage.
+    pub commit_value: u64,
+    pub reveal: BuiltPsbtDTO,
+}
+
+fn parse_amount(amount: &Option<String>) -> anyhow::Result<Option<u128>> {
+    Ok(match amount {
+        Some(amount) => Some(amount.parse::<u128>()?),
+        None => None,
+    })
+}
+
+impl BuildEtchingParams {
+    fn etching(&self) -> anyhow::Result<Etching> {
+        let spaced_rune = self.rune.parse::<SpacedRune>()?;
+        let divisibility = self.divisibility.unwrap_or_default();
+        if divisibility > Etching::MAX_DIVISIBILITY {
+            bail!("divisibility must be at most {}", Etching::MAX_DIVISIBILITY);
+        }
+        let terms = match &self.terms {
+            Some(terms) => Some(Terms {
+                amount: parse_amount(&terms.amount)?,
+                cap: parse_amount(&terms.cap)?,
+                height: (terms.height_start, terms.height_end),
+                offset: (terms.offset_start, terms.offset_end),
+            }),
+            None => None,
+        };
+        let etching = Etching {
+            divisibility: Some(divisibility),
+            premine: parse_amount(&self.premine)?,
+            rune: Some(spaced_rune.rune),
+            spacers: Some(spaced_rune.spacers),
+            symbol: self.symbol,
+            terms,
+            turbo: self.turbo,
+        };
+        if etching.supply().is_none() {
+            bail!("premine plus cap * amount overflows the supply");
+        }
+        Ok(etching)
+    }
+}
+
+/// The reveal input spends a script path whose tapscript pushes the rune
+/// commitment: `<pubkey> OP_CHECKSIG OP_FALSE OP_IF <commitment> OP_ENDIF`.
+fn etching_tapscript(rune: Rune, pubkey: XOnlyPublicKey) -> anyhow::Result<(ScriptBuf, TaprootSpendInfo)> {
+    let commitment = rune.commitment();
+    let tapscript = script::Builder::new()
+        .push_slice(pubkey.serialize())
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_FALSE)
+        .push_opcode(opcodes::all::OP_IF)
+        .push_slice(<&script::PushBytes>::try_from(commitment.as_slice())?)
+        .push_opcode(opcodes::all::OP_ENDIF)
+        .into_script();
+    let secp = Secp256k1::verification_only();
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, tapscript.clone())?
+        .finalize(&secp, pubkey)
+        .map_err(|_| anyhow::anyhow!("failed to build taproot tree"))?;
+    Ok((tapscript, spend_info))
+}
+
+/// Virtual size of the signed reveal: one script path spend carrying a
+/// schnorr signature, the tapscript and the control block.
+fn reveal_vsize(reveal: &Transaction, tapscript: &ScriptBuf, control_block: &[u8]) -> usize {
+    let mut signed = reveal.clone();
+    signed.input = vec![TxIn {
+        previous_output: OutPoint::null(),
+        script_sig: ScriptBuf::new(),
+        sequence: reveal_sequence(),
+        witness: Witness::from_slice(&[&[0u8; 64][..], tapscript.as_bytes(), control_block]),
+    }];
+    signed.vsize()
+}
+
+/// A reveal mined `COMMIT_CONFIRMATIONS - 1` blocks after its commit sees the
+/// commit with `COMMIT_CONFIRMATIONS` confirmations.
+fn reveal_sequence() -> Sequence {
+    Sequence::from_height(Runestone::COMMIT_CONFIRMATIONS - 1)
+}
+
+pub async fn build_etching(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
+    Extension(fee_estimator): Extension<Arc<FeeEstimator>>,
+    Json(params): Json<BuildEtchingParams>,
+) -> anyhow::Result<Json<R<BuiltEtchingDTO>>, AppError> {
+    let etching = params.etching()?;
+    let rune = etching.rune.unwrap();
+    let spaced_rune = SpacedRune::new(rune, etching.spacers.unwrap_or_default());
+    if rune.is_reserved() {
+        return Err(anyhow::anyhow!("rune {} is reserved", rune).into());
+    }
+    if db.rune_to_rune_id_get(&rune).is_some() {
+        return Err(anyhow::anyhow!("rune {} is already etched", rune).into());
+    }
+    let next_height = db.latest_indexed_height().unwrap_or_default() + 1;
+    let minimum = chain.minimum_rune_at_height(Height(next_height));
+    if rune < minimum {
+        return Err(anyhow::anyhow!("rune {} is below the minimum {} at height {}", rune, minimum, next_height).into());
+    }
+
+    let pubkey = XOnlyPublicKey::from_slice(&hex::decode(&params.pubkey)?).map_err(anyhow::Error::from)?;
+    let receive = chain.parse_address(&params.address)?.require_network(chain.network())?;
+    let postage = params.postage.unwrap_or(DEFAULT_POSTAGE);
+    if postage < DEFAULT_POSTAGE {
+        return Err(anyhow::anyhow!("postage must be at least {} sats", DEFAULT_POSTAGE).into());
+    }
+
+    let (tapscript, spend_info) = etching_tapscript(rune, pubkey)?;
+    let control_block = spend_info
+        .control_block(&(tapscript.clone(), LeafVersion::TapScript))
+        .ok_or_else(|| anyhow::anyhow!("missing control block"))?;
+    let commit_address = Address::p2tr_tweaked(spend_info.output_key(), chain.network());
+
+    let runestone = Runestone {
+        etching: Some(etching),
+        pointer: Some(0),
+        ..Default::default()
+    };
+    let mut reveal = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(postage),
+                script_pubkey: receive.script_pubkey(),
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: runestone.encipher(),
+            },
+        ],
+    };
+    let fee_rate = fee_rate(&fee_estimator, params.fee_rate).await?;
+    let reveal_fee = (reveal_vsize(&reveal, &tapscript, &control_block.serialize()) as f64 * fee_rate).ceil() as u64;
+    let commit_output = match (&params.commit_outpoint, params.commit_value) {
+        (Some(outpoint), Some(value)) => {
+            reveal.input.push(TxIn {
+                previous_output: outpoint.parse::<OutPoint>()?,
+                script_sig: ScriptBuf::new(),
+                sequence: reveal_sequence(),
+                witness: Witness::new(),
+            });
+            Some(TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: commit_address.script_pubkey(),
+            })
+        }
+        (None, None) => None,
+        _ => return Err(anyhow::anyhow!("commit_outpoint and commit_value must be given together").into()),
+    };
+    let mut psbt = Psbt::from_unsigned_tx(reveal).map_err(anyhow::Error::from)?;
+    if let Some(commit_output) = commit_output {
+        let input = &mut psbt.inputs[0];
+        input.witness_utxo = Some(commit_output);
+        input.tap_internal_key = Some(pubkey);
+        input.tap_merkle_root = spend_info.merkle_root();
+        input.tap_scripts.insert(control_block.clone(), (tapscript.clone(), LeafVersion::TapScript));
+    }
+
+    Ok(Json(R::with_data(BuiltEtchingDTO {
+        rune: spaced_rune.to_string(),
+        commitment: hex::encode(rune.commitment()),
+        tapscript: hex::encode(tapscript.as_bytes()),
+        control_block: hex::encode(control_block.serialize()),
+        commit_address: chain.format_address(&commit_address),
+        commit_script_pubkey: hex::encode(commit_address.script_pubkey().as_bytes()),
+        reveal_sequence: reveal_sequence().to_consensus_u32(),
+        commit_confirmations: Runestone::COMMIT_CONFIRMATIONS,
+        reveal_fee,
+        commit_value: postage + reveal_fee,
+        reveal: BuiltPsbtDTO::new(&psbt, fee_rate),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(vout: u32, amount: u128) -> (u128, RuneUtxo) {
+        (amount, RuneUtxo {
+            txid: "00".into(),
+            vout,
+            value: 546,
+            amount: amount.to_string(),
+            other_runes: HashMap::new(),
+        })
+    }
+
+    fn vouts(selected: Vec<(u128, RuneUtxo)>) -> Vec<u32> {
+        selected.into_iter().map(|x| x.1.vout).collect()
+    }
+
+    #[test]
+    fn single_output_covering_target() {
+        let candidates = vec![candidate(0, 50), candidate(1, 500), candidate(2, 120), candidate(3, 100)];
+        assert_eq!(vouts(select(candidates.clone(), 100).unwrap()), vec![3]);
+        assert_eq!(vouts(select(candidates, 101).unwrap()), vec![2]);
+    }
+
+    #[test]
+    fn largest_first() {
+        let candidates = vec![candidate(0, 10), candidate(1, 40), candidate(2, 30), candidate(3, 20)];
+        assert_eq!(vouts(select(candidates, 65).unwrap()), vec![1, 2]);
+    }
+
+    #[test]
+    fn insufficient() {
+        assert!(select(vec![candidate(0, 10), candidate(1, 20)], 31).is_err());
+        assert!(select(vec![candidate(0, 10)], 0).is_err());
+    }
+
+    #[test]
+    fn mint_psbt() {
+        use ordinals::Artifact;
+
+        let rune_id = RuneId { block: 840000, tx: 3 };
+        let receive = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".parse::<Address<_>>().unwrap().assume_checked();
+        let built = unsigned_psbt(mint_outputs(rune_id, &receive, 546), 2.0).unwrap();
+        let psbt = built.psbt_base64.parse::<Psbt>().unwrap();
+        assert_eq!(psbt.serialize_hex(), built.psbt_hex);
+
+        let tx = psbt.unsigned_tx;
+        assert_eq!(tx.output[0].script_pubkey, receive.script_pubkey());
+        assert_eq!(tx.output[0].value.to_sat(), 546);
+        let Some(Artifact::Runestone(runestone)) = Runestone::decipher(&tx) else {
+            panic!("expected a runestone");
+        };
+        assert_eq!(runestone.mint, Some(rune_id));
+        assert_eq!(runestone.pointer, Some(0));
+    }
+
+    #[test]
+    fn etching_params() {
+        let params: BuildEtchingParams = serde_json::from_value(serde_json::json!({
+            "rune": "UNCOMMON•GOODS",
+            "divisibility": 2,
+            "symbol": "⧉",
+            "premine": "1000",
+            "terms": { "amount": "100", "cap": "10", "height_end": 900000 },
+            "pubkey": "",
+            "address": "",
+        })).unwrap();
+        let etching = params.etching().unwrap();
+        assert_eq!(etching.rune, Some(Rune(2055900680524219742)));
+        assert_eq!(etching.spacers, Some(128));
+        assert_eq!(etching.supply(), Some(2000));
+        assert_eq!(etching.terms.unwrap().height, (None, Some(900000)));
+
+        let params: BuildEtchingParams = serde_json::from_value(serde_json::json!({
+            "rune": "UNCOMMONGOODS",
+            "premine": u128::MAX.to_string(),
+            "terms": { "amount": "1", "cap": "1" },
+            "pubkey": "",
+            "address": "",
+        })).unwrap();
+        assert!(params.etching().is_err());
+    }
+
+    #[test]
+    fn etching_commitment() {
+        let rune = Rune(2055900680524219742);
+        let pubkey = XOnlyPublicKey::from_slice(
+            &hex::decode("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap(),
+        ).unwrap();
+        let (tapscript, spend_info) = etching_tapscript(rune, pubkey).unwrap();
+
+        let pushes = tapscript.instructions().filter_map(|x| x.unwrap().push_bytes().map(|x| x.as_bytes().to_vec())).collect::<Vec<_>>();
+        assert!(pushes.contains(&rune.commitment()));
+        assert!(spend_info.control_block(&(tapscript, LeafVersion::TapScript)).is_some());
+
+        // mined COMMIT_CONFIRMATIONS - 1 blocks after the commit
+        assert_eq!(reveal_sequence().to_relative_lock_time(), Some(bitcoin::relative::LockTime::Blocks(bitcoin::relative::Height::from(5))));
+    }
+
+    #[test]
+    fn reveal_size() {
+        let rune = Rune(2055900680524219742);
+        let pubkey = XOnlyPublicKey::from_slice(
+            &hex::decode("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap(),
+        ).unwrap();
+        let (tapscript, spend_info) = etching_tapscript(rune, pubkey).unwrap();
+        let control_block = spend_info.control_block(&(tapscript.clone(), LeafVersion::TapScript)).unwrap();
+        let reveal = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut { value: Amount::from_sat(546), script_pubkey: ScriptBuf::new_op_return([0u8; 20]) }],
+        };
+        let unsigned = reveal.vsize();
+        let vsize = reveal_vsize(&reveal, &tapscript, &control_block.serialize());
+        // outpoint, sequence and script length are not discounted, the witness is
+        assert!(vsize > unsigned + 41);
+        assert!(vsize < unsigned + 41 + 60);
+    }
+}
@@ -0,0 +1,100 @@
+//! `Cache-Control` headers that follow the indexer tip, so CDNs can cache GET
+//! responses until the next block is expected.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::db::{RunesDB, Store};
+
+/// Blocks on top of a height before data at that height is treated as final.
+pub const IMMUTABLE_CONFIRMATIONS: u32 = 6;
+const BLOCK_INTERVAL_SECS: u64 = 600;
+const MIN_TIP_MAX_AGE_SECS: u64 = 5;
+
+/// How long a response stays valid, set by handlers as a response extension.
+/// Responses without one are treated as [`CachePolicy::Tip`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Changes with the next block.
+    Tip,
+    /// Data buried under [`IMMUTABLE_CONFIRMATIONS`] blocks.
+    Immutable,
+    /// Mempool or operator data.
+    NoStore,
+}
+
+impl CachePolicy {
+    /// `Immutable` once `height` is buried deep enough below `latest_height`.
+    pub fn at_height(height: u32, latest_height: u32) -> Self {
+        if height + IMMUTABLE_CONFIRMATIONS <= latest_height {
+            CachePolicy::Immutable
+        } else {
+            CachePolicy::Tip
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheControlState {
+    pub db: Arc<RunesDB>,
+    pub tip_max_age_secs: u64,
+    pub immutable_max_age_secs: u64,
+}
+
+/// Seconds until the block after the one mined at `tip_time` is expected,
+/// between [`MIN_TIP_MAX_AGE_SECS`] and `max`.
+fn tip_max_age(tip_time: Option<u64>, now: u64, max: u64) -> u64 {
+    let remaining = tip_time.map(|time| (time + BLOCK_INTERVAL_SECS).saturating_sub(now)).unwrap_or(0);
+    remaining.clamp(MIN_TIP_MAX_AGE_SECS, max.max(MIN_TIP_MAX_AGE_SECS))
+}
+
+pub async fn cache_control(State(state): State<CacheControlState>, req: Request, next: Next) -> Response {
+    let cacheable = req.method() == Method::GET;
+    let mut response = next.run(req).await;
+    if response.headers().contains_key(header::CACHE_CONTROL) {
+        return response;
+    }
+    let policy = response.extensions().get::<CachePolicy>().copied().unwrap_or(CachePolicy::Tip);
+    let value = match policy {
+        _ if !cacheable || !response.status().is_success() => "no-store".to_string(),
+        CachePolicy::NoStore => "no-store".to_string(),
+        CachePolicy::Immutable => format!("public, max-age={}, immutable", state.immutable_max_age_secs),
+        CachePolicy::Tip => {
+            let tip_time = state.db.latest_height().and_then(|x| state.db.height_to_block_header_get(x)).map(|x| x.time as u64);
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            format!("public, max-age={}", tip_max_age(tip_time, now, state.tip_max_age_secs))
+        }
+    };
+    response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_str(&value).unwrap());
+    response
+}
+
+/// Marks every response of a route as [`CachePolicy::NoStore`].
+pub async fn no_store(mut response: Response) -> Response {
+    response.extensions_mut().insert(CachePolicy::NoStore);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tip_max_age_follows_block_cadence() {
+        assert_eq!(tip_max_age(Some(1_000), 1_000, 60), 60);
+        assert_eq!(tip_max_age(Some(1_000), 1_570, 60), 30);
+        assert_eq!(tip_max_age(Some(1_000), 5_000, 60), MIN_TIP_MAX_AGE_SECS);
+        assert_eq!(tip_max_age(None, 5_000, 60), MIN_TIP_MAX_AGE_SECS);
+    }
+
+    #[test]
+    fn immutable_after_confirmations() {
+        assert_eq!(CachePolicy::at_height(100, 105), CachePolicy::Tip);
+        assert_eq!(CachePolicy::at_height(100, 106), CachePolicy::Immutable);
+    }
+}
@@ -2,16 +2,18 @@ use std::str::FromStr;
 use std::sync::Arc;
 
 use axum::{Extension, Json};
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use bitcoin::Txid;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use ordinals::{RuneId, SpacedRune};
 
-use crate::api::dto::{AppError, serialize_as_string};
+use crate::api::dto::{AppError, RuneEntryDTO, serialize_as_string};
+use crate::api::handler::runes_total;
 use crate::cache::{CacheKey, CacheMethod, MokaCache};
-use crate::db::RunesDB;
+use crate::db::{RunesDB, Store};
+use crate::db::model::RuneEntryCompatPageParams;
 
 #[derive(Debug, Serialize)]
 pub struct R<T> {
@@ -69,22 +71,38 @@ pub struct PagedRunesParams {
     pub sort: Option<String>,
 }
 
-
-
+#[derive(Debug, Serialize)]
+pub struct PagedRunes {
+    /// Runes matching the filter across all pages.
+    pub total: u64,
+    pub list: Vec<RuneEntryDTO>,
+}
 
 pub async fn paged_runes(
     Extension(cache): Extension<Arc<MokaCache>>,
     Extension(db): Extension<Arc<RunesDB>>,
-    Path(params): Path<PagedRunesParams>,
+    Query(params): Query<PagedRunesParams>,
 ) -> anyhow::Result<Json<Value>, AppError> {
-    let cache_key = CacheKey::new(CacheMethod::CompatPagedRunes, serde_json::to_value(params).unwrap());
-    if let Some(cached) = cache.get(&cache_key).await {
-        return Ok(Json(cached));
-    }
-    
-    // db.sqlite_rune_entry_list_for_compat(&params)?;
-
-    Ok(Json(Value::Null))
+    let cache_key = CacheKey::new(CacheMethod::CompatPagedRunes, serde_json::to_value(&params)?);
+    let value = cache.try_get_with(cache_key, async {
+        let params = RuneEntryCompatPageParams {
+            offset: params.offset,
+            limit: params.limit.clamp(1, 1000),
+            mint_type: params.mint_type,
+            search: params.search,
+            sort: params.sort,
+        };
+        let total = runes_total(&cache, &db, &params.filter()?).await?;
+        let list = db.sqlite_rune_entry_list_for_compat(&params)?.into_iter().map(RuneEntryDTO::from).collect();
+        let r = R {
+            status: true,
+            status_code: 200,
+            message: "success".to_string(),
+            data: PagedRunes { total, list },
+        };
+        Ok(serde_json::to_value(&r)?)
+    }).await?;
+    Ok(Json(value))
 }
 
 
@@ -94,10 +112,19 @@ pub async fn address_runes(
     Path(address_string): Path<String>,
 ) -> anyhow::Result<Json<Value>, AppError> {
     let cache_key = CacheKey::new(CacheMethod::CompatAddressUtxos, Value::String(address_string.clone()));
-    if let Some(cached) = cache.get(&cache_key).await {
-        return Ok(Json(cached));
-    }
+    let value = cache.try_get_with(cache_key, async {
+        let r = R {
+            status: true,
+            status_code: 200,
+            message: "success".to_string(),
+            data: address_rune_values(&db, address_string.clone())?,
+        };
+        Ok(serde_json::to_value(&r)?)
+    }).await?;
+    Ok(Json(value))
+}
 
+fn address_rune_values(db: &RunesDB, address_string: String) -> anyhow::Result<Vec<RuneValue>> {
     let unspent = db.sqlite_rune_balance_list_unspent_by_address(&address_string)?;
     let mut items: Vec<RuneValue> = vec![];
     for x in unspent.iter() {
@@ -122,15 +149,5 @@ pub async fn address_runes(
             },
         });
     }
-    let r = R {
-        status: true,
-        status_code: 200,
-        message: "success".to_string(),
-        data: items,
-    };
-    let value = serde_json::to_value(&r)?;
-    let mut cloned = value.clone();
-    cloned["cache"] = Value::Bool(true);
-    cache.insert(cache_key, cloned).await;
-    Ok(Json(value))
+    Ok(items)
 }
@@ -7,9 +7,10 @@ use bitcoin::{OutPoint, Txid};
 use serde::{Deserialize, Serialize, Serializer};
 use serde::ser::{SerializeMap, SerializeSeq};
 
-use ordinals::{RuneId, SpacedRune};
+use ordinals::{Artifact, Flaw, RuneId, SpacedRune};
 
-use crate::db::model::RuneEntryForQueryInsert;
+use crate::api::request_id;
+use crate::db::model::{CenotaphForQueryInsert, InscriptionForQuery, RuneEntryForQueryInsert};
 use crate::entry::RuneEntry;
 use crate::lot::Lot;
 
@@ -35,6 +36,11 @@ impl From<bitcoin::address::ParseError> for AppError {
         AppError(err.into())
     }
 }
+impl From<bitcoin::address::Error> for AppError {
+    fn from(err: bitcoin::address::Error) -> Self {
+        AppError(err.into())
+    }
+}
 impl From<bitcoin::transaction::ParseOutPointError> for AppError {
     fn from(err: bitcoin::transaction::ParseOutPointError) -> Self {
         AppError(err.into())
@@ -288,11 +294,36 @@ impl ExpandRuneEntry {
 pub struct Paged<T> {
     pub next: bool,
     pub list: Vec<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    /// Cursor of the next page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<usize>,
+    /// Keyset cursor of the next page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
 }
 
 impl<T> Paged<T> {
     pub fn new(next: bool, list: Vec<T>) -> Self {
-        Paged { next, list }
+        Paged { next, list, total: None, cursor: None, after: None }
+    }
+
+    /// Sets the next page cursor for an offset based `cursor`.
+    pub fn with_cursor(mut self, cursor: usize) -> Self {
+        self.cursor = self.next.then_some(cursor + self.list.len());
+        self
+    }
+
+    /// Sets the next page keyset cursor from the last key of this page.
+    pub fn with_after(mut self, after: Option<impl ToString>) -> Self {
+        self.after = after.filter(|_| self.next).map(|x| x.to_string());
+        self
+    }
+
+    pub fn with_total(mut self, total: u64) -> Self {
+        self.total = Some(total);
+        self
     }
 }
 
@@ -305,6 +336,9 @@ pub struct R<T> {
     pub message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response: Option<T>,
+    /// Set on errors, the `X-Request-Id` of the failed request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl<T> R<T> {
@@ -314,6 +348,7 @@ impl<T> R<T> {
             code: Some(code),
             message: Some(msg),
             response: None,
+            request_id: request_id::current(),
         }
     }
 
@@ -323,6 +358,7 @@ impl<T> R<T> {
             code: None,
             message: None,
             response: Some(data),
+            request_id: None,
         }
     }
 }
@@ -362,6 +398,72 @@ impl RunesTxParams {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RunesScriptParams {
+    pub script: String,
+    /// Output count of the transaction the script belongs to, edicts pointing
+    /// past it are flawed. Defaults to 1, the script being the only output.
+    pub outputs: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunesScriptDTO {
+    pub artifact: Option<Artifact>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flaw: Option<String>,
+}
+
+/// The deciphered runestone of a transaction, `height` is null for
+/// transactions the index has no rows for.
+#[derive(Debug, Serialize)]
+pub struct RunestoneDTO {
+    pub txid: String,
+    pub height: Option<u32>,
+    pub artifact: Option<Artifact>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flaw: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResolvedRuneDTO {
+    pub rune_id: String,
+    pub entry: RuneEntryDTO,
+}
+
+/// How a rune was etched. `runestone` is deciphered from the reveal transaction
+/// and null when bitcoind RPC isn't configured.
+#[derive(Debug, Serialize)]
+pub struct RuneEtchingDTO {
+    pub rune_id: String,
+    pub spaced_rune: String,
+    pub reveal_txid: String,
+    pub commit_txid: Option<String>,
+    pub etcher: Option<String>,
+    pub height: u32,
+    pub reserved: bool,
+    pub runestone: Option<Artifact>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunesValidateParams {
+    pub raw_tx: Option<String>,
+    pub psbt_hex: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunesValidateDTO {
+    /// No runestone flaw and no runes burned.
+    pub valid: bool,
+    pub cenotaph: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flaw: Option<Flaw>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Input runes the transaction would burn, by cenotaph or by OP_RETURN outputs.
+    #[serde(serialize_with = "serialize_runes_burned_map")]
+    pub burned: HashMap<RuneId, Lot>,
+}
+
 #[derive(Debug, Serialize, Default)]
 pub struct RunesTxDTO {
     pub runes: Vec<ExpandRuneEntry>,
@@ -377,9 +479,23 @@ pub struct RunesTxDTO {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RunesPageParams {
     pub cursor: Option<usize>,
+    /// Keyset cursor `height:number` from a previous page, preferred over `cursor`.
+    pub after: Option<String>,
     pub size: Option<usize>,
     pub keywords: Option<String>,
+    /// `etching|holders|mints|supply|timestamp|transactions`, or the legacy `asc|desc`.
     pub sort: Option<String>,
+    /// `asc|desc`
+    pub order: Option<String>,
+    pub mintable: Option<bool>,
+    pub fairmint: Option<bool>,
+    pub turbo: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutputsParams {
+    #[serde(default)]
+    pub include_mempool: bool,
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -387,6 +503,10 @@ pub struct OutputsDTO {
     pub runes: Vec<ExpandRuneEntry>,
     #[serde(serialize_with = "serialize_vec_runes_balance_map")]
     pub outputs: Vec<HashMap<RuneId, u128>>,
+    /// With `include_mempool`, the unconfirmed tx spending each outpoint. The
+    /// balances of those outpoints are left out of `outputs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spent_by: Option<Vec<Option<Txid>>>,
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -404,12 +524,236 @@ pub struct UTXOWithRuneValueDTO {
     pub runes_value: HashMap<String, String>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct OutputRuneBalanceDTO {
+    pub rune_id: String,
+    pub spaced_rune: String,
+    pub amount: String,
+    pub divisibility: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<char>,
+}
+
+/// A range of sats in an output, end exclusive, with the rarity of its first
+/// sat.
+#[derive(Debug, Serialize)]
+pub struct SatRangeDTO {
+    pub start: u64,
+    pub end: u64,
+    pub rarity: String,
+}
+
+/// An indexed rune output, from its `OUTPOINT_TO_RUNE_BALANCES` entry and
+/// rune_balance rows.
+#[derive(Debug, Serialize)]
+pub struct OutputDetailDTO {
+    pub outpoint: String,
+    pub height: u32,
+    pub spent: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spent_height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spent_txid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spent_vin: Option<u32>,
+    pub value: u64,
+    /// None for scripts without an address, such as bare multisig.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    pub script_pubkey: String,
+    pub balances: Vec<OutputRuneBalanceDTO>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AddressRuneUTXOsDTO {
     pub utxos: Vec<UTXOWithRuneValueDTO>,
     pub runes: Vec<RuneEntryDTO>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddressUtxosParams {
+    pub rune_id: Option<String>,
+    pub min_amount: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Mempool view of an address next to its confirmed balances. Only spends of
+/// confirmed outputs and outputs paid by transactions with confirmed rune
+/// inputs are seen, chains of unconfirmed transfers are not followed.
+#[derive(Debug, Serialize)]
+pub struct AddressUnconfirmedDTO {
+    pub confirmed: HashMap<String, String>,
+    /// Runes sent to the address by unconfirmed transactions.
+    pub incoming: HashMap<String, String>,
+    /// Confirmed runes of the address spent by unconfirmed transactions.
+    pub outgoing: HashMap<String, String>,
+    /// incoming - outgoing, signed.
+    pub delta: HashMap<String, String>,
+    pub txids: Vec<Txid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddressBalancesParams {
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressRuneBalanceDTO {
+    pub rune_id: String,
+    pub amount: String,
+    pub utxos: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressBalancesDTO {
+    pub height: u32,
+    pub balances: Vec<AddressRuneBalanceDTO>,
+    pub runes: Vec<RuneEntryDTO>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockRuneMintsDTO {
+    pub rune_id: String,
+    pub mints: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockRuneBurnedDTO {
+    pub rune_id: String,
+    pub amount: String,
+}
+
+/// Runes activity of one block, `transfers` counts outputs receiving runes
+/// by transfer and `transfer_txs` the transactions creating them.
+#[derive(Debug, Serialize)]
+pub struct BlockRunesDTO {
+    pub height: u32,
+    pub block_hash: String,
+    /// rolling hash of the rune state after the block, none for blocks indexed
+    /// before it was kept
+    pub state_hash: Option<String>,
+    pub etchings: Vec<RuneEntryDTO>,
+    pub mints: Vec<BlockRuneMintsDTO>,
+    pub burned: Vec<BlockRuneBurnedDTO>,
+    pub transfers: u32,
+    pub transfer_txs: u32,
+}
+
+/// Height window of `/runes/etched`, both ends inclusive.
+#[derive(Debug, Deserialize)]
+pub struct EtchedRunesParams {
+    pub from_height: Option<u32>,
+    pub to_height: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+/// Block range of `/stats/blocks`, both ends inclusive.
+#[derive(Debug, Deserialize)]
+pub struct BlockStatsParams {
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+}
+
+/// Page of the event log, after `since_seq`.
+#[derive(Debug, Deserialize)]
+pub struct EventsParams {
+    pub since_seq: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopHoldersParams {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuneHolderDTO {
+    pub address: String,
+    pub amount: String,
+    /// Share of the rune supply in percent.
+    pub share: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopHoldersDTO {
+    pub rune_id: String,
+    pub supply: String,
+    pub holders: Vec<RuneHolderDTO>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuneTxsPageParams {
+    pub cursor: Option<usize>,
+    pub size: Option<usize>,
+}
+
+/// Height window of `/rune/:id/txs/range`, both ends inclusive.
+#[derive(Debug, Deserialize)]
+pub struct RuneTxsRangeParams {
+    pub from: u32,
+    pub to: Option<u32>,
+    pub cursor: Option<usize>,
+    pub size: Option<usize>,
+}
+
+/// A transaction minting a rune, `amount` is the rune's mint amount.
+#[derive(Debug, Serialize)]
+pub struct RuneMintDTO {
+    pub txid: String,
+    pub height: u32,
+    pub minter: String,
+    pub amount: String,
+}
+
+/// An inscription and where it is, `location` is the satpoint of its sat as
+/// `txid:vout:offset`, null with the address when the sat went to the fees.
+#[derive(Debug, Serialize)]
+pub struct InscriptionDTO {
+    pub id: String,
+    pub number: u32,
+    pub content_type: Option<String>,
+    pub content_length: u64,
+    pub content_encoding: Option<String>,
+    pub metaprotocol: Option<String>,
+    pub genesis_height: u32,
+    pub genesis_ts: u32,
+    pub location: Option<String>,
+    pub address: Option<String>,
+    pub location_height: u32,
+}
+
+impl From<InscriptionForQuery> for InscriptionDTO {
+    fn from(x: InscriptionForQuery) -> Self {
+        let InscriptionForQuery { inscription, location } = x;
+        InscriptionDTO {
+            id: inscription.inscription_id,
+            number: inscription.number,
+            content_type: inscription.content_type,
+            content_length: inscription.content_length,
+            content_encoding: inscription.content_encoding,
+            metaprotocol: inscription.metaprotocol,
+            genesis_height: inscription.height,
+            genesis_ts: inscription.ts,
+            location: location.vout.map(|vout| format!("{}:{}:{}", location.txid, vout, location.sat_offset)),
+            address: location.address,
+            location_height: location.height,
+        }
+    }
+}
+
+/// Net effect of a transaction on one rune. Burns to OP_RETURN outputs are
+/// not stored as balances, so they show up as inputs not matched by outputs.
+#[derive(Debug, Serialize)]
+pub struct RuneTxEffectDTO {
+    pub txid: String,
+    pub height: u32,
+    pub input: String,
+    pub output: String,
+    pub minted: String,
+    pub burned: String,
+    pub moved: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct RuneEntryDTO {
     pub rune_id: String,
@@ -443,6 +787,10 @@ pub struct RuneEntryDTO {
     pub transactions: u32,
     pub height: u32,
     pub ts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etcher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_txid: Option<String>,
 }
 
 impl From<RuneEntryForQueryInsert> for RuneEntryDTO {
@@ -471,6 +819,8 @@ impl From<RuneEntryForQueryInsert> for RuneEntryDTO {
             transactions: value.transactions,
             height: value.height,
             ts: value.ts,
+            etcher: value.etcher,
+            commit_txid: value.commit_txid,
         }
     }
 }
@@ -484,5 +834,7 @@ pub struct RuneTx {
     pub burned: HashMap<String, String>,
     pub minted: HashMap<String, String>,
     pub premine: HashMap<String, String>,
+    /// why the runestone is a cenotaph and what it destroyed
+    pub cenotaph: Option<CenotaphForQueryInsert>,
 }
 
@@ -1,10 +1,12 @@
 use std::any::Any;
 
-use axum::body::Bytes;
+use axum::body::{Body, Bytes};
+use axum::BoxError;
 use axum::http;
 use axum::http::{header, StatusCode};
 use axum::response::Response;
 use http_body_util::Full;
+use tower::load_shed::error::Overloaded;
 use crate::api::dto::R;
 
 pub fn handle_panic(err: Box<dyn Any + Send + 'static>) -> http::Response<Full<Bytes>> {
@@ -25,3 +27,19 @@ pub fn handle_panic(err: Box<dyn Any + Send + 'static>) -> http::Response<Full<B
         .body(Full::from(body))
         .unwrap()
 }
+
+/// Requests rejected by the load-shed layer once `concurrency_limit` requests are
+/// in flight get a 429 instead of queueing for a SQLite connection.
+pub async fn handle_overload(err: BoxError) -> Response {
+    let (status, message) = if err.is::<Overloaded>() {
+        (StatusCode::TOO_MANY_REQUESTS, "Server is busy, retry later".to_string())
+    } else {
+        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    };
+    let body: R<()> = R::error(-1, message);
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap()
+}
@@ -6,7 +6,9 @@ use std::sync::Arc;
 use axum::{Extension, Json};
 use axum::extract::{Path, Query};
 use axum::response::IntoResponse;
-use bitcoin::{Address, OutPoint, Transaction};
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, OutPoint, ScriptBuf, Transaction, TxOut};
 use bitcoin::psbt::Psbt;
 use bitcoincore_rpc::json::Bip125Replaceable::No;
 use itertools::Itertools;
@@ -14,18 +16,32 @@ use log::info;
 use rusqlite::params;
 use serde_json::{json, Value};
 
-use ordinals::{Artifact, Edict, Rune, RuneId, Runestone, SpacedRune};
+use ordinals::{Artifact, Edict, Rune, RuneId, Runestone, Sat, SpacedRune};
 
-use crate::api::dto::{AddressRuneUTXOsDTO, AppError, ExpandRuneEntry, OutputsDTO, Paged, R, RuneEntryDTO, RunesPageParams, RunesPSBTParams, RunesTxDTO, RunesTxParams, RuneTx, UTXOWithRuneValueDTO};
+use crate::api::cache_control::CachePolicy;
+use crate::api::dto::{AddressBalancesDTO, AddressBalancesParams, AddressRuneBalanceDTO, AddressRuneUTXOsDTO, AddressUnconfirmedDTO, AddressUtxosParams, AppError, BlockRuneBurnedDTO, BlockRuneMintsDTO, BlockRunesDTO, BlockStatsParams, EtchedRunesParams, EventsParams, ExpandRuneEntry, InscriptionDTO, OutputDetailDTO, OutputRuneBalanceDTO, OutputsDTO, OutputsParams, Paged, R, RuneEntryDTO, RuneEtchingDTO, RuneHolderDTO, RuneMintDTO, ResolvedRuneDTO, RunesPageParams, RunesPSBTParams, RunesScriptDTO, RunesScriptParams, RunesValidateDTO, RunesValidateParams, RunesTxDTO, RunesTxParams, RuneTx, RuneTxEffectDTO, RuneTxsPageParams, RuneTxsRangeParams, RunestoneDTO, SatRangeDTO, TopHoldersDTO, TopHoldersParams, UTXOWithRuneValueDTO};
 use crate::api::util::hex_to_base64;
 use crate::api::vo::RuneBalanceGroupKey;
 use crate::cache::{CacheKey, CacheMethod, MokaCache};
+use crate::chain::Chain;
 use crate::db::model::RuneEntryForQueryInsert;
-use crate::db::RunesDB;
+use crate::db::model::{BlockStats, RuneEventForQuery, RuneEntryFilter, RuneEntryPageKey, RuneEntryPageQuery, RuneEntrySort};
+use crate::db::{RunesDB, Store, HEIGHT_RUNE_ID_TO_BURNED, HEIGHT_RUNE_ID_TO_MINTS};
+use crate::entry::{RuneEntry, Statistic};
+use crate::fee::{FeeEstimate, FeeEstimator, FEE_TARGETS};
+use crate::inscription::InscriptionId;
+use crate::protocol::find_protocol;
+use crate::mempool::{MempoolTracker, MempoolTxStatus};
 use crate::into_usize::IntoUsize;
+use crate::reindex::ReindexStatus;
 use crate::lot::Lot;
+use crate::rpc::AsyncClient;
 use crate::updater::RuneUpdater;
 
+const MAX_DECODE_OUTPUTS: usize = 10_000;
+/// Days of etchings `/stats` reports.
+const ETCHINGS_PER_DAY_DAYS: u32 = 30;
+
 fn format_size(bytes: u64) -> String {
     let sizes = ["Bytes", "KB", "MB", "GB", "TB", "PB", "EB", "ZB", "YB"];
     let factor = 1024.0;
@@ -46,18 +62,44 @@ fn format_size(bytes: u64) -> String {
 
 pub async fn stats(
     Extension(db): Extension<Arc<RunesDB>>,
+    Extension(cache): Extension<Arc<MokaCache>>,
+    Extension(reindex_status): Extension<Arc<ReindexStatus>>,
 ) -> anyhow::Result<Json<R<Value>>, AppError> {
     let indexed_height = db.latest_indexed_height();
     let latest_height = db.latest_height();
     let remaining_height = latest_height.unwrap_or_default() - indexed_height.unwrap_or_default();
     let db_size = fs_extra::dir::get_size(db.rocksdb.path().parent().unwrap())?;
+    let column_families = db.rocksdb_cf_stats()?.into_iter().map(|x| json!({
+        "name": x.name,
+        "size": format_size(x.sst_size + x.memtable_size),
+        "sst_size": x.sst_size,
+        "memtable_size": x.memtable_size,
+        "estimated_keys": x.estimated_keys,
+        "live_files": x.live_files,
+    })).collect::<Vec<_>>();
+    let sqlite_size = db.sqlite_file_size()?;
+    let sqlite_wal_size = db.sqlite_wal_size();
+    let pool = db.sqlite.state();
+    let cache_stats = cache.stats().await;
+    let network = db.network_stats();
+    let etchings_per_day = db.sqlite_etchings_per_day(ETCHINGS_PER_DAY_DAYS)?;
     Ok(Json(R::with_data(json!({
         "indexer": {
             "indexed_height": indexed_height,
+            "state_hash": indexed_height.and_then(|x| db.height_to_state_hash_get(x)).map(hex::encode),
             "latest_height": latest_height,
             "remaining_height": remaining_height,
             "remaining_percentage": format!("{:.5}%", remaining_height as f64 / latest_height.unwrap_or_default() as f64 * 100.0)
         },
+        "reindex": reindex_status.progress(),
+        "network": {
+            "runes": db.statistic_to_value_get(&Statistic::Runes).unwrap_or_default(),
+            "mints": network.mints.to_string(),
+            "burned": network.burned.to_string(),
+            "holders": network.holders,
+            "transactions": network.transactions,
+            "etchings_per_day": etchings_per_day,
+        },
         "binary": {
             "version": env!("CARGO_PKG_VERSION"),
             "timestamp": env!("VERGEN_BUILD_TIMESTAMP"),
@@ -65,9 +107,123 @@ pub async fn stats(
             "rustc": env!("VERGEN_RUSTC_SEMVER"),
         },
         "db": format_size(db_size),
+        "storage": {
+            "rocksdb": column_families,
+            "sqlite": {
+                "size": format_size(sqlite_size),
+                "bytes": sqlite_size,
+                "wal_size": format_size(sqlite_wal_size),
+                "wal_bytes": sqlite_wal_size,
+            },
+        },
+        "sqlite_pool": {
+            "max_size": db.sqlite.max_size(),
+            "connections": pool.connections,
+            "idle": pool.idle_connections,
+            "active": pool.connections - pool.idle_connections,
+        },
+        "cache": {
+            "entries": cache_stats.entries,
+            "weighted_size": cache_stats.weighted_size,
+            "hits": cache_stats.hits,
+            "misses": cache_stats.misses,
+            "hit_ratio": cache_stats.hit_ratio(),
+        },
+    }))))
+}
+
+/// Most blocks `/stats/blocks` returns at once.
+const BLOCK_STATS_MAX_RANGE: u32 = 1000;
+
+/// Indexing stats per block, the latest 100 blocks by default.
+pub async fn block_stats(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Query(params): Query<BlockStatsParams>,
+) -> anyhow::Result<Json<R<Vec<BlockStats>>>, AppError> {
+    let to = params.to.or_else(|| db.latest_indexed_height()).unwrap_or_default();
+    let from = params.from.unwrap_or(to.saturating_sub(99));
+    if from > to {
+        return Err(anyhow::anyhow!("from {} is above to {}", from, to).into());
+    }
+    if to - from >= BLOCK_STATS_MAX_RANGE {
+        return Err(anyhow::anyhow!("at most {} blocks per request", BLOCK_STATS_MAX_RANGE).into());
+    }
+    Ok(Json(R::with_data(db.sqlite_block_stats_list(from, to)?)))
+}
+
+pub async fn block_runes(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(height): Path<u32>,
+) -> anyhow::Result<(Extension<CachePolicy>, Json<R<BlockRunesDTO>>), AppError> {
+    let Some(header) = db.height_to_block_header_get(height) else {
+        return Err(anyhow::anyhow!("block {} is not indexed", height).into());
+    };
+    let etchings = db.sqlite_rune_entry_list_by_height(height)?.into_iter().map(|x| x.into()).collect();
+    let mints = db.height_rune_id_list_at_height(HEIGHT_RUNE_ID_TO_MINTS, height)?
+        .into_iter()
+        .map(|(rune_id, mints)| BlockRuneMintsDTO { rune_id: rune_id.to_string(), mints: mints.to_string() })
+        .collect();
+    let burned = db.height_rune_id_list_at_height(HEIGHT_RUNE_ID_TO_BURNED, height)?
+        .into_iter()
+        .map(|(rune_id, amount)| BlockRuneBurnedDTO { rune_id: rune_id.to_string(), amount: amount.to_string() })
+        .collect();
+    let (transfers, transfer_txs) = db.sqlite_transfer_count_at_height(height)?;
+    let latest_height = committed_height(&db)?;
+    Ok((Extension(CachePolicy::at_height(height, latest_height)), Json(R::with_data(BlockRunesDTO {
+        height,
+        block_hash: header.block_hash().to_string(),
+        state_hash: db.height_to_state_hash_get(height).map(hex::encode),
+        etchings,
+        mints,
+        burned,
+        transfers,
+        transfer_txs,
     }))))
 }
 
+/// Widest height window `/runes/etched` accepts.
+const ETCHED_MAX_RANGE: u32 = 1000;
+/// Most runes `/runes/etched` returns at once.
+const ETCHED_MAX_LIMIT: u32 = 1000;
+
+/// Runes etched in a height window, newest first, the latest 144 blocks by
+/// default.
+pub async fn etched_runes(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Query(params): Query<EtchedRunesParams>,
+) -> anyhow::Result<Json<R<Vec<RuneEntryDTO>>>, AppError> {
+    let to = params.to_height.or_else(|| db.latest_indexed_height()).unwrap_or_default();
+    let from = params.from_height.unwrap_or(to.saturating_sub(143));
+    if from > to {
+        return Err(anyhow::anyhow!("from_height {} is above to_height {}", from, to).into());
+    }
+    if to - from >= ETCHED_MAX_RANGE {
+        return Err(anyhow::anyhow!("at most {} blocks per request", ETCHED_MAX_RANGE).into());
+    }
+    let limit = params.limit.unwrap_or(100);
+    if limit == 0 || limit > ETCHED_MAX_LIMIT {
+        return Err(anyhow::anyhow!("limit must be between 1 and {}", ETCHED_MAX_LIMIT).into());
+    }
+    let runes = db.sqlite_rune_entry_list_etched(from, to, limit)?.into_iter().map(|x| x.into()).collect();
+    Ok(Json(R::with_data(runes)))
+}
+
+/// Most events `/events` returns at once.
+const EVENTS_MAX_LIMIT: u32 = 1000;
+
+/// The rune event log after `since_seq`, consumers resume from the last `seq`
+/// they processed.
+pub async fn events(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Query(params): Query<EventsParams>,
+) -> anyhow::Result<Json<R<Vec<RuneEventForQuery>>>, AppError> {
+    let limit = params.limit.unwrap_or(100);
+    if limit == 0 || limit > EVENTS_MAX_LIMIT {
+        return Err(anyhow::anyhow!("limit must be between 1 and {}", EVENTS_MAX_LIMIT).into());
+    }
+    Ok(Json(R::with_data(db.sqlite_rune_event_list(params.since_seq.unwrap_or_default(), limit)?)))
+}
+
 pub async fn block_height(
     Extension(db): Extension<Arc<RunesDB>>,
 ) -> anyhow::Result<Json<R<Option<u32>>>, AppError> {
@@ -76,41 +232,121 @@ pub async fn block_height(
 }
 
 
+/// Accepts a rune id, a spaced rune name, a plain rune name or the txid of
+/// the etching.
+pub(crate) fn resolve_rune_id(db: &RunesDB, id: &str) -> Option<RuneId> {
+    if let Ok(id) = RuneId::from_str(id) {
+        Some(id)
+    } else if let Ok(txid) = bitcoin::Txid::from_str(id) {
+        db.sqlite_rune_entry_get_by_etching_txid(&txid.to_string()).ok().flatten().and_then(|x| RuneId::from_str(&x.rune_id).ok())
+    } else if let Ok(v) = SpacedRune::from_str(id) {
+        db.rune_to_rune_id_get(&v.rune)
+    } else if let Ok(v) = Rune::from_str(id) {
+        db.rune_to_rune_id_get(&v)
+    } else {
+        None
+    }
+}
+
+/// `resolve_rune_id` answering unknown ids from the not found cache until it
+/// expires or a block is indexed.
+pub(crate) async fn resolve_rune_id_cached(cache: &MokaCache, db: &RunesDB, id: &str) -> Option<RuneId> {
+    let cache_key = CacheKey::new(CacheMethod::UnknownRune, Value::String(id.to_string()));
+    if cache.is_not_found(&cache_key).await {
+        return None;
+    }
+    let rune_id = resolve_rune_id(db, id);
+    if rune_id.is_none() {
+        cache.insert_not_found(cache_key).await;
+    }
+    rune_id
+}
+
+/// The canonical rune id and entry of any identifier `resolve_rune_id` accepts.
+pub async fn resolve_rune(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(query): Path<String>,
+) -> anyhow::Result<Json<Option<R<ResolvedRuneDTO>>>, AppError> {
+    let Some(rune_id) = resolve_rune_id(&db, &query) else {
+        return Ok(Json(None));
+    };
+    let Some(entry) = db.sqlite_rune_entry_get_by_id(rune_id.to_string())? else {
+        return Ok(Json(None));
+    };
+    Ok(Json(Some(R::with_data(ResolvedRuneDTO { rune_id: rune_id.to_string(), entry: entry.into() }))))
+}
+
+/// The script of an address, scripts without an address are given by their hex,
+/// as they are stored in sqlite.
+pub(crate) fn address_script(address: &str, chain: Chain) -> anyhow::Result<ScriptBuf> {
+    match chain.parse_address(address) {
+        Ok(address) => Ok(address.require_network(chain.network())?.script_pubkey()),
+        Err(_) => ScriptBuf::from_hex(address).map_err(|_| anyhow::anyhow!("invalid address: {}", address)),
+    }
+}
+
+/// Height of the last block whose sqlite rows are committed. Responses read
+/// from sqlite are consistent up to it, rocksdb may already be further.
+pub(crate) fn committed_height(db: &RunesDB) -> anyhow::Result<u32> {
+    Ok(db.sqlite_block_commit_latest()?.map(|x| x.0).unwrap_or_default())
+}
+
 pub async fn get_rune_by_id(
     Extension(cache): Extension<Arc<MokaCache>>,
     Extension(db): Extension<Arc<RunesDB>>,
     Path(id): Path<String>,
 ) -> anyhow::Result<Json<Option<Value>>, AppError> {
-    let rune_id = {
-        if let Ok(id) = RuneId::from_str(&id) {
-            Some(id)
-        } else if let Ok(v) = SpacedRune::from_str(&id) {
-            db.rune_to_rune_id_get(&v.rune)
-        } else if let Ok(v) = Rune::from_str(&id) {
-            db.rune_to_rune_id_get(&v)
-        } else {
-            None
-        }
-    };
-
-    if rune_id.is_none() {
+    let Some(rune_id) = resolve_rune_id_cached(&cache, &db, &id).await else {
         return Ok(Json(None));
-    }
+    };
 
     let cache_key = CacheKey::new(CacheMethod::HandlerRuneById, Value::String(id.clone()));
-    if let Some(value) = cache.get(&cache_key).await {
-        return Ok(Json(Some(value)));
-    }
-
-    let entry: Option<RuneEntryDTO> = db.sqlite_rune_entry_get_by_id(rune_id.unwrap().to_string()).unwrap_or(None).map(|x| x.into());
-    let r = R::with_data(entry);
-    let value = serde_json::to_value(r)?;
-    let mut cloned = value.clone();
-    cloned["cache"] = Value::Bool(true);
-    cache.insert(cache_key, cloned).await;
+    let value = cache.try_get_with(cache_key, async {
+        let entry: Option<RuneEntryDTO> = db.sqlite_rune_entry_get_by_id(rune_id.to_string()).unwrap_or(None).map(|x| x.into());
+        Ok(serde_json::to_value(R::with_data(entry))?)
+    }).await?;
     Ok(Json(Some(value)))
 }
 
+/// The etching of a rune. Reserved runes are allocated without a commitment,
+/// so they have no commit txid or etcher.
+pub async fn rune_etching(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(client): Extension<Option<AsyncClient>>,
+    Path(id): Path<String>,
+) -> anyhow::Result<Json<Option<R<RuneEtchingDTO>>>, AppError> {
+    let Some(rune_id) = resolve_rune_id(&db, &id) else {
+        return Ok(Json(None));
+    };
+    let (Some(entry), Some(row)) = (db.rune_id_to_rune_entry_get(&rune_id), db.sqlite_rune_entry_get_by_id(rune_id.to_string())?) else {
+        return Ok(Json(None));
+    };
+    let runestone = match client {
+        Some(client) => {
+            let block_hash = db.height_to_block_header_get(entry.block as _).map(|x| x.block_hash());
+            client.get_raw_transaction(entry.etching, block_hash).await?.and_then(|tx| Runestone::decipher(&tx))
+        }
+        None => None,
+    };
+    Ok(Json(Some(R::with_data(RuneEtchingDTO {
+        rune_id: rune_id.to_string(),
+        spaced_rune: entry.spaced_rune.to_string(),
+        reveal_txid: entry.etching.to_string(),
+        commit_txid: row.commit_txid,
+        etcher: row.etcher,
+        height: entry.block as _,
+        reserved: entry.spaced_rune.rune.is_reserved(),
+        runestone,
+    }))))
+}
+
+/// Rune count for a listing filter, cached apart from the pages so every page
+/// of a listing shares one `COUNT(*)` per block.
+pub(crate) async fn runes_total(cache: &MokaCache, db: &RunesDB, filter: &RuneEntryFilter) -> anyhow::Result<u64> {
+    let cache_key = CacheKey::new(CacheMethod::HandlerRunesTotal, serde_json::to_value(filter)?);
+    let total = cache.try_get_with(cache_key, async { Ok(json!(db.sqlite_rune_entry_count(filter)?)) }).await?;
+    total.as_u64().ok_or_else(|| anyhow::anyhow!("invalid runes total: {}", total))
+}
 
 pub async fn paged_runes(
     Extension(cache): Extension<Arc<MokaCache>>,
@@ -118,34 +354,76 @@ pub async fn paged_runes(
     Query(params): Query<RunesPageParams>,
 ) -> anyhow::Result<Json<Value>, AppError> {
     let cache_key = CacheKey::new(CacheMethod::HandlerPagedRunes, serde_json::to_value(&params)?);
-    if let Some(value) = cache.get(&cache_key).await {
-        return Ok(Json(value));
-    }
-    let (next, list) = db.rune_entry_paged(
-        params.cursor.unwrap_or(0).max(0),
-        params.size.unwrap_or(10).clamp(1, 1000),
-        params.keywords,
-        params.sort,
-    );
-    let latest_height = db.latest_height().unwrap_or_default();
-    let runes = list.iter().map(|x| ExpandRuneEntry::load(x.0, x.1, latest_height)).collect::<Vec<_>>();
-    let r = R::with_data(Paged::new(next, runes));
-    let value = serde_json::to_value(r)?;
-    let mut cloned = value.clone();
-    cloned["cache"] = Value::Bool(true);
-    cache.insert(cache_key, cloned).await;
+    let value = cache.try_get_with(cache_key, async {
+        Ok(serde_json::to_value(R::with_data(runes_page(&cache, &db, params).await?))?)
+    }).await?;
     Ok(Json(value))
 }
 
+async fn runes_page(cache: &MokaCache, db: &RunesDB, params: RunesPageParams) -> anyhow::Result<Paged<ExpandRuneEntry>> {
+    let filter = RuneEntryFilter {
+        keywords: params.keywords,
+        mintable: params.mintable,
+        fairmint: params.fairmint,
+        turbo: params.turbo,
+    };
+    let total = runes_total(cache, db, &filter).await?;
+    let (sort, desc) = match (params.sort.as_deref(), params.order.as_deref()) {
+        // legacy `sort=asc|desc` orders by etching
+        (Some("asc"), None) => (RuneEntrySort::Etching, false),
+        (Some("desc"), None) => (RuneEntrySort::Etching, true),
+        (sort, order) => {
+            let sort = match sort {
+                Some(sort) => RuneEntrySort::from_str(sort)?,
+                None if filter.keywords.is_some() => RuneEntrySort::Relevance,
+                None => RuneEntrySort::Etching,
+            };
+            let desc = match order {
+                None | Some("asc") => false,
+                Some("desc") => true,
+                Some(order) => return Err(anyhow::anyhow!("invalid order `{order}`, expected asc or desc")),
+            };
+            (sort, desc)
+        }
+    };
+    let cursor = params.cursor.unwrap_or(0);
+    let after = params.after.as_deref().map(RuneEntryPageKey::from_str).transpose()?;
+    let query = RuneEntryPageQuery {
+        after,
+        offset: cursor,
+        size: params.size.unwrap_or(10).clamp(1, 1000),
+        filter,
+        sort,
+        desc,
+    };
+    let (next, list) = db.sqlite_rune_entry_paged(&query)?;
+    let last = list.last().map(|x| x.0);
+    let latest_height = db.latest_height().unwrap_or_default();
+    let mut runes = vec![];
+    for (_, rune_id) in list {
+        let rune_id = RuneId::from_str(&rune_id).unwrap();
+        let Some(entry) = db.rune_id_to_rune_entry_get(&rune_id) else {
+            return Err(anyhow::anyhow!("rune entry {rune_id} not found"));
+        };
+        runes.push(ExpandRuneEntry::load(rune_id, entry, latest_height));
+    }
+    let mut paged = Paged::new(next, runes).with_after(last.filter(|_| sort != RuneEntrySort::Relevance)).with_total(total);
+    if after.is_none() {
+        paged = paged.with_cursor(cursor);
+    }
+    Ok(paged)
+}
+
 
-fn decode_runes_tx(db: &RunesDB, tx: Transaction) -> anyhow::Result<RunesTxDTO> {
+pub fn decode_runes_tx(db: &RunesDB, tx: Transaction) -> anyhow::Result<RunesTxDTO> {
     let mut runes_set = HashSet::new();
     let mut inputs = HashMap::new();
     let mut unallocated: HashMap<RuneId, Lot> = HashMap::new();
     let mut allocated: Vec<HashMap<RuneId, Lot>> = vec![HashMap::new(); tx.output.len()];
-    for (index, vin) in tx.input.iter().enumerate() {
-        let point = vin.previous_output;
-        if let Some(v) = db.outpoint_to_rune_balances_get(&point) {
+    let prevouts: Vec<OutPoint> = tx.input.iter().map(|vin| vin.previous_output).collect();
+    let entries = db.outpoint_to_rune_balances_multi_get(&prevouts);
+    for (index, entry) in entries.into_iter().enumerate() {
+        if let Some(v) = entry {
             let balances_buffer = v.2;
             let mut balance_map = HashMap::new();
             let mut i = 0;
@@ -380,8 +658,89 @@ pub async fn runes_decode_tx(
     Ok(Json(R::with_data(x)))
 }
 
+/// Deciphers a lone runestone script by wrapping it in a transaction with the
+/// script as output 0.
+fn decode_runes_script(script: ScriptBuf, outputs: usize) -> RunesScriptDTO {
+    let mut output = vec![TxOut { value: Amount::ZERO, script_pubkey: script }];
+    output.resize(outputs.max(1), TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::new() });
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![],
+        output,
+    };
+    let artifact = Runestone::decipher(&tx);
+    let flaw = match &artifact {
+        Some(Artifact::Cenotaph(cenotaph)) => cenotaph.flaw.map(|x| x.to_string()),
+        _ => None,
+    };
+    RunesScriptDTO { artifact, flaw }
+}
+
+pub async fn runes_decode_script(
+    Json(params): Json<RunesScriptParams>,
+) -> anyhow::Result<Json<R<RunesScriptDTO>>, AppError> {
+    let script = ScriptBuf::from_bytes(hex::decode(&params.script)?);
+    let outputs = params.outputs.unwrap_or(1).clamp(1, MAX_DECODE_OUTPUTS);
+    Ok(Json(R::with_data(decode_runes_script(script, outputs))))
+}
+
+/// The runestone of a transaction as deciphered, without the balance flow of
+/// `/runes/tx/:txid`. Transactions the index has rows for are fetched from
+/// their block, others need bitcoind's `-txindex` unless in the mempool.
+pub async fn tx_runestone(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(client): Extension<Option<AsyncClient>>,
+    Path(txid): Path<String>,
+) -> anyhow::Result<Json<Option<R<RunestoneDTO>>>, AppError> {
+    let txid = bitcoin::Txid::from_str(&txid)?;
+    let Some(client) = client else {
+        return Err(anyhow::anyhow!("bitcoind RPC is not configured").into());
+    };
+    let height = db.sqlite_tx_height(&txid.to_string())?;
+    let block_hash = height.and_then(|x| db.height_to_block_header_get(x)).map(|x| x.block_hash());
+    let Some(tx) = client.get_raw_transaction(txid, block_hash).await? else {
+        return Ok(Json(None));
+    };
+    let artifact = Runestone::decipher(&tx);
+    let flaw = match &artifact {
+        Some(Artifact::Cenotaph(cenotaph)) => cenotaph.flaw.map(|x| x.to_string()),
+        _ => None,
+    };
+    Ok(Json(Some(R::with_data(RunestoneDTO { txid: txid.to_string(), height, artifact, flaw }))))
+}
+
+fn validate_runes_tx(db: &RunesDB, tx: Transaction) -> anyhow::Result<RunesValidateDTO> {
+    let flaw = match Runestone::decipher(&tx) {
+        Some(Artifact::Cenotaph(cenotaph)) => Some(cenotaph.flaw),
+        _ => None,
+    };
+    let burned = decode_runes_tx(db, tx)?.burned;
+    Ok(RunesValidateDTO {
+        valid: flaw.is_none() && burned.is_empty(),
+        cenotaph: flaw.is_some(),
+        reason: flaw.flatten().map(|x| x.to_string()),
+        flaw: flaw.flatten(),
+        burned,
+    })
+}
+
+pub async fn runes_validate(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Json(params): Json<RunesValidateParams>,
+) -> anyhow::Result<Json<R<RunesValidateDTO>>, AppError> {
+    let tx = match (&params.raw_tx, &params.psbt_hex) {
+        (Some(raw_tx), _) => bitcoin::consensus::deserialize(&hex::decode(raw_tx)?)?,
+        (None, Some(psbt_hex)) => Psbt::from_str(&hex_to_base64(psbt_hex)?)?.unsigned_tx,
+        (None, None) => return Err(anyhow::anyhow!("`raw_tx` or `psbt_hex` is required").into()),
+    };
+    Ok(Json(R::with_data(validate_runes_tx(&db, tx)?)))
+}
+
 pub async fn outputs_runes(
     Extension(db): Extension<Arc<RunesDB>>,
+    Extension(mempool): Extension<Arc<MempoolTracker>>,
+    Query(params): Query<OutputsParams>,
     Json(outpoints): Json<Vec<String>>,
 ) -> anyhow::Result<Json<R<OutputsDTO>>, AppError> {
     if outpoints.is_empty() {
@@ -389,9 +748,16 @@ pub async fn outputs_runes(
     }
     let mut runes_set = HashSet::new();
     let mut outputs = vec![];
+    let mut spent_by = vec![];
     for outpoint in outpoints {
         let outpoint = OutPoint::from_str(&outpoint)?;
         let mut balance_map = HashMap::new();
+        let spending_txid = if params.include_mempool { mempool.spent_by(&outpoint) } else { None };
+        spent_by.push(spending_txid);
+        if spending_txid.is_some() {
+            outputs.push(balance_map);
+            continue;
+        }
         if let Some(v) = db.outpoint_to_rune_balances_get(&outpoint) {
             let balances_buffer = v.2;
             let mut i = 0;
@@ -410,9 +776,15 @@ pub async fn outputs_runes(
         let r = db.rune_id_to_rune_entry_get(&x).unwrap();
         runes.push(ExpandRuneEntry::load(x, r, latest_height));
     }
-    Ok(Json(R::with_data(OutputsDTO { runes, outputs })))
+    Ok(Json(R::with_data(OutputsDTO {
+        runes,
+        outputs,
+        spent_by: params.include_mempool.then_some(spent_by),
+    })))
 }
 
+/// Entries in request order, each given as a rune id or name, null for runes
+/// that don't exist.
 pub async fn get_runes_by_rune_ids(
     Extension(db): Extension<Arc<RunesDB>>,
     Json(rune_ids): Json<Vec<String>>,
@@ -423,15 +795,9 @@ pub async fn get_runes_by_rune_ids(
     }
     let latest_height = db.latest_height().unwrap_or_default();
     for x in rune_ids {
-        match RuneId::from_str(&x) {
-            Ok(id) => match db.rune_id_to_rune_entry_get(&id) {
-                None => runes.push(None),
-                Some(v) => {
-                    runes.push(Some(ExpandRuneEntry::load(id, v, latest_height)));
-                }
-            },
-            Err(_) => runes.push(None),
-        }
+        let entry = resolve_rune_id(&db, &x)
+            .and_then(|id| db.rune_id_to_rune_entry_get(&id).map(|v| ExpandRuneEntry::load(id, v, latest_height)));
+        runes.push(entry);
     }
     Ok(Json(R::with_data(runes)))
 }
@@ -443,37 +809,38 @@ pub async fn get_tx(
 ) -> anyhow::Result<Json<Option<Value>>, AppError> {
     bitcoin::Txid::from_str(&txid)?;
     let cache_key = CacheKey::new(CacheMethod::HandlerTx, Value::String(txid.clone()));
-    if let Some(value) = cache.get(&cache_key).await {
-        return Ok(Json(Some(value)));
-    }
+    let value = cache.try_get_with(cache_key, async {
+        Ok(serde_json::to_value(R::with_data(rune_tx(&db, txid.clone())?))?)
+    }).await?;
+    Ok(Json(Some(value)))
+}
 
+fn rune_tx(db: &RunesDB, txid: String) -> anyhow::Result<RuneTx> {
     let rows = db.sqlite_rune_balance_list_by_txid(&txid)?;
     let etching_rune_entry = db.sqlite_rune_entry_get_by_etching_txid(&txid)?;
+    let cenotaph = db.sqlite_cenotaph_get(&txid)?;
 
     if rows.is_empty() && etching_rune_entry.is_none() {
-        let r = R::with_data(RuneTx::default());
-        let value = serde_json::to_value(r)?;
-        let mut cloned = value.clone();
-        cloned["cache"] = Value::Bool(true);
-        cache.insert(cache_key, cloned).await;
-        return Ok(Json(Some(value)));
+        return Ok(RuneTx {
+            actions: cenotaph.iter().map(|_| "cenotaph".to_string()).collect(),
+            cenotaph,
+            ..Default::default()
+        });
     }
 
     if rows.is_empty() && etching_rune_entry.is_some() {
-        let r = R::with_data(RuneTx {
+        let mut actions = vec!["etching".to_string()];
+        actions.extend(cenotaph.iter().map(|_| "cenotaph".to_string()));
+        return Ok(RuneTx {
             runes: vec![etching_rune_entry.unwrap().into()],
-            actions: vec!["etching".into()],
+            actions,
             inputs: HashMap::new(),
             outputs: HashMap::new(),
             burned: HashMap::new(),
             minted: HashMap::new(),
             premine: HashMap::new(),
+            cenotaph,
         });
-        let value = serde_json::to_value(r)?;
-        let mut cloned = value.clone();
-        cloned["cache"] = Value::Bool(true);
-        cache.insert(cache_key, cloned).await;
-        return Ok(Json(Some(value)));
     }
 
 
@@ -546,11 +913,14 @@ pub async fn get_tx(
     if etching_rune_entry.is_some() {
         actions.insert("etching".into());
     }
+    if cenotaph.is_some() {
+        actions.insert("cenotaph".into());
+    }
 
 
     let runes = db.sqlite_rune_entry_list_by_ids(&rune_ids)?.into_iter().map(|x| x.into()).collect();
 
-    let tx = RuneTx {
+    Ok(RuneTx {
         runes,
         actions: actions.into_iter().collect(),
         inputs,
@@ -558,53 +928,532 @@ pub async fn get_tx(
         burned,
         minted,
         premine,
-    };
-
-    let r = R::with_data(tx);
-    let value = serde_json::to_value(r)?;
-    let mut cloned = value.clone();
-    cloned["cache"] = Value::Bool(true);
-    cache.insert(cache_key, cloned).await;
-    Ok(Json(Some(value)))
+        cenotaph,
+    })
 }
 
 pub async fn address_runes_utxos(
     Extension(cache): Extension<Arc<MokaCache>>,
     Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
     Path(address_string): Path<String>,
+    Query(params): Query<AddressUtxosParams>,
 ) -> anyhow::Result<Json<Value>, AppError> {
-    let cache_key = CacheKey::new(CacheMethod::HandlerAddressUtxos, Value::String(address_string.clone()));
-    if let Some(value) = cache.get(&cache_key).await {
-        info!("cache hit: {}", &address_string);
-        return Ok(Json(value));
-    }
+    let cache_key = CacheKey::new(CacheMethod::HandlerAddressUtxos, json!([address_string, params]));
+    let value = cache.try_get_with(cache_key, async {
+        info!("cache miss: {}", &address_string);
+        Ok(serde_json::to_value(R::with_data(address_rune_utxos(&cache, &db, chain, &address_string, &params).await?))?)
+    }).await?;
+    Ok(Json(value))
+}
+
+async fn address_rune_utxos(cache: &MokaCache, db: &RunesDB, chain: Chain, address_string: &str, params: &AddressUtxosParams) -> anyhow::Result<AddressRuneUTXOsDTO> {
+    let rune_id = match &params.rune_id {
+        Some(id) => match resolve_rune_id_cached(cache, db, id).await {
+            Some(rune_id) => Some(rune_id),
+            None => return Err(anyhow::anyhow!("unknown rune: {}", id)),
+        },
+        None => None,
+    };
+    let min_amount = match &params.min_amount {
+        Some(amount) => Some(amount.parse::<u128>()?),
+        None => None,
+    };
+    let limit = params.limit.map_or(usize::MAX, |x| x.clamp(1, 1000));
+    let script = address_script(address_string, chain)?;
 
-    let unspent = db.sqlite_rune_balance_list_unspent_by_address(&address_string)?;
+    let mut outputs = db.spk_to_outpoints_list(&script)?;
+    outputs.sort_by_key(|(outpoint, height, _)| (*height, outpoint.txid, outpoint.vout));
     let mut rune_ids = HashSet::new();
-    let unspent_map = unspent.iter().into_group_map_by(|x| RuneBalanceGroupKey {
-        txid: x.txid.clone(),
-        vout: x.vout,
-    });
     let mut utxos = vec![];
-    for (k, v) in unspent_map.iter() {
+    for (outpoint, _, value) in outputs {
+        if utxos.len() >= limit {
+            break;
+        }
+        let Some((_, _, buffer)) = db.outpoint_to_rune_balances_get(&outpoint) else {
+            continue;
+        };
+        let balances = RuneUpdater::decode_rune_balances(&buffer)?;
+        let matched = balances.iter().any(|(id, amount)| {
+            rune_id.iter().all(|x| x == id) && min_amount.iter().all(|x| amount >= x)
+        });
+        if !matched {
+            continue;
+        }
         let mut balance_map = HashMap::new();
-        for e in v {
-            rune_ids.insert(e.rune_id.clone());
-            balance_map.insert(e.rune_id.clone(), e.rune_amount.clone());
+        for (id, amount) in balances {
+            rune_ids.insert(id.to_string());
+            balance_map.insert(id.to_string(), amount.to_string());
         }
         utxos.push(UTXOWithRuneValueDTO {
-            txid: k.txid.clone(),
-            vout: k.vout,
-            value: v.first().unwrap().value,
+            txid: outpoint.txid.to_string(),
+            vout: outpoint.vout,
+            value,
             runes_value: balance_map,
         });
     }
     let runes = db.sqlite_rune_entry_list_by_ids(&rune_ids)?.into_iter().map(|x| x.into()).collect();
-    let r = R::with_data(AddressRuneUTXOsDTO { utxos, runes });
-    let value = serde_json::to_value(r)?;
-    let mut cloned = value.clone();
-    cloned["cache"] = Value::Bool(true);
-    cache.insert(cache_key, cloned).await;
-    info!("cache miss: {}", &address_string);
-    Ok(Json(value))
+    Ok(AddressRuneUTXOsDTO { utxos, runes })
+}
+
+pub async fn address_runes_balances(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
+    Path(address_string): Path<String>,
+    Query(params): Query<AddressBalancesParams>,
+) -> anyhow::Result<(Extension<CachePolicy>, Json<R<AddressBalancesDTO>>), AppError> {
+    let latest_height = committed_height(&db)?;
+    let height = params.height.unwrap_or(latest_height);
+    if height > latest_height {
+        return Err(anyhow::anyhow!("height {} is above the indexed height {}", height, latest_height).into());
+    }
+
+    let mut balance_map: HashMap<String, (u128, u32)> = HashMap::new();
+    if params.height.is_none() {
+        let script = address_script(&address_string, chain)?;
+        for (rune_id, amount, utxos) in db.script_hash_rune_id_to_balance_list(&script)? {
+            balance_map.insert(rune_id.to_string(), (amount, utxos));
+        }
+    } else {
+        // past balances are only in the rows of sqlite
+        for e in db.sqlite_rune_balance_list_unspent_by_address_at_height(&address_string, height)? {
+            let amount = e.rune_amount.parse::<u128>().map_err(anyhow::Error::from)?;
+            let balance = balance_map.entry(e.rune_id).or_default();
+            balance.0 += amount;
+            balance.1 += 1;
+        }
+    }
+    let rune_ids: HashSet<String> = balance_map.keys().cloned().collect();
+    let balances = balance_map
+        .into_iter()
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .map(|(rune_id, (amount, utxos))| AddressRuneBalanceDTO {
+            rune_id,
+            amount: amount.to_string(),
+            utxos,
+        })
+        .collect();
+    let runes = db.sqlite_rune_entry_list_by_ids(&rune_ids)?.into_iter().map(|x| x.into()).collect();
+    Ok((Extension(CachePolicy::at_height(height, latest_height)), Json(R::with_data(AddressBalancesDTO { height, balances, runes }))))
+}
+
+pub async fn rune_top_holders(
+    Extension(cache): Extension<Arc<MokaCache>>,
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(id): Path<String>,
+    Query(params): Query<TopHoldersParams>,
+) -> anyhow::Result<Json<Option<Value>>, AppError> {
+    let Some(rune_id) = resolve_rune_id_cached(&cache, &db, &id).await else {
+        return Ok(Json(None));
+    };
+    let Some(entry) = db.rune_id_to_rune_entry_get(&rune_id) else {
+        return Ok(Json(None));
+    };
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+
+    let cache_key = CacheKey::new(CacheMethod::HandlerTopHolders, json!([rune_id.to_string(), limit]));
+    let value = cache.try_get_with(cache_key, async {
+        Ok(serde_json::to_value(R::with_data(top_holders(&db, rune_id, &entry, limit)?))?)
+    }).await?;
+    Ok(Json(Some(value)))
+}
+
+fn top_holders(db: &RunesDB, rune_id: RuneId, entry: &RuneEntry, limit: usize) -> anyhow::Result<TopHoldersDTO> {
+    let (_, holders) = db.sqlite_rune_holders_paged(&rune_id.to_string(), 0, limit)?;
+    let supply = entry.supply();
+    let holders = holders
+        .into_iter()
+        .map(|(address, amount)| RuneHolderDTO {
+            address,
+            amount: amount.to_string(),
+            share: if supply == 0 { 0.0 } else { amount as f64 * 100.0 / supply as f64 },
+        })
+        .collect();
+
+    Ok(TopHoldersDTO {
+        rune_id: rune_id.to_string(),
+        supply: supply.to_string(),
+        holders,
+    })
+}
+
+pub async fn rune_txs(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(id): Path<String>,
+    Query(params): Query<RuneTxsPageParams>,
+) -> anyhow::Result<Json<Option<R<Paged<RuneTxEffectDTO>>>>, AppError> {
+    let Some(rune_id) = resolve_rune_id(&db, &id) else {
+        return Ok(Json(None));
+    };
+    let rune_id = rune_id.to_string();
+    let cursor = params.cursor.unwrap_or(0);
+    let (next, txs) = db.sqlite_rune_tx_paged(
+        &rune_id,
+        cursor,
+        params.size.unwrap_or(10).clamp(1, 1000),
+    )?;
+
+    let list = rune_tx_effects(&db, &rune_id, txs)?;
+    Ok(Json(Some(R::with_data(Paged::new(next, list).with_cursor(cursor)))))
+}
+
+/// Mints of a rune, oldest first.
+pub async fn rune_mints(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(id): Path<String>,
+    Query(params): Query<RuneTxsPageParams>,
+) -> anyhow::Result<Json<Option<R<Paged<RuneMintDTO>>>>, AppError> {
+    let Some(rune_id) = resolve_rune_id(&db, &id) else {
+        return Ok(Json(None));
+    };
+    let Some(entry) = db.rune_id_to_rune_entry_get(&rune_id) else {
+        return Ok(Json(None));
+    };
+    let amount = entry.terms.and_then(|x| x.amount).unwrap_or_default().to_string();
+    let cursor = params.cursor.unwrap_or(0);
+    let (next, mints) = db.sqlite_rune_mint_paged(
+        &rune_id.to_string(),
+        cursor,
+        params.size.unwrap_or(10).clamp(1, 1000),
+    )?;
+
+    let list = mints
+        .into_iter()
+        .map(|(txid, height, minter)| RuneMintDTO { txid, height, minter, amount: amount.clone() })
+        .collect();
+    Ok(Json(Some(R::with_data(Paged::new(next, list).with_cursor(cursor)))))
+}
+
+/// An inscription by its `{txid}i{index}` id, null for ids not indexed.
+pub async fn inscription_detail(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(id): Path<String>,
+) -> anyhow::Result<Json<Option<R<InscriptionDTO>>>, AppError> {
+    let id = InscriptionId::from_str(&id)?;
+    let inscription = db.sqlite_inscription_get(&id.to_string())?;
+    Ok(Json(inscription.map(|x| R::with_data(x.into()))))
+}
+
+/// Inscriptions an address holds, the latest received first.
+pub async fn address_inscriptions(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(address): Path<String>,
+    Query(params): Query<RuneTxsPageParams>,
+) -> anyhow::Result<Json<Option<R<Paged<InscriptionDTO>>>>, AppError> {
+    let cursor = params.cursor.unwrap_or(0);
+    let (next, inscriptions) = db.sqlite_inscription_paged_by_address(
+        &address,
+        cursor,
+        params.size.unwrap_or(10).clamp(1, 1000),
+    )?;
+    let list = inscriptions.into_iter().map(InscriptionDTO::from).collect();
+    Ok(Json(Some(R::with_data(Paged::new(next, list).with_cursor(cursor)))))
+}
+
+/// Data of a protocol sub-indexer, what `key` is depends on the protocol.
+pub async fn protocol_query(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path((name, key)): Path<(String, String)>,
+) -> anyhow::Result<Json<Option<R<Value>>>, AppError> {
+    let Some(protocol) = find_protocol(&name) else {
+        return Err(anyhow::anyhow!("unknown protocol: {}", name).into());
+    };
+    Ok(Json(protocol.query(&db, &key)?.map(R::with_data)))
+}
+
+/// An indexed rune output, spent or not. Outputs that never held runes are
+/// not indexed and return null.
+pub async fn output_detail(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
+    Path(outpoint): Path<String>,
+) -> anyhow::Result<(Extension<CachePolicy>, Json<Option<R<OutputDetailDTO>>>), AppError> {
+    let outpoint = OutPoint::from_str(&outpoint)?;
+    let latest_height = committed_height(&db)?;
+    let Some((height, spent_height, balances_buffer)) = db.outpoint_to_rune_balances_get(&outpoint) else {
+        return Ok((Extension(CachePolicy::Tip), Json(None)));
+    };
+    let Some(row) = db.sqlite_rune_balance_list_by_txid(&outpoint.txid.to_string())?.into_iter().find(|x| x.vout == outpoint.vout) else {
+        return Err(anyhow::anyhow!("no rune balance rows for {}", outpoint).into());
+    };
+
+    let mut balances = vec![];
+    let mut i = 0;
+    while i < balances_buffer.len() {
+        let ((id, amount), length) = RuneUpdater::decode_rune_balance(&balances_buffer[i..])?;
+        i += length;
+        let entry = db.rune_id_to_rune_entry_get(&id).ok_or_else(|| anyhow::anyhow!("rune {} not found", id))?;
+        balances.push(OutputRuneBalanceDTO {
+            rune_id: id.to_string(),
+            spaced_rune: entry.spaced_rune.to_string(),
+            amount: amount.to_string(),
+            divisibility: entry.divisibility,
+            symbol: entry.symbol,
+        });
+    }
+
+    // the address column holds the script hex when the script has no address
+    let address = chain.parse_address(&row.address).ok().and_then(|x| x.require_network(chain.network()).ok());
+    let (address, script_pubkey) = match address {
+        Some(address) => (Some(chain.format_address(&address)), address.script_pubkey().to_hex_string()),
+        None => (None, row.address),
+    };
+    let spent = spent_height > 0;
+    let policy = if spent { CachePolicy::at_height(spent_height, latest_height) } else { CachePolicy::Tip };
+    Ok((Extension(policy), Json(Some(R::with_data(OutputDetailDTO {
+        outpoint: outpoint.to_string(),
+        height,
+        spent,
+        spent_height: spent.then_some(spent_height),
+        spent_txid: row.spent_txid,
+        spent_vin: row.spent_vin,
+        value: row.value,
+        address,
+        script_pubkey,
+        balances,
+    })))))
+}
+
+/// The sat ranges of an unspent output, in order, with the sat index on.
+/// Spent and unknown outputs return null.
+pub async fn output_sats(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(outpoint): Path<String>,
+) -> anyhow::Result<Json<Option<R<Vec<SatRangeDTO>>>>, AppError> {
+    if db.statistic_to_value_get(&Statistic::IndexSats).is_none() {
+        return Err(anyhow::anyhow!("the sat index is off").into());
+    }
+    let outpoint = OutPoint::from_str(&outpoint)?;
+    let ranges = db.outpoint_to_sat_ranges_get(&outpoint);
+    if ranges.is_empty() {
+        return Ok(Json(None));
+    }
+    Ok(Json(Some(R::with_data(ranges.into_iter().map(|(start, end)| SatRangeDTO {
+        start,
+        end,
+        rarity: Sat(start).rarity().to_string(),
+    }).collect()))))
+}
+
+/// Most blocks `/rune/:id/txs/range` covers at once.
+const RUNE_TXS_MAX_RANGE: u32 = 10_000;
+
+/// Transactions of a rune in a height window, oldest first, so consumers can
+/// pull what changed since the last height they saw.
+pub async fn rune_txs_range(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(id): Path<String>,
+    Query(params): Query<RuneTxsRangeParams>,
+) -> anyhow::Result<Json<Option<R<Paged<RuneTxEffectDTO>>>>, AppError> {
+    let Some(rune_id) = resolve_rune_id(&db, &id) else {
+        return Ok(Json(None));
+    };
+    let to = params.to.or_else(|| db.latest_indexed_height()).unwrap_or_default();
+    if params.from > to {
+        return Err(anyhow::anyhow!("from {} is above to {}", params.from, to).into());
+    }
+    if to - params.from >= RUNE_TXS_MAX_RANGE {
+        return Err(anyhow::anyhow!("at most {} blocks per request", RUNE_TXS_MAX_RANGE).into());
+    }
+    let rune_id = rune_id.to_string();
+    let cursor = params.cursor.unwrap_or(0);
+    let (next, txs) = db.sqlite_rune_tx_range(
+        &rune_id,
+        params.from,
+        to,
+        cursor,
+        params.size.unwrap_or(100).clamp(1, 1000),
+    )?;
+    let list = rune_tx_effects(&db, &rune_id, txs)?;
+    Ok(Json(Some(R::with_data(Paged::new(next, list).with_cursor(cursor)))))
+}
+
+fn rune_tx_effects(db: &RunesDB, rune_id: &String, txs: Vec<(String, u32)>) -> anyhow::Result<Vec<RuneTxEffectDTO>> {
+    let txids = txs.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+    let mut amounts: HashMap<String, (u128, u128)> = HashMap::new();
+    for e in db.sqlite_rune_balance_list_by_rune_id_txids(rune_id, &txids)? {
+        let amount = e.rune_amount.parse::<u128>()?;
+        if let Some(spent_txid) = e.spent_txid {
+            amounts.entry(spent_txid).or_default().0 += amount;
+        }
+        amounts.entry(e.txid).or_default().1 += amount;
+    }
+
+    let list = txs
+        .into_iter()
+        .map(|(txid, height)| {
+            let (input, output) = amounts.get(&txid).copied().unwrap_or_default();
+            RuneTxEffectDTO {
+                txid,
+                height,
+                input: input.to_string(),
+                output: output.to_string(),
+                minted: output.saturating_sub(input).to_string(),
+                burned: input.saturating_sub(output).to_string(),
+                moved: input.min(output).to_string(),
+            }
+        })
+        .collect();
+    Ok(list)
+}
+
+pub async fn fees(
+    Extension(fee_estimator): Extension<Arc<FeeEstimator>>,
+) -> anyhow::Result<Json<R<Vec<FeeEstimate>>>, AppError> {
+    let mut estimates = vec![];
+    for target in FEE_TARGETS {
+        estimates.push(fee_estimator.estimate(target).await);
+    }
+    Ok(Json(R::with_data(estimates)))
+}
+
+pub async fn mempool_tx_status(
+    Extension(mempool): Extension<Arc<MempoolTracker>>,
+    Path(txid): Path<String>,
+) -> anyhow::Result<Json<R<MempoolTxStatus>>, AppError> {
+    Ok(Json(R::with_data(mempool.status(&txid.parse()?))))
+}
+
+pub async fn address_runes_unconfirmed(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
+    Extension(mempool): Extension<Arc<MempoolTracker>>,
+    Path(address_string): Path<String>,
+) -> anyhow::Result<Json<R<AddressUnconfirmedDTO>>, AppError> {
+    let address = chain.parse_address(&address_string)?.require_network(chain.network())?;
+    let script_pubkey = address.script_pubkey();
+
+    let mut confirmed: HashMap<String, u128> = HashMap::new();
+    let mut outgoing: HashMap<String, u128> = HashMap::new();
+    let mut txids = HashSet::new();
+    for e in db.sqlite_rune_balance_list_unspent_by_address(&address_string)? {
+        let amount = e.rune_amount.parse::<u128>().map_err(anyhow::Error::from)?;
+        *confirmed.entry(e.rune_id.clone()).or_default() += amount;
+        let outpoint = OutPoint { txid: e.txid.parse()?, vout: e.vout };
+        if let Some(txid) = mempool.spent_by(&outpoint) {
+            *outgoing.entry(e.rune_id).or_default() += amount;
+            txids.insert(txid);
+        }
+    }
+
+    let mut incoming: HashMap<String, u128> = HashMap::new();
+    for tx in mempool.txs_paying_to(&script_pubkey) {
+        let txid = tx.txid();
+        let vouts = tx.output.iter().enumerate()
+            .filter(|(_, x)| x.script_pubkey == script_pubkey)
+            .map(|(vout, _)| vout)
+            .collect::<HashSet<_>>();
+        let decoded = decode_runes_tx(&db, tx)?;
+        for (vout, balances) in decoded.outputs {
+            if !vouts.contains(&vout) {
+                continue;
+            }
+            for (id, lot) in balances {
+                *incoming.entry(id.to_string()).or_default() += lot.n();
+                txids.insert(txid);
+            }
+        }
+    }
+
+    let mut delta = HashMap::new();
+    for id in incoming.keys().chain(outgoing.keys()).unique() {
+        let received = incoming.get(id).copied().unwrap_or_default();
+        let sent = outgoing.get(id).copied().unwrap_or_default();
+        let value = if received >= sent {
+            (received - sent).to_string()
+        } else {
+            format!("-{}", sent - received)
+        };
+        delta.insert(id.clone(), value);
+    }
+    let to_strings = |map: HashMap<String, u128>| map.into_iter().map(|(k, v)| (k, v.to_string())).collect();
+    Ok(Json(R::with_data(AddressUnconfirmedDTO {
+        confirmed: to_strings(confirmed),
+        incoming: to_strings(incoming),
+        outgoing: to_strings(outgoing),
+        delta,
+        txids: txids.into_iter().sorted().collect(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use ordinals::{Edict, Flaw};
+
+    use crate::entry::RuneEntry;
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn decode_script() {
+        let id = RuneId { block: 840000, tx: 3 };
+        let runestone = Runestone {
+            edicts: vec![Edict { id, amount: 10, output: 2 }],
+            ..Default::default()
+        };
+        let script = runestone.encipher();
+
+        let decoded = decode_runes_script(script.clone(), 3);
+        assert_eq!(decoded.artifact, Some(Artifact::Runestone(runestone)));
+        assert!(decoded.flaw.is_none());
+
+        // edict output past the output count
+        let decoded = decode_runes_script(script, 1);
+        let Some(Artifact::Cenotaph(cenotaph)) = decoded.artifact else {
+            panic!("expected a cenotaph");
+        };
+        assert_eq!(cenotaph.flaw, Some(Flaw::EdictOutput));
+        assert_eq!(decoded.flaw, Some(Flaw::EdictOutput.to_string()));
+
+        assert!(decode_runes_script(ScriptBuf::new(), 1).artifact.is_none());
+    }
+
+    #[test]
+    fn validate_reports_cenotaph_flaw() {
+        let path = TempDir::new("validate");
+        let db = RunesDB::new(&path);
+
+        let runestone = Runestone {
+            edicts: vec![Edict { id: RuneId { block: 840000, tx: 3 }, amount: 10, output: 5 }],
+            ..Default::default()
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut { value: Amount::ZERO, script_pubkey: runestone.encipher() }],
+        };
+        let report = validate_runes_tx(&db, tx.clone()).unwrap();
+        assert!(!report.valid);
+        assert!(report.cenotaph);
+        assert_eq!(report.flaw, Some(Flaw::EdictOutput));
+        assert_eq!(report.reason, Some(Flaw::EdictOutput.to_string()));
+
+        let mut tx = tx;
+        tx.output[0].script_pubkey = Runestone::default().encipher();
+        let report = validate_runes_tx(&db, tx).unwrap();
+        assert!(report.valid);
+        assert!(!report.cenotaph);
+    }
+
+    #[test]
+    fn resolve_identifiers() {
+        let path = TempDir::new("resolve");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let id = RuneId { block: 840000, tx: 3 };
+        let rune = Rune::from_str("UNCOMMONGOODS").unwrap();
+        let entry = RuneEntry { block: id.block, etching: bitcoin::Txid::from_str(&"ab".repeat(32)).unwrap(), spaced_rune: SpacedRune { rune, spacers: 128 }, ..Default::default() };
+        db.rune_to_rune_id_put(&rune, &id);
+        db.sqlite_rune_entry_insert_all(&[RuneEntryForQueryInsert::new(&id, &entry, id.block as _, None, None)]).unwrap();
+
+        for query in ["840000:3", "UNCOMMON•GOODS", "UNCOMMONGOODS", &"ab".repeat(32)] {
+            assert_eq!(resolve_rune_id(&db, query), Some(id), "{}", query);
+        }
+        for query in ["UNCOMMONGOOD", &"cd".repeat(32), "uncommon goods"] {
+            assert_eq!(resolve_rune_id(&db, query), None, "{}", query);
+        }
+    }
 }
@@ -0,0 +1,369 @@
+//! A subset of the Hiro Runes API (`/runes/v1`), served from the index so
+//! its consumers can switch over by changing the base URL.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use ordinals::RuneId;
+
+use crate::api::dto::AppError;
+use crate::api::handler::{address_script, resolve_rune_id};
+use crate::cache::{CacheKey, CacheMethod, MokaCache};
+use crate::chain::Chain;
+use crate::db::model::{RuneActivityForQuery, RuneEntryFilter, RuneEntryPageQuery, RuneEntrySort};
+use crate::db::{RunesDB, Store};
+use crate::entry::RuneEntry;
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 60;
+
+#[derive(Debug, Deserialize)]
+pub struct HiroPageParams {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+impl HiroPageParams {
+    fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroPaged<T> {
+    pub limit: usize,
+    pub offset: usize,
+    pub total: u64,
+    pub results: Vec<T>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroRune {
+    pub id: RuneId,
+    pub number: u64,
+    pub name: String,
+    pub spaced_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroMintTerms {
+    pub amount: Option<String>,
+    pub cap: Option<String>,
+    pub height_start: Option<u64>,
+    pub height_end: Option<u64>,
+    pub offset_start: Option<u64>,
+    pub offset_end: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroSupply {
+    pub current: String,
+    pub minted: String,
+    pub total_mints: String,
+    pub mint_percentage: String,
+    pub mintable: bool,
+    pub burned: String,
+    /// Burns are only tracked as an amount.
+    pub total_burns: Option<String>,
+    pub premine: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroLocation {
+    pub block_hash: Option<String>,
+    pub block_height: u64,
+    pub tx_id: String,
+    pub tx_index: Option<u32>,
+    pub vout: Option<u32>,
+    pub output: Option<String>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroEtching {
+    pub id: RuneId,
+    pub name: String,
+    pub spaced_name: String,
+    pub number: u64,
+    pub divisibility: u8,
+    pub symbol: String,
+    pub turbo: bool,
+    pub mint_terms: HiroMintTerms,
+    pub supply: HiroSupply,
+    pub location: HiroLocation,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroHolder {
+    pub address: String,
+    pub balance: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroBalance {
+    pub rune: HiroRune,
+    pub address: String,
+    pub balance: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiroActivity {
+    pub rune: HiroRune,
+    pub address: String,
+    pub receiver_address: Option<String>,
+    pub amount: String,
+    pub operation: String,
+    pub location: HiroLocation,
+}
+
+/// Amount with the rune's divisibility applied, e.g. `1.05`.
+fn decimal(amount: u128, divisibility: u8) -> String {
+    let cutoff = 10u128.pow(divisibility.into());
+    let whole = amount / cutoff;
+    let fractional = amount % cutoff;
+    if fractional == 0 {
+        return whole.to_string();
+    }
+    let fractional = format!("{fractional:0>width$}", width = usize::from(divisibility));
+    format!("{whole}.{}", fractional.trim_end_matches('0'))
+}
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, Json(json!({"error": "Not found"}))).into_response()
+}
+
+fn block_hash(db: &RunesDB, height: u64) -> Option<String> {
+    db.height_to_block_header_get(height as u32).map(|x| x.block_hash().to_string())
+}
+
+fn rune(id: RuneId, entry: &RuneEntry) -> HiroRune {
+    HiroRune {
+        id,
+        number: entry.number,
+        name: entry.spaced_rune.rune.to_string(),
+        spaced_name: entry.spaced_rune.to_string(),
+    }
+}
+
+fn etching(db: &RunesDB, id: RuneId, entry: &RuneEntry, latest_height: u32) -> HiroEtching {
+    let terms = entry.terms.unwrap_or_default();
+    let mint_percentage = match terms.cap {
+        Some(cap) if cap > 0 => entry.mints as f64 * 100.0 / cap as f64,
+        _ => 0.0,
+    };
+    HiroEtching {
+        id,
+        name: entry.spaced_rune.rune.to_string(),
+        spaced_name: entry.spaced_rune.to_string(),
+        number: entry.number,
+        divisibility: entry.divisibility,
+        symbol: entry.symbol.unwrap_or('¤').to_string(),
+        turbo: entry.turbo,
+        mint_terms: HiroMintTerms {
+            amount: terms.amount.map(|x| decimal(x, entry.divisibility)),
+            cap: terms.cap.map(|x| x.to_string()),
+            height_start: terms.height.0,
+            height_end: terms.height.1,
+            offset_start: terms.offset.0,
+            offset_end: terms.offset.1,
+        },
+        supply: HiroSupply {
+            current: decimal(entry.supply(), entry.divisibility),
+            minted: decimal(entry.supply() - entry.premine, entry.divisibility),
+            total_mints: entry.mints.to_string(),
+            mint_percentage: format!("{mint_percentage:.4}"),
+            mintable: entry.mintable(latest_height as u64 + 1).is_ok(),
+            burned: decimal(entry.burned, entry.divisibility),
+            total_burns: None,
+            premine: decimal(entry.premine, entry.divisibility),
+        },
+        location: HiroLocation {
+            block_hash: block_hash(db, entry.block),
+            block_height: entry.block,
+            tx_id: entry.etching.to_string(),
+            tx_index: Some(id.tx),
+            vout: None,
+            output: None,
+            timestamp: entry.timestamp,
+        },
+    }
+}
+
+pub async fn etchings(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Query(params): Query<HiroPageParams>,
+) -> anyhow::Result<Json<HiroPaged<HiroEtching>>, AppError> {
+    let query = RuneEntryPageQuery {
+        offset: params.offset(),
+        size: params.limit(),
+        sort: RuneEntrySort::Etching,
+        desc: true,
+        ..Default::default()
+    };
+    let (_, list) = db.sqlite_rune_entry_paged(&query)?;
+    let latest_height = db.latest_height().unwrap_or_default();
+    let mut results = vec![];
+    for (_, rune_id) in list {
+        let rune_id = RuneId::from_str(&rune_id).unwrap();
+        let entry = db.rune_id_to_rune_entry_get(&rune_id).unwrap();
+        results.push(etching(&db, rune_id, &entry, latest_height));
+    }
+    Ok(Json(HiroPaged {
+        limit: params.limit(),
+        offset: params.offset(),
+        total: db.sqlite_rune_entry_count(&RuneEntryFilter::default())?,
+        results,
+    }))
+}
+
+pub async fn get_etching(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(etching_id): Path<String>,
+) -> anyhow::Result<Response, AppError> {
+    let Some(id) = resolve_rune_id(&db, &etching_id) else {
+        return Ok(not_found());
+    };
+    let Some(entry) = db.rune_id_to_rune_entry_get(&id) else {
+        return Ok(not_found());
+    };
+    let latest_height = db.latest_height().unwrap_or_default();
+    Ok(Json(etching(&db, id, &entry, latest_height)).into_response())
+}
+
+pub async fn etching_holders(
+    Extension(cache): Extension<Arc<MokaCache>>,
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(etching_id): Path<String>,
+    Query(params): Query<HiroPageParams>,
+) -> anyhow::Result<Response, AppError> {
+    let Some(id) = resolve_rune_id(&db, &etching_id) else {
+        return Ok(not_found());
+    };
+    let Some(entry) = db.rune_id_to_rune_entry_get(&id) else {
+        return Ok(not_found());
+    };
+    let (offset, limit) = (params.offset(), params.limit());
+    let cache_key = CacheKey::new(CacheMethod::HiroEtchingHolders, json!([id.to_string(), offset, limit]));
+    let value = cache.try_get_with(cache_key, async {
+        let (total, holders) = db.sqlite_rune_holders_paged(&id.to_string(), offset, limit)?;
+        Ok(serde_json::to_value(HiroPaged {
+            limit,
+            offset,
+            total,
+            results: holders
+                .into_iter()
+                .map(|(address, amount)| HiroHolder { address, balance: decimal(amount, entry.divisibility) })
+                .collect(),
+        })?)
+    }).await?;
+    Ok(Json(value).into_response())
+}
+
+pub async fn etching_holder(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
+    Path((etching_id, address)): Path<(String, String)>,
+) -> anyhow::Result<Response, AppError> {
+    let Some(id) = resolve_rune_id(&db, &etching_id) else {
+        return Ok(not_found());
+    };
+    let Some(entry) = db.rune_id_to_rune_entry_get(&id) else {
+        return Ok(not_found());
+    };
+    let Some((balance, _)) = db.script_hash_rune_id_to_balance_get(&address_script(&address, chain)?, &id) else {
+        return Ok(not_found());
+    };
+    Ok(Json(HiroHolder { address, balance: decimal(balance, entry.divisibility) }).into_response())
+}
+
+pub async fn address_balances(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(chain): Extension<Chain>,
+    Path(address): Path<String>,
+    Query(params): Query<HiroPageParams>,
+) -> anyhow::Result<Json<HiroPaged<HiroBalance>>, AppError> {
+    let balances = db.script_hash_rune_id_to_balance_list(&address_script(&address, chain)?)?;
+    let total = balances.len() as u64;
+    let mut results = vec![];
+    for (id, amount, _) in balances.into_iter().sorted_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.to_string().cmp(&b.0.to_string()))).skip(params.offset()).take(params.limit()) {
+        let entry = db.rune_id_to_rune_entry_get(&id).unwrap();
+        results.push(HiroBalance {
+            rune: rune(id, &entry),
+            address: address.clone(),
+            balance: decimal(amount, entry.divisibility),
+        });
+    }
+    Ok(Json(HiroPaged {
+        limit: params.limit(),
+        offset: params.offset(),
+        total,
+        results,
+    }))
+}
+
+pub async fn address_activity(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(address): Path<String>,
+    Query(params): Query<HiroPageParams>,
+) -> anyhow::Result<Json<HiroPaged<HiroActivity>>, AppError> {
+    let (total, list) = db.sqlite_rune_activity_by_address(&address, params.offset(), params.limit())?;
+    let mut entries: HashMap<String, (RuneId, RuneEntry)> = HashMap::new();
+    let mut results = vec![];
+    for x in list {
+        let RuneActivityForQuery { rune_id, txid, vout, rune_amount, operation, height, idx, ts } = x;
+        if !entries.contains_key(&rune_id) {
+            let id = RuneId::from_str(&rune_id).unwrap();
+            entries.insert(rune_id.clone(), (id, db.rune_id_to_rune_entry_get(&id).unwrap()));
+        }
+        let (id, entry) = &entries[&rune_id];
+        results.push(HiroActivity {
+            rune: rune(*id, entry),
+            address: address.clone(),
+            receiver_address: None,
+            amount: decimal(rune_amount.parse().map_err(anyhow::Error::from)?, entry.divisibility),
+            operation,
+            location: HiroLocation {
+                block_hash: block_hash(&db, height as u64),
+                block_height: height as u64,
+                output: vout.map(|vout| format!("{txid}:{vout}")),
+                tx_id: txid,
+                tx_index: idx,
+                vout,
+                timestamp: ts as u64,
+            },
+        });
+    }
+    Ok(Json(HiroPaged {
+        limit: params.limit(),
+        offset: params.offset(),
+        total,
+        results,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_amounts() {
+        assert_eq!(decimal(0, 0), "0");
+        assert_eq!(decimal(105, 2), "1.05");
+        assert_eq!(decimal(150, 2), "1.5");
+        assert_eq!(decimal(1, 3), "0.001");
+        assert_eq!(decimal(u128::MAX, 38), "3.40282366920938463463374607431768211455");
+    }
+}
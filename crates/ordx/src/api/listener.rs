@@ -0,0 +1,112 @@
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use log::{info, warn};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::watch;
+
+/// A single entry of the comma separated `api_host` setting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddr {
+    Tcp(String),
+    Unix(String),
+}
+
+impl ListenAddr {
+    pub fn parse_list(api_host: &str) -> Vec<ListenAddr> {
+        api_host
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|x| match x.strip_prefix("unix:") {
+                Some(path) => ListenAddr::Unix(path.to_string()),
+                None => ListenAddr::Tcp(x.to_string()),
+            })
+            .collect()
+    }
+
+    /// Serves `app` until `shutdown` flips to true, then waits for in-flight requests.
+    pub async fn serve(self, app: Router, shutdown: watch::Receiver<bool>) -> anyhow::Result<()> {
+        match self {
+            ListenAddr::Tcp(host) => serve_tcp(host, app, shutdown).await,
+            ListenAddr::Unix(path) => serve_unix(path, app, shutdown).await,
+        }
+    }
+}
+
+async fn wait_for_shutdown(mut shutdown: watch::Receiver<bool>) {
+    // a dropped sender also means shutdown
+    let _ = shutdown.wait_for(|x| *x).await;
+}
+
+async fn serve_tcp(host: String, app: Router, shutdown: watch::Receiver<bool>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&host).await?;
+    info!("Listening on {}", host);
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+        .with_graceful_shutdown(wait_for_shutdown(shutdown))
+        .await?;
+    info!("Stopped listening on {}", host);
+    Ok(())
+}
+
+async fn serve_unix(path: String, app: Router, shutdown: watch::Receiver<bool>) -> anyhow::Result<()> {
+    // a stale socket left behind by a previous run would make bind fail
+    if let Ok(metadata) = std::fs::metadata(&path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    if let Some(parent) = Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    info!("Listening on unix:{}", path);
+    let graceful = GracefulShutdown::new();
+    let signal = wait_for_shutdown(shutdown);
+    tokio::pin!(signal);
+    loop {
+        let (socket, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = &mut signal => break,
+        };
+        let service = TowerToHyperService::new(app.clone());
+        let builder = Builder::new(TokioExecutor::new());
+        let conn = builder.serve_connection_with_upgrades(TokioIo::new(socket), service);
+        let conn = graceful.watch(conn.into_owned());
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                warn!("Unix socket connection error: {}", e);
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    let _ = std::fs::remove_file(&path);
+    info!("Stopped listening on unix:{}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_list() {
+        assert_eq!(
+            ListenAddr::parse_list("0.0.0.0:3000, unix:/run/ordx/api.sock,"),
+            vec![
+                ListenAddr::Tcp("0.0.0.0:3000".to_string()),
+                ListenAddr::Unix("/run/ordx/api.sock".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Per-route request metrics, served in the Prometheus text format at `/metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::body::HttpBody;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Seconds.
+const DURATION_BUCKETS: [f64; 13] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+/// Bytes, before compression.
+const SIZE_BUCKETS: [f64; 9] = [128.0, 512.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0];
+
+/// Requests without a matched route share one label, so random paths can't
+/// grow the series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    // per bucket, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, bounds: &[f64], value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; bounds.len()];
+        }
+        if let Some(i) = bounds.iter().position(|x| value <= *x) {
+            self.buckets[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str, bounds: &[f64]) {
+        let mut cumulative = 0;
+        for (i, bound) in bounds.iter().enumerate() {
+            cumulative += self.buckets.get(i).copied().unwrap_or_default();
+            writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}").unwrap();
+        }
+        writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count).unwrap();
+        writeln!(out, "{name}_sum{{{labels}}} {}", self.sum).unwrap();
+        writeln!(out, "{name}_count{{{labels}}} {}", self.count).unwrap();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RouteKey {
+    route: String,
+    method: String,
+    status: u16,
+}
+
+#[derive(Debug, Default)]
+struct RouteStats {
+    duration: Histogram,
+    size: Histogram,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ApiMetrics {
+    routes: Arc<Mutex<BTreeMap<RouteKey, RouteStats>>>,
+}
+
+impl ApiMetrics {
+    fn observe(&self, key: RouteKey, duration_secs: f64, size: Option<u64>) {
+        let mut routes = self.routes.lock().unwrap();
+        let stats = routes.entry(key).or_default();
+        stats.duration.observe(&DURATION_BUCKETS, duration_secs);
+        if let Some(size) = size {
+            stats.size.observe(&SIZE_BUCKETS, size as f64);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let routes = self.routes.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# HELP ordx_http_request_duration_seconds Time until the response head is ready.\n");
+        out.push_str("# TYPE ordx_http_request_duration_seconds histogram\n");
+        for (key, stats) in routes.iter() {
+            stats.duration.render(&mut out, "ordx_http_request_duration_seconds", &key.labels(), &DURATION_BUCKETS);
+        }
+        out.push_str("# HELP ordx_http_response_size_bytes Response body size before compression.\n");
+        out.push_str("# TYPE ordx_http_response_size_bytes histogram\n");
+        for (key, stats) in routes.iter().filter(|(_, x)| x.size.count > 0) {
+            stats.size.render(&mut out, "ordx_http_response_size_bytes", &key.labels(), &SIZE_BUCKETS);
+        }
+        out
+    }
+}
+
+impl RouteKey {
+    fn labels(&self) -> String {
+        format!("route=\"{}\",method=\"{}\",status=\"{}\"", escape_label(&self.route), self.method, self.status)
+    }
+}
+
+fn escape_label(x: &str) -> String {
+    x.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+pub async fn track(State(metrics): State<ApiMetrics>, req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map(|x| x.as_str().to_string()).unwrap_or(UNMATCHED_ROUTE.to_string());
+    let method = req.method().to_string();
+    let start = Instant::now();
+    let response = next.run(req).await;
+    let size = response.body().size_hint().exact().or_else(|| {
+        response.headers().get(header::CONTENT_LENGTH).and_then(|x| x.to_str().ok()?.parse().ok())
+    });
+    let key = RouteKey { route, method, status: response.status().as_u16() };
+    metrics.observe(key, start.elapsed().as_secs_f64(), size);
+    response
+}
+
+pub async fn render(State(metrics): State<ApiMetrics>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_cumulative_buckets() {
+        let metrics = ApiMetrics::default();
+        let key = || RouteKey { route: "/rune/:id".into(), method: "GET".into(), status: 200 };
+        metrics.observe(key(), 0.002, Some(100));
+        metrics.observe(key(), 0.02, Some(2000));
+        metrics.observe(key(), 20.0, None);
+
+        let text = metrics.render();
+        let labels = "route=\"/rune/:id\",method=\"GET\",status=\"200\"";
+        assert!(text.contains(&format!("ordx_http_request_duration_seconds_bucket{{{labels},le=\"0.001\"}} 0\n")));
+        assert!(text.contains(&format!("ordx_http_request_duration_seconds_bucket{{{labels},le=\"0.0025\"}} 1\n")));
+        assert!(text.contains(&format!("ordx_http_request_duration_seconds_bucket{{{labels},le=\"10\"}} 2\n")));
+        assert!(text.contains(&format!("ordx_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 3\n")));
+        assert!(text.contains(&format!("ordx_http_request_duration_seconds_count{{{labels}}} 3\n")));
+        assert!(text.contains(&format!("ordx_http_response_size_bytes_bucket{{{labels},le=\"128\"}} 1\n")));
+        assert!(text.contains(&format!("ordx_http_response_size_bytes_count{{{labels}}} 2\n")));
+    }
+}
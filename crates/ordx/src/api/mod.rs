@@ -1,23 +1,38 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{Extension, http, Router};
 use axum::body::Body;
+use axum::error_handling::HandleErrorLayer;
 use axum::http::{header, Response, StatusCode};
-use axum::routing::{get, post};
-use log::info;
-use tower_governor::governor::GovernorConfigBuilder;
-use tower_governor::GovernorLayer;
-use tower_governor::key_extractor::SmartIpKeyExtractor;
+use axum::middleware;
+use axum::routing::{delete, get, post};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tower::ServiceBuilder;
 use tower_http::catch_panic::CatchPanicLayer;
-use tower_http::cors::CorsLayer;
-use tower_http::trace::TraceLayer;
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::Level;
 
+use crate::api::auth::ApiKeyStore;
+use crate::api::cache_control::CacheControlState;
 use crate::api::dto::R;
-use crate::api::error::handle_panic;
+use crate::api::error::{handle_overload, handle_panic};
+use crate::api::listener::ListenAddr;
+use crate::api::metrics::ApiMetrics;
+use crate::api::reload::ReloadableLayers;
 use crate::cache::MokaCache;
+use crate::chain::Chain;
+use crate::control::IndexerControl;
+use crate::db::writer::SqliteWriter;
 use crate::db::RunesDB;
+use crate::fee::FeeEstimator;
+use crate::mempool::MempoolTracker;
+use crate::reindex::ReindexStatus;
+use crate::rpc::AsyncClient;
 use crate::settings::Settings;
+use crate::watch::Watches;
 
 pub mod ip;
 pub mod handler;
@@ -26,57 +41,177 @@ pub mod error;
 pub mod util;
 pub mod compat;
 pub mod vo;
+pub mod auth;
+pub mod admin;
+pub mod listener;
+pub mod builder;
+pub mod ord;
+pub mod hiro;
+pub mod cache_control;
+pub mod metrics;
+pub mod request_id;
+pub mod watchlist;
+pub mod reload;
 
-pub async fn create_server(settings: Arc<Settings>, runes_db: Arc<RunesDB>, cache: Arc<MokaCache>) -> anyhow::Result<()> {
-    let governor_conf = Arc::new(
-        GovernorConfigBuilder::default()
-            .per_millisecond(settings.ip_limit_per_mills)
-            .burst_size(settings.ip_limit_burst_size)
-            .key_extractor(SmartIpKeyExtractor)
-            .use_headers()
-            .finish()
-            .unwrap(),
-    );
-    let mut app = Router::new()
-        .fallback(|uri: http::Uri| async move {
-            let body: R<()> = R::error(-1, format!("No route: {}", &uri));
-            let body = serde_json::to_string(&body).unwrap();
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header(header::CONTENT_TYPE, "application/json")
-                .body(Body::from(body))
-                .unwrap()
-        })
+#[allow(clippy::too_many_arguments)]
+pub async fn create_server(settings: Arc<Settings>, chain: Chain, runes_db: Arc<RunesDB>, sqlite_writer: Arc<SqliteWriter>, cache: Arc<MokaCache>, rpc_client: Option<AsyncClient>, fee_estimator: Arc<FeeEstimator>, mempool: Arc<MempoolTracker>, watches: Arc<Watches>, indexer_control: Arc<IndexerControl>, reindex_status: Arc<ReindexStatus>, shutdown: watch::Receiver<bool>) -> anyhow::Result<()> {
+    let app = create_router(Arc::clone(&settings), chain, runes_db, sqlite_writer, cache, rpc_client, fee_estimator, mempool, watches, indexer_control, reindex_status)?;
+    serve(&settings, app, shutdown).await
+}
+
+/// The API of one network, served at the root on its own or nested by
+/// [`nest_networks`].
+#[allow(clippy::too_many_arguments)]
+pub fn create_router(settings: Arc<Settings>, chain: Chain, runes_db: Arc<RunesDB>, sqlite_writer: Arc<SqliteWriter>, cache: Arc<MokaCache>, rpc_client: Option<AsyncClient>, fee_estimator: Arc<FeeEstimator>, mempool: Arc<MempoolTracker>, watches: Arc<Watches>, indexer_control: Arc<IndexerControl>, reindex_status: Arc<ReindexStatus>) -> anyhow::Result<Router> {
+    let reloadable = Arc::new(ReloadableLayers::new(&settings)?);
+    reload::reload_on_sighup(chain, Arc::clone(&reloadable), Arc::clone(&cache))?;
+    let api_key_store = Arc::new(ApiKeyStore::load(&settings, &runes_db)?);
+    let admin = Router::new()
+        .route("/keys", get(admin::list_api_keys).post(admin::create_api_key))
+        .route("/keys/:id", delete(admin::revoke_api_key))
+        .route("/webhooks", get(admin::list_webhooks).post(admin::create_webhook))
+        .route("/webhooks/:id", delete(admin::delete_webhook))
+        .route("/webhooks/:id/deliveries", get(admin::list_webhook_deliveries))
+        .route("/reindex", post(admin::reindex))
+        .route("/db/flush", post(admin::flush_db))
+        .route("/db/compact", post(admin::compact_db))
+        .route("/backup", post(admin::backup))
+        .route("/backup/sqlite", get(admin::sqlite_backup))
+        .route("/cache/stats", get(admin::cache_stats))
+        .route("/cache/purge", post(admin::purge_cache))
+        .route("/reload", post(admin::reload_settings))
+        .route_layer(middleware::from_fn(auth::require_admin))
+        .route_layer(middleware::map_response(cache_control::no_store));
+    let ord = Router::new()
+        .route("/rune/:rune", get(ord::rune))
+        .route("/runes", get(ord::runes))
+        .route("/runes/:page", get(ord::runes_paginated))
+        .route("/runes/balances", get(ord::runes_balances))
+        .route("/output/:outpoint", get(ord::output));
+    let hiro = Router::new()
+        .route("/etchings", get(hiro::etchings))
+        .route("/etchings/:etching", get(hiro::get_etching))
+        .route("/etchings/:etching/holders", get(hiro::etching_holders))
+        .route("/etchings/:etching/holders/:address", get(hiro::etching_holder))
+        .route("/addresses/:address/balances", get(hiro::address_balances))
+        .route("/addresses/:address/activity", get(hiro::address_activity));
+    // a key only sees and deletes its own watches, admin keys every watch
+    let watch = Router::new()
+        .route("/watch", get(watchlist::list_watches).post(watchlist::create_watch).layer(middleware::map_response(cache_control::no_store)))
+        .route("/watch/stream", get(watchlist::stream))
+        .route("/watch/:id", delete(watchlist::delete_watch))
+        .route_layer(middleware::from_fn(auth::require_api_key));
+    let cache_control_state = CacheControlState {
+        db: Arc::clone(&runes_db),
+        tip_max_age_secs: settings.cache_control_tip_max_age_secs,
+        immutable_max_age_secs: settings.cache_control_immutable_max_age_secs,
+    };
+    let api_metrics = ApiMetrics::default();
+    let app = Router::new()
+        .fallback(no_route)
         .route("/stats", get(handler::stats))
+        .route("/stats/blocks", get(handler::block_stats))
+        .route("/metrics", get(metrics::render).with_state(api_metrics.clone()))
+        .route("/block/:height/runes", get(handler::block_runes))
+        .route("/events", get(handler::events).layer(middleware::map_response(cache_control::no_store)))
+        .route("/fees", get(handler::fees))
+        .route("/output/:outpoint", get(handler::output_detail))
+        .route("/output/:outpoint/sats", get(handler::output_sats))
+        .route("/inscription/:id", get(handler::inscription_detail))
+        .route("/address/:address/inscriptions", get(handler::address_inscriptions))
+        .route("/protocol/:name/:key", get(handler::protocol_query))
         .route("/rune/:id", get(handler::get_rune_by_id))
+        .route("/rune/:id/holders/top", get(handler::rune_top_holders))
+        .route("/rune/:id/txs", get(handler::rune_txs))
+        .route("/rune/:id/mints", get(handler::rune_mints))
+        .route("/rune/:id/etching", get(handler::rune_etching))
+        .route("/rune/:id/txs/range", get(handler::rune_txs_range))
         .route("/runes/list", get(handler::paged_runes))
+        .route("/runes/etched", get(handler::etched_runes))
+        .route("/runes/resolve/:query", get(handler::resolve_rune))
         .route("/runes/decode/psbt", post(handler::runes_decode_psbt))
         .route("/runes/decode/tx", post(handler::runes_decode_tx))
+        .route("/runes/decode/script", post(handler::runes_decode_script))
+        .route("/runes/validate", post(handler::runes_validate))
         .route("/runes/outputs", post(handler::outputs_runes))
         .route("/runes/ids", post(handler::get_runes_by_rune_ids))
+        .route("/runes/select-utxos", post(builder::select_utxos))
+        .route("/runes/build/mint", post(builder::build_mint))
+        .route("/runes/build/etching", post(builder::build_etching))
         .route("/runes/tx/:txid", get(handler::get_tx))
+        .route("/tx/:txid/runestone", get(handler::tx_runestone))
+        .route("/runes/mempool/tx/:txid", get(handler::mempool_tx_status).layer(middleware::map_response(cache_control::no_store)))
         .route("/runes/address/:address/utxo", get(handler::address_runes_utxos))
+        .route("/runes/address/:address/balances", get(handler::address_runes_balances))
+        .route("/runes/address/:address/unconfirmed", get(handler::address_runes_unconfirmed).layer(middleware::map_response(cache_control::no_store)))
         // compact
         .route("/runes/utxo/:address", get(compat::address_runes))
-        .route("/runes", get(compat::address_runes))
+        .route("/runes", get(compat::paged_runes))
+        .merge(watch)
+        .nest("/admin", admin)
+        .nest("/ord", ord)
+        .nest("/runes/v1", hiro)
+        .layer(middleware::from_fn_with_state(cache_control_state, cache_control::cache_control))
 
-        .layer(GovernorLayer {
-            config: governor_conf,
-        })
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_overload))
+                .load_shed()
+                .concurrency_limit(settings.concurrency_limit.max(1)),
+        )
+        .layer(middleware::from_fn_with_state(Arc::clone(&reloadable), reload::rate_limit))
+        .layer(middleware::from_fn(auth::api_key_auth))
         .layer(CatchPanicLayer::custom(handle_panic))
-        .layer(TraceLayer::new_for_http())
-        .layer(CorsLayer::permissive())
+        .layer(middleware::from_fn_with_state(api_metrics, metrics::track))
+        // event streams must reach the client unbuffered
+        .layer(CompressionLayer::new().compress_when(SizeAbove::new(settings.compression_min_size).and(NotForContentType::const_new("text/event-stream"))))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_id::make_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(middleware::from_fn(request_id::propagate))
+        .layer(middleware::from_fn_with_state(Arc::clone(&reloadable), reload::cors))
         .layer(Extension(runes_db))
+        .layer(Extension(sqlite_writer))
         .layer(Extension(cache))
+        .layer(Extension(api_key_store))
+        .layer(Extension(chain))
+        .layer(Extension(rpc_client))
+        .layer(Extension(fee_estimator))
+        .layer(Extension(mempool))
+        .layer(Extension(watches))
+        .layer(Extension(indexer_control))
+        .layer(Extension(reindex_status))
+        .layer(Extension(reloadable))
         ;
+    Ok(app)
+}
+
+/// The APIs of several networks in one server, each under `/<chain>`, e.g.
+/// `/testnet4/rune/:id`.
+pub fn nest_networks(routers: Vec<(Chain, Router)>) -> Router {
+    routers.into_iter().fold(Router::new().fallback(no_route), |app, (chain, router)| app.nest(&format!("/{}", chain), router))
+}
+
+async fn no_route(uri: http::Uri) -> Response<Body> {
+    let body: R<()> = R::error(-1, format!("No route: {}", &uri));
+    let body = serde_json::to_string(&body).unwrap();
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
 
-    let listener = tokio::net::TcpListener::bind(&settings.api_host)
-        .await?;
-    info!("Listening on {}", settings.api_host);
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-        .await?;
+/// Serves `app` on every address of `api_host` until `shutdown`.
+pub async fn serve(settings: &Settings, app: Router, shutdown: watch::Receiver<bool>) -> anyhow::Result<()> {
+    let mut listeners = JoinSet::new();
+    for addr in ListenAddr::parse_list(&settings.api_host) {
+        listeners.spawn(addr.serve(app.clone(), shutdown.clone()));
+    }
+    while let Some(result) = listeners.join_next().await {
+        result??;
+    }
     Ok(())
 }
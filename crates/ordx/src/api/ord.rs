@@ -0,0 +1,216 @@
+//! Routes with the JSON shapes of `ord --index-runes server`, nested under `/ord`
+//! so tools written against ord only need a new base URL.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use bitcoin::{Address, OutPoint, ScriptBuf, Txid};
+use bitcoin::address::NetworkUnchecked;
+use serde::Serialize;
+
+use ordinals::{Pile, RuneId, SpacedRune};
+
+use crate::api::dto::AppError;
+use crate::api::handler::resolve_rune_id;
+use crate::db::model::{RuneEntryPageQuery, RuneEntrySort};
+use crate::db::{RunesDB, Store};
+use crate::entry::RuneEntry;
+use crate::rpc::AsyncClient;
+use crate::updater::RuneUpdater;
+
+/// ord's page size for `/runes`.
+const RUNES_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Serialize)]
+pub struct OrdRune {
+    pub entry: RuneEntry,
+    pub id: RuneId,
+    pub mintable: bool,
+    pub parent: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrdRunes {
+    pub entries: Vec<(SpacedRune, RuneEntry)>,
+    pub more: bool,
+    pub prev: Option<usize>,
+    pub next: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrdOutput {
+    pub address: Option<Address<NetworkUnchecked>>,
+    pub indexed: bool,
+    pub inscriptions: Vec<String>,
+    pub outpoint: OutPoint,
+    pub runes: BTreeMap<SpacedRune, Pile>,
+    pub sat_ranges: Option<Vec<(u64, u64)>>,
+    pub script_pubkey: ScriptBuf,
+    pub spent: bool,
+    pub transaction: Txid,
+    pub value: u64,
+}
+
+pub async fn rune(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(rune): Path<String>,
+) -> anyhow::Result<Response, AppError> {
+    let Some(id) = resolve_rune_id(&db, &rune) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(entry) = db.rune_id_to_rune_entry_get(&id) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let latest_height = db.latest_height().unwrap_or_default();
+    Ok(Json(OrdRune {
+        entry,
+        id,
+        mintable: entry.mintable(latest_height as u64 + 1).is_ok(),
+        parent: None,
+    }).into_response())
+}
+
+pub async fn runes(
+    db: Extension<Arc<RunesDB>>,
+) -> anyhow::Result<Json<OrdRunes>, AppError> {
+    runes_paginated(db, Path(0)).await
+}
+
+/// Newest runes first, like ord.
+pub async fn runes_paginated(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Path(page): Path<usize>,
+) -> anyhow::Result<Json<OrdRunes>, AppError> {
+    let query = RuneEntryPageQuery {
+        offset: page.saturating_mul(RUNES_PAGE_SIZE),
+        size: RUNES_PAGE_SIZE,
+        sort: RuneEntrySort::Etching,
+        desc: true,
+        ..Default::default()
+    };
+    let (more, list) = db.sqlite_rune_entry_paged(&query)?;
+    let mut entries = vec![];
+    for (_, rune_id) in list {
+        let rune_id = RuneId::from_str(&rune_id).unwrap();
+        let entry = db.rune_id_to_rune_entry_get(&rune_id).unwrap();
+        entries.push((entry.spaced_rune, entry));
+    }
+    Ok(Json(OrdRunes {
+        entries,
+        more,
+        prev: page.checked_sub(1),
+        next: more.then_some(page + 1),
+    }))
+}
+
+pub async fn runes_balances(
+    Extension(db): Extension<Arc<RunesDB>>,
+) -> anyhow::Result<Json<BTreeMap<SpacedRune, BTreeMap<OutPoint, Pile>>>, AppError> {
+    let mut entries: BTreeMap<String, RuneEntry> = BTreeMap::new();
+    let mut balances: BTreeMap<SpacedRune, BTreeMap<OutPoint, Pile>> = BTreeMap::new();
+    for (rune_id, txid, vout, amount) in db.sqlite_rune_balance_list_unspent()? {
+        if !entries.contains_key(&rune_id) {
+            let entry = db.rune_id_to_rune_entry_get(&RuneId::from_str(&rune_id).unwrap()).unwrap();
+            entries.insert(rune_id.clone(), entry);
+        }
+        let entry = &entries[&rune_id];
+        let outpoint = OutPoint::new(Txid::from_str(&txid).unwrap(), vout);
+        balances.entry(entry.spaced_rune).or_default().insert(outpoint, entry.pile(amount.parse().unwrap()));
+    }
+    Ok(Json(balances))
+}
+
+pub async fn output(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(client): Extension<Option<AsyncClient>>,
+    Path(outpoint): Path<String>,
+) -> anyhow::Result<Response, AppError> {
+    let Some(client) = client else {
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, "bitcoind RPC is not configured").into_response());
+    };
+    let outpoint = OutPoint::from_str(&outpoint)?;
+    let Some(info) = client.get_raw_transaction_info(outpoint.txid).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(vout) = info.vout.iter().find(|x| x.n == outpoint.vout) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let indexed = info.blockhash.is_some();
+    let spent = indexed && !client.is_unspent(outpoint).await?;
+
+    let mut runes = BTreeMap::new();
+    if spent {
+        for x in db.sqlite_rune_balance_list_by_txid(&outpoint.txid.to_string())? {
+            if x.vout == outpoint.vout {
+                let entry = db.rune_id_to_rune_entry_get(&RuneId::from_str(&x.rune_id).unwrap()).unwrap();
+                runes.insert(entry.spaced_rune, entry.pile(x.rune_amount.parse().unwrap()));
+            }
+        }
+    } else if let Some(v) = db.outpoint_to_rune_balances_get(&outpoint) {
+        let balances_buffer = v.2;
+        let mut i = 0;
+        while i < balances_buffer.len() {
+            let ((id, balance), length) = RuneUpdater::decode_rune_balance(&balances_buffer[i..])?;
+            i += length;
+            let entry = db.rune_id_to_rune_entry_get(&id).unwrap();
+            runes.insert(entry.spaced_rune, entry.pile(balance));
+        }
+    }
+
+    Ok(Json(OrdOutput {
+        address: vout.script_pub_key.address.clone(),
+        indexed,
+        inscriptions: vec![],
+        outpoint,
+        runes,
+        sat_ranges: None,
+        script_pubkey: ScriptBuf::from_bytes(vout.script_pub_key.hex.clone()),
+        spent,
+        transaction: outpoint.txid,
+        value: vout.value.to_sat(),
+    }).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_shape() {
+        let outpoint = OutPoint::new(Txid::from_str("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b").unwrap(), 0);
+        let mut runes = BTreeMap::new();
+        runes.insert(SpacedRune::from_str("UNCOMMON•GOODS").unwrap(), Pile { amount: 1, divisibility: 0, symbol: Some('⧉') });
+        let output = OrdOutput {
+            address: None,
+            indexed: true,
+            inscriptions: vec![],
+            outpoint,
+            runes,
+            sat_ranges: None,
+            script_pubkey: ScriptBuf::from_bytes(vec![0x6a]),
+            spent: false,
+            transaction: outpoint.txid,
+            value: 546,
+        };
+        assert_eq!(
+            serde_json::to_value(output).unwrap(),
+            serde_json::json!({
+                "address": null,
+                "indexed": true,
+                "inscriptions": [],
+                "outpoint": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b:0",
+                "runes": {"UNCOMMON•GOODS": {"amount": 1, "divisibility": 0, "symbol": "⧉"}},
+                "sat_ranges": null,
+                "script_pubkey": "6a",
+                "spent": false,
+                "transaction": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+                "value": 546,
+            })
+        );
+    }
+}
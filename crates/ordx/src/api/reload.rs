@@ -0,0 +1,194 @@
+//! Settings the API picks up without a restart, on SIGHUP or `POST /admin/reload`:
+//! the rate limit, CORS and the cache expiry of every method. The environment
+//! is read once at start, so only the file at CONFIG_FILE can change them.
+
+use std::convert::Infallible;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use axum::extract::{Request, State};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use log::{info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+use tower::util::BoxLayer;
+use tower::{Layer, ServiceExt};
+use tower_governor::governor::GovernorConfigBuilder;
+use tower_governor::GovernorLayer;
+use tower_http::cors::CorsLayer;
+
+use crate::api::auth::{ApiKey, ClientIpKeyExtractor};
+use crate::cache::MokaCache;
+use crate::chain::Chain;
+use crate::settings::Settings;
+
+type RateLimitLayer = BoxLayer<Next, Request, Response, Infallible>;
+
+/// The layers built from reloadable settings, applied by [`rate_limit`] and
+/// [`cors`] in place of fixed ones.
+pub struct ReloadableLayers {
+    layers: RwLock<Layers>,
+}
+
+#[derive(Clone)]
+struct Layers {
+    // (per_millisecond, burst_size) the limiter was built with
+    rate: (u64, u32),
+    rate_limit: RateLimitLayer,
+    cors: CorsLayer,
+}
+
+impl ReloadableLayers {
+    pub fn new(settings: &Settings) -> anyhow::Result<Self> {
+        let rate = (settings.ip_limit_per_mills, settings.ip_limit_burst_size);
+        Ok(Self {
+            layers: RwLock::new(Layers {
+                rate,
+                rate_limit: rate_limit_layer(rate)?,
+                cors: cors_layer(settings)?,
+            }),
+        })
+    }
+
+    /// Swaps in the layers of `settings`, keeping the current ones when they are
+    /// invalid. The limiter, and the requests it counted, is only replaced when
+    /// the rate changes.
+    pub fn reload(&self, settings: &Settings) -> anyhow::Result<()> {
+        let rate = (settings.ip_limit_per_mills, settings.ip_limit_burst_size);
+        let current = self.current();
+        let layers = Layers {
+            rate,
+            rate_limit: if rate == current.rate { current.rate_limit } else { rate_limit_layer(rate)? },
+            cors: cors_layer(settings)?,
+        };
+        *self.layers.write().unwrap() = layers;
+        Ok(())
+    }
+
+    fn current(&self) -> Layers {
+        self.layers.read().unwrap().clone()
+    }
+}
+
+fn rate_limit_layer((per_millisecond, burst_size): (u64, u32)) -> anyhow::Result<RateLimitLayer> {
+    let config = GovernorConfigBuilder::default()
+        .per_millisecond(per_millisecond)
+        .burst_size(burst_size)
+        .key_extractor(ClientIpKeyExtractor)
+        .use_headers()
+        .finish()
+        .context("ip limit period and burst size must be positive")?;
+    Ok(BoxLayer::new(GovernorLayer { config: Arc::new(config) }))
+}
+
+fn cors_layer(settings: &Settings) -> anyhow::Result<CorsLayer> {
+    let layer = CorsLayer::permissive();
+    let Some(origins) = &settings.cors_allowed_origins else {
+        return Ok(layer);
+    };
+    let origins = origins.split(',')
+        .map(|x| HeaderValue::from_str(x.trim()).with_context(|| format!("invalid CORS origin: {}", x)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(layer.allow_origin(origins))
+}
+
+/// The IP limit of anonymous requests, key holders have the per tier limits of
+/// their key instead.
+pub async fn rate_limit(State(layers): State<Arc<ReloadableLayers>>, request: Request, next: Next) -> Response {
+    if request.extensions().get::<ApiKey>().is_some() {
+        return next.run(request).await;
+    }
+    let rate_limit = layers.current().rate_limit;
+    rate_limit.layer(next).oneshot(request).await.unwrap_or_else(|e| match e {})
+}
+
+pub async fn cors(State(layers): State<Arc<ReloadableLayers>>, request: Request, next: Next) -> Response {
+    let cors = layers.current().cors;
+    cors.layer(next).oneshot(request).await.unwrap_or_else(|e| match e {})
+}
+
+/// Reloads the settings of `chain` into `layers` and `cache`.
+pub fn reload(chain: Chain, layers: &ReloadableLayers, cache: &MokaCache) -> anyhow::Result<()> {
+    let settings = Settings::try_load()?.network_settings()?.into_iter()
+        .find(|x| x.network.as_deref().and_then(|x| x.parse::<Chain>().ok()) == Some(chain))
+        .with_context(|| format!("no settings for {}", chain))?;
+    layers.reload(&settings)?;
+    cache.reload(&settings);
+    info!("Reloaded the settings of {}", chain);
+    Ok(())
+}
+
+/// Reloads on every SIGHUP until the process exits.
+pub fn reload_on_sighup(chain: Chain, layers: Arc<ReloadableLayers>, cache: Arc<MokaCache>) -> anyhow::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if let Err(e) = reload(chain, &layers, &cache) {
+                warn!("Failed to reload the settings of {}: {}", chain, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+
+    use crate::api::auth::{hash_api_key, ApiKeyTier};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn keys_skip_the_ip_limit() {
+        let settings = Settings { ip_limit_per_mills: 60_000, ip_limit_burst_size: 2, ..Default::default() };
+        let layers = Arc::new(ReloadableLayers::new(&settings).unwrap());
+        let router = Router::new()
+            .route("/", get(|| async { "" }))
+            .layer(middleware::from_fn_with_state(layers, rate_limit));
+        let status = |key: Option<ApiKeyTier>| {
+            let mut request = Request::new(Body::empty());
+            if let Some(tier) = key {
+                request.extensions_mut().insert(ApiKey {
+                    id: 1,
+                    name: "test".to_string(),
+                    key_hash: hash_api_key("secret"),
+                    tier,
+                    rate_limit_per_minute: tier.rate_limit_per_minute(),
+                    daily_quota: tier.daily_quota(),
+                });
+            }
+            let router = router.clone();
+            async move { router.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(status(None).await, StatusCode::OK);
+        assert_eq!(status(None).await, StatusCode::OK);
+        assert_eq!(status(None).await, StatusCode::TOO_MANY_REQUESTS);
+        for _ in 0..10 {
+            assert_eq!(status(Some(ApiKeyTier::Premium)).await, StatusCode::OK);
+        }
+    }
+
+    #[test]
+    fn reload() {
+        let mut settings = Settings { ip_limit_per_mills: 100, ip_limit_burst_size: 10, ..Default::default() };
+        let layers = ReloadableLayers::new(&settings).unwrap();
+
+        settings.cors_allowed_origins = Some("https://a.example, https://b.example".into());
+        settings.ip_limit_burst_size = 20;
+        layers.reload(&settings).unwrap();
+        assert_eq!(layers.current().rate, (100, 20));
+
+        settings.ip_limit_burst_size = 0;
+        assert!(layers.reload(&settings).is_err());
+        settings.ip_limit_burst_size = 20;
+        settings.cors_allowed_origins = Some("https://a.example,\u{1}".into());
+        assert!(layers.reload(&settings).is_err());
+        assert_eq!(layers.current().rate, (100, 20));
+    }
+}
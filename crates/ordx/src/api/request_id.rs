@@ -0,0 +1,68 @@
+//! `X-Request-Id` of every request, taken from the client when it sends a
+//! usable one, so a failing request can be found in the server logs.
+
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::Span;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming id kept, longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The id of the request being handled, for error bodies built outside of a
+/// handler's arguments.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|x| x.clone()).ok()
+}
+
+fn incoming(headers: &HeaderMap) -> Option<String> {
+    let id = headers.get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let usable = !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|x| x.is_ascii_graphic());
+    usable.then(|| id.to_string())
+}
+
+/// Sets the id on the request, for the trace span, and on the response, and
+/// keeps it as `current` while the request is handled.
+pub async fn propagate(mut req: Request, next: Next) -> Response {
+    let id = incoming(req.headers()).unwrap_or_else(|| hex::encode(rand::random::<[u8; 16]>()));
+    let value = HeaderValue::from_str(&id).unwrap();
+    req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    let mut response = REQUEST_ID.scope(id, next.run(req)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
+
+/// The request span of the trace layer, with the id `propagate` set.
+pub fn make_span(req: &Request<Body>) -> Span {
+    let id = req.headers().get(&REQUEST_ID_HEADER).and_then(|x| x.to_str().ok()).unwrap_or_default();
+    tracing::info_span!("request", method = %req.method(), uri = %req.uri(), version = ?req.version(), request_id = %id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::dto::R;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn incoming_ids_and_error_bodies() {
+        let headers = |id: &str| HeaderMap::from_iter([(REQUEST_ID_HEADER, HeaderValue::from_str(id).unwrap())]);
+        assert_eq!(incoming(&headers("abc-123")), Some("abc-123".to_string()));
+        assert_eq!(incoming(&headers("")), None);
+        assert_eq!(incoming(&headers("a b")), None);
+        assert_eq!(incoming(&headers(&"a".repeat(MAX_REQUEST_ID_LEN + 1))), None);
+        assert_eq!(incoming(&HeaderMap::new()), None);
+
+        assert_eq!(R::<()>::error(-1, "failed".into()).request_id, None);
+        let error = REQUEST_ID.scope("abc-123".into(), async { R::<()>::error(-1, "failed".into()) }).await;
+        assert_eq!(error.request_id, Some("abc-123".to_string()));
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::extract::{Path, Query};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::{Extension, Json};
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::api::auth::{unix_timestamp, ApiKey};
+use crate::api::dto::{AppError, R};
+use crate::chain::Chain;
+use crate::db::model::{WatchForInsert, WatchForQuery, WebhookForInsert};
+use crate::db::writer::SqliteWriter;
+use crate::db::RunesDB;
+use crate::watch::Watches;
+use crate::webhook::{validate_public_url, validate_url, WebhookFilter};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWatchParams {
+    pub address: String,
+    /// Also deliver the changes to this webhook url.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedWatchDTO {
+    pub id: u32,
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<u32>,
+    /// Signs the webhook deliveries, only returned here.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchStreamParams {
+    /// Comma separated, every watched address if unset.
+    pub addresses: Option<String>,
+}
+
+/// The watches a key may see and delete, its own or every watch for an admin
+/// key.
+fn owner(key: &ApiKey) -> Option<u32> {
+    (!key.is_admin()).then_some(key.id)
+}
+
+pub async fn create_watch(
+    Extension(writer): Extension<Arc<SqliteWriter>>,
+    Extension(chain): Extension<Chain>,
+    Extension(key): Extension<ApiKey>,
+    Json(params): Json<CreateWatchParams>,
+) -> anyhow::Result<Json<R<CreatedWatchDTO>>, AppError> {
+    let address = chain.format_address(&chain.parse_address(&params.address)?.require_network(chain.network())?);
+    let now = unix_timestamp();
+    let webhook = match params.url {
+        Some(url) => {
+            if key.is_admin() {
+                validate_url(&url)?;
+            } else {
+                validate_public_url(&url).await?;
+            }
+            Some(WebhookForInsert {
+                url,
+                event: WebhookFilter::Watch(address.clone()).to_string(),
+                secret: hex::encode(rand::random::<[u8; 32]>()),
+                created_ts: now,
+            })
+        }
+        None => None,
+    };
+    let secret = webhook.as_ref().map(|x| x.secret.clone());
+    let watch_address = address.clone();
+    let (id, webhook_id) = writer.call(move |db| {
+        let webhook_id = webhook.map(|x| db.sqlite_webhook_insert(&x)).transpose()?;
+        let id = db.sqlite_watch_insert(&WatchForInsert { address: watch_address, webhook_id, api_key_id: key.id, created_ts: now })?;
+        Ok((id, webhook_id))
+    }).await?;
+    Ok(Json(R::with_data(CreatedWatchDTO { id, address, webhook_id, secret })))
+}
+
+pub async fn list_watches(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(key): Extension<ApiKey>,
+) -> anyhow::Result<Json<R<Vec<WatchForQuery>>>, AppError> {
+    Ok(Json(R::with_data(db.sqlite_watch_list(owner(&key))?)))
+}
+
+pub async fn delete_watch(
+    Extension(writer): Extension<Arc<SqliteWriter>>,
+    Extension(key): Extension<ApiKey>,
+    Path(id): Path<u32>,
+) -> anyhow::Result<Json<R<bool>>, AppError> {
+    Ok(Json(R::with_data(writer.call(move |db| db.sqlite_watch_delete(id, owner(&key))).await?)))
+}
+
+/// Server-sent events, one `transfer` or `unconfirmed` event per balance
+/// change of an address the key watches.
+pub async fn stream(
+    Extension(db): Extension<Arc<RunesDB>>,
+    Extension(watches): Extension<Arc<Watches>>,
+    Extension(key): Extension<ApiKey>,
+    Query(params): Query<WatchStreamParams>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let addresses: Option<HashSet<String>> = params.addresses
+        .map(|x| x.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect());
+    let receiver = watches.subscribe();
+    let owner = owner(&key);
+    let events = stream::unfold((receiver, addresses), move |(mut receiver, addresses)| {
+        let db = Arc::clone(&db);
+        async move {
+            loop {
+                let notification = match receiver.recv().await {
+                    Ok(x) => x,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                };
+                let Some(address) = notification.address() else {
+                    continue;
+                };
+                if addresses.as_ref().is_some_and(|x| !x.contains(address)) {
+                    continue;
+                }
+                // watches of the key are read per event, a stream sees the ones added after it opened
+                if owner.is_some() && !db.sqlite_watch_addresses(owner).is_ok_and(|x| x.iter().any(|x| x == address)) {
+                    continue;
+                }
+                let event = Event::default()
+                    .event(notification.event.kind())
+                    .json_data(&notification)
+                    .unwrap_or_else(|_| Event::default().comment("unserializable"));
+                return Some((Ok(event), (receiver, addresses)));
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
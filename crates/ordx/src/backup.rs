@@ -0,0 +1,140 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::bail;
+use log::info;
+use serde::Serialize;
+
+use crate::db::RunesDB;
+
+pub const USAGE: &str = "Usage: ordx backup --out <dir>";
+
+/// Options of the `backup` subcommand, which must not run while the indexer
+/// is running, `POST /admin/backup` takes the same backup from a running one.
+#[derive(Debug, PartialEq)]
+pub struct BackupOptions {
+    pub out: PathBuf,
+}
+
+impl BackupOptions {
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut out = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => {
+                    let Some(value) = args.next() else {
+                        bail!("--out requires a value\n{USAGE}");
+                    };
+                    out = Some(PathBuf::from(value));
+                }
+                _ => bail!("unknown argument `{arg}`\n{USAGE}"),
+            }
+        }
+        let Some(out) = out else {
+            bail!("--out is required\n{USAGE}");
+        };
+        Ok(BackupOptions { out })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Backup {
+    pub path: String,
+    pub indexed_height: Option<u32>,
+    pub sqlite_size: u64,
+    pub elapsed_ms: u128,
+}
+
+/// Writes a rocksdb checkpoint and a copy of the sqlite database into `rocksdb`
+/// and `sqlite.db` under `out`, the layout of a data dir, so the backup opens
+/// like any index.
+///
+/// Safe next to a running daemon: sqlite is copied from one read transaction
+/// first and rocksdb is checkpointed after it, so rocksdb is at most the
+/// blocks committed in between ahead of sqlite, which
+/// [`RunesDB::reconcile_commit`] catches up on when the backup is opened.
+pub fn run(runes_db: &RunesDB, out: &Path) -> anyhow::Result<Backup> {
+    let t = Instant::now();
+    let rocksdb_path = out.join("rocksdb");
+    let sqlite_path = out.join("sqlite.db");
+    if rocksdb_path.exists() || sqlite_path.exists() {
+        bail!("{} already holds a backup", out.display());
+    }
+    fs::create_dir_all(out)?;
+    runes_db.sqlite_vacuum_into(&sqlite_path)?;
+    let indexed_height = runes_db.latest_indexed_height();
+    runes_db.rocksdb_checkpoint(&rocksdb_path)?;
+    let backup = Backup {
+        path: out.display().to_string(),
+        indexed_height,
+        sqlite_size: fs::metadata(&sqlite_path)?.len(),
+        elapsed_ms: t.elapsed().as_millis(),
+    };
+    info!("Backed up to {} at height {:?}, {}ms", backup.path, backup.indexed_height, backup.elapsed_ms);
+    Ok(backup)
+}
+
+/// Copies the sqlite database next to it and returns the copy opened for
+/// reading. The file is unlinked right away, it is gone once the handle is
+/// dropped.
+pub fn sqlite_snapshot(runes_db: &RunesDB) -> anyhow::Result<fs::File> {
+    let path = runes_db.data_dir().join(format!("sqlite.db.snapshot-{:016x}", rand::random::<u64>()));
+    let result = runes_db.sqlite_backup_to(&path).and_then(|_| Ok(fs::File::open(&path)?));
+    let _ = fs::remove_file(&path);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Store;
+    use crate::entry::Statistic;
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn args(x: &str) -> Vec<String> {
+        x.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(BackupOptions::parse(&args("--out /backups/1")).unwrap(), BackupOptions { out: PathBuf::from("/backups/1") });
+        assert!(BackupOptions::parse(&args("")).is_err());
+        assert!(BackupOptions::parse(&args("--out")).is_err());
+        assert!(BackupOptions::parse(&args("--out /tmp --force")).is_err());
+    }
+
+    #[test]
+    fn backup_opens_as_an_index() {
+        let path = TempDir::new("backup");
+        let db = RunesDB::new(path.join("data"));
+        db.init_sqlite().unwrap();
+        db.statistic_to_value_put(&Statistic::Runes, 7);
+
+        let out = path.join("backup");
+        let backup = run(&db, &out).unwrap();
+        assert!(backup.sqlite_size > 0);
+        assert!(run(&db, &out).is_err());
+        db.statistic_to_value_put(&Statistic::Runes, 8);
+
+        let restored = RunesDB::new(&out);
+        assert_eq!(restored.statistic_to_value_get(&Statistic::Runes), Some(7));
+        let count: u32 = restored.sqlite.get().unwrap().query_row("SELECT COUNT(*) FROM rune_entry", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn sqlite_snapshot_is_unlinked() {
+        let path = TempDir::new("sqlite-snapshot");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let mut file = sqlite_snapshot(&db).unwrap();
+        let mut bytes = vec![];
+        std::io::Read::read_to_end(&mut file, &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"SQLite format 3\0"));
+        assert!(!fs::read_dir(&path).unwrap().any(|x| x.unwrap().file_name().to_string_lossy().contains("snapshot")));
+    }
+}
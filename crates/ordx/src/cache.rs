@@ -1,15 +1,26 @@
-use std::hash::Hash;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{ScriptBuf, Txid};
+use log::warn;
 use moka::future::Cache;
+use moka::policy::EvictionPolicy;
+use moka::Expiry;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::db::RunesDB;
 use crate::settings::Settings;
 
 #[derive(Debug, Clone)]
 pub struct CacheKey(pub CacheMethod, pub Value);
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CacheMethod {
     HandlerAddressUtxos,
     CompatAddressUtxos,
@@ -17,15 +28,44 @@ pub enum CacheMethod {
     HandlerRuneById,
     HandlerTx,
     CompatPagedRunes,
+    HandlerTopHolders,
+    HandlerRunesTotal,
+    HiroEtchingHolders,
+    UnknownRune,
+}
+
+impl CacheMethod {
+    pub const ALL: [CacheMethod; 10] = [
+        CacheMethod::HandlerAddressUtxos,
+        CacheMethod::CompatAddressUtxos,
+        CacheMethod::HandlerPagedRunes,
+        CacheMethod::HandlerRuneById,
+        CacheMethod::HandlerTx,
+        CacheMethod::CompatPagedRunes,
+        CacheMethod::HandlerTopHolders,
+        CacheMethod::HandlerRunesTotal,
+        CacheMethod::HiroEtchingHolders,
+        CacheMethod::UnknownRune,
+    ];
 }
 
 impl CacheKey {
     pub fn new(method: CacheMethod, params: Value) -> Self {
         Self(method, params)
     }
+
+    /// Whether the params are `subject` or a list led by it, e.g. the address
+    /// of an address utxos page or the rune id of a top holders list.
+    pub fn is_about(&self, subject: &str) -> bool {
+        match &self.1 {
+            Value::String(x) => x == subject,
+            Value::Array(x) => x.first().and_then(Value::as_str) == Some(subject),
+            _ => false,
+        }
+    }
 }
 
-impl Hash for CacheKey {
+impl std::hash::Hash for CacheKey {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.0.hash(state);
         self.1.hash(state);
@@ -40,13 +80,409 @@ impl PartialEq for CacheKey {
 
 impl Eq for CacheKey {}
 
-pub type MokaCache = Cache<CacheKey, Value>;
+/// API response cache, counting hits and misses for `/admin/cache/stats`.
+pub struct MokaCache {
+    /// One cache per method, each with its own expiry and capacity.
+    inner: HashMap<CacheMethod, Cache<CacheKey, Value>>,
+    expirations: HashMap<CacheMethod, Arc<RwLock<Expiration>>>,
+    /// Lookups that found nothing, kept briefly so probes of random ids don't
+    /// reach the databases. `None` when disabled.
+    not_found: Option<Cache<CacheKey, ()>>,
+    persistent: Option<Persistent>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entries: u64,
+    pub weighted_size: u64,
+    pub not_found_entries: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Share of lookups served from the cache, 0 before the first lookup.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// Time to live and to idle of the entries of a method.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Expiration {
+    time_to_live: Duration,
+    time_to_idle: Duration,
+}
+
+impl Expiration {
+    fn of(settings: &Settings, method: CacheMethod) -> Self {
+        let x = settings.cache_methods.get(&method).copied().unwrap_or_default();
+        Self {
+            time_to_live: Duration::from_secs(x.time_to_live_secs.unwrap_or(settings.cache_time_to_live_secs)),
+            time_to_idle: Duration::from_secs(x.time_to_idle_secs.unwrap_or(settings.cache_time_to_idle_secs)),
+        }
+    }
+}
+
+/// Expires entries like `time_to_live` and `time_to_idle` of the cache builder,
+/// reading the expiration on every write and read so that a reload applies to
+/// the entries cached already.
+struct MethodExpiry(Arc<RwLock<Expiration>>);
+
+impl Expiry<CacheKey, Value> for MethodExpiry {
+    fn expire_after_create(&self, _key: &CacheKey, _value: &Value, _created_at: Instant) -> Option<Duration> {
+        let x = *self.0.read().unwrap();
+        Some(x.time_to_live.min(x.time_to_idle))
+    }
+
+    fn expire_after_read(&self, _key: &CacheKey, _value: &Value, read_at: Instant, _duration_until_expiry: Option<Duration>, last_modified_at: Instant) -> Option<Duration> {
+        let x = *self.0.read().unwrap();
+        let lived = read_at.saturating_duration_since(last_modified_at);
+        Some(x.time_to_idle.min(x.time_to_live.saturating_sub(lived)))
+    }
+
+    fn expire_after_update(&self, key: &CacheKey, value: &Value, updated_at: Instant, _duration_until_expiry: Option<Duration>) -> Option<Duration> {
+        self.expire_after_create(key, value, updated_at)
+    }
+}
+
+/// The disk tier of the methods set `persistent`, so a restart doesn't leave
+/// their expensive responses to be recomputed by the first burst of requests.
+struct Persistent {
+    db: Arc<RunesDB>,
+    methods: HashSet<CacheMethod>,
+}
+
+impl Persistent {
+    fn key(key: &CacheKey) -> [u8; 32] {
+        sha256::Hash::hash(&serde_json::to_vec(&(key.0, &key.1)).unwrap()).to_byte_array()
+    }
+
+    fn get(&self, key: &[u8; 32]) -> Option<Value> {
+        match self.db.response_cache_get(key) {
+            Ok(value) => value.and_then(|x| serde_json::from_slice(&x).ok()),
+            Err(e) => {
+                warn!("Failed to read the response cache: {}", e);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &[u8; 32], value: &Value) {
+        if let Err(e) = self.db.response_cache_put(key, &serde_json::to_vec(value).unwrap()) {
+            warn!("Failed to write the response cache: {}", e);
+        }
+    }
+
+    fn clear(&self) {
+        if let Err(e) = self.db.response_cache_clear() {
+            warn!("Failed to clear the response cache: {}", e);
+        }
+    }
+}
+
+impl MokaCache {
+    /// The cache of every method with its capacity and expiration in `settings`.
+    fn new(settings: &Settings, not_found: Option<Cache<CacheKey, ()>>) -> Self {
+        let expirations = CacheMethod::ALL.into_iter()
+            .map(|method| (method, Arc::new(RwLock::new(Expiration::of(settings, method)))))
+            .collect::<HashMap<_, _>>();
+        let inner = expirations.iter()
+            .map(|(method, expiration)| {
+                let max_entries = settings.cache_methods.get(method).and_then(|x| x.max_entries);
+                let cache = Cache::builder()
+                    .max_capacity(max_entries.unwrap_or(settings.cache_max_entries))
+                    .expire_after(MethodExpiry(Arc::clone(expiration)))
+                    .build();
+                (*method, cache)
+            })
+            .collect();
+        Self {
+            inner,
+            expirations,
+            not_found,
+            persistent: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Applies the expiration of every method in `settings`, capacities are
+    /// fixed once the caches are built.
+    pub fn reload(&self, settings: &Settings) {
+        for (method, expiration) in &self.expirations {
+            *expiration.write().unwrap() = Expiration::of(settings, *method);
+        }
+    }
+
+    pub async fn get(&self, key: &CacheKey) -> Option<Value> {
+        let value = self.inner[&key.0].get(key).await;
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    pub async fn insert(&self, key: CacheKey, value: Value) {
+        self.inner[&key.0].insert(key, value).await
+    }
+
+    /// Keeps the responses of `methods` in the RESPONSE_CACHE CF of `db` too,
+    /// its writes need the primary.
+    pub fn with_persistent(mut self, db: Arc<RunesDB>, methods: HashSet<CacheMethod>) -> Self {
+        self.persistent = Some(Persistent { db, methods }).filter(|x| !x.methods.is_empty());
+        self
+    }
+
+    /// The value of `key`, computed by `init` on a miss. Concurrent misses of
+    /// the same key wait for the first `init` instead of running their own, and
+    /// get its value marked `cache` like any hit. Errors are not cached.
+    pub async fn try_get_with<F>(&self, key: CacheKey, init: F) -> anyhow::Result<Value>
+    where
+        F: Future<Output = anyhow::Result<Value>>,
+    {
+        let persistent = self.persistent.as_ref()
+            .filter(|x| x.methods.contains(&key.0))
+            .map(|x| (x, Persistent::key(&key)));
+        let mut computed = None;
+        let entry = self.inner[&key.0].entry(key).or_try_insert_with(async {
+            let value = match persistent.as_ref().and_then(|(x, key)| x.get(key)) {
+                Some(value) => value,
+                None => {
+                    let value = init.await?;
+                    if let Some((x, key)) = &persistent {
+                        x.put(key, &value);
+                    }
+                    computed = Some(value.clone());
+                    value
+                }
+            };
+            let mut cloned = value;
+            if let Value::Object(x) = &mut cloned {
+                x.insert("cache".to_string(), Value::Bool(true));
+            }
+            Ok::<_, anyhow::Error>(cloned)
+        }).await.map_err(|e| anyhow::anyhow!(e))?;
+        let counter = if computed.is_some() { &self.misses } else { &self.hits };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(computed.unwrap_or_else(|| entry.into_value()))
+    }
+
+    /// Whether `key` was recently looked up without a result, counted as a hit.
+    pub async fn is_not_found(&self, key: &CacheKey) -> bool {
+        let Some(not_found) = &self.not_found else {
+            return false;
+        };
+        let found = not_found.contains_key(key);
+        if found {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        found
+    }
+
+    pub async fn insert_not_found(&self, key: CacheKey) {
+        if let Some(not_found) = &self.not_found {
+            not_found.insert(key, ()).await
+        }
+    }
+
+    /// Called after every block, which also drops the not found entries of
+    /// runes it etched.
+    pub fn invalidate_all(&self) {
+        for cache in self.inner.values() {
+            cache.invalidate_all();
+        }
+        if let Some(not_found) = &self.not_found {
+            not_found.invalidate_all();
+        }
+        if let Some(persistent) = &self.persistent {
+            persistent.clear();
+        }
+    }
+
+    /// Evicts the entries of `method` (all methods when `None`) that are about
+    /// `subject` (any when `None`), returning how many were evicted from memory.
+    /// The disk entries are only known by hash, they are cleared whenever a
+    /// persistent method may match.
+    pub async fn purge(&self, method: Option<CacheMethod>, subject: Option<&str>) -> u64 {
+        let matches = |key: &CacheKey| method.map_or(true, |x| key.0 == x) && subject.map_or(true, |x| key.is_about(x));
+        let mut purged = 0;
+        for (_, cache) in self.inner.iter().filter(|(x, _)| method.map_or(true, |method| **x == method)) {
+            let keys = cache.iter()
+                .map(|(key, _)| key)
+                .filter(|key| matches(key))
+                .collect::<Vec<_>>();
+            for key in &keys {
+                cache.invalidate(key.as_ref()).await;
+            }
+            purged += keys.len() as u64;
+        }
+        if let Some(not_found) = &self.not_found {
+            let keys = not_found.iter()
+                .map(|(key, _)| key)
+                .filter(|key| matches(key))
+                .collect::<Vec<_>>();
+            for key in &keys {
+                not_found.invalidate(key.as_ref()).await;
+            }
+            purged += keys.len() as u64;
+        }
+        if let Some(persistent) = &self.persistent {
+            if method.map_or(true, |x| persistent.methods.contains(&x)) {
+                persistent.clear();
+            }
+        }
+        purged
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let (mut entries, mut weighted_size) = (0, 0);
+        for cache in self.inner.values() {
+            cache.run_pending_tasks().await;
+            entries += cache.entry_count();
+            weighted_size += cache.weighted_size();
+        }
+        let not_found_entries = match &self.not_found {
+            Some(not_found) => {
+                not_found.run_pending_tasks().await;
+                not_found.entry_count()
+            }
+            None => 0,
+        };
+        CacheStats {
+            entries,
+            weighted_size,
+            not_found_entries,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
 
 pub fn create_cache(settings: &Settings) -> MokaCache {
-    Cache::builder()
+    let not_found = (settings.cache_not_found_time_to_live_secs > 0).then(|| Cache::builder()
         .max_capacity(settings.cache_max_entries)
-        .time_to_live(Duration::from_secs(settings.cache_time_to_live_secs))
-        .time_to_idle(Duration::from_secs(settings.cache_time_to_idle_secs))
+        .time_to_live(Duration::from_secs(settings.cache_not_found_time_to_live_secs))
+        .build());
+    MokaCache::new(settings, not_found)
+}
+
+/// Commit transaction of an etching, as far as the commitment check needs it.
+#[derive(Debug, Clone)]
+pub struct CommitTx {
+    pub height: u32,
+    pub scripts: Vec<ScriptBuf>,
+}
+
+pub type CommitTxCache = Cache<Txid, CommitTx>;
+
+/// Commit tx lookups outlive a block, etch attempts keep pointing at the same commit txs.
+pub fn create_commit_tx_cache(settings: &Settings) -> CommitTxCache {
+    Cache::builder()
+        .max_capacity(settings.commit_tx_cache_max_entries)
+        .eviction_policy(EvictionPolicy::lru())
         .build()
 }
 
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::settings::CacheMethodSettings;
+
+    use super::*;
+
+    #[test]
+    fn cache_key_is_about() {
+        let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        assert!(CacheKey::new(CacheMethod::HandlerAddressUtxos, json!([address, {"page": 1}])).is_about(address));
+        assert!(CacheKey::new(CacheMethod::HandlerRuneById, json!("840000:3")).is_about("840000:3"));
+        assert!(!CacheKey::new(CacheMethod::HandlerTopHolders, json!(["840000:3", 10])).is_about("840000:1"));
+        assert!(!CacheKey::new(CacheMethod::HandlerRunesTotal, json!({"keywords": "840000:3"})).is_about("840000:3"));
+    }
+
+    #[test]
+    fn hit_ratio() {
+        let stats = |hits, misses| CacheStats { entries: 0, weighted_size: 0, not_found_entries: 0, hits, misses };
+        assert_eq!(stats(0, 0).hit_ratio(), 0.0);
+        assert_eq!(stats(3, 1).hit_ratio(), 0.75);
+    }
+
+    #[tokio::test]
+    async fn coalesces_misses() {
+        let cache = create_cache(&Settings { cache_max_entries: 16, cache_time_to_live_secs: 60, cache_time_to_idle_secs: 60, ..Default::default() });
+        let inits = AtomicU64::new(0);
+        let get = || cache.try_get_with(CacheKey::new(CacheMethod::HandlerTx, json!("txid")), async {
+            inits.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(json!({"data": 1}))
+        });
+        let values = futures_util::future::join_all((0..8).map(|_| get())).await;
+        assert_eq!(inits.load(Ordering::Relaxed), 1);
+        let values = values.into_iter().collect::<anyhow::Result<Vec<_>>>().unwrap();
+        assert_eq!(values.iter().filter(|x| x["cache"] == json!(true)).count(), 7);
+        let stats = cache.stats().await;
+        assert_eq!((stats.hits, stats.misses), (7, 1));
+
+        let failed = cache.try_get_with(CacheKey::new(CacheMethod::HandlerTx, json!("other")), async { Err(anyhow::anyhow!("db error")) }).await;
+        assert_eq!(failed.unwrap_err().to_string(), "db error");
+        assert_eq!(cache.stats().await.entries, 1);
+    }
+
+    #[tokio::test]
+    async fn method_settings() {
+        let mut settings = Settings { cache_max_entries: 16, cache_time_to_live_secs: 60, cache_time_to_idle_secs: 60, ..Default::default() };
+        settings.cache_methods.insert(CacheMethod::HandlerAddressUtxos, CacheMethodSettings { max_entries: Some(0), ..Default::default() });
+        let cache = create_cache(&settings);
+        cache.insert(CacheKey::new(CacheMethod::HandlerAddressUtxos, json!("address")), json!({})).await;
+        cache.insert(CacheKey::new(CacheMethod::HandlerRuneById, json!("840000:3")), json!({})).await;
+        assert_eq!(cache.stats().await.entries, 1);
+        assert!(cache.get(&CacheKey::new(CacheMethod::HandlerRuneById, json!("840000:3"))).await.is_some());
+        assert_eq!(cache.purge(Some(CacheMethod::HandlerTx), None).await, 0);
+        assert_eq!(cache.purge(None, Some("840000:3")).await, 1);
+    }
+
+    #[tokio::test]
+    async fn reload() {
+        let mut settings = Settings { cache_max_entries: 16, cache_time_to_live_secs: 60, cache_time_to_idle_secs: 60, ..Default::default() };
+        let cache = create_cache(&settings);
+        let key = || CacheKey::new(CacheMethod::HandlerTx, json!("txid"));
+        cache.insert(key(), json!({})).await;
+        assert!(cache.get(&key()).await.is_some());
+
+        // the read after the reload applies the new expiration to the entry
+        settings.cache_methods.insert(CacheMethod::HandlerTx, CacheMethodSettings { time_to_live_secs: Some(0), ..Default::default() });
+        cache.reload(&settings);
+        cache.get(&key()).await;
+        assert!(cache.get(&key()).await.is_none());
+        cache.insert(key(), json!({})).await;
+        assert!(cache.get(&key()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let key = || CacheKey::new(CacheMethod::UnknownRune, json!("NOTARUNE"));
+        let cache = create_cache(&Settings { cache_max_entries: 16, cache_not_found_time_to_live_secs: 30, ..Default::default() });
+        assert!(!cache.is_not_found(&key()).await);
+        cache.insert_not_found(key()).await;
+        assert!(cache.is_not_found(&key()).await);
+        assert_eq!(cache.stats().await.not_found_entries, 1);
+        assert_eq!(cache.purge(Some(CacheMethod::UnknownRune), Some("NOTARUNE")).await, 1);
+        assert!(!cache.is_not_found(&key()).await);
+
+        cache.insert_not_found(key()).await;
+        cache.invalidate_all();
+        assert!(!cache.is_not_found(&key()).await);
+
+        let disabled = create_cache(&Settings { cache_max_entries: 16, ..Default::default() });
+        disabled.insert_not_found(key()).await;
+        assert!(!disabled.is_not_found(&key()).await);
+    }
+}
@@ -2,12 +2,21 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 use anyhow::{bail, Error};
-use bitcoin::{Address, Block, Network, OutPoint, Script};
+use bitcoin::address::{AddressEncoding, NetworkUnchecked, Payload};
+use bitcoin::bech32::Hrp;
+use bitcoin::blockdata::constants::{genesis_block, PUBKEY_ADDRESS_PREFIX_TEST, SCRIPT_ADDRESS_PREFIX_TEST, SUBSIDY_HALVING_INTERVAL};
+use bitcoin::blockdata::{block, locktime, opcodes, script, transaction};
+use bitcoin::hash_types::TxMerkleNode;
+use bitcoin::hashes::Hash;
+use bitcoin::p2p::Magic;
+use bitcoin::script::PushBytesBuf;
+use bitcoin::{Address, Amount, Block, BlockHash, CompactTarget, Network, OutPoint, Script, Sequence, Transaction, TxIn, TxOut, WitnessProgram, WitnessVersion, Witness};
 use serde::{Deserialize, Serialize};
 
-use ordinals::Rune;
+use ordinals::{Height, Rune, COIN_VALUE};
 
 #[derive(Default, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -18,27 +27,102 @@ pub enum Chain {
     Testnet4,
     Signet,
     Regtest,
+    /// A private signet or test harness, with the parameters given to
+    /// [`Chain::set_custom`]. Everything else is signet's: the genesis block,
+    /// which all signets share, and the base58 prefixes.
+    Custom,
 }
 
+/// Parameters of [`Chain::Custom`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomChain {
+    pub magic: Magic,
+    pub bech32_hrp: Hrp,
+    pub first_rune_height: u32,
+    pub subsidy_halving_interval: u32,
+}
+
+impl CustomChain {
+    pub fn subsidy(&self, height: u32) -> u64 {
+        subsidy(self.subsidy_halving_interval, height)
+    }
+
+    pub fn starting_sat(&self, height: u32) -> u64 {
+        starting_sat(self.subsidy_halving_interval, height)
+    }
+
+    pub fn minimum_rune_at_height(&self, height: Height) -> Rune {
+        Rune::minimum_at_height_with_interval(self.first_rune_height, self.subsidy_halving_interval, height)
+    }
+
+    /// Parses an address, bech32 ones by the HRP of the chain.
+    pub fn parse_address(&self, address: &str) -> anyhow::Result<Address<NetworkUnchecked>> {
+        if let Ok((address_hrp, version, program)) = bitcoin::bech32::segwit::decode(address) {
+            if address_hrp != self.bech32_hrp {
+                bail!("address {} is not on the custom chain, its HRP is {}", address, self.bech32_hrp);
+            }
+            let program = WitnessProgram::new(WitnessVersion::try_from(version)?, PushBytesBuf::try_from(program)?)?;
+            return Ok(Address::new(Network::Signet, Payload::WitnessProgram(program)));
+        }
+        Ok(Address::from_str(address)?)
+    }
+
+    /// The string of an address, with the HRP of the chain.
+    pub fn format_address(&self, address: &Address) -> String {
+        AddressEncoding {
+            payload: address.payload(),
+            p2pkh_prefix: PUBKEY_ADDRESS_PREFIX_TEST,
+            p2sh_prefix: SCRIPT_ADDRESS_PREFIX_TEST,
+            hrp: self.bech32_hrp,
+        }.to_string()
+    }
+}
+
+static CUSTOM_CHAIN: OnceLock<CustomChain> = OnceLock::new();
+
 impl Chain {
+    /// Sets the parameters of [`Chain::Custom`], once per process.
+    pub fn set_custom(custom: CustomChain) -> anyhow::Result<()> {
+        if *CUSTOM_CHAIN.get_or_init(|| custom.clone()) != custom {
+            bail!("the custom chain is already set with other parameters");
+        }
+        Ok(())
+    }
+
+    fn custom() -> &'static CustomChain {
+        CUSTOM_CHAIN.get().expect("the custom chain parameters are not set")
+    }
+
+    /// Network used for address encoding. bitcoin 0.31 has no testnet4 variant,
+    /// testnet4 shares testnet3's address prefixes (`tb`, `m`/`n`, `2`) so it maps
+    /// to `Network::Testnet`. Don't use it for anything else that differs by chain,
+    /// e.g. rune heights or genesis block, go through the `Chain` methods instead.
     pub fn network(self) -> Network {
         self.into()
     }
 
+    pub fn magic(self) -> Magic {
+        match self {
+            Self::Testnet4 => Magic::from_bytes([0x1c, 0x16, 0x3f, 0x28]),
+            Self::Custom => Self::custom().magic,
+            _ => self.network().magic(),
+        }
+    }
+
     pub fn default_rpc_port(self) -> u16 {
         match self {
             Self::Mainnet => 8332,
             Self::Regtest => 18443,
-            Self::Signet => 38332,
+            Self::Signet | Self::Custom => 38332,
             Self::Testnet => 18332,
-            Self::Testnet4 => 28332,
+            Self::Testnet4 => 48332,
         }
     }
 
     pub fn inscription_content_size_limit(self) -> Option<usize> {
         match self {
             Self::Mainnet | Self::Regtest => None,
-            Self::Testnet | Self::Testnet4 | Self::Signet => Some(1024),
+            Self::Testnet | Self::Testnet4 | Self::Signet | Self::Custom => Some(1024),
         }
     }
 
@@ -48,12 +132,50 @@ impl Chain {
             Self::Regtest => 0,
             Self::Signet => 112402,
             Self::Testnet => 2413343,
-            Self::Testnet4 => 0,
+            Self::Testnet4 | Self::Custom => 0,
         }
     }
 
     pub fn first_rune_height(self) -> u32 {
-        Rune::first_rune_height(self.into())
+        match self {
+            Self::Testnet4 => 0,
+            Self::Custom => Self::custom().first_rune_height,
+            _ => Rune::first_rune_height(self.network()),
+        }
+    }
+
+    /// Blocks between subsidy halvings, which also set the pace the rune names
+    /// unlock at. Regtest uses mainnet's, like ord.
+    pub fn subsidy_halving_interval(self) -> u32 {
+        match self {
+            Self::Custom => Self::custom().subsidy_halving_interval,
+            _ => SUBSIDY_HALVING_INTERVAL,
+        }
+    }
+
+    pub fn subsidy(self, height: u32) -> u64 {
+        subsidy(self.subsidy_halving_interval(), height)
+    }
+
+    /// The first sat of the subsidy at `height`.
+    pub fn starting_sat(self, height: u32) -> u64 {
+        starting_sat(self.subsidy_halving_interval(), height)
+    }
+
+    /// Height indexing starts from on an empty database. Testnet runes unlock at
+    /// 2520000 but the first one was etched at 2583205, skip the empty blocks.
+    pub fn default_start_height(self) -> u32 {
+        match self {
+            Self::Testnet => 2583205,
+            _ => self.first_rune_height(),
+        }
+    }
+
+    pub fn minimum_rune_at_height(self, height: Height) -> Rune {
+        match self {
+            Self::Custom => Self::custom().minimum_rune_at_height(height),
+            _ => Rune::minimum_at_height_with_interval(self.first_rune_height(), self.subsidy_halving_interval(), height),
+        }
     }
 
     pub fn jubilee_height(self) -> u32 {
@@ -62,12 +184,15 @@ impl Chain {
             Self::Regtest => 110,
             Self::Signet => 175392,
             Self::Testnet => 2544192,
-            Self::Testnet4 => 0,
+            Self::Testnet4 | Self::Custom => 0,
         }
     }
 
     pub fn genesis_block(self) -> Block {
-        bitcoin::blockdata::constants::genesis_block(self.network())
+        match self {
+            Self::Testnet4 => testnet4_genesis_block(),
+            _ => genesis_block(self.network()),
+        }
     }
 
     pub fn genesis_coinbase_outpoint(self) -> OutPoint {
@@ -84,6 +209,23 @@ impl Chain {
         Address::from_script(script, self.network())
     }
 
+    /// Parses an address, bech32 ones of the custom chain by its HRP. Check
+    /// the network with `require_network(chain.network())`.
+    pub fn parse_address(self, address: &str) -> anyhow::Result<Address<NetworkUnchecked>> {
+        match self {
+            Self::Custom => Self::custom().parse_address(address),
+            _ => Ok(Address::from_str(address)?),
+        }
+    }
+
+    /// The string of an address, with the custom HRP on the custom chain.
+    pub fn format_address(self, address: &Address) -> String {
+        match self {
+            Self::Custom => Self::custom().format_address(address),
+            _ => address.to_string(),
+        }
+    }
+
     pub fn join_with_data_dir(self, data_dir: impl AsRef<Path>) -> PathBuf {
         match self {
             Self::Mainnet => data_dir.as_ref().to_owned(),
@@ -91,17 +233,71 @@ impl Chain {
             Self::Testnet4 => data_dir.as_ref().join("testnet4"),
             Self::Signet => data_dir.as_ref().join("signet"),
             Self::Regtest => data_dir.as_ref().join("regtest"),
+            Self::Custom => data_dir.as_ref().join("custom"),
         }
     }
 }
 
+fn subsidy(interval: u32, height: u32) -> u64 {
+    match height / interval {
+        halvings @ 0..=63 => (50 * COIN_VALUE) >> halvings,
+        _ => 0,
+    }
+}
+
+fn starting_sat(interval: u32, height: u32) -> u64 {
+    let epochs = (height / interval).min(64);
+    let epochs_sats = (0..epochs).map(|x| u64::from(interval) * subsidy(interval, x * interval)).sum::<u64>();
+    epochs_sats + u64::from(height % interval) * subsidy(interval, height)
+}
+
+const TESTNET4_GENESIS_MESSAGE: &[u8; 76] = b"03/May/2024 000000000000000000001ebd58c244970b3aa9d783bb001011fbe8ea8e98e00e";
+
+fn testnet4_genesis_block() -> Block {
+    let script_sig = script::Builder::new()
+        .push_int(486604799)
+        // CScriptNum(4), pushed as data instead of OP_4
+        .push_slice([4])
+        .push_slice(<&script::PushBytes>::try_from(TESTNET4_GENESIS_MESSAGE.as_slice()).unwrap())
+        .into_script();
+    let script_pubkey = script::Builder::new()
+        .push_slice([0; 33])
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .into_script();
+    let coinbase = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: locktime::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig,
+            sequence: Sequence::MAX,
+            witness: Witness::default(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(50 * 100_000_000),
+            script_pubkey,
+        }],
+    };
+    Block {
+        header: block::Header {
+            version: block::Version::ONE,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::from_raw_hash(coinbase.txid().to_raw_hash()),
+            time: 1714777860,
+            bits: CompactTarget::from_consensus(0x1d00ffff),
+            nonce: 393743547,
+        },
+        txdata: vec![coinbase],
+    }
+}
+
 impl From<Chain> for Network {
     fn from(chain: Chain) -> Network {
         match chain {
             Chain::Mainnet => Network::Bitcoin,
             Chain::Testnet => Network::Testnet,
             Chain::Testnet4 => Network::Testnet,
-            Chain::Signet => Network::Signet,
+            Chain::Signet | Chain::Custom => Network::Signet,
             Chain::Regtest => Network::Regtest,
         }
     }
@@ -118,6 +314,7 @@ impl Display for Chain {
                 Self::Signet => "signet",
                 Self::Testnet => "testnet",
                 Self::Testnet4 => "testnet4",
+                Self::Custom => "custom",
             }
         )
     }
@@ -133,6 +330,7 @@ impl FromStr for Chain {
             "signet" => Ok(Self::Signet),
             "testnet" | "test" => Ok(Self::Testnet),
             "testnet4" | "test4" => Ok(Self::Testnet4),
+            "custom" => Ok(Self::Custom),
             _ => bail!("invalid chain `{s}`"),
         }
     }
@@ -152,5 +350,59 @@ mod tests {
             "foo".parse::<Chain>().unwrap_err().to_string(),
             "invalid chain `foo`"
         );
+        assert_eq!("testnet4".parse::<Chain>().unwrap(), Chain::Testnet4);
+    }
+
+    #[test]
+    fn testnet4() {
+        let chain = Chain::Testnet4;
+        assert_eq!(
+            chain.genesis_block().block_hash().to_string(),
+            "00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043"
+        );
+        assert_eq!(chain.magic().to_bytes(), [0x1c, 0x16, 0x3f, 0x28]);
+        assert_ne!(chain.magic(), Chain::Testnet.magic());
+        assert_eq!(chain.first_rune_height(), 0);
+        assert_eq!(chain.minimum_rune_at_height(Height(0)).to_string(), "ZZYZXBRKWXVA");
+        assert_eq!(Chain::Testnet.first_rune_height(), 2520000);
+        assert_eq!(Chain::Testnet.default_start_height(), 2583205);
+
+        let script = bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros());
+        assert!(chain.address_from_script(&script).unwrap().to_string().starts_with("tb1"));
+    }
+
+    #[test]
+    fn custom() {
+        let custom = CustomChain {
+            magic: Magic::from_bytes([1, 2, 3, 4]),
+            bech32_hrp: Hrp::parse("sb").unwrap(),
+            first_rune_height: 100,
+            subsidy_halving_interval: 150,
+        };
+        assert_eq!("custom".parse::<Chain>().unwrap(), Chain::Custom);
+        assert_eq!(Chain::Custom.to_string(), "custom");
+        assert_eq!(custom.minimum_rune_at_height(Height(99)).to_string(), "AAAAAAAAAAAAA");
+        assert_eq!(custom.minimum_rune_at_height(Height(100)).to_string(), "XVRIRIRIRIRJ");
+        assert_eq!(custom.minimum_rune_at_height(Height(243)).to_string(), "A");
+        assert_eq!((custom.subsidy(149), custom.subsidy(150)), (50 * COIN_VALUE, 25 * COIN_VALUE));
+        assert_eq!(custom.starting_sat(151), 150 * 50 * COIN_VALUE + 25 * COIN_VALUE);
+        for height in [0, 1, 209999, 210000, 840000, 7_000_000] {
+            assert_eq!(Chain::Mainnet.subsidy(height), Height(height).subsidy());
+            assert_eq!(Chain::Mainnet.starting_sat(height), Height(height).starting_sat().n());
+        }
+
+        for script in [
+            bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros()),
+            bitcoin::ScriptBuf::new_p2pkh(&bitcoin::PubkeyHash::all_zeros()),
+        ] {
+            let address = Chain::Custom.address_from_script(&script).unwrap();
+            let formatted = custom.format_address(&address);
+            let parsed = custom.parse_address(&formatted).unwrap().require_network(Chain::Custom.network()).unwrap();
+            assert_eq!(parsed.script_pubkey(), script);
+        }
+        let address = Chain::Custom.address_from_script(&bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros())).unwrap();
+        assert!(custom.format_address(&address).starts_with("sb1"));
+        assert!(custom.parse_address(&address.to_string()).is_err());
+        assert!(Chain::Signet.parse_address(&custom.format_address(&address)).is_err());
     }
 }
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use bitcoin::{OutPoint, Txid};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+
+use ordinals::{Pile, Rune, RuneId, SpacedRune};
+
+use crate::db::RunesDB;
+use crate::entry::RuneEntry;
+
+pub const USAGE: &str = "Usage: ordx compare --ord-export <dir> [--height <height>]";
+
+/// Options of the `compare` subcommand. `ord_export` holds the JSON bodies of
+/// ord's own API at `height`: `runes.json`, the `entries` of every `/runes`
+/// page in one array, and `balances.json`, the `/runes/balances` response.
+#[derive(Debug, PartialEq)]
+pub struct CompareOptions {
+    pub ord_export: PathBuf,
+    /// the indexed height if none
+    pub height: Option<u32>,
+}
+
+impl CompareOptions {
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut ord_export = None;
+        let mut height = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+            if !matches!(flag, "--ord-export" | "--height") {
+                bail!("unknown argument `{arg}`\n{USAGE}");
+            }
+            let Some(value) = args.next() else {
+                bail!("{flag} requires a value\n{USAGE}");
+            };
+            match flag {
+                "--ord-export" => ord_export = Some(PathBuf::from(value)),
+                _ => height = Some(value.parse()?),
+            }
+        }
+        let Some(ord_export) = ord_export else {
+            bail!("--ord-export is required\n{USAGE}");
+        };
+        Ok(CompareOptions { ord_export, height })
+    }
+}
+
+/// An `ord` index dump, runes are keyed by name as ord doesn't list rune ids.
+#[derive(Debug, Default)]
+pub struct OrdExport {
+    pub runes: Vec<(SpacedRune, RuneEntry)>,
+    pub balances: BTreeMap<SpacedRune, BTreeMap<OutPoint, Pile>>,
+}
+
+impl OrdExport {
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        fn read<T: DeserializeOwned>(path: PathBuf) -> anyhow::Result<T> {
+            let file = File::open(&path).with_context(|| format!("failed to open {:?}", path))?;
+            serde_json::from_reader(BufReader::new(file)).with_context(|| format!("failed to parse {:?}", path))
+        }
+        Ok(OrdExport { runes: read(dir.join("runes.json"))?, balances: read(dir.join("balances.json"))? })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    EntryMissingInOrd { rune_id: RuneId, rune: SpacedRune },
+    EntryMissingInOrdx { rune: SpacedRune },
+    Entry { rune: SpacedRune, field: &'static str, ordx: String, ord: String },
+    BalanceMissingInOrd { outpoint: OutPoint, rune: SpacedRune, amount: u128 },
+    BalanceMissingInOrdx { outpoint: OutPoint, rune: SpacedRune, amount: u128 },
+    Balance { outpoint: OutPoint, rune: SpacedRune, ordx: u128, ord: u128 },
+}
+
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Discrepancy::EntryMissingInOrd { rune_id, rune } =>
+                write!(f, "rune {} {} missing in ord", rune_id, rune),
+            Discrepancy::EntryMissingInOrdx { rune } =>
+                write!(f, "rune {} missing in ordx", rune),
+            Discrepancy::Entry { rune, field, ordx, ord } =>
+                write!(f, "rune {} {} differs, ordx: {}, ord: {}", rune, field, ordx, ord),
+            Discrepancy::BalanceMissingInOrd { outpoint, rune, amount } =>
+                write!(f, "balance {} {} {} missing in ord", outpoint, rune, amount),
+            Discrepancy::BalanceMissingInOrdx { outpoint, rune, amount } =>
+                write!(f, "balance {} {} {} missing in ordx", outpoint, rune, amount),
+            Discrepancy::Balance { outpoint, rune, ordx, ord } =>
+                write!(f, "balance {} {} differs, ordx: {}, ord: {}", outpoint, rune, ordx, ord),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CompareReport {
+    pub height: u32,
+    pub entries: usize,
+    pub balances: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+pub fn run(runes_db: &RunesDB, options: &CompareOptions) -> anyhow::Result<CompareReport> {
+    let Some(indexed_height) = runes_db.latest_indexed_height() else {
+        bail!("nothing is indexed yet");
+    };
+    let height = options.height.unwrap_or(indexed_height);
+    if height > indexed_height {
+        bail!("height {} is above the indexed height {}", height, indexed_height);
+    }
+    info!("Loading the ord export {:?} ...", options.ord_export);
+    let ord = OrdExport::load(&options.ord_export)?;
+    info!("Comparing {} runes and {} balances at height {} ...", ord.runes.len(), ord.balances.values().map(BTreeMap::len).sum::<usize>(), height);
+
+    let entries = runes_db.rune_entries_at_height(height)?;
+    let runes: BTreeMap<String, SpacedRune> = entries.iter().map(|(id, entry)| (id.to_string(), entry.spaced_rune)).collect();
+    let mut balances = Vec::new();
+    runes_db.sqlite_rune_balance_for_each_unspent_at_height(height, |balance| {
+        let Some(rune) = runes.get(&balance.rune_id) else {
+            bail!("balance {}:{} of unknown rune {}", balance.txid, balance.vout, balance.rune_id);
+        };
+        let outpoint = OutPoint::new(Txid::from_str(&balance.txid)?, balance.vout);
+        balances.push((outpoint, *rune, u128::from_str(&balance.rune_amount)?));
+        Ok(())
+    })?;
+
+    let mut report = CompareReport { height, entries: entries.len(), balances: balances.len(), ..Default::default() };
+    compare_entries(&entries, &ord.runes, &mut report.discrepancies);
+    compare_balances(&balances, &ord.balances, &mut report.discrepancies);
+    for discrepancy in &report.discrepancies {
+        warn!("{}", discrepancy);
+    }
+    info!("Compare done at height {}, runes: {}, balances: {}, discrepancies: {}", height, report.entries, report.balances, report.discrepancies.len());
+    Ok(report)
+}
+
+fn compare_entries(ordx: &[(RuneId, RuneEntry)], ord: &[(SpacedRune, RuneEntry)], discrepancies: &mut Vec<Discrepancy>) {
+    let mut ord: BTreeMap<Rune, RuneEntry> = ord.iter().map(|(rune, entry)| (rune.rune, *entry)).collect();
+    for (rune_id, entry) in ordx {
+        let rune = entry.spaced_rune;
+        let Some(other) = ord.remove(&rune.rune) else {
+            discrepancies.push(Discrepancy::EntryMissingInOrd { rune_id: *rune_id, rune });
+            continue;
+        };
+        let fields = [
+            ("block", entry.block.to_string(), other.block.to_string()),
+            ("number", entry.number.to_string(), other.number.to_string()),
+            ("etching", entry.etching.to_string(), other.etching.to_string()),
+            ("spacers", rune.spacers.to_string(), other.spaced_rune.spacers.to_string()),
+            ("divisibility", entry.divisibility.to_string(), other.divisibility.to_string()),
+            ("symbol", format!("{:?}", entry.symbol), format!("{:?}", other.symbol)),
+            ("premine", entry.premine.to_string(), other.premine.to_string()),
+            ("terms", format!("{:?}", entry.terms), format!("{:?}", other.terms)),
+            ("turbo", entry.turbo.to_string(), other.turbo.to_string()),
+            ("mints", entry.mints.to_string(), other.mints.to_string()),
+            ("burned", entry.burned.to_string(), other.burned.to_string()),
+        ];
+        for (field, ordx, ord) in fields {
+            if ordx != ord {
+                discrepancies.push(Discrepancy::Entry { rune, field, ordx, ord });
+            }
+        }
+    }
+    discrepancies.extend(ord.into_values().map(|entry| Discrepancy::EntryMissingInOrdx { rune: entry.spaced_rune }));
+}
+
+fn compare_balances(ordx: &[(OutPoint, SpacedRune, u128)], ord: &BTreeMap<SpacedRune, BTreeMap<OutPoint, Pile>>, discrepancies: &mut Vec<Discrepancy>) {
+    let mut ord: BTreeMap<(OutPoint, Rune), (SpacedRune, u128)> = ord
+        .iter()
+        .flat_map(|(rune, piles)| piles.iter().map(|(outpoint, pile)| ((*outpoint, rune.rune), (*rune, pile.amount))))
+        .collect();
+    for (outpoint, rune, amount) in ordx {
+        match ord.remove(&(*outpoint, rune.rune)) {
+            None => discrepancies.push(Discrepancy::BalanceMissingInOrd { outpoint: *outpoint, rune: *rune, amount: *amount }),
+            Some((_, other)) if other != *amount => discrepancies.push(Discrepancy::Balance { outpoint: *outpoint, rune: *rune, ordx: *amount, ord: other }),
+            Some(_) => {}
+        }
+    }
+    discrepancies.extend(ord.into_iter().map(|((outpoint, _), (rune, amount))| Discrepancy::BalanceMissingInOrdx { outpoint, rune, amount }));
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn args(x: &str) -> Vec<String> {
+        x.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(
+            CompareOptions::parse(&args("--ord-export /tmp/ord --height 840000")).unwrap(),
+            CompareOptions { ord_export: PathBuf::from("/tmp/ord"), height: Some(840000) }
+        );
+        assert!(CompareOptions::parse(&[]).is_err());
+        assert!(CompareOptions::parse(&args("--ord-export")).is_err());
+        assert!(CompareOptions::parse(&args("--ord-export /tmp/ord --repair")).is_err());
+    }
+
+    #[test]
+    fn entries_and_balances_against_an_ord_export() {
+        let rune = |name: &str| SpacedRune::from_str(name).unwrap();
+        let entry = |name: &str, mints: u128| RuneEntry {
+            block: 840000,
+            burned: 0,
+            divisibility: 0,
+            etching: Txid::all_zeros(),
+            mints,
+            number: 0,
+            premine: 0,
+            spaced_rune: rune(name),
+            symbol: None,
+            terms: None,
+            timestamp: 0,
+            turbo: false,
+        };
+        let outpoint = |vout| OutPoint::new(Txid::all_zeros(), vout);
+        let pile = |amount| Pile { amount, divisibility: 0, symbol: None };
+
+        let path = TempDir::new("compare");
+        std::fs::create_dir_all(&path).unwrap();
+        let runes = vec![(rune("A•A"), entry("A•A", 2)), (rune("C"), entry("C", 0))];
+        std::fs::write(path.join("runes.json"), serde_json::to_vec(&runes).unwrap()).unwrap();
+        let balances = BTreeMap::from([
+            (rune("A•A"), BTreeMap::from([(outpoint(0), pile(10)), (outpoint(1), pile(5))])),
+            (rune("C"), BTreeMap::from([(outpoint(2), pile(1))])),
+        ]);
+        std::fs::write(path.join("balances.json"), serde_json::to_vec(&balances).unwrap()).unwrap();
+        let ord = OrdExport::load(&path).unwrap();
+
+        let mut discrepancies = vec![];
+        let ordx = [(RuneId { block: 840000, tx: 1 }, entry("AA", 3)), (RuneId { block: 840000, tx: 2 }, entry("B", 0))];
+        compare_entries(&ordx, &ord.runes, &mut discrepancies);
+        assert_eq!(discrepancies, vec![
+            Discrepancy::Entry { rune: rune("AA"), field: "spacers", ordx: "0".into(), ord: "1".into() },
+            Discrepancy::Entry { rune: rune("AA"), field: "mints", ordx: "3".into(), ord: "2".into() },
+            Discrepancy::EntryMissingInOrd { rune_id: RuneId { block: 840000, tx: 2 }, rune: rune("B") },
+            Discrepancy::EntryMissingInOrdx { rune: rune("C") },
+        ]);
+
+        discrepancies.clear();
+        compare_balances(&[(outpoint(0), rune("AA"), 10), (outpoint(1), rune("AA"), 6), (outpoint(3), rune("B"), 1)], &ord.balances, &mut discrepancies);
+        assert_eq!(discrepancies, vec![
+            Discrepancy::Balance { outpoint: outpoint(1), rune: rune("AA"), ordx: 6, ord: 5 },
+            Discrepancy::BalanceMissingInOrd { outpoint: outpoint(3), rune: rune("B"), amount: 1 },
+            Discrepancy::BalanceMissingInOrdx { outpoint: outpoint(2), rune: rune("C"), amount: 1 },
+        ]);
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use anyhow::bail;
+
+use crate::updater::REORG_DEPTH;
+
+/// Requests from the admin API to the indexing loop in `main`.
+#[derive(Debug)]
+pub struct IndexerControl {
+    first_rune_height: u32,
+    // false on API replicas, which have no indexing loop to take requests
+    indexing: bool,
+    // first height to index again, 0 when nothing is pending
+    reindex_from: AtomicU32,
+}
+
+impl IndexerControl {
+    pub fn new(first_rune_height: u32) -> Self {
+        IndexerControl {
+            first_rune_height,
+            indexing: true,
+            reindex_from: AtomicU32::new(0),
+        }
+    }
+
+    /// For processes that only serve the API, every request is refused.
+    pub fn read_only() -> Self {
+        IndexerControl {
+            first_rune_height: 0,
+            indexing: false,
+            reindex_from: AtomicU32::new(0),
+        }
+    }
+
+    /// Asks the indexer to roll back to `height` and index from there. Only
+    /// heights within the reorg window can be rolled back.
+    pub fn request_reindex(&self, height: u32, indexed_height: u32) -> anyhow::Result<()> {
+        if !self.indexing {
+            bail!("this instance does not index, send the request to the indexer");
+        }
+        if height < self.first_rune_height {
+            bail!("height {} is below the first rune height {}", height, self.first_rune_height);
+        }
+        if height > indexed_height {
+            bail!("height {} is above the indexed height {}", height, indexed_height);
+        }
+        if indexed_height - height >= REORG_DEPTH {
+            bail!("height {} is more than {} blocks below the indexed height {}, a full reindex is required", height, REORG_DEPTH, indexed_height);
+        }
+        self.reindex_from.store(height, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// The pending reindex height, cleared once taken.
+    pub fn take_reindex(&self) -> Option<u32> {
+        let height = self.reindex_from.swap(0, Ordering::SeqCst);
+        (height != 0).then_some(height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reindex_within_reorg_window() {
+        let control = IndexerControl::new(100);
+        assert!(control.request_reindex(99, 105).is_err());
+        assert!(control.request_reindex(106, 105).is_err());
+        assert!(control.request_reindex(200 - REORG_DEPTH, 200).is_err());
+        assert_eq!(control.take_reindex(), None);
+
+        control.request_reindex(103, 105).unwrap();
+        assert_eq!(control.take_reindex(), Some(103));
+        assert_eq!(control.take_reindex(), None);
+
+        assert!(IndexerControl::read_only().request_reindex(103, 105).is_err());
+    }
+}
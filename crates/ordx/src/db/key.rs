@@ -0,0 +1,340 @@
+use anyhow::bail;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{OutPoint, Script};
+
+use ordinals::{Rune, RuneId};
+
+use crate::entry::{Entry, EntryBytes, Statistic};
+
+/// RocksDB key codec. Integers are big endian so the byte order of the encoded
+/// keys follows the numeric order, height prefixed keys can be scanned and
+/// range deleted by height.
+pub trait Key: Sized {
+    const LEN: usize;
+
+    fn encode(&self) -> Vec<u8>;
+
+    fn decode_unchecked(bytes: &[u8]) -> Self;
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != Self::LEN {
+            bail!("Invalid key length: {}, expected: {}", bytes.len(), Self::LEN);
+        }
+        Ok(Self::decode_unchecked(bytes))
+    }
+}
+
+/// HEIGHT_TO_BLOCK_HEADER: height
+impl Key for u32 {
+    const LEN: usize = 4;
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        u32::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+/// RUNE_ID_TO_RUNE_ENTRY, RUNE_ID_TO_MINTS, RUNE_ID_TO_BURNED: block (8) + tx (4)
+impl Key for RuneId {
+    const LEN: usize = 12;
+
+    fn encode(&self) -> Vec<u8> {
+        self.store_bytes()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        RuneId::load_bytes(bytes)
+    }
+}
+
+/// RUNE_TO_RUNE_ID: rune (16)
+impl Key for Rune {
+    const LEN: usize = 16;
+
+    fn encode(&self) -> Vec<u8> {
+        self.store_bytes()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        Rune::load_bytes(bytes)
+    }
+}
+
+/// OUTPOINT_TO_RUNE_BALANCES: consensus encoded outpoint, txid (32) + vout (4)
+impl Key for OutPoint {
+    const LEN: usize = 36;
+
+    fn encode(&self) -> Vec<u8> {
+        self.store().to_vec()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        OutPoint::load(bytes.try_into().unwrap())
+    }
+}
+
+/// STATISTIC_TO_VALUE: statistic (1)
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct StatisticKey(pub u8);
+
+impl From<Statistic> for StatisticKey {
+    fn from(statistic: Statistic) -> Self {
+        StatisticKey(statistic.key())
+    }
+}
+
+impl Key for StatisticKey {
+    const LEN: usize = 1;
+
+    fn encode(&self) -> Vec<u8> {
+        vec![self.0]
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        StatisticKey(bytes[0])
+    }
+}
+
+/// HEIGHT_TO_STATISTIC_COUNT: statistic (1) + height (4)
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct StatisticHeightKey {
+    pub statistic: u8,
+    pub height: u32,
+}
+
+impl StatisticHeightKey {
+    pub fn new(statistic: &Statistic, height: u32) -> Self {
+        StatisticHeightKey { statistic: statistic.key(), height }
+    }
+}
+
+impl Key for StatisticHeightKey {
+    const LEN: usize = 5;
+
+    fn encode(&self) -> Vec<u8> {
+        [&[self.statistic][..], &self.height.encode()].concat()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        StatisticHeightKey {
+            statistic: bytes[0],
+            height: u32::decode_unchecked(&bytes[1..5]),
+        }
+    }
+}
+
+/// HEIGHT_RUNE_ID_TO_MINTS, HEIGHT_RUNE_ID_TO_BURNED: height (4) + rune id (12)
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct HeightRuneIdKey {
+    pub height: u32,
+    pub rune_id: RuneId,
+}
+
+impl Key for HeightRuneIdKey {
+    const LEN: usize = 16;
+
+    fn encode(&self) -> Vec<u8> {
+        [self.height.encode(), self.rune_id.encode()].concat()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        HeightRuneIdKey {
+            height: u32::decode_unchecked(&bytes[0..4]),
+            rune_id: RuneId::decode_unchecked(&bytes[4..16]),
+        }
+    }
+}
+
+/// HEIGHT_OUTPOINT_TO_RUNE_IDS, HEIGHT_OUTPOINT_TO_INSCRIPTIONS: height (4) + outpoint (36)
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct HeightOutPointKey {
+    pub height: u32,
+    pub outpoint: OutPoint,
+}
+
+impl Key for HeightOutPointKey {
+    const LEN: usize = 40;
+
+    fn encode(&self) -> Vec<u8> {
+        [self.height.encode(), self.outpoint.encode()].concat()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        HeightOutPointKey {
+            height: u32::decode_unchecked(&bytes[0..4]),
+            outpoint: OutPoint::decode_unchecked(&bytes[4..40]),
+        }
+    }
+}
+
+/// sha256 of a script pubkey, the fixed length prefix outputs are indexed by.
+pub fn script_hash(script: &Script) -> [u8; 32] {
+    sha256::Hash::hash(script.as_bytes()).to_byte_array()
+}
+
+/// SPK_TO_OUTPOINTS: script hash (32) + outpoint (36)
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct ScriptHashOutPointKey {
+    pub script_hash: [u8; 32],
+    pub outpoint: OutPoint,
+}
+
+impl Key for ScriptHashOutPointKey {
+    const LEN: usize = 68;
+
+    fn encode(&self) -> Vec<u8> {
+        [&self.script_hash[..], &self.outpoint.encode()].concat()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        ScriptHashOutPointKey {
+            script_hash: bytes[0..32].try_into().unwrap(),
+            outpoint: OutPoint::decode_unchecked(&bytes[32..68]),
+        }
+    }
+}
+
+/// SCRIPT_HASH_RUNE_ID_TO_BALANCE: script hash (32) + rune id (12)
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct ScriptHashRuneIdKey {
+    pub script_hash: [u8; 32],
+    pub rune_id: RuneId,
+}
+
+impl Key for ScriptHashRuneIdKey {
+    const LEN: usize = 44;
+
+    fn encode(&self) -> Vec<u8> {
+        [&self.script_hash[..], &self.rune_id.encode()].concat()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        ScriptHashRuneIdKey {
+            script_hash: bytes[0..32].try_into().unwrap(),
+            rune_id: RuneId::decode_unchecked(&bytes[32..44]),
+        }
+    }
+}
+
+/// RUNE_ID_TO_OUTPOINTS: rune id (12) + outpoint (36)
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RuneIdOutPointKey {
+    pub rune_id: RuneId,
+    pub outpoint: OutPoint,
+}
+
+impl Key for RuneIdOutPointKey {
+    const LEN: usize = 48;
+
+    fn encode(&self) -> Vec<u8> {
+        [self.rune_id.encode(), self.outpoint.encode()].concat()
+    }
+
+    fn decode_unchecked(bytes: &[u8]) -> Self {
+        RuneIdOutPointKey {
+            rune_id: RuneId::decode_unchecked(&bytes[0..12]),
+            outpoint: OutPoint::decode_unchecked(&bytes[12..48]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::str::FromStr;
+
+    use bitcoin::Txid;
+
+    use super::*;
+
+    fn round_trip<K: Key + PartialEq + Debug>(key: K) {
+        let bytes = key.encode();
+        assert_eq!(bytes.len(), K::LEN);
+        assert_eq!(K::decode(&bytes).unwrap(), key);
+        assert!(K::decode(&bytes[1..]).is_err());
+    }
+
+    fn outpoint() -> OutPoint {
+        OutPoint {
+            txid: Txid::from_str("0d3d5b7f0c31e0d4a5cde2dc8f3f5dd6e8b1b27c4f7f8bfb2c6b4a0a6d7f2e1c").unwrap(),
+            vout: 3,
+        }
+    }
+
+    #[test]
+    fn height() {
+        round_trip(840_000u32);
+        assert_eq!(840_000u32.encode(), 840_000u32.to_be_bytes());
+    }
+
+    #[test]
+    fn rune_id() {
+        round_trip(RuneId { block: 840_000, tx: 7 });
+    }
+
+    #[test]
+    fn rune() {
+        round_trip(Rune(2055900680524219742));
+    }
+
+    #[test]
+    fn outpoint_key() {
+        round_trip(outpoint());
+    }
+
+    #[test]
+    fn statistic() {
+        round_trip(StatisticKey::from(Statistic::LatestHeight));
+    }
+
+    #[test]
+    fn statistic_height() {
+        let key = StatisticHeightKey::new(&Statistic::Runes, 840_000);
+        round_trip(key);
+        assert_eq!(key.encode()[0], Statistic::Runes.key());
+    }
+
+    #[test]
+    fn height_rune_id() {
+        let key = HeightRuneIdKey { height: 840_001, rune_id: RuneId { block: 840_000, tx: 7 } };
+        round_trip(key);
+        // height comes first, the rune block must not be read as the height
+        assert_eq!(&key.encode()[0..4], &840_001u32.to_be_bytes());
+    }
+
+    #[test]
+    fn height_outpoint() {
+        round_trip(HeightOutPointKey { height: 840_001, outpoint: outpoint() });
+    }
+
+    #[test]
+    fn height_order() {
+        let low = HeightRuneIdKey { height: 255, rune_id: RuneId { block: u64::MAX, tx: u32::MAX } };
+        let high = HeightRuneIdKey { height: 256, rune_id: RuneId { block: 0, tx: 0 } };
+        assert!(low.encode() < high.encode());
+    }
+
+    #[test]
+    fn rune_id_outpoint() {
+        round_trip(RuneIdOutPointKey { rune_id: RuneId { block: 840_000, tx: 7 }, outpoint: outpoint() });
+    }
+
+    #[test]
+    fn script_hash_outpoint() {
+        let script_hash = script_hash(&bitcoin::ScriptBuf::from_hex("51").unwrap());
+        let key = ScriptHashOutPointKey { script_hash, outpoint: outpoint() };
+        round_trip(key);
+        assert_eq!(&key.encode()[0..32], &script_hash);
+    }
+
+    #[test]
+    fn script_hash_rune_id() {
+        let script_hash = script_hash(&bitcoin::ScriptBuf::from_hex("51").unwrap());
+        let key = ScriptHashRuneIdKey { script_hash, rune_id: RuneId { block: 840_000, tx: 7 } };
+        round_trip(key);
+        assert_eq!(&key.encode()[0..32], &script_hash);
+    }
+}
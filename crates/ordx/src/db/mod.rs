@@ -1,39 +1,222 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::path::Path;
-use std::time::Instant;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use anyhow::{bail, Context};
 use bitcoin::block::Header;
-use bitcoin::OutPoint;
-use log::info;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::{BlockHash, OutPoint, Script, ScriptBuf, Txid};
+use tracing::{info, instrument, trace, warn};
 use r2d2::{CustomizeConnection, Pool};
 use r2d2_sqlite::SqliteConnectionManager;
-use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Error, IteratorMode, Options, WriteBatch, DB};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Direction, Error, FlushOptions, IteratorMode, Options, SliceTransform, WriteBatch, DB};
+use rusqlite::backup::{Backup, StepResult};
 use rusqlite::types::ToSqlOutput;
-use rusqlite::{params, params_from_iter, Connection, Row, ToSql};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, ToSql};
 
 use ordinals::{Rune, RuneId};
 
-use crate::db::model::{RuneBalanceForInsert, RuneBalanceForQuery, RuneBalanceForTemp, RuneBalanceForUpdate, RuneEntryCompatPageParams, RuneEntryForQueryInsert, RuneEntryForTemp, RuneEntryForUpdate};
-use crate::entry::{Entry, EntryBytes, RuneBalanceEntry, RuneEntry, Statistic};
-use crate::updater::REORG_DEPTH;
+use crate::chain::Chain;
+use crate::db::key::{script_hash, HeightOutPointKey, HeightRuneIdKey, Key, RuneIdOutPointKey, ScriptHashOutPointKey, ScriptHashRuneIdKey, StatisticHeightKey, StatisticKey};
+use crate::db::model::{AddressTransferForQuery, CenotaphForQueryInsert, ApiKeyForInsert, BlockStats, ColumnFamilyStats, ApiKeyForQuery, EtchingsPerDay, InscriptionForInsert, InscriptionForQuery, InscriptionTransferForInsert, NetworkStats, RuneActivityForQuery, RuneBalanceForInsert, RuneEventForQuery, RuneBalanceForQuery, RuneBalanceForTemp, RuneBalanceForUpdate, RuneEntryCompatPageParams, RuneEntryForQueryInsert, RuneEntryForTemp, RuneEntryFilter, RuneEntryPageKey, RuneEntryPageQuery, RuneEntrySort, RuneEntryForUpdate, ReorgPlan, WatchForInsert, WatchForQuery, WebhookDeliveryForInsert, WebhookDeliveryForQuery, WebhookForInsert, WebhookForQuery};
+use crate::entry::{Entry, EntryBytes, RuneBalanceEntry, RuneEntry, SatRange, Statistic};
+use crate::inscription::InscriptionId;
+use crate::protocol::{Protocol, PROTOCOLS};
+use crate::updater::{RuneUpdater, REORG_DEPTH};
+use crate::webhook::{deliveries, transfer_events_in, RuneEvent};
 
+pub mod key;
 pub mod model;
+pub mod writer;
+
+/// Spent balance rows pruned per block, the history of a database that turns
+/// pruning on goes over the following blocks.
+const PRUNE_SPENT_ROWS: usize = 100_000;
+
+/// Pool size and the tunable pragmas of the sqlite connections, the fixed
+/// pragmas are in `sql/pragma.sql`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SqliteOptions {
+    pub pool_max_size: u32,
+    pub pool_min_idle: u32,
+    pub busy_timeout_ms: u64,
+    pub journal_mode: String,
+    pub synchronous: String,
+    pub mmap_size: u64,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        SqliteOptions {
+            pool_max_size: 100,
+            pool_min_idle: 1,
+            busy_timeout_ms: 5000,
+            journal_mode: "WAL".to_string(),
+            synchronous: "NORMAL".to_string(),
+            mmap_size: 512 * 1024 * 1024,
+        }
+    }
+}
+
+impl SqliteOptions {
+    const JOURNAL_MODES: [&'static str; 6] = ["DELETE", "TRUNCATE", "PERSIST", "MEMORY", "WAL", "OFF"];
+    const SYNCHRONOUS: [&'static str; 4] = ["OFF", "NORMAL", "FULL", "EXTRA"];
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.pool_max_size == 0 {
+            bail!("sqlite pool max size must be at least 1");
+        }
+        if self.pool_min_idle > self.pool_max_size {
+            bail!("sqlite pool min idle {} exceeds the max size {}", self.pool_min_idle, self.pool_max_size);
+        }
+        if !Self::JOURNAL_MODES.contains(&self.journal_mode.to_uppercase().as_str()) {
+            bail!("unknown sqlite journal mode `{}`, expected one of {:?}", self.journal_mode, Self::JOURNAL_MODES);
+        }
+        if !Self::SYNCHRONOUS.contains(&self.synchronous.to_uppercase().as_str()) {
+            bail!("unknown sqlite synchronous level `{}`, expected one of {:?}", self.synchronous, Self::SYNCHRONOUS);
+        }
+        Ok(())
+    }
+
+    fn pragmas(&self) -> String {
+        format!(
+            "{}\nPRAGMA busy_timeout = {};\nPRAGMA journal_mode = {};\nPRAGMA synchronous = {};\nPRAGMA mmap_size = {};",
+            include_str!("../../sql/pragma.sql"),
+            self.busy_timeout_ms,
+            self.journal_mode.to_uppercase(),
+            self.synchronous.to_uppercase(),
+            self.mmap_size,
+        )
+    }
+}
+
+/// Memory, compaction and compression settings of the rocksdb column families.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RocksdbOptions {
+    /// Shared by every column family, 0 keeps rocksdb's cache per column family.
+    pub block_cache_size: usize,
+    pub write_buffer_size: usize,
+    pub max_background_jobs: i32,
+    pub compression: DBCompressionType,
+    /// Overrides `compression` by column family name.
+    pub cf_compression: HashMap<String, DBCompressionType>,
+    /// 0 lets rocksdb size the WAL from the write buffers.
+    pub max_total_wal_size: u64,
+}
+
+impl Default for RocksdbOptions {
+    fn default() -> Self {
+        RocksdbOptions {
+            block_cache_size: 0,
+            write_buffer_size: 64 * 1024 * 1024,
+            max_background_jobs: 2,
+            compression: DBCompressionType::Snappy,
+            cf_compression: HashMap::new(),
+            max_total_wal_size: 0,
+        }
+    }
+}
+
+impl RocksdbOptions {
+    pub fn parse_compression(name: &str) -> anyhow::Result<DBCompressionType> {
+        Ok(match name.trim().to_lowercase().as_str() {
+            "none" => DBCompressionType::None,
+            "snappy" => DBCompressionType::Snappy,
+            "zlib" => DBCompressionType::Zlib,
+            "bz2" => DBCompressionType::Bz2,
+            "lz4" => DBCompressionType::Lz4,
+            "lz4hc" => DBCompressionType::Lz4hc,
+            "zstd" => DBCompressionType::Zstd,
+            _ => bail!("unknown rocksdb compression `{}`, expected none, snappy, zlib, bz2, lz4, lz4hc or zstd", name),
+        })
+    }
+
+    /// `CF=compression` pairs, comma separated.
+    pub fn parse_cf_compression(value: &str) -> anyhow::Result<HashMap<String, DBCompressionType>> {
+        let mut compression = HashMap::new();
+        for pair in value.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let Some((cf_name, name)) = pair.split_once('=') else {
+                bail!("expected `CF=compression`, got `{}`", pair);
+            };
+            let cf_name = cf_name.trim();
+            if !COLUMN_FAMILIES.contains(&cf_name) {
+                bail!("unknown column family `{}`", cf_name);
+            }
+            compression.insert(cf_name.to_string(), Self::parse_compression(name)?);
+        }
+        Ok(compression)
+    }
+
+    fn db_options(&self) -> Options {
+        let mut db_opts = Options::default();
+        db_opts.set_max_background_jobs(self.max_background_jobs);
+        if self.max_total_wal_size > 0 {
+            db_opts.set_max_total_wal_size(self.max_total_wal_size);
+        }
+        db_opts
+    }
+
+    fn cf_options(&self, cf_name: &str, cache: Option<&Cache>) -> Options {
+        let mut cf_opts = Options::default();
+        cf_opts.set_compaction_style(rocksdb::DBCompactionStyle::Level);
+        cf_opts.set_compression_type(self.cf_compression.get(cf_name).copied().unwrap_or(self.compression));
+        cf_opts.set_write_buffer_size(self.write_buffer_size);
+        let mut table_opts = BlockBasedOptions::default();
+        // whole keys for gets, and prefixes for prefix scans where the CF has an extractor
+        table_opts.set_bloom_filter(BLOOM_BITS_PER_KEY, false);
+        if let Some(cache) = cache {
+            table_opts.set_block_cache(cache);
+        }
+        cf_opts.set_block_based_table_factory(&table_opts);
+        if let Some((_, len)) = PREFIX_LENGTHS.iter().find(|(name, _)| *name == cf_name) {
+            cf_opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(*len));
+            cf_opts.set_memtable_prefix_bloom_ratio(MEMTABLE_PREFIX_BLOOM_RATIO);
+        }
+        cf_opts
+    }
+
+    fn block_cache(&self) -> Option<Cache> {
+        (self.block_cache_size > 0).then(|| Cache::new_lru_cache(self.block_cache_size))
+    }
+
+    fn cf_descriptors<'a>(&self, cf_names: impl IntoIterator<Item = &'a str>) -> Vec<ColumnFamilyDescriptor> {
+        let cache = self.block_cache();
+        cf_names.into_iter()
+            .map(|name| ColumnFamilyDescriptor::new(name, self.cf_options(name, cache.as_ref())))
+            .collect()
+    }
+}
 
-#[derive(Copy, Clone, Debug)]
-struct Customizer;
+#[derive(Clone, Debug)]
+struct Customizer {
+    pragmas: String,
+}
 
 
 impl CustomizeConnection<Connection, rusqlite::Error> for Customizer {
     fn on_acquire(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
-        let ok = conn.execute_batch(include_str!("../../sql/pragma.sql")).is_ok();
-        info!("Acquired connection: {}", ok);
+        // only an empty database can switch without a VACUUM, and journal_mode
+        // writes its header
+        if conn.query_row("PRAGMA page_count", [], |row| row.get::<_, u32>(0)).is_ok_and(|x| x == 0) {
+            let _ = conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL");
+        }
+        let ok = conn.execute_batch(&self.pragmas).is_ok();
+        trace!("Acquired connection: {}", ok);
         Ok(())
     }
 }
 
 type SqlitePool = Pool<SqliteConnectionManager>;
 
+/// Uncommitted writes of the block being indexed, cf name -> key -> value, `None` is a delete.
+type PendingWrites = HashMap<String, HashMap<Vec<u8>, Option<Vec<u8>>>>;
+
+/// A minting transaction, (txid, height, minter address).
+type RuneMint = (String, u32, String);
+
 pub struct RunesDB {
     pub rocksdb: DB,
     pub sqlite: SqlitePool,
@@ -48,59 +231,183 @@ pub const RUNE_TO_RUNE_ID: &str = "RUNE_TO_RUNE_ID";
 
 pub const HEIGHT_OUTPOINT_TO_RUNE_IDS: &str = "HEIGHT_OUTPOINT_TO_RUNE_IDS";
 
-pub const RUNE_ID_HEIGHT_TO_MINTS: &str = "RUNE_ID_HEIGHT_TO_MINTS";
-pub const RUNE_ID_HEIGHT_TO_BURNED: &str = "RUNE_ID_HEIGHT_TO_BURNED";
+pub const HEIGHT_RUNE_ID_TO_MINTS: &str = "HEIGHT_RUNE_ID_TO_MINTS";
+pub const HEIGHT_RUNE_ID_TO_BURNED: &str = "HEIGHT_RUNE_ID_TO_BURNED";
+
+// keyed by rune id first until the height first CFs above replaced them
+const LEGACY_HEIGHT_KEYED_CFS: [(&str, &str); 2] = [
+    ("RUNE_ID_HEIGHT_TO_MINTS", HEIGHT_RUNE_ID_TO_MINTS),
+    ("RUNE_ID_HEIGHT_TO_BURNED", HEIGHT_RUNE_ID_TO_BURNED),
+];
 
 pub const RUNE_ID_TO_MINTS: &str = "RUNE_ID_TO_MINTS";
 pub const RUNE_ID_TO_BURNED: &str = "RUNE_ID_TO_BURNED";
 
+// unspent outputs with runes by script, (height, value) values
+pub const SPK_TO_OUTPOINTS: &str = "SPK_TO_OUTPOINTS";
+// script hash and value of every output with runes, spends only know the outpoint
+pub const OUTPOINT_TO_SCRIPT_HASH: &str = "OUTPOINT_TO_SCRIPT_HASH";
+// unspent outputs by rune, amount values
+pub const RUNE_ID_TO_OUTPOINTS: &str = "RUNE_ID_TO_OUTPOINTS";
+// rolling hash of the rune state after each block, see RunesDB::state_hash
+pub const HEIGHT_TO_STATE_HASH: &str = "HEIGHT_TO_STATE_HASH";
+// rune balances of scripts over their unspent outputs, amount (16) + outputs (4) values
+pub const SCRIPT_HASH_RUNE_ID_TO_BALANCE: &str = "SCRIPT_HASH_RUNE_ID_TO_BALANCE";
+// inscriptions on unspent outputs, inscription id (36) + sat offset (8) values
+pub const OUTPOINT_TO_INSCRIPTIONS: &str = "OUTPOINT_TO_INSCRIPTIONS";
+// the OUTPOINT_TO_INSCRIPTIONS value before the first change at a height, empty
+// without one, kept for the reorg window
+pub const HEIGHT_OUTPOINT_TO_INSCRIPTIONS: &str = "HEIGHT_OUTPOINT_TO_INSCRIPTIONS";
+// sat ranges of unspent outputs, 11 byte SatRange entries, and their undo log
+// like the one of the inscriptions
+pub const OUTPOINT_TO_SAT_RANGES: &str = "OUTPOINT_TO_SAT_RANGES";
+pub const HEIGHT_OUTPOINT_TO_SAT_RANGES: &str = "HEIGHT_OUTPOINT_TO_SAT_RANGES";
+// protocol CFs, see crate::protocol, with height (4) + tag (16) + key keyed
+// undo records of every protocol, [0] without a prior value or [1] + the value
+pub const PROTOCOL_ALKANES: &str = "PROTOCOL_ALKANES";
+pub const HEIGHT_PROTOCOL_KEY_UNDO: &str = "HEIGHT_PROTOCOL_KEY_UNDO";
+// API responses of the persistent cache methods by sha256 of their cache key,
+// height (4) + state hash (32) of the indexed state they are about + the json
+pub const RESPONSE_CACHE: &str = "RESPONSE_CACHE";
+
+/// Outpoint keyed CFs and their undo logs.
+const OUTPOINT_UNDO_CFS: [(&str, &str); 2] = [
+    (OUTPOINT_TO_INSCRIPTIONS, HEIGHT_OUTPOINT_TO_INSCRIPTIONS),
+    (OUTPOINT_TO_SAT_RANGES, HEIGHT_OUTPOINT_TO_SAT_RANGES),
+];
+
+/// The CFs whose changes the state hash commits to, in hashing order.
+const STATE_HASH_CFS: [&str; 2] = [RUNE_ID_TO_RUNE_ENTRY, OUTPOINT_TO_RUNE_BALANCES];
+
+pub const COLUMN_FAMILIES: [&str; 23] = [
+    HEIGHT_TO_BLOCK_HEADER,
+    HEIGHT_TO_STATISTIC_COUNT,
+    STATISTIC_TO_VALUE,
+    OUTPOINT_TO_RUNE_BALANCES,
+    RUNE_ID_TO_RUNE_ENTRY,
+    RUNE_TO_RUNE_ID,
+    HEIGHT_RUNE_ID_TO_MINTS,
+    HEIGHT_RUNE_ID_TO_BURNED,
+    RUNE_ID_TO_MINTS,
+    RUNE_ID_TO_BURNED,
+    HEIGHT_OUTPOINT_TO_RUNE_IDS,
+    SPK_TO_OUTPOINTS,
+    OUTPOINT_TO_SCRIPT_HASH,
+    RUNE_ID_TO_OUTPOINTS,
+    HEIGHT_TO_STATE_HASH,
+    SCRIPT_HASH_RUNE_ID_TO_BALANCE,
+    OUTPOINT_TO_INSCRIPTIONS,
+    HEIGHT_OUTPOINT_TO_INSCRIPTIONS,
+    PROTOCOL_ALKANES,
+    HEIGHT_PROTOCOL_KEY_UNDO,
+    OUTPOINT_TO_SAT_RANGES,
+    HEIGHT_OUTPOINT_TO_SAT_RANGES,
+    RESPONSE_CACHE,
+];
+
+/// Fixed key prefixes the CFs are scanned by with `prefix_iterator_cf`, the
+/// statistic key (1 byte), the height (4 bytes), the rune id (12 bytes) or the
+/// script hash (32 bytes).
+/// Seeks in these CFs skip the sst files whose bloom filter lacks the prefix, so
+/// scans that run past one prefix have to use `full_iterator_cf`.
+const PREFIX_LENGTHS: [(&str, usize); 10] = [
+    (HEIGHT_TO_STATISTIC_COUNT, 1),
+    (HEIGHT_RUNE_ID_TO_MINTS, 4),
+    (HEIGHT_RUNE_ID_TO_BURNED, 4),
+    (HEIGHT_OUTPOINT_TO_RUNE_IDS, 4),
+    (HEIGHT_OUTPOINT_TO_INSCRIPTIONS, 4),
+    (HEIGHT_PROTOCOL_KEY_UNDO, 4),
+    (HEIGHT_OUTPOINT_TO_SAT_RANGES, 4),
+    (SPK_TO_OUTPOINTS, 32),
+    (RUNE_ID_TO_OUTPOINTS, 12),
+    (SCRIPT_HASH_RUNE_ID_TO_BALANCE, 32),
+];
+const BLOOM_BITS_PER_KEY: f64 = 10.0;
+/// Share of the write buffer used for the prefix bloom of the memtable.
+const MEMTABLE_PREFIX_BLOOM_RATIO: f64 = 0.1;
+
 
 impl RunesDB {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
-        let mut db_opts = Options::default();
+        Self::open(path, &SqliteOptions::default(), &RocksdbOptions::default())
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, sqlite: &SqliteOptions, rocksdb_options: &RocksdbOptions) -> Self {
+        let mut db_opts = rocksdb_options.db_options();
         db_opts.create_if_missing(true);
         db_opts.create_missing_column_families(true);
         db_opts.set_compaction_style(rocksdb::DBCompactionStyle::Level);
-        db_opts.set_compression_type(rocksdb::DBCompressionType::Snappy);
-
-        let cf_names = [
-            HEIGHT_TO_BLOCK_HEADER,
-            HEIGHT_TO_STATISTIC_COUNT,
-            STATISTIC_TO_VALUE,
-            OUTPOINT_TO_RUNE_BALANCES,
-            RUNE_ID_TO_RUNE_ENTRY,
-            RUNE_TO_RUNE_ID,
-            RUNE_ID_HEIGHT_TO_MINTS,
-            RUNE_ID_HEIGHT_TO_BURNED,
-            RUNE_ID_TO_MINTS,
-            RUNE_ID_TO_BURNED,
-            HEIGHT_OUTPOINT_TO_RUNE_IDS,
-        ];
-        let cf_descriptors: Vec<_> = cf_names.iter()
-            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()))
-            .collect();
+        db_opts.set_compression_type(rocksdb_options.compression);
 
         let rocksdb_path = path.as_ref().join("rocksdb");
-        info!("Using rocksdb at {:?}", &rocksdb_path);
+        let existing_cfs = DB::list_cf(&db_opts, &rocksdb_path).unwrap_or_default();
+        let legacy_cfs = LEGACY_HEIGHT_KEYED_CFS.iter()
+            .map(|(legacy, _)| *legacy)
+            .filter(|legacy| existing_cfs.iter().any(|x| x == legacy));
+        let cf_descriptors = rocksdb_options.cf_descriptors(COLUMN_FAMILIES.into_iter().chain(legacy_cfs));
+
+        info!("Using rocksdb at {:?}, {:?}", &rocksdb_path, rocksdb_options);
         let open_rocksdb = Instant::now();
-        let rocksdb = DB::open_cf_descriptors(&db_opts, rocksdb_path, cf_descriptors).unwrap();
+        let mut rocksdb = DB::open_cf_descriptors(&db_opts, rocksdb_path, cf_descriptors).unwrap();
+        let sqlite = Self::sqlite_pool(path.as_ref(), sqlite);
+        migrate_legacy_height_keyed_cfs(&mut rocksdb, &sqlite).unwrap();
         info!("Rocksdb opened, {:?}", open_rocksdb.elapsed());
 
-        let sqlite_path = path.as_ref().join("sqlite.db");
-        info!("Using sqlite at {:?}", &sqlite_path);
+        RunesDB { rocksdb, sqlite }
+    }
+
+    /// Opens the rocksdb under `path` as a read-only secondary of the process
+    /// indexing it, keeping the secondary's own files in `secondary_path`.
+    /// Writes fail; [`RunesDB::catch_up_with_primary`] picks up new blocks.
+    pub fn new_secondary<P: AsRef<Path>>(path: P, secondary_path: P, sqlite: &SqliteOptions, rocksdb_options: &RocksdbOptions) -> Self {
+        let mut db_opts = rocksdb_options.db_options();
+        // a secondary has to keep every file of the primary open
+        db_opts.set_max_open_files(-1);
+
+        let rocksdb_path = path.as_ref().join("rocksdb");
+        info!("Using rocksdb at {:?} as secondary in {:?}", &rocksdb_path, secondary_path.as_ref());
+        let rocksdb = DB::open_cf_descriptors_as_secondary(&db_opts, &rocksdb_path, &secondary_path.as_ref().to_path_buf(), rocksdb_options.cf_descriptors(COLUMN_FAMILIES)).unwrap();
+        RunesDB { rocksdb, sqlite: Self::sqlite_pool(path.as_ref(), sqlite) }
+    }
+
+    fn sqlite_pool(path: &Path, options: &SqliteOptions) -> SqlitePool {
+        let sqlite_path = path.join("sqlite.db");
+        info!("Using sqlite at {:?}, {:?}", &sqlite_path, options);
         let manager = SqliteConnectionManager::file(sqlite_path);
-        let sqlite = Pool::builder()
-            .min_idle(Some(1))
-            .max_size(100)
-            .connection_customizer(Box::new(Customizer))
+        Pool::builder()
+            .min_idle(Some(options.pool_min_idle))
+            .max_size(options.pool_max_size)
+            .connection_customizer(Box::new(Customizer { pragmas: options.pragmas() }))
             .build(manager)
-            .unwrap();
-        RunesDB { rocksdb, sqlite }
+            .unwrap()
+    }
+
+    pub fn catch_up_with_primary(&self) -> anyhow::Result<()> {
+        Ok(self.rocksdb.try_catch_up_with_primary()?)
     }
 
     pub fn init_sqlite(&self) -> anyhow::Result<()> {
         let conn = self.sqlite.get()?;
+        let has_fts: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'rune_entry_fts')", [], |row| row.get(0))?;
         conn.execute_batch(include_str!("../../sql/init.sql"))?;
+        let has_etcher: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM pragma_table_info('rune_entry') WHERE name = 'etcher')", [], |row| row.get(0))?;
+        if !has_etcher {
+            // entries indexed before the column existed keep a null etcher
+            conn.execute("ALTER TABLE rune_entry ADD COLUMN etcher TEXT", [])?;
+        }
+        let has_commit_txid: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM pragma_table_info('rune_entry') WHERE name = 'commit_txid')", [], |row| row.get(0))?;
+        if !has_commit_txid {
+            conn.execute("ALTER TABLE rune_entry ADD COLUMN commit_txid TEXT", [])?;
+        }
+        let has_watch_api_key_id: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM pragma_table_info('watch') WHERE name = 'api_key_id')", [], |row| row.get(0))?;
+        if !has_watch_api_key_id {
+            // watches created before the column existed are only listed to admin keys
+            conn.execute("ALTER TABLE watch ADD COLUMN api_key_id INTEGER", [])?;
+        }
+        if !has_fts {
+            // databases created before the search index existed have entries the triggers never saw
+            conn.execute("INSERT INTO rune_entry_fts (rune_entry_fts) VALUES ('rebuild')", [])?;
+        }
         Ok(())
     }
 
@@ -124,6 +431,11 @@ impl RunesDB {
         self.rocksdb.get_cf(cf, key)
     }
 
+    pub fn multi_get(&self, cf_name: &str, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let cf = self.get_cf(cf_name);
+        self.rocksdb.multi_get_cf(keys.iter().map(|key| (cf, key))).into_iter().collect()
+    }
+
     pub fn del(&self, cf_name: &str, key: &[u8]) -> Result<(), Error> {
         let cf = self.get_cf(cf_name);
         self.rocksdb.delete_cf(cf, key)
@@ -135,7 +447,7 @@ impl RunesDB {
 
     pub fn list(&self, cf_name: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
         let cf = self.get_cf(cf_name);
-        self.rocksdb.iterator_cf(cf, IteratorMode::Start)
+        self.rocksdb.full_iterator_cf(cf, IteratorMode::Start)
             .map(|r| {
                 let (k, v) = r.unwrap();
                 (k.to_vec(), v.to_vec())
@@ -143,270 +455,444 @@ impl RunesDB {
             .collect()
     }
 
+    #[instrument(skip_all, fields(ops = batch.len()))]
     pub fn write_batch(&self, batch: WriteBatch) -> Result<(), Error> {
         self.rocksdb.write(batch)
     }
 
+    /// A writer for the next block, see [`BlockWriter`].
+    pub fn block_writer(&self) -> BlockWriter<'_> {
+        BlockWriter { db: self, pending: Mutex::new(HashMap::new()) }
+    }
+
+    /// sha256 of the state hash of the block below, or zeros without one, and
+    /// of the rune entries and outpoint balances the block wrote, in key order
+    /// with deletes marked. Two instances indexing the same blocks from the
+    /// same height agree on every hash, the first one that differs is the
+    /// first diverging block.
+    fn state_hash(&self, height: u32, pending: &PendingWrites) -> [u8; 32] {
+        let mut engine = sha256::Hash::engine();
+        let prev = height.checked_sub(1).and_then(|x| self.height_to_state_hash_get(x)).unwrap_or_default();
+        engine.input(&prev);
+        for cf_name in STATE_HASH_CFS {
+            let Some(writes) = pending.get(cf_name) else {
+                continue;
+            };
+            for (key, value) in writes.iter().collect::<BTreeMap<_, _>>() {
+                engine.input(&(key.len() as u32).to_be_bytes());
+                engine.input(key);
+                match value {
+                    Some(value) => {
+                        engine.input(&[1]);
+                        engine.input(&(value.len() as u32).to_be_bytes());
+                        engine.input(value);
+                    }
+                    None => engine.input(&[0]),
+                }
+            }
+        }
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
 
-    // specific methods
-    pub fn height_outpoint_to_rune_ids_batch_put_and_del(&self, height: u32, outpoints: &HashMap<OutPoint, HashSet<RuneId>>) {
+    /// The latest indexed height and its state hash, response cache entries of
+    /// any other state are stale.
+    fn response_cache_tag(&self) -> Option<Vec<u8>> {
+        let height = self.latest_indexed_height()?;
+        let mut tag = height.encode();
+        tag.extend(self.height_to_state_hash_get(height)?);
+        Some(tag)
+    }
+
+    pub fn response_cache_get(&self, key: &[u8; 32]) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(tag) = self.response_cache_tag() else {
+            return Ok(None);
+        };
+        let value = self.rocksdb.get_cf(self.get_cf(RESPONSE_CACHE), key)?;
+        Ok(value.and_then(|x| x.strip_prefix(tag.as_slice()).map(<[u8]>::to_vec)))
+    }
+
+    pub fn response_cache_put(&self, key: &[u8; 32], value: &[u8]) -> anyhow::Result<()> {
+        let Some(mut tag) = self.response_cache_tag() else {
+            return Ok(());
+        };
+        tag.extend(value);
+        Ok(self.rocksdb.put_cf(self.get_cf(RESPONSE_CACHE), key, tag)?)
+    }
+
+    pub fn response_cache_clear(&self) -> anyhow::Result<()> {
         let mut batch = WriteBatch::default();
+        // keys are 32 bytes, the longer end is past all of them
+        batch.delete_range_cf(self.get_cf(RESPONSE_CACHE), &[0u8; 32][..], &[u8::MAX; 33][..]);
+        Ok(self.write_batch(batch)?)
+    }
+
+    // specific methods
+    pub fn height_outpoint_to_rune_ids_put_and_del_with_batch(&self, batch: &mut WriteBatch, height: u32, outpoints: &HashMap<OutPoint, HashSet<RuneId>>) {
         let cf = self.get_cf(HEIGHT_OUTPOINT_TO_RUNE_IDS);
-        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::Start);
+        let iter = self.rocksdb.full_iterator_cf(cf, IteratorMode::Start);
         let mut deleted = 0;
         for x in iter {
             let (k, _) = x.unwrap();
-            let h = u32::from_be_bytes([k[0], k[1], k[2], k[3]]) as i64;
+            let h = HeightOutPointKey::decode(&k).unwrap().height as i64;
             if (height as i64) - h < (REORG_DEPTH as i64) {
                 break;
             }
             batch.delete_cf(cf, &k);
             deleted += 1;
         }
-        if outpoints.is_empty() {
-            if deleted > 0 {
-                info!("<= HEIGHT_OUTPOINT_TO_RUNE_IDS, inserted: {}, deleted: {}", outpoints.len(), deleted);
-                self.rocksdb.write(batch).unwrap();
-            }
-            return;
-        }
         for (outpoint, value) in outpoints {
-            let mut key = height.to_be_bytes().to_vec();
-            key.extend_from_slice(&outpoint.store());
+            let key = HeightOutPointKey { height, outpoint: *outpoint }.encode();
             batch.put_cf(cf, &key, value.iter().map(|x| x.store_bytes()).collect::<Vec<_>>().concat().as_slice());
         }
-        self.rocksdb.write(batch).unwrap();
-        info!("<= HEIGHT_OUTPOINT_TO_RUNE_IDS, inserted: {}, deleted: {}", outpoints.len(), deleted);
-    }
-
-    pub fn statistic_to_value_put(&self, statistic: &Statistic, value: u32) {
-        self.put(STATISTIC_TO_VALUE, &[statistic.key()], &value.to_be_bytes()).unwrap()
+        if !outpoints.is_empty() || deleted > 0 {
+            info!(inserted = outpoints.len(), deleted, "<= HEIGHT_OUTPOINT_TO_RUNE_IDS");
+        }
     }
 
     pub fn statistic_to_value_put_with_batch(&self, wtx: &mut WriteBatch, statistic: &Statistic, value: u32) {
-        wtx.put_cf(self.get_cf(STATISTIC_TO_VALUE), [statistic.key()], value.to_be_bytes())
+        wtx.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(*statistic).encode(), value.to_be_bytes())
     }
 
-    pub fn statistic_to_value_get(&self, statistic: &Statistic) -> Option<u32> {
-        self.get(STATISTIC_TO_VALUE, &[statistic.key()])
-            .map(|opt| opt.map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
-    }
-
-    pub fn statistic_to_value_inc(&self, statistic: &Statistic) {
-        let current = self.statistic_to_value_get(statistic).unwrap_or_default() + 1;
-        self.put(STATISTIC_TO_VALUE, &[statistic.key()], &current.to_be_bytes()).unwrap()
-    }
-
-    pub fn rune_id_to_mints_put(&self, key: &RuneId, value: u128) {
-        self.put(RUNE_ID_TO_MINTS, &key.store_bytes(), &value.to_be_bytes()).unwrap()
-    }
-
-    pub fn rune_id_to_mints_get(&self, key: &RuneId) -> Option<u128> {
-        self.get(RUNE_ID_TO_MINTS, &key.store_bytes())
-            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
-    }
-
-    pub fn rune_id_to_mints_inc(&self, key: &RuneId) -> u128 {
-        let current = self.rune_id_to_mints_get(key).unwrap_or_default() + 1;
-        self.put(RUNE_ID_TO_MINTS, &key.store_bytes(), &current.to_be_bytes()).unwrap();
-        current
-    }
-
-    pub fn rune_id_to_burned_put(&self, key: &RuneId, value: u128) {
-        self.put(RUNE_ID_TO_BURNED, &key.store_bytes(), &value.to_be_bytes()).unwrap()
-    }
-
-    pub fn rune_id_to_burned_get(&self, key: &RuneId) -> Option<u128> {
-        self.get(RUNE_ID_TO_BURNED, &key.store_bytes())
-            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    pub fn network_stats(&self) -> NetworkStats {
+        NetworkStats {
+            mints: self.statistic_to_total_get(&Statistic::Mints).unwrap_or_default(),
+            burned: self.statistic_to_total_get(&Statistic::Burned).unwrap_or_default(),
+            transactions: self.statistic_to_value_get(&Statistic::RuneTransactions).unwrap_or_default(),
+            holders: self.statistic_to_value_get(&Statistic::Holders).unwrap_or_default(),
+        }
     }
 
-    pub fn rune_id_to_burned_inc(&self, key: &RuneId) -> u128 {
-        let current = self.rune_id_to_burned_get(key).unwrap_or_default() + 1;
-        self.put(RUNE_ID_TO_BURNED, &key.store_bytes(), &current.to_be_bytes()).unwrap();
-        current
+    /// Computes the network totals of a database indexed before they were
+    /// maintained, a no-op once they exist.
+    pub fn init_network_stats(&self) -> anyhow::Result<()> {
+        if self.statistic_to_value_get(&Statistic::Holders).is_some() || self.latest_indexed_height().is_none() {
+            return Ok(());
+        }
+        let t = Instant::now();
+        let sum = |cf_name: &str| self.list(cf_name).iter().map(|(_, v)| u128::from_be_bytes(v.as_slice().try_into().unwrap())).sum::<u128>();
+        let mints = sum(RUNE_ID_TO_MINTS);
+        let burned = sum(RUNE_ID_TO_BURNED);
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        let transactions: u32 = conn.query_row("SELECT COUNT(*) FROM (SELECT txid FROM rune_balance UNION SELECT spent_txid FROM rune_balance WHERE spent_height > 0)", [], |row| row.get(0))?;
+        let holders = self.sqlite_rune_entry_holders_sum()?;
+        self.statistic_to_total_put(&Statistic::Mints, mints);
+        self.statistic_to_total_put(&Statistic::Burned, burned);
+        self.statistic_to_value_put(&Statistic::RuneTransactions, transactions);
+        self.statistic_to_value_put(&Statistic::Holders, holders);
+        info!(mints, burned, transactions, holders, elapsed = ?t.elapsed(), "Network stats initialized");
+        Ok(())
     }
 
-
-    pub fn rune_id_height_to_mints_put(&self, rune_id: &RuneId, height: u32, value: u128) {
-        let mut combined_key = rune_id.store_bytes();
-        combined_key.extend_from_slice(&height.to_be_bytes());
-        self.put(RUNE_ID_HEIGHT_TO_MINTS, &combined_key, &value.to_be_bytes()).unwrap()
+    /// Marks the first block with holders and transactions deltas, the blocks
+    /// after the current tip for a database indexed before they were kept.
+    pub fn init_counter_undo(&self) {
+        if self.statistic_to_value_get(&Statistic::CounterUndoHeight).is_some() {
+            return;
+        }
+        let height = self.latest_indexed_height().map_or(0, |x| x + 1);
+        self.statistic_to_value_put(&Statistic::CounterUndoHeight, height);
     }
 
-    pub fn rune_id_height_to_mints_get(&self, rune_id: &RuneId, height: u32) -> Option<u128> {
-        let mut combined_key = rune_id.store_bytes();
-        combined_key.extend_from_slice(&height.to_be_bytes());
-        self.get(RUNE_ID_HEIGHT_TO_MINTS, &combined_key)
-            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    /// Builds SPK_TO_OUTPOINTS and OUTPOINT_TO_SCRIPT_HASH from the sqlite rows of
+    /// a database indexed before they existed. Outputs spent within the reorg
+    /// window get their script hash too, so a reorg can give them back.
+    pub fn init_script_hash_index(&self, chain: Chain) -> anyhow::Result<()> {
+        if self.statistic_to_value_get(&Statistic::ScriptHashIndex).is_some() {
+            return Ok(());
+        }
+        if let Some(latest_height) = self.latest_indexed_height() {
+            let t = Instant::now();
+            let conn = self.sqlite.get()?;
+            let mut stmt = conn.prepare(
+                // language=sqlite
+                "SELECT DISTINCT txid, vout, value, address, height, spent_height FROM rune_balance WHERE spent_height = 0 OR spent_height + ? > ?"
+            )?;
+            let mut rows = stmt.query(params![REORG_DEPTH, latest_height])?;
+            let mut batch = WriteBatch::default();
+            let mut indexed = 0;
+            while let Some(row) = rows.next()? {
+                let outpoint = OutPoint { txid: row.get::<_, String>(0)?.parse::<Txid>()?, vout: row.get(1)? };
+                let address: String = row.get(3)?;
+                // the address column holds the script hex when the script has no address
+                let script = match chain.parse_address(&address) {
+                    Ok(address) => address.require_network(chain.network())?.script_pubkey(),
+                    Err(_) => ScriptBuf::from_hex(&address)?,
+                };
+                let spent_height: u32 = row.get(5)?;
+                self.script_hash_put_with_batch(&mut batch, &script, &outpoint, row.get(4)?, row.get(2)?, spent_height == 0);
+                indexed += 1;
+                if batch.len() >= 100_000 {
+                    self.rocksdb.write(std::mem::take(&mut batch))?;
+                }
+            }
+            self.rocksdb.write(batch)?;
+            info!("Indexed {} outputs by script, {:?}", indexed, t.elapsed());
+        }
+        self.statistic_to_value_put(&Statistic::ScriptHashIndex, 1);
+        Ok(())
     }
 
-    pub fn rune_id_height_to_mints_inc(&self, rune_id: &RuneId, height: u32) {
-        let mut combined_key = rune_id.store_bytes();
-        combined_key.extend_from_slice(&height.to_be_bytes());
-        let current = self.rune_id_height_to_mints_get(rune_id, height).unwrap_or_default() + 1;
-        self.put(RUNE_ID_HEIGHT_TO_MINTS, &combined_key, &current.to_be_bytes()).unwrap()
+    fn script_hash_put_with_batch(&self, batch: &mut WriteBatch, script: &Script, outpoint: &OutPoint, height: u32, value: u64, unspent: bool) {
+        let script_hash = script_hash(script);
+        batch.put_cf(self.get_cf(OUTPOINT_TO_SCRIPT_HASH), outpoint.encode(), [&script_hash[..], &value.to_be_bytes()].concat());
+        if unspent {
+            let key = ScriptHashOutPointKey { script_hash, outpoint: *outpoint };
+            batch.put_cf(self.get_cf(SPK_TO_OUTPOINTS), key.encode(), [&height.to_be_bytes()[..], &value.to_be_bytes()].concat());
+        }
     }
 
-    pub fn rune_id_to_mints_sum_to_height(&self, rune_id: &RuneId, to_height: u32) -> u128 {
-        let cf = self.get_cf(RUNE_ID_HEIGHT_TO_MINTS);
-        let prefix = rune_id.store_bytes();
-        let prefix_len = prefix.len();
-        let iter = self.rocksdb.prefix_iterator_cf(cf, &prefix);
-        let mut count = 0;
-        for x in iter {
-            let (k, v) = x.unwrap();
-
-            if prefix != k[0..prefix_len] {
+    /// Unspent outputs with runes of `script` as (outpoint, height, value), in outpoint order.
+    pub fn spk_to_outpoints_list(&self, script: &Script) -> anyhow::Result<Vec<(OutPoint, u32, u64)>> {
+        let script_hash = script_hash(script);
+        let mut outputs = vec![];
+        for x in self.rocksdb.prefix_iterator_cf(self.get_cf(SPK_TO_OUTPOINTS), script_hash) {
+            let (k, v) = x?;
+            let key = ScriptHashOutPointKey::decode(&k)?;
+            if key.script_hash != script_hash {
                 break;
             }
-
-            let height = u32::from_be_bytes([k[0], k[1], k[2], k[3]]);
-            if height <= to_height {
-                let v = u128::from_be_bytes([
-                    v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7],
-                    v[8], v[9], v[10], v[11], v[12], v[13], v[14], v[15],
-                ]);
-                count += v;
-            }
+            outputs.push((key.outpoint, u32::from_be_bytes(v[0..4].try_into()?), u64::from_be_bytes(v[4..12].try_into()?)));
         }
-        count
-    }
-
-    pub fn rune_id_height_to_burned_put(&self, rune_id: &RuneId, height: u32, value: u128) {
-        let mut combined_key = rune_id.store_bytes();
-        combined_key.extend_from_slice(&height.to_be_bytes());
-        self.put(RUNE_ID_HEIGHT_TO_BURNED, &combined_key, &value.to_be_bytes()).unwrap()
-    }
-
-    pub fn rune_id_height_to_burned_put_with_batch(&self, wtx: &mut WriteBatch, rune_id: &RuneId, height: u32, value: u128) {
-        let mut combined_key = rune_id.store_bytes();
-        combined_key.extend_from_slice(&height.to_be_bytes());
-        wtx.put_cf(self.get_cf(RUNE_ID_HEIGHT_TO_BURNED), &combined_key, value.to_be_bytes())
-    }
-
-    pub fn rune_id_height_to_burned_get(&self, rune_id: &RuneId, height: u32) -> Option<u128> {
-        let mut combined_key = rune_id.store_bytes();
-        combined_key.extend_from_slice(&height.to_be_bytes());
-        self.get(RUNE_ID_HEIGHT_TO_BURNED, &combined_key)
-            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+        Ok(outputs)
     }
 
-    pub fn rune_id_height_to_burned_sum_to_height(&self, rune_id: &RuneId, to_height: u32) -> u128 {
-        let cf = self.get_cf(RUNE_ID_HEIGHT_TO_BURNED);
-        let prefix = rune_id.store_bytes();
-        let prefix_len = prefix.len();
-        let iter = self.rocksdb.prefix_iterator_cf(cf, &prefix);
-        let mut count = 0;
-        for x in iter {
-            let (k, v) = x.unwrap();
-
-            if prefix != k[0..prefix_len] {
-                break;
+    /// Builds RUNE_ID_TO_OUTPOINTS from the unspent OUTPOINT_TO_RUNE_BALANCES
+    /// entries of a database indexed before it existed.
+    pub fn init_rune_outpoints_index(&self) -> anyhow::Result<()> {
+        if self.statistic_to_value_get(&Statistic::RuneOutPointsIndex).is_some() {
+            return Ok(());
+        }
+        let t = Instant::now();
+        let cf = self.get_cf(RUNE_ID_TO_OUTPOINTS);
+        let mut batch = WriteBatch::default();
+        let mut indexed = 0;
+        for x in self.rocksdb.iterator_cf(self.get_cf(OUTPOINT_TO_RUNE_BALANCES), IteratorMode::Start) {
+            let (k, v) = x?;
+            let (_, spent_height, buffer) = RuneBalanceEntry::load_bytes(&v);
+            if spent_height > 0 {
+                continue;
             }
-
-            let height = u32::from_be_bytes([k[0], k[1], k[2], k[3]]);
-            if height <= to_height {
-                let v = u128::from_be_bytes([
-                    v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7],
-                    v[8], v[9], v[10], v[11], v[12], v[13], v[14], v[15],
-                ]);
-                count += v;
+            let outpoint = OutPoint::decode(&k)?;
+            for (rune_id, amount) in RuneUpdater::decode_rune_balances(&buffer)? {
+                batch.put_cf(cf, RuneIdOutPointKey { rune_id, outpoint }.encode(), amount.to_be_bytes());
+                indexed += 1;
+            }
+            if batch.len() >= 100_000 {
+                self.rocksdb.write(std::mem::take(&mut batch))?;
             }
         }
-        count
+        self.statistic_to_value_put_with_batch(&mut batch, &Statistic::RuneOutPointsIndex, 1);
+        self.rocksdb.write(batch)?;
+        if indexed > 0 {
+            info!("Indexed {} rune balances by rune, {:?}", indexed, t.elapsed());
+        }
+        Ok(())
     }
 
-    pub fn outpoint_to_rune_balances_put(&self, key: &OutPoint, value: RuneBalanceEntry) {
-        self.put(OUTPOINT_TO_RUNE_BALANCES, &key.store(), &value.store_bytes()).unwrap()
+    /// Unspent outputs holding `rune_id` with their amounts, in outpoint order.
+    pub fn rune_id_to_outpoints_list(&self, rune_id: &RuneId) -> anyhow::Result<Vec<(OutPoint, u128)>> {
+        let mut outputs = vec![];
+        for x in self.rocksdb.prefix_iterator_cf(self.get_cf(RUNE_ID_TO_OUTPOINTS), rune_id.encode()) {
+            let (k, v) = x?;
+            let key = RuneIdOutPointKey::decode(&k)?;
+            if key.rune_id != *rune_id {
+                break;
+            }
+            outputs.push((key.outpoint, u128::from_be_bytes(v.as_ref().try_into()?)));
+        }
+        Ok(outputs)
     }
 
-    pub fn outpoint_to_rune_balances_get(&self, key: &OutPoint) -> Option<RuneBalanceEntry> {
-        self.get(OUTPOINT_TO_RUNE_BALANCES, &key.store())
-            .map(|opt| opt.map(|bytes| RuneBalanceEntry::load_bytes(&bytes))).unwrap()
+    /// Builds SCRIPT_HASH_RUNE_ID_TO_BALANCE from SPK_TO_OUTPOINTS and the
+    /// balances of these outputs, for a database indexed before it existed.
+    pub fn init_script_hash_balance_index(&self) -> anyhow::Result<()> {
+        if self.statistic_to_value_get(&Statistic::ScriptHashBalanceIndex).is_some() {
+            return Ok(());
+        }
+        let t = Instant::now();
+        let cf = self.get_cf(SCRIPT_HASH_RUNE_ID_TO_BALANCE);
+        let mut batch = WriteBatch::default();
+        let mut scripts = 0;
+        type ScriptBalances = ([u8; 32], BTreeMap<RuneId, (u128, u32)>);
+        // outputs are in script hash order, so a script is complete once the next one starts
+        let mut current: Option<ScriptBalances> = None;
+        let mut flush = |batch: &mut WriteBatch, current: Option<ScriptBalances>| {
+            let Some((script_hash, balances)) = current else {
+                return;
+            };
+            for (rune_id, balance) in balances {
+                batch.put_cf(cf, ScriptHashRuneIdKey { script_hash, rune_id }.encode(), encode_script_balance(balance));
+            }
+            scripts += 1;
+        };
+        for x in self.rocksdb.full_iterator_cf(self.get_cf(SPK_TO_OUTPOINTS), IteratorMode::Start) {
+            let (k, _) = x?;
+            let key = ScriptHashOutPointKey::decode(&k)?;
+            if current.as_ref().is_some_and(|(script_hash, _)| *script_hash != key.script_hash) {
+                flush(&mut batch, current.take());
+            }
+            let (_, balances) = current.get_or_insert_with(|| (key.script_hash, BTreeMap::new()));
+            let Some((_, _, buffer)) = self.outpoint_to_rune_balances_get(&key.outpoint) else {
+                continue;
+            };
+            for (rune_id, amount) in RuneUpdater::decode_rune_balances(&buffer)? {
+                let balance = balances.entry(rune_id).or_default();
+                balance.0 += amount;
+                balance.1 += 1;
+            }
+            if batch.len() >= 100_000 {
+                self.rocksdb.write(std::mem::take(&mut batch))?;
+            }
+        }
+        flush(&mut batch, current);
+        self.statistic_to_value_put_with_batch(&mut batch, &Statistic::ScriptHashBalanceIndex, 1);
+        self.rocksdb.write(batch)?;
+        if scripts > 0 {
+            info!("Indexed the rune balances of {} scripts, {:?}", scripts, t.elapsed());
+        }
+        Ok(())
     }
 
-
-    pub fn rune_id_to_rune_entry_put(&self, key: &RuneId, value: &RuneEntry) {
-        self.put(RUNE_ID_TO_RUNE_ENTRY, &key.store_bytes(), &value.store_bytes()).unwrap()
+    /// Rune balances over the unspent outputs of `script` as (rune id, amount, outputs), in rune id order.
+    pub fn script_hash_rune_id_to_balance_list(&self, script: &Script) -> anyhow::Result<Vec<(RuneId, u128, u32)>> {
+        let script_hash = script_hash(script);
+        let mut balances = vec![];
+        for x in self.rocksdb.prefix_iterator_cf(self.get_cf(SCRIPT_HASH_RUNE_ID_TO_BALANCE), script_hash) {
+            let (k, v) = x?;
+            let key = ScriptHashRuneIdKey::decode(&k)?;
+            if key.script_hash != script_hash {
+                break;
+            }
+            let (amount, outputs) = decode_script_balance(&v);
+            balances.push((key.rune_id, amount, outputs));
+        }
+        Ok(balances)
     }
 
-    pub fn rune_id_to_rune_entry_get(&self, key: &RuneId) -> Option<RuneEntry> {
-        self.get(RUNE_ID_TO_RUNE_ENTRY, &key.store_bytes())
-            .map(|opt| opt.map(|bytes| RuneEntry::load_bytes(&bytes))).unwrap()
-    }
-    pub fn rune_id_to_rune_entry_del(&self, key: &RuneId) {
-        self.del(RUNE_ID_TO_RUNE_ENTRY, &key.store_bytes()).unwrap()
+    /// Amount of `rune_id` in unspent outputs, its supply less what was burned.
+    pub fn rune_id_to_outpoints_supply(&self, rune_id: &RuneId) -> anyhow::Result<u128> {
+        Ok(self.rune_id_to_outpoints_list(rune_id)?.into_iter().map(|(_, amount)| amount).sum())
     }
 
-    pub fn rune_entry_paged(&self, cursor: usize, size: usize, keywords: Option<String>, sort: Option<String>) -> (bool, Vec<(RuneId, RuneEntry)>) {
-        let cf = self.get_cf(RUNE_ID_TO_RUNE_ENTRY);
-        let keywords = keywords.map(|x| x.to_uppercase());
-        let mode = match sort.as_deref() {
-            Some("asc") => IteratorMode::Start,
-            Some("desc") => IteratorMode::End,
-            _ => IteratorMode::Start,
-        };
-        let mut iter = self.rocksdb.iterator_cf(cf, mode);
-        let mut list = vec![];
-        let mut cursor = cursor;
-        while cursor > 0 {
-            if let Some(keywords) = &keywords {
-                if let Some(v) = iter.next() {
-                    let (k, v) = v.unwrap();
-                    let key = RuneId::load_bytes(&k);
-                    let value = RuneEntry::load_bytes(&v);
-                    if value.spaced_rune.rune.to_string().contains(keywords) || value.spaced_rune.to_string().contains(keywords) || key.to_string().contains(keywords) {
-                        cursor -= 1;
-                    }
-                } else {
-                    return (false, list);
-                }
-            } else {
-                if iter.next().is_none() {
-                    return (false, list);
-                }
-                cursor -= 1;
-            }
+    /// Flips the sqlite `mintable` flag of runes whose start or end height lies
+    /// between the tip it was last refreshed at and `latest_height`, returns the
+    /// changed rows. Mints and reorgs update the flag of the runes they touch.
+    pub fn refresh_mintable(&self, latest_height: u32) -> anyhow::Result<usize> {
+        let refreshed_height = self.statistic_to_value_get(&Statistic::MintableHeight).unwrap_or_default();
+        if refreshed_height == latest_height {
+            return Ok(0);
         }
-        while let Some(v) = iter.next() {
-            let (k, v) = v.unwrap();
-            let key = RuneId::load_bytes(&k);
-            let value = RuneEntry::load_bytes(&v);
-            if let Some(keywords) = &keywords {
-                if !value.spaced_rune.rune.to_string().contains(keywords) && !value.spaced_rune.to_string().contains(keywords) && !key.to_string().contains(keywords) {
+        let (from, to) = (refreshed_height.min(latest_height), refreshed_height.max(latest_height));
+        let mut conn = self.sqlite.get()?;
+        let tx = conn.transaction()?;
+        let rune_ids: Vec<String> = {
+            let mut stmt = tx.prepare_cached(
+                // language=sqlite
+                "SELECT rune_id FROM rune_entry WHERE fairmint = false AND (start_height > ?1 AND start_height <= ?2 OR height + start_offset > ?1 AND height + start_offset <= ?2 OR end_height > ?1 AND end_height <= ?2 OR height + end_offset > ?1 AND height + end_offset <= ?2)"
+            )?;
+            let rows = stmt.query_map(params![from, to], |row| row.get(0))?;
+            rows.map(|x| x.unwrap()).collect()
+        };
+        let mut changed = 0;
+        {
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET mintable = ?1 WHERE rune_id = ?2 AND mintable != ?1")?;
+            for rune_id in &rune_ids {
+                let Some(entry) = self.rune_id_to_rune_entry_get(&rune_id.parse()?) else {
                     continue;
-                }
-            }
-            list.push((key, value));
-            if list.len() >= size {
-                return (iter.next().is_some(), list);
+                };
+                let mintable = entry.mintable(latest_height as _).unwrap_or(0) > 0;
+                changed += stmt.execute(params![mintable, rune_id])?;
             }
         }
-        (false, list)
+        tx.commit()?;
+        // written through, the indexer may be buffering the next block already
+        let mut batch = WriteBatch::default();
+        self.statistic_to_value_put_with_batch(&mut batch, &Statistic::MintableHeight, latest_height);
+        self.write_batch(batch)?;
+        Ok(changed)
     }
 
-    pub fn rune_to_rune_id_put(&self, key: &Rune, value: &RuneId) {
-        self.put(RUNE_TO_RUNE_ID, &key.store_bytes(), &value.store_bytes()).unwrap()
-    }
+    /// Deletes the balances spent at `height - after_blocks` or earlier from sqlite
+    /// and their outpoints from rocksdb, returns the deleted rows.
+    pub fn prune_spent(&self, height: u32, after_blocks: u32) -> anyhow::Result<usize> {
+        let Some(spent_height) = height.checked_sub(after_blocks) else {
+            return Ok(0);
+        };
+        let mut conn = self.sqlite.get()?;
+        let tx = conn.transaction()?;
+        let rows: Vec<(i64, String, u32)> = {
+            let mut stmt = tx.prepare_cached(
+                // language=sqlite
+                "SELECT id, txid, vout FROM rune_balance WHERE spent_height > 0 AND spent_height <= ? LIMIT ?"
+            )?;
+            let rows = stmt.query_map(params![spent_height, PRUNE_SPENT_ROWS], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.map(|x| x.unwrap()).collect()
+        };
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        {
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("DELETE FROM rune_balance WHERE id = ?")?;
+            for (id, _, _) in &rows {
+                stmt.execute([id])?;
+            }
+        }
+        tx.commit()?;
 
-    pub fn rune_to_rune_id_del(&self, key: &Rune) {
-        self.del(RUNE_TO_RUNE_ID, &key.store_bytes()).unwrap()
+        // only the committed rows leave rocksdb, a failed delete just keeps
+        // spent outpoints around
+        let mut batch = WriteBatch::default();
+        for (_, txid, vout) in &rows {
+            let k = OutPoint { txid: txid.parse()?, vout: *vout }.encode();
+            batch.delete_cf(self.get_cf(OUTPOINT_TO_RUNE_BALANCES), &k);
+            batch.delete_cf(self.get_cf(OUTPOINT_TO_SCRIPT_HASH), &k);
+        }
+        self.write_batch(batch)?;
+        Ok(rows.len())
     }
 
-    pub fn rune_to_rune_id_get(&self, key: &Rune) -> Option<RuneId> {
-        self.get(RUNE_TO_RUNE_ID, &key.store_bytes())
-            .map(|opt| opt.map(|bytes| RuneId::load_bytes(&bytes))).unwrap()
+    /// Per rune sum of the values at `height` and above, what a reorg to `height` takes back.
+    pub fn height_rune_id_sum_from_height(&self, cf_name: &str, height: u32) -> anyhow::Result<HashMap<RuneId, u128>> {
+        let cf = self.get_cf(cf_name);
+        let mut sums = HashMap::new();
+        for x in self.rocksdb.full_iterator_cf(cf, IteratorMode::From(&height.encode(), Direction::Forward)) {
+            let (k, v) = x?;
+            let rune_id = HeightRuneIdKey::decode(&k)?.rune_id;
+            *sums.entry(rune_id).or_default() += u128::from_be_bytes(v.as_ref().try_into()?);
+        }
+        Ok(sums)
     }
 
-
-    pub fn height_to_block_header_put(&self, key: u32, value: &Header) {
-        self.put(HEIGHT_TO_BLOCK_HEADER, &key.to_be_bytes(), &value.store_bytes()).unwrap()
+    /// Per rune values recorded at exactly `height`, in rune id order.
+    pub fn height_rune_id_list_at_height(&self, cf_name: &str, height: u32) -> anyhow::Result<Vec<(RuneId, u128)>> {
+        let cf = self.get_cf(cf_name);
+        let mut values = vec![];
+        for x in self.rocksdb.prefix_iterator_cf(cf, height.encode()) {
+            let (k, v) = x?;
+            let key = HeightRuneIdKey::decode(&k)?;
+            if key.height != height {
+                break;
+            }
+            values.push((key.rune_id, u128::from_be_bytes(v.as_ref().try_into()?)));
+        }
+        Ok(values)
     }
 
-    pub fn height_to_block_header_get(&self, key: u32) -> Option<Header> {
-        self.get(HEIGHT_TO_BLOCK_HEADER, &key.to_be_bytes())
-            .map(|opt| opt.map(|bytes| Header::load_bytes(&bytes))).unwrap()
+    /// Committed entries of a protocol CF under `prefix`, in key order.
+    pub fn protocol_scan(&self, cf_name: &str, prefix: &[u8]) -> anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let iter = self.rocksdb.iterator_cf(self.get_cf(cf_name), IteratorMode::From(prefix, Direction::Forward));
+        let mut entries = Vec::new();
+        for x in iter {
+            let (k, v) = x?;
+            if !k.starts_with(prefix) {
+                break;
+            }
+            entries.push((k.to_vec(), v.to_vec()));
+        }
+        Ok(entries)
     }
 
     pub fn latest_indexed_height(&self) -> Option<u32> {
@@ -416,8 +902,7 @@ impl RunesDB {
             None => None,
             Some(v) => {
                 let k = v.unwrap().0;
-                let height = u32::from_be_bytes([k[0], k[1], k[2], k[3]]);
-                Some(height)
+                Some(u32::decode(&k).unwrap())
             }
         }
     }
@@ -426,41 +911,18 @@ impl RunesDB {
         self.statistic_to_value_get(&Statistic::LatestHeight)
     }
 
-    pub fn height_to_statistic_count_put(&self, statistic: &Statistic, height: u32, value: u32) {
-        let mut combined_key: [u8; 5] = [0; 5];
-        combined_key[0] = statistic.key();
-        combined_key[1..].copy_from_slice(&height.to_be_bytes());
-        self.put(HEIGHT_TO_STATISTIC_COUNT, &combined_key, &value.to_be_bytes()).unwrap()
-    }
-
-    pub fn height_to_statistic_count_inc(&self, statistic: &Statistic, height: u32) {
-        let mut combined_key: [u8; 5] = [0; 5];
-        combined_key[0] = statistic.key();
-        combined_key[1..].copy_from_slice(&height.to_be_bytes());
-        let current = self.height_to_statistic_count_get(statistic, height).unwrap_or_default() + 1;
-        self.put(HEIGHT_TO_STATISTIC_COUNT, &combined_key, &current.to_be_bytes()).unwrap()
-    }
-
-    pub fn height_to_statistic_count_get(&self, statistic: &Statistic, height: u32) -> Option<u32> {
-        let mut combined_key: [u8; 5] = [0; 5];
-        combined_key[0] = statistic.key();
-        combined_key[1..].copy_from_slice(&height.to_be_bytes());
-        self.get(HEIGHT_TO_STATISTIC_COUNT, &combined_key)
-            .map(|opt| opt.map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
-    }
-
     pub fn height_to_statistic_count_sum_to_height(&self, statistic: &Statistic, to_height: u32) -> u32 {
         let cf = self.get_cf(HEIGHT_TO_STATISTIC_COUNT);
         let prefix = statistic.key();
-        let iter = self.rocksdb.prefix_iterator_cf(cf, [prefix]);
+        let iter = self.rocksdb.prefix_iterator_cf(cf, StatisticKey::from(*statistic).encode());
         let mut count = 0;
         for x in iter {
             let (k, v) = x.unwrap();
-            if k[0] != prefix {
+            let key = StatisticHeightKey::decode(&k).unwrap();
+            if key.statistic != prefix {
                 break;
             }
-            let height = u32::from_be_bytes([k[1], k[2], k[3], k[4]]);
-            if height <= to_height {
+            if key.height <= to_height {
                 let v = u32::from_be_bytes([v[0], v[1], v[2], v[3]]);
                 count += v;
             }
@@ -468,95 +930,74 @@ impl RunesDB {
         count
     }
 
-    pub fn reorg_to_height(&self, height: u32, latest_height: u32) -> anyhow::Result<()> {
-        info!("Reorg to height: {}", height);
-
-        // Delete all data after height
-        info!("<= HEIGHT_TO_BLOCK_HEADER ...");
-        let cf = self.get_cf(HEIGHT_TO_BLOCK_HEADER);
-        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::End);
-        let mut batch = WriteBatch::default();
-        let mut deleted = 0;
-        for v in iter {
-            let (k, _) = v.unwrap();
-            let h = u32::from_be_bytes([k[0], k[1], k[2], k[3]]);
-            if h >= height {
-                batch.delete_cf(cf, &k);
-                deleted += 1;
-            } else {
+    pub fn height_to_statistic_count_sum_from_height(&self, statistic: &Statistic, from_height: u32) -> u32 {
+        let cf = self.get_cf(HEIGHT_TO_STATISTIC_COUNT);
+        let from = StatisticHeightKey::new(statistic, from_height).encode();
+        let mut count = 0;
+        for x in self.rocksdb.iterator_cf(cf, IteratorMode::From(&from, Direction::Forward)) {
+            let (k, v) = x.unwrap();
+            if StatisticHeightKey::decode(&k).unwrap().statistic != statistic.key() {
                 break;
             }
+            count += u32::from_be_bytes([v[0], v[1], v[2], v[3]]);
         }
-        info!("<= HEIGHT_TO_BLOCK_HEADER deleted: {}", deleted);
+        count
+    }
 
-        info!("<= HEIGHT_TO_STATISTIC_COUNT ...");
+    /// Highest height with a count of `statistic`.
+    pub fn height_to_statistic_count_latest_height(&self, statistic: &Statistic) -> anyhow::Result<Option<u32>> {
         let cf = self.get_cf(HEIGHT_TO_STATISTIC_COUNT);
-        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::End);
-        let mut deleted = 0;
-        for v in iter {
-            let (k, _) = v.unwrap();
-            let h = u32::from_be_bytes([k[1], k[2], k[3], k[4]]);
-            if h >= height {
-                batch.delete_cf(cf, &k);
-                deleted += 1;
-            } else {
-                break;
-            }
-        }
-        info!("<= HEIGHT_TO_STATISTIC_COUNT deleted: {}",  deleted);
+        let last = StatisticHeightKey::new(statistic, u32::MAX).encode();
+        let Some(x) = self.rocksdb.full_iterator_cf(cf, IteratorMode::From(&last, Direction::Reverse)).next() else {
+            return Ok(None);
+        };
+        let key = StatisticHeightKey::decode(&x?.0)?;
+        Ok((key.statistic == statistic.key()).then_some(key.height))
+    }
 
-        info!("<= RUNE_ID_HEIGHT_TO_MINTS ...");
-        let cf = self.get_cf(RUNE_ID_HEIGHT_TO_MINTS);
-        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::End);
-        let mut deleted = 0;
-        for v in iter {
-            let (k, _) = v.unwrap();
-            let h = u64::from_be_bytes(k[0..8].try_into().unwrap());
-            if h >= height as _ {
-                batch.delete_cf(cf, &k);
-                deleted += 1;
-            } else {
-                break;
-            }
-        }
-        info!("<= RUNE_ID_HEIGHT_TO_MINTS deleted: {}", deleted);
+    #[instrument(skip(self))]
+    pub fn reorg_to_height(&self, height: u32, latest_height: u32) -> anyhow::Result<()> {
+        info!("Reorg to height: {}", height);
 
-        info!("<= RUNE_ID_HEIGHT_TO_BURNED ...");
-        let cf = self.get_cf(RUNE_ID_HEIGHT_TO_BURNED);
-        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::End);
-        let mut deleted = 0;
-        for v in iter {
-            let (k, _) = v.unwrap();
-            let h = u64::from_be_bytes(k[0..8].try_into().unwrap());
-            if h >= height as _ {
-                batch.delete_cf(cf, &k);
-                deleted += 1;
-            } else {
-                break;
-            }
+        let from = height.encode();
+        // exclusive end of height prefixed ranges, no block reaches this height
+        let end = u32::MAX.encode();
+        let mut batch = WriteBatch::default();
+
+        // Delete all data after height, height prefixed CFs are dropped with range tombstones
+        batch.delete_range_cf(self.get_cf(HEIGHT_TO_BLOCK_HEADER), &from, &end);
+        batch.delete_range_cf(self.get_cf(HEIGHT_TO_STATE_HASH), &from, &end);
+        let removed_transactions = self.height_to_statistic_count_sum_from_height(&Statistic::RuneTransactions, height);
+        let removed_inscriptions = self.height_to_statistic_count_sum_from_height(&Statistic::Inscriptions, height);
+        let removed_sat_ranges = self.height_to_statistic_count_sum_from_height(&Statistic::SatRanges, height);
+        for statistic in [Statistic::Runes, Statistic::ReservedRunes, Statistic::RuneTransactions, Statistic::Inscriptions, Statistic::SatRanges] {
+            let start = StatisticHeightKey::new(&statistic, height).encode();
+            let end = StatisticHeightKey::new(&statistic, u32::MAX).encode();
+            batch.delete_range_cf(self.get_cf(HEIGHT_TO_STATISTIC_COUNT), start, end);
         }
-        info!("<= RUNE_ID_HEIGHT_TO_BURNED deleted: {}",deleted);
+        info!("<= HEIGHT_TO_BLOCK_HEADER/HEIGHT_TO_STATISTIC_COUNT deleted from {}", height);
 
+        let removed_mints = self.height_rune_id_sum_from_height(HEIGHT_RUNE_ID_TO_MINTS, height)?;
+        let removed_burned = self.height_rune_id_sum_from_height(HEIGHT_RUNE_ID_TO_BURNED, height)?;
+        batch.delete_range_cf(self.get_cf(HEIGHT_RUNE_ID_TO_MINTS), &from, &end);
+        batch.delete_range_cf(self.get_cf(HEIGHT_RUNE_ID_TO_BURNED), &from, &end);
+        info!("<= HEIGHT_RUNE_ID_TO_MINTS/HEIGHT_RUNE_ID_TO_BURNED runes: {}/{}", removed_mints.len(), removed_burned.len());
 
         info!("<= RUNE_ID_TO_RUNE_ENTRY/RUNE_TO_RUNE_ID ...");
+        // rune ids start with the etching block, so the etched runes are a key range too
+        let rune_id_from = RuneId { block: height as u64, tx: 0 }.encode();
+        let rune_id_end = RuneId { block: u64::MAX, tx: 0 }.encode();
         let cf = self.get_cf(RUNE_ID_TO_RUNE_ENTRY);
-        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::End);
+        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::From(&rune_id_from, Direction::Forward));
         let mut deleted = 0;
         for v in iter {
-            let (k, _) = v.unwrap();
-            let h = u64::from_be_bytes(k[0..8].try_into().unwrap());
-            if h >= height as _ {
-                {
-                    let rune_id = RuneId::load_bytes(&k);
-                    let entry = self.rune_id_to_rune_entry_get(&rune_id).unwrap();
-                    let cf = self.get_cf(RUNE_TO_RUNE_ID);
-                    batch.delete_cf(cf, &entry.spaced_rune.rune.store_bytes());
-                }
-                batch.delete_cf(cf, &k);
-                deleted += 1;
-            } else {
-                break;
-            }
+            let (_, v) = v?;
+            let entry = RuneEntry::load_bytes(&v);
+            batch.delete_cf(self.get_cf(RUNE_TO_RUNE_ID), entry.spaced_rune.rune.store_bytes());
+            deleted += 1;
+        }
+        for cf_name in [RUNE_ID_TO_RUNE_ENTRY, RUNE_ID_TO_MINTS, RUNE_ID_TO_BURNED] {
+            batch.delete_range_cf(self.get_cf(cf_name), &rune_id_from, &rune_id_end);
         }
         info!("<= RUNE_ID_TO_RUNE_ENTRY deleted: {}", deleted);
 
@@ -564,40 +1005,123 @@ impl RunesDB {
         info!("<= OUTPOINT_TO_RUNE_BALANCES ...");
         let temp_cf = self.get_cf(HEIGHT_OUTPOINT_TO_RUNE_IDS);
         let otrb_cf = self.get_cf(OUTPOINT_TO_RUNE_BALANCES);
-        let iter = self.rocksdb.iterator_cf(temp_cf, IteratorMode::End);
+        let spk_cf = self.get_cf(SPK_TO_OUTPOINTS);
+        let oths_cf = self.get_cf(OUTPOINT_TO_SCRIPT_HASH);
+        let rito_cf = self.get_cf(RUNE_ID_TO_OUTPOINTS);
+        let iter = self.rocksdb.full_iterator_cf(temp_cf, IteratorMode::From(&from, Direction::Forward));
         let mut deleted = 0;
         let mut changed = 0;
         let mut changed_rune_ids = HashSet::new();
+        // per script balance changes as (restored amount, removed amount, outputs)
+        let mut script_balances: HashMap<ScriptHashRuneIdKey, (u128, u128, i64)> = HashMap::new();
         for x in iter {
             let (tk, tv) = x?;
-            let h = u32::from_be_bytes([tk[0], tk[1], tk[2], tk[3]]);
-            if h >= height {
-                batch.delete_cf(temp_cf, &tk);
-                let k = &tk[4..];
-                let v = self.rocksdb.get_cf(otrb_cf, k)?.unwrap();
-                let confirmed_height = u32::from_le_bytes(v[0..4].try_into()?);
-                if confirmed_height >= height {
-                    batch.delete_cf(otrb_cf, k);
-                    deleted += 1;
-                    continue;
+            let k = &HeightOutPointKey::decode(&tk)?.outpoint.encode();
+            // gone if prune_spent deleted it, it was spent below the reorg
+            let Some(v) = self.rocksdb.get_cf(otrb_cf, k)? else {
+                continue;
+            };
+            // script hash (32) + value (8), missing for outputs light mode doesn't index
+            let script_hash = self.rocksdb.get_cf(oths_cf, k)?;
+            let confirmed_height = u32::from_le_bytes(v[0..4].try_into()?);
+            if confirmed_height >= height {
+                batch.delete_cf(otrb_cf, k);
+                let unspent = u32::from_le_bytes(v[4..8].try_into()?) == 0;
+                for (rune_id, amount) in RuneUpdater::decode_rune_balances(&RuneBalanceEntry::load_bytes(&v).2)? {
+                    batch.delete_cf(rito_cf, [&rune_id.encode()[..], k].concat());
+                    // a spent output already left the balance of its script
+                    if let (Some(sv), true) = (&script_hash, unspent) {
+                        let balance = script_balances.entry(ScriptHashRuneIdKey { script_hash: sv[0..32].try_into()?, rune_id }).or_default();
+                        balance.1 += amount;
+                        balance.2 -= 1;
+                    }
                 }
-                let spent_height = u32::from_le_bytes(v[4..8].try_into()?);
-                if spent_height >= height {
-                    let mut entry = RuneBalanceEntry::load_bytes(&v);
-                    entry.1 = 0;
-                    batch.put_cf(otrb_cf, k, &entry.store_bytes());
-                    changed += 1;
-                    tv.chunks(12).for_each(|x| {
-                        let rune_id = RuneId::load_bytes(x);
-                        changed_rune_ids.insert(rune_id);
-                    });
+                if let Some(sv) = script_hash {
+                    batch.delete_cf(spk_cf, [&sv[0..32], k].concat());
+                    batch.delete_cf(oths_cf, k);
                 }
-            } else {
-                break;
+                deleted += 1;
+                continue;
+            }
+            let spent_height = u32::from_le_bytes(v[4..8].try_into()?);
+            if spent_height >= height {
+                let mut entry = RuneBalanceEntry::load_bytes(&v);
+                for (rune_id, amount) in RuneUpdater::decode_rune_balances(&entry.2)? {
+                    batch.put_cf(rito_cf, [&rune_id.encode()[..], k].concat(), amount.to_be_bytes());
+                    if let Some(sv) = &script_hash {
+                        let balance = script_balances.entry(ScriptHashRuneIdKey { script_hash: sv[0..32].try_into()?, rune_id }).or_default();
+                        balance.0 += amount;
+                        balance.2 += 1;
+                    }
+                }
+                entry.1 = 0;
+                batch.put_cf(otrb_cf, k, &entry.store_bytes());
+                if let Some(sv) = script_hash {
+                    batch.put_cf(spk_cf, [&sv[0..32], k].concat(), [&confirmed_height.to_be_bytes()[..], &sv[32..40]].concat());
+                }
+                changed += 1;
+                tv.chunks(12).for_each(|x| {
+                    let rune_id = RuneId::load_bytes(x);
+                    changed_rune_ids.insert(rune_id);
+                });
             }
         }
+        batch.delete_range_cf(temp_cf, &from, &end);
         info!("<= OUTPOINT_TO_RUNE_BALANCES deleted: {}, changed: {}", deleted, changed);
 
+        let shrb_cf = self.get_cf(SCRIPT_HASH_RUNE_ID_TO_BALANCE);
+        for (key, (restored, removed, outputs)) in &script_balances {
+            let (amount, current) = self.script_hash_rune_id_to_balance_get_by_key(key).unwrap_or_default();
+            let outputs = current as i64 + outputs;
+            if outputs > 0 {
+                batch.put_cf(shrb_cf, key.encode(), encode_script_balance(((amount + restored).saturating_sub(*removed), outputs as u32)));
+            } else {
+                batch.delete_cf(shrb_cf, key.encode());
+            }
+        }
+        info!("<= SCRIPT_HASH_RUNE_ID_TO_BALANCE changed: {}", script_balances.len());
+
+        for (cf_name, undo_cf_name) in OUTPOINT_UNDO_CFS {
+            // the first undo record of an outpoint holds its value before the reverted blocks
+            let undo_cf = self.get_cf(undo_cf_name);
+            let cf = self.get_cf(cf_name);
+            let mut restored = HashSet::new();
+            for x in self.rocksdb.full_iterator_cf(undo_cf, IteratorMode::From(&from, Direction::Forward)) {
+                let (k, v) = x?;
+                let outpoint = HeightOutPointKey::decode(&k)?.outpoint;
+                if !restored.insert(outpoint) {
+                    continue;
+                }
+                if v.is_empty() {
+                    batch.delete_cf(cf, outpoint.encode());
+                } else {
+                    batch.put_cf(cf, outpoint.encode(), v);
+                }
+            }
+            batch.delete_range_cf(undo_cf, &from, &end);
+            info!("<= {} restored: {}", cf_name, restored.len());
+        }
+
+        let undo_cf = self.get_cf(HEIGHT_PROTOCOL_KEY_UNDO);
+        let mut restored = HashSet::new();
+        for x in self.rocksdb.full_iterator_cf(undo_cf, IteratorMode::From(&from, Direction::Forward)) {
+            let (k, v) = x?;
+            let tag = u128::from_be_bytes(k[4..20].try_into()?);
+            let Some(protocol) = PROTOCOLS.iter().find(|x| x.tag() == tag) else {
+                warn!("Undo record of unknown protocol tag {}", tag);
+                continue;
+            };
+            if !restored.insert(k[4..].to_vec()) {
+                continue;
+            }
+            match v.first() {
+                Some(1) => batch.put_cf(self.get_cf(protocol.column_family()), &k[20..], &v[1..]),
+                _ => batch.delete_cf(self.get_cf(protocol.column_family()), &k[20..]),
+            }
+        }
+        batch.delete_range_cf(undo_cf, &from, &end);
+        info!("<= PROTOCOL keys restored: {}", restored.len());
+
         self.rocksdb.write(batch)?;
 
         info!("Write stage 1 done.");
@@ -608,20 +1132,48 @@ impl RunesDB {
 
         info!("<= STATISTIC_TO_VALUE Statistic::Runes ...");
         let runes_count = self.height_to_statistic_count_sum_to_height(&Statistic::Runes, height - 1);
-        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), [Statistic::Runes.key()], runes_count.to_be_bytes());
+        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(Statistic::Runes).encode(), runes_count.to_be_bytes());
         info!("<= STATISTIC_TO_VALUE Statistic::Runes {}", runes_count);
 
         info!("<= STATISTIC_TO_VALUE Statistic::ReservedRunes ...");
         let reserved_runes_count = self.height_to_statistic_count_sum_to_height(&Statistic::ReservedRunes, height - 1);
-        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), [Statistic::ReservedRunes.key()], reserved_runes_count.to_be_bytes());
+        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(Statistic::ReservedRunes).encode(), reserved_runes_count.to_be_bytes());
         info!("<= STATISTIC_TO_VALUE Statistic::ReservedRunes {}", reserved_runes_count);
 
+        // the totals may predate the per height records, so only what was removed is subtracted
+        let mints = self.statistic_to_total_get(&Statistic::Mints).unwrap_or_default().saturating_sub(removed_mints.values().sum());
+        let burned = self.statistic_to_total_get(&Statistic::Burned).unwrap_or_default().saturating_sub(removed_burned.values().sum());
+        let transactions = self.statistic_to_value_get(&Statistic::RuneTransactions).unwrap_or_default().saturating_sub(removed_transactions);
+        let inscriptions = self.statistic_to_value_get(&Statistic::Inscriptions).unwrap_or_default().saturating_sub(removed_inscriptions);
+        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(Statistic::Inscriptions).encode(), inscriptions.to_be_bytes());
+        let sat_ranges = self.statistic_to_total_get(&Statistic::SatRanges).unwrap_or_default().saturating_sub(removed_sat_ranges.into());
+        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(Statistic::SatRanges).encode(), sat_ranges.to_be_bytes());
+        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(Statistic::Mints).encode(), mints.to_be_bytes());
+        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(Statistic::Burned).encode(), burned.to_be_bytes());
+        batch.put_cf(self.get_cf(STATISTIC_TO_VALUE), StatisticKey::from(Statistic::RuneTransactions).encode(), transactions.to_be_bytes());
+        info!("<= STATISTIC_TO_VALUE Statistic::Mints/Burned/RuneTransactions {}/{}/{}", mints, burned, transactions);
+
 
         info!("<= SQLITE: Deleting/Updating rune_balances, rune_entry ...");
         let mut conn = self.sqlite.get().unwrap();
         let del_rune_balance_count = conn.execute("DELETE FROM rune_balance WHERE height >= ?", params![height])?;
         let update_rune_balance_count = conn.execute("UPDATE rune_balance SET spent_height = 0, spent_txid = null, spent_vin = null, spent_ts = null WHERE spent_height >= ?", params![height])?;
         let del_rune_count = conn.execute("DELETE FROM rune_entry WHERE height >= ?", params![height])?;
+        conn.execute("DELETE FROM rune_edict WHERE height >= ?", params![height])?;
+        conn.execute("DELETE FROM cenotaph WHERE height >= ?", params![height])?;
+        conn.execute("DELETE FROM inscription WHERE height >= ?", params![height])?;
+        conn.execute("DELETE FROM inscription_transfer WHERE height >= ?", params![height])?;
+        // holders and transactions are undone by their deltas while all reverted blocks have them
+        let counter_undo = self.statistic_to_value_get(&Statistic::CounterUndoHeight).is_some_and(|x| x <= height) && height + REORG_DEPTH > latest_height;
+        if counter_undo {
+            // language=sqlite
+            conn.execute("UPDATE rune_entry SET holders = rune_entry.holders - d.holders, transactions = rune_entry.transactions - d.transactions FROM (SELECT rune_id, SUM(holders) AS holders, SUM(transactions) AS transactions FROM rune_counter_delta WHERE height >= ? GROUP BY rune_id) AS d WHERE rune_entry.rune_id = d.rune_id", params![height])?;
+        } else {
+            self.statistic_to_value_put(&Statistic::CounterUndoHeight, height);
+        }
+        conn.execute("DELETE FROM rune_counter_delta WHERE height >= ?", params![height])?;
+        let reverted = self.sqlite_rune_event_revert_from_height(&mut conn, height)?;
+        info!("<= SQLITE: Reverted blocks in the event log {}", reverted);
         info!("<= SQLITE: Deleted rune_balances {}, Updated rune_balances {}, Deleted rune_entry {}", del_rune_balance_count, update_rune_balance_count, del_rune_count);
 
 
@@ -629,103 +1181,67 @@ impl RunesDB {
 
 
         info!("<= RUNE_ID_TO_RUNE_ENTRY ...");
+        // rune numbers are assigned in etching order, the remaining runes keep theirs
         let cf = self.get_cf(RUNE_ID_TO_RUNE_ENTRY);
-        let iter = self.rocksdb.iterator_cf(cf, IteratorMode::Start);
+        let runes_total = match self.rocksdb.iterator_cf(cf, IteratorMode::End).next() {
+            Some(x) => RuneEntry::load_bytes(&x?.1).number as u32 + 1,
+            None => 0,
+        };
+        if runes_count != runes_total {
+            bail!("Runes count mismatch: {} != {}", runes_count, runes_total);
+        }
 
-        let mut runes_total = 0;
+        let affected_rune_ids: HashSet<RuneId> = removed_mints.keys()
+            .chain(removed_burned.keys())
+            .chain(changed_rune_ids.iter())
+            .filter(|rune_id| rune_id.block < height as u64)
+            .copied()
+            .collect();
         let mut changed_runes = HashMap::new();
-        for (number, v) in iter.enumerate() {
-            runes_total += 1;
-            let mut has_changed = false;
-            let (k, v) = v.unwrap();
-            let key = RuneId::load_bytes(&k);
-            let mut entry = RuneEntry::load_bytes(&v);
-            let burned = self.rune_id_height_to_burned_sum_to_height(&key, height);
-            batch.put_cf(self.get_cf(RUNE_ID_TO_BURNED), &k, burned.to_be_bytes());
-
-            if entry.burned != burned {
-                entry.burned = burned;
-                has_changed = true;
-            }
-
-            let mints = self.rune_id_to_mints_sum_to_height(&key, height);
-            batch.put_cf(self.get_cf(RUNE_ID_TO_MINTS), &k, mints.to_be_bytes());
-
-            if entry.mints != mints {
-                entry.mints = mints;
-                has_changed = true;
-            }
-
-            let number = number as u64;
-
-            if entry.number != number {
-                entry.number = number;
-                has_changed = true;
+        for key in affected_rune_ids {
+            let Some(mut entry) = self.rune_id_to_rune_entry_get(&key) else {
+                continue;
+            };
+            let k = key.store_bytes();
+            if let Some(mints) = removed_mints.get(&key) {
+                entry.mints -= mints;
+                batch.put_cf(self.get_cf(RUNE_ID_TO_MINTS), &k, entry.mints.to_be_bytes());
             }
-
-            if has_changed {
-                batch.put_cf(cf, &k, &entry.store_bytes());
-            }
-
-            if has_changed || changed_rune_ids.contains(&key) {
-                changed_runes.insert(key.to_string(), RuneEntryForUpdate {
-                    rune_id: key.to_string(),
-                    mints: entry.mints.to_string(),
-                    burned: entry.burned.to_string(),
-                    mintable: entry.mintable(latest_height as _).unwrap_or(0) > 0,
-                });
+            if let Some(burned) = removed_burned.get(&key) {
+                entry.burned = entry.burned.checked_sub(*burned)
+                    .with_context(|| format!("burned of {} below the {} reorged", key, burned))?;
+                batch.put_cf(self.get_cf(RUNE_ID_TO_BURNED), &k, entry.burned.to_be_bytes());
             }
+            batch.put_cf(cf, &k, &entry.store_bytes());
+
+            changed_runes.insert(key.to_string(), RuneEntryForUpdate {
+                rune_id: key.to_string(),
+                mints: entry.mints.to_string(),
+                burned: entry.burned.to_string(),
+                mintable: entry.mintable(latest_height as _).unwrap_or(0) > 0,
+            });
         }
-        info!("<= RUNE_ID_TO_RUNE_ENTRY {}", runes_total);
-        if runes_count != runes_total {
-            panic!("Runes count mismatch: {} != {}", runes_count, runes_total);
-        }
-        self.rocksdb.write(batch).unwrap();
+        info!("<= RUNE_ID_TO_RUNE_ENTRY total: {}, changed: {}", runes_total, changed_runes.len());
+        self.rocksdb.write(batch)?;
         info!("Write stage 3 done.");
 
         info!("<= SQLITE: Updating rune entries {}", changed_runes.len());
 
-        let mut runes_txs = HashMap::new();
-        let mut runes_holders = HashMap::new();
-        if !changed_runes.is_empty() {
-            let t = Instant::now();
-            let need_update_runes = changed_runes.keys().collect::<Vec<&String>>();
-            for sub in need_update_runes.chunks(100) {
-                let placeholders = sub.iter().map(|_| "?").collect::<Vec<&str>>().join(",");
-                let sql = format!("SELECT rune_id, COUNT(DISTINCT _txid) AS txs FROM (SELECT rune_id, txid AS _txid FROM rune_balance where rune_id in ({}) UNION ALL SELECT rune_id, spent_txid AS _txid FROM rune_balance WHERE rune_id in ({}) AND spent_height > 0) AS _ GROUP BY rune_id", &placeholders, &placeholders);
-                let mut stmt = conn.prepare_cached(&sql)?;
-                stmt.query_map(params_from_iter(sub.iter().chain(sub.iter())), |row| {
-                    Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
-                })?.for_each(|x| {
-                    let (rune_id, txs) = x.unwrap();
-                    runes_txs.insert(rune_id, txs);
-                });
-                let sql = format!("SELECT rune_id, COUNT(DISTINCT address) AS addresses FROM rune_balance where rune_id in ({}) and spent_height = 0 GROUP BY rune_id", &placeholders);
-                let mut stmt = conn.prepare_cached(&sql)?;
-                stmt.query_map(params_from_iter(sub.iter()), |row| {
-                    Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
-                })?.for_each(|x| {
-                    let (rune_id, holders) = x.unwrap();
-                    runes_holders.insert(rune_id, holders);
-                });
-            }
-            info!("Querying {} runes txs and holders from sqlite, {:?}", need_update_runes.len(), t.elapsed());
+        if !counter_undo {
+            self.sqlite_rune_entry_recount(&changed_runes.keys().cloned().collect::<Vec<_>>())?;
         }
 
-
         let tx = conn.transaction()?;
         let update_rune_entries: Vec<&RuneEntryForUpdate> = changed_runes.values().collect();
 
         if !update_rune_entries.is_empty() {
             let t = Instant::now();
-            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET mintable = ?, mints = ?, burned = ?, holders = ?, transactions = ? WHERE rune_id = ?")?;
+            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET mintable = ?, mints = ?, burned = ? WHERE rune_id = ?")?;
             for entry in &update_rune_entries {
                 stmt.execute(params![
                     entry.mintable,
                     entry.mints,
                     entry.burned,
-                    runes_holders.get(&entry.rune_id).unwrap_or(&0),
-                    runes_txs.get(&entry.rune_id).unwrap_or(&0),
                     entry.rune_id,
                 ])?;
             }
@@ -733,25 +1249,309 @@ impl RunesDB {
         }
 
         tx.commit()?;
+
+        let holders = self.sqlite_rune_entry_holders_sum()?;
+        self.statistic_to_value_put(&Statistic::Holders, holders);
+        info!("<= STATISTIC_TO_VALUE Statistic::Holders {}", holders);
         info!("Write stage 4 done.");
         Ok(())
     }
 
-    pub fn flush_rocksdb(&self) {
-        self.rocksdb.flush_wal(true).unwrap();
-        self.rocksdb.flush().unwrap();
+    /// Frees up to `pages` pages of the freelist, if the database was created
+    /// with incremental auto vacuum, and refreshes the query planner
+    /// statistics. Returns the freed pages.
+    pub fn sqlite_maintenance(&self, pages: u32) -> anyhow::Result<u32> {
+        let conn = self.sqlite.get()?;
+        let mut freed = 0;
+        let auto_vacuum: u32 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+        // 2 is incremental
+        if auto_vacuum == 2 {
+            let freelist = |conn: &Connection| conn.query_row("PRAGMA freelist_count", [], |row| row.get::<_, u32>(0));
+            let before = freelist(&conn)?;
+            // stepped until done, a single step frees only part of the pages
+            let mut stmt = conn.prepare(&format!("PRAGMA incremental_vacuum({})", pages))?;
+            let mut rows = stmt.query([])?;
+            while rows.next()?.is_some() {}
+            freed = before.saturating_sub(freelist(&conn)?);
+        }
+        // sampled, scanning every row of rune_balance would hold the writer for hours
+        conn.execute_batch("PRAGMA analysis_limit = 1000; ANALYZE;")?;
+        Ok(freed)
+    }
+
+    /// Recounts holders and transactions of rune entries from their balances,
+    /// for when the per block deltas are unavailable or untrusted.
+    pub fn sqlite_rune_entry_recount(&self, rune_ids: &[String]) -> anyhow::Result<()> {
+        if rune_ids.is_empty() {
+            return Ok(());
+        }
+        let t = Instant::now();
+        let mut conn = self.sqlite.get()?;
+        let tx = conn.transaction()?;
+        for sub in rune_ids.chunks(100) {
+            let mut runes_txs = HashMap::new();
+            let mut runes_holders = HashMap::new();
+            let placeholders = sub.iter().map(|_| "?").collect::<Vec<&str>>().join(",");
+            let sql = format!("SELECT rune_id, COUNT(DISTINCT _txid) AS txs FROM (SELECT rune_id, txid AS _txid FROM rune_balance where rune_id in ({}) UNION ALL SELECT rune_id, spent_txid AS _txid FROM rune_balance WHERE rune_id in ({}) AND spent_height > 0) AS _ GROUP BY rune_id", &placeholders, &placeholders);
+            let mut stmt = tx.prepare_cached(&sql)?;
+            stmt.query_map(params_from_iter(sub.iter().chain(sub.iter())), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+            })?.for_each(|x| {
+                let (rune_id, txs) = x.unwrap();
+                runes_txs.insert(rune_id, txs);
+            });
+            let sql = format!("SELECT rune_id, COUNT(DISTINCT address) AS addresses FROM rune_balance where rune_id in ({}) and spent_height = 0 GROUP BY rune_id", &placeholders);
+            let mut stmt = tx.prepare_cached(&sql)?;
+            stmt.query_map(params_from_iter(sub.iter()), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+            })?.for_each(|x| {
+                let (rune_id, holders) = x.unwrap();
+                runes_holders.insert(rune_id, holders);
+            });
+            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET holders = ?, transactions = ? WHERE rune_id = ?")?;
+            for rune_id in sub {
+                stmt.execute(params![
+                    runes_holders.get(rune_id).unwrap_or(&0),
+                    runes_txs.get(rune_id).unwrap_or(&0),
+                    rune_id,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        info!("Recounting {} runes txs and holders from sqlite, {:?}", rune_ids.len(), t.elapsed());
+        Ok(())
+    }
+
+    /// Empties the tables `rebuild-sqlite` fills from rocksdb, api keys, webhooks,
+    /// watches and the event log are kept.
+    pub fn sqlite_rebuild_clear(&self) -> anyhow::Result<()> {
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        conn.execute_batch("DELETE FROM rune_balance; DELETE FROM rune_entry; DELETE FROM rune_counter_delta; DELETE FROM block_commit;")?;
+        Ok(())
     }
 
+    pub fn sqlite_rune_entry_insert_all(&self, entries: &[RuneEntryForQueryInsert]) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.get()?;
+        let tx = conn.transaction()?;
+        {
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("INSERT INTO rune_entry (rune_id, etching, number, rune, spaced_rune, symbol, divisibility, premine, amount, cap, start_height, end_height, start_offset, end_offset, turbo, fairmint, height, ts, mintable, mints, burned, holders, transactions, etcher, commit_txid) VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)")?;
+            for entry in entries {
+                stmt.execute(params![
+                    entry.rune_id, entry.etching, entry.number, entry.rune, entry.spaced_rune, entry.symbol, entry.divisibility, entry.premine,
+                    entry.amount, entry.cap, entry.start_height, entry.end_height, entry.start_offset, entry.end_offset, entry.turbo, entry.fairmint,
+                    entry.height, entry.ts, entry.mintable, entry.mints, entry.burned, entry.holders, entry.transactions, entry.etcher, entry.commit_txid,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
 
-    pub fn to_sqlite(&self, rune_temp: RuneEntryForTemp, mut balance_temp: RuneBalanceForTemp) -> anyhow::Result<()> {
-        let now = Instant::now();
+    /// Writes the balance rows a block created, marks the ones it spent and
+    /// commits the block, all in one transaction.
+    pub fn sqlite_rune_balance_rebuild_block(&self, height: u32, block_hash: &BlockHash, inserts: &[RuneBalanceForInsert], spends: &[RuneBalanceForUpdate]) -> anyhow::Result<()> {
         let mut conn = self.sqlite.get()?;
         let tx = conn.transaction()?;
+        {
+            // language=sqlite
+            let mut insert = tx.prepare_cached("INSERT INTO rune_balance (txid, vout, value, rune_id, rune_amount, address, premine, mint, burn, cenotaph, transfer, height, idx, ts, spent_height, spent_ts, spent_txid, spent_vin) VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)")?;
+            for x in inserts {
+                insert.execute(params![
+                    x.txid, x.vout, x.value, x.rune_id, x.rune_amount, x.address, x.premine, x.mint, x.burn,
+                    x.cenotaph, x.transfer, x.height, x.idx, x.ts, x.spent_height, x.spent_ts, x.spent_txid, x.spent_vin,
+                ])?;
+            }
+            // language=sqlite
+            let mut spend = tx.prepare_cached("UPDATE rune_balance SET spent_height = ?, spent_txid = ?, spent_vin = ?, spent_ts = ? WHERE txid = ? AND vout = ? AND rune_id = ?")?;
+            for x in spends {
+                spend.execute(params![x.spent_height, x.spent_txid, x.spent_vin, x.spent_ts, x.txid, x.vout, x.rune_id])?;
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO block_commit (height, block_hash, ts) VALUES (?, ?, ?)",
+            params![height, block_hash.to_string(), now_ts()],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Rune entries etched at or before `height`, with mints and burned as they
+    /// were at that height.
+    pub fn rune_entries_at_height(&self, height: u32) -> anyhow::Result<Vec<(RuneId, RuneEntry)>> {
+        let later_mints = self.height_rune_id_sum_from_height(HEIGHT_RUNE_ID_TO_MINTS, height + 1)?;
+        let later_burned = self.height_rune_id_sum_from_height(HEIGHT_RUNE_ID_TO_BURNED, height + 1)?;
+        let mut entries = vec![];
+        for x in self.rocksdb.iterator_cf(self.get_cf(RUNE_ID_TO_RUNE_ENTRY), IteratorMode::Start) {
+            let (k, v) = x?;
+            let rune_id = RuneId::decode(&k)?;
+            if rune_id.block > height as u64 {
+                break;
+            }
+            let mut entry = RuneEntry::load_bytes(&v);
+            entry.mints -= later_mints.get(&rune_id).copied().unwrap_or_default();
+            let burned = later_burned.get(&rune_id).copied().unwrap_or_default();
+            entry.burned = entry.burned.checked_sub(burned)
+                .with_context(|| format!("burned of {} below the {} burned after {}", rune_id, burned, height))?;
+            entries.push((rune_id, entry));
+        }
+        Ok(entries)
+    }
+
+    /// Counts what [`RunesDB::reorg_to_height`] would delete or reset, without writing.
+    pub fn reorg_plan(&self, height: u32) -> anyhow::Result<ReorgPlan> {
+        let from = height.encode();
+        let count_from = |cf_name: &str, from: &[u8]| -> anyhow::Result<u64> {
+            let mut count = 0;
+            for x in self.rocksdb.full_iterator_cf(self.get_cf(cf_name), IteratorMode::From(from, Direction::Forward)) {
+                x?;
+                count += 1;
+            }
+            Ok(count)
+        };
+
+        let otrb_cf = self.get_cf(OUTPOINT_TO_RUNE_BALANCES);
+        let mut outpoints_deleted = 0;
+        let mut outpoints_unspent = 0;
+        for x in self.rocksdb.full_iterator_cf(self.get_cf(HEIGHT_OUTPOINT_TO_RUNE_IDS), IteratorMode::From(&from, Direction::Forward)) {
+            let (tk, _) = x?;
+            let k = HeightOutPointKey::decode(&tk)?.outpoint.encode();
+            let Some(v) = self.rocksdb.get_cf(otrb_cf, k)? else {
+                continue;
+            };
+            if u32::from_le_bytes(v[0..4].try_into()?) >= height {
+                outpoints_deleted += 1;
+            } else if u32::from_le_bytes(v[4..8].try_into()?) >= height {
+                outpoints_unspent += 1;
+            }
+        }
+
+        let conn = self.sqlite.get()?;
+        let count = |sql: &str| conn.query_row(sql, params![height], |row| row.get::<_, u64>(0));
+        Ok(ReorgPlan {
+            height,
+            block_headers: count_from(HEIGHT_TO_BLOCK_HEADER, &from)?,
+            rune_entries: count_from(RUNE_ID_TO_RUNE_ENTRY, &RuneId { block: height as u64, tx: 0 }.encode())?,
+            height_mints: count_from(HEIGHT_RUNE_ID_TO_MINTS, &from)?,
+            height_burned: count_from(HEIGHT_RUNE_ID_TO_BURNED, &from)?,
+            outpoints_deleted,
+            outpoints_unspent,
+            sqlite_rune_balances_deleted: count("SELECT COUNT(*) FROM rune_balance WHERE height >= ?")?,
+            sqlite_rune_balances_unspent: count("SELECT COUNT(*) FROM rune_balance WHERE height < ?1 AND spent_height >= ?1")?,
+            sqlite_rune_entries_deleted: count("SELECT COUNT(*) FROM rune_entry WHERE height >= ?")?,
+        })
+    }
+
+    /// Syncs the WAL and flushes the memtables of every column family.
+    pub fn flush_rocksdb(&self) -> anyhow::Result<()> {
+        self.rocksdb.flush_wal(true)?;
+        let cfs = COLUMN_FAMILIES.iter().map(|name| self.get_cf(name)).collect::<Vec<_>>();
+        self.rocksdb.flush_cfs_opt(&cfs, &FlushOptions::default())?;
+        Ok(())
+    }
+
+    /// Size, key estimate and live sst files of every column family.
+    pub fn rocksdb_cf_stats(&self) -> anyhow::Result<Vec<ColumnFamilyStats>> {
+        let live_files = self.rocksdb.live_files()?;
+        let mut stats = vec![];
+        for name in COLUMN_FAMILIES {
+            let cf = self.get_cf(name);
+            stats.push(ColumnFamilyStats {
+                name: name.to_string(),
+                sst_size: self.rocksdb.property_int_value_cf(cf, "rocksdb.total-sst-files-size")?.unwrap_or_default(),
+                memtable_size: self.rocksdb.property_int_value_cf(cf, "rocksdb.cur-size-all-mem-tables")?.unwrap_or_default(),
+                estimated_keys: self.rocksdb.property_int_value_cf(cf, "rocksdb.estimate-num-keys")?.unwrap_or_default(),
+                live_files: live_files.iter().filter(|x| x.column_family_name == name).count() as u64,
+            });
+        }
+        Ok(stats)
+    }
 
-        let mut need_update_runes = HashSet::new();
+    /// Size of the sqlite database file, the WAL not included.
+    pub fn sqlite_file_size(&self) -> anyhow::Result<u64> {
+        let path = self.rocksdb.path().parent().unwrap().join("sqlite.db");
+        Ok(std::fs::metadata(path)?.len())
+    }
+
+    /// Hard links the live rocksdb files into `path`, which must not exist yet.
+    pub fn rocksdb_checkpoint(&self, path: &Path) -> anyhow::Result<()> {
+        Checkpoint::new(&self.rocksdb)?.create_checkpoint(path)?;
+        Ok(())
+    }
+
+    /// Writes a vacuumed copy of the sqlite database as of one transaction to
+    /// `path`, which must not exist yet.
+    pub fn sqlite_vacuum_into(&self, path: &Path) -> anyhow::Result<()> {
+        let conn = self.sqlite.get()?;
+        conn.execute("VACUUM INTO ?", params![path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Copies the sqlite database to `path` with the online backup API, in one
+    /// step so the copy is the snapshot of a single read transaction.
+    pub fn sqlite_backup_to(&self, path: &Path) -> anyhow::Result<()> {
+        let conn = self.sqlite.get()?;
+        let mut dst = Connection::open(path)?;
+        let backup = Backup::new(&conn, &mut dst)?;
+        match backup.step(-1)? {
+            StepResult::Done => Ok(()),
+            x => bail!("sqlite backup did not finish: {:?}", x),
+        }
+    }
+
+    /// The directory holding the rocksdb and sqlite files.
+    pub fn data_dir(&self) -> &Path {
+        self.rocksdb.path().parent().unwrap()
+    }
+
+    /// Size of the sqlite WAL, 0 right after a checkpoint truncated it.
+    pub fn sqlite_wal_size(&self) -> u64 {
+        let path = self.rocksdb.path().parent().unwrap().join("sqlite.db-wal");
+        std::fs::metadata(path).map(|x| x.len()).unwrap_or_default()
+    }
+
+    /// Runs a manual compaction over the whole key range of `cf_name`, or of every
+    /// column family when it is `None`. Returns the compacted column families.
+    pub fn compact_rocksdb(&self, cf_name: Option<&str>) -> anyhow::Result<Vec<String>> {
+        let cf_names: Vec<&str> = match cf_name {
+            Some(name) if COLUMN_FAMILIES.contains(&name) => vec![name],
+            Some(name) => bail!("unknown column family {}", name),
+            None => COLUMN_FAMILIES.to_vec(),
+        };
+        for name in &cf_names {
+            let start = Instant::now();
+            self.rocksdb.compact_range_cf(self.get_cf(name), None::<&[u8]>, None::<&[u8]>);
+            info!("Compacted {}, {:?}", name, start.elapsed());
+        }
+        Ok(cf_names.into_iter().map(String::from).collect())
+    }
+
+    /// Flushes rocksdb and checkpoints the sqlite WAL, call once nothing else uses the db.
+    pub fn close(&self) -> anyhow::Result<()> {
+        self.flush_rocksdb()?;
+        self.rocksdb.cancel_all_background_work(true);
+        let conn = self.sqlite.get()?;
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+
+
+    #[instrument(skip_all, fields(entries = rune_temp.inserts.len(), balances = balance_temp.inserts.len()))]
+    pub fn to_sqlite(&self, height: u32, block_hash: &BlockHash, rune_temp: RuneEntryForTemp, mut balance_temp: RuneBalanceForTemp, events: &[RuneEvent]) -> anyhow::Result<usize> {
+        let now = Instant::now();
+        let mut conn = self.sqlite.get()?;
+        let tx = conn.transaction()?;
 
         let mut has_op = false;
 
+        // holders and transactions are maintained by what the block changes:
+        // unspent rows created and earlier rows spent per (rune_id, address), and
+        // the txids per rune_id, all new since a txid belongs to one block
+        let mut created: HashMap<(String, String), i64> = HashMap::new();
+        let mut spent: HashMap<(String, String), i64> = HashMap::new();
+        let mut runes_txids: HashMap<String, HashSet<String>> = HashMap::new();
+
         balance_temp.update_inserts();
         let insert_rune_balances: Vec<&RuneBalanceForInsert> = balance_temp.inserts.values().collect();
         if !insert_rune_balances.is_empty() {
@@ -786,7 +1586,14 @@ impl RunesDB {
                     values.push(&entry.spent_ts);
                     values.push(&entry.spent_txid);
                     values.push(&entry.spent_vin);
-                    need_update_runes.insert(entry.rune_id.clone());
+                    let txids = runes_txids.entry(entry.rune_id.clone()).or_default();
+                    txids.insert(entry.txid.clone());
+                    if let Some(spent_txid) = &entry.spent_txid {
+                        txids.insert(spent_txid.clone());
+                    }
+                    if entry.spent_height == 0 {
+                        *created.entry((entry.rune_id.clone(), entry.address.clone())).or_default() += 1;
+                    }
                 }
                 tx.execute(&sql, values.as_slice())?;
             }
@@ -797,9 +1604,9 @@ impl RunesDB {
         if !update_rune_balances.is_empty() {
             has_op = true;
             let t = Instant::now();
-            let mut stmt = tx.prepare_cached("UPDATE rune_balance SET spent_height = ?, spent_txid = ?, spent_vin = ?, spent_ts = ? WHERE txid = ? AND vout = ? AND rune_id = ?")?;
+            let mut stmt = tx.prepare_cached("UPDATE rune_balance SET spent_height = ?, spent_txid = ?, spent_vin = ?, spent_ts = ? WHERE txid = ? AND vout = ? AND rune_id = ? RETURNING address")?;
             for entry in &update_rune_balances {
-                stmt.execute(params![
+                let address: Option<String> = stmt.query_row(params![
                     entry.spent_height,
                     entry.spent_txid,
                     entry.spent_vin,
@@ -807,56 +1614,70 @@ impl RunesDB {
                     entry.txid,
                     entry.vout,
                     entry.rune_id,
-                ])?;
-                need_update_runes.insert(entry.rune_id.clone());
+                ], |row| row.get(0)).optional()?;
+                // light mode has no rows for most outputs
+                let Some(address) = address else {
+                    continue;
+                };
+                runes_txids.entry(entry.rune_id.clone()).or_default().insert(entry.spent_txid.clone());
+                *spent.entry((entry.rune_id.clone(), address)).or_default() += 1;
             }
             info!("Updating {} rune balances in sqlite, {:?}", update_rune_balances.len(), t.elapsed());
         }
 
-        tx.commit()?;
+        if !balance_temp.edicts.is_empty() {
+            has_op = true;
+            let t = Instant::now();
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("INSERT INTO rune_edict (txid, edict, rune_id, amount, allocated, output, height, idx, ts) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+            for edict in &balance_temp.edicts {
+                stmt.execute(params![edict.txid, edict.edict, edict.rune_id, edict.amount, edict.allocated, edict.output, edict.height, edict.idx, edict.ts])?;
+            }
+            info!("Inserting {} rune edicts to sqlite, {:?}", balance_temp.edicts.len(), t.elapsed());
+        }
 
-        for x in rune_temp.updates.values() {
-            need_update_runes.insert(x.rune_id.clone());
+        if !balance_temp.cenotaphs.is_empty() {
+            has_op = true;
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("INSERT OR REPLACE INTO cenotaph (txid, flaw, description, etching, mint, burned, height, idx, ts) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+            for x in &balance_temp.cenotaphs {
+                stmt.execute(params![x.txid, x.flaw, x.description, x.etching, x.mint, serde_json::to_string(&x.burned)?, x.height, x.idx, x.ts])?;
+            }
         }
-        for x in rune_temp.inserts.values() {
-            if x.mints.parse::<u128>().unwrap() > 0 || x.premine.parse::<u128>().unwrap() > 0 || x.burned.parse::<u128>().unwrap() > 0 {
-                need_update_runes.insert(x.rune_id.clone());
+
+        if !balance_temp.inscriptions.is_empty() {
+            has_op = true;
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("INSERT INTO inscription (inscription_id, number, txid, input, content_type, content_length, content_encoding, metaprotocol, height, idx, ts) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+            for x in &balance_temp.inscriptions {
+                stmt.execute(params![x.inscription_id, x.number, x.txid, x.input, x.content_type, x.content_length, x.content_encoding, x.metaprotocol, x.height, x.idx, x.ts])?;
             }
         }
-        let mut runes_txs = HashMap::new();
-        let mut runes_holders = HashMap::new();
-        if !need_update_runes.is_empty() {
+
+        if !balance_temp.inscription_transfers.is_empty() {
             has_op = true;
             let t = Instant::now();
-            let need_update_runes = need_update_runes.clone().into_iter().collect::<Vec<String>>();
-            for sub in need_update_runes.chunks(100) {
-                let placeholders = sub.iter().map(|_| "?").collect::<Vec<&str>>().join(",");
-                let t = Instant::now();
-                let sql = format!("SELECT rune_id, COUNT(DISTINCT _txid) AS txs FROM (SELECT rune_id, txid AS _txid FROM rune_balance where rune_id in ({}) UNION ALL SELECT rune_id, spent_txid AS _txid FROM rune_balance WHERE rune_id in ({}) AND spent_height > 0) AS _ GROUP BY rune_id", &placeholders, &placeholders);
-                let mut stmt = conn.prepare_cached(&sql)?;
-                stmt.query_map(params_from_iter(sub.iter().chain(sub.iter())), |row| {
-                    Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
-                })?.for_each(|x| {
-                    let (rune_id, txs) = x.unwrap();
-                    runes_txs.insert(rune_id, txs);
-                });
-                info!("Querying {} runes txs from sqlite, {:?}", sub.len(), t.elapsed());
-                let t = Instant::now();
-                let sql = format!("SELECT rune_id, COUNT(DISTINCT address) AS addresses FROM rune_balance where rune_id in ({}) and spent_height = 0 GROUP BY rune_id", &placeholders);
-                let mut stmt = conn.prepare_cached(&sql)?;
-                stmt.query_map(params_from_iter(sub.iter()), |row| {
-                    Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
-                })?.for_each(|x| {
-                    let (rune_id, holders) = x.unwrap();
-                    runes_holders.insert(rune_id, holders);
-                });
-                info!("Querying {} runes holders from sqlite, {:?}", sub.len(), t.elapsed());
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("INSERT INTO inscription_transfer (inscription_id, txid, vout, sat_offset, address, height, idx, ts) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")?;
+            for x in &balance_temp.inscription_transfers {
+                stmt.execute(params![x.inscription_id, x.txid, x.vout, x.sat_offset, x.address, x.height, x.idx, x.ts])?;
             }
-            info!("Querying {} runes txs and holders from sqlite, {:?}", need_update_runes.len(), t.elapsed());
+            info!("Inserting {} inscription transfers to sqlite, {:?}", balance_temp.inscription_transfers.len(), t.elapsed());
         }
 
+        // an address holds a rune while it has an unspent row of it
+        let mut runes_holders: HashMap<String, i64> = HashMap::new();
+        if !created.is_empty() || !spent.is_empty() {
+            let mut stmt = tx.prepare_cached("SELECT COUNT(*) FROM rune_balance WHERE address = ? AND rune_id = ? AND spent_height = 0")?;
+            for key in created.keys().chain(spent.keys()).collect::<HashSet<_>>() {
+                let after: i64 = stmt.query_row(params![key.1, key.0], |row| row.get(0))?;
+                let before = after - created.get(key).unwrap_or(&0) + spent.get(key).unwrap_or(&0);
+                *runes_holders.entry(key.0.clone()).or_default() += (after > 0) as i64 - (before > 0) as i64;
+            }
+        }
+        let runes_txs: HashMap<String, i64> = runes_txids.into_iter().map(|(k, v)| (k, v.len() as i64)).collect();
 
-        let tx = conn.transaction()?;
+        let transfers = transfer_events_in(self, &tx, height)?;
 
         let mut used_rune_ids = HashSet::new();
 
@@ -866,12 +1687,12 @@ impl RunesDB {
             let t = Instant::now();
             for items in insert_rune_entries.chunks(500) {
                 let mut sql = String::from(
-                    "INSERT INTO rune_entry (rune_id, etching, number, rune, spaced_rune, symbol, divisibility, premine, amount, cap, start_height, end_height, start_offset, end_offset, turbo, fairmint, height, ts, mintable, mints, burned, holders, transactions) VALUES ",
+                    "INSERT INTO rune_entry (rune_id, etching, number, rune, spaced_rune, symbol, divisibility, premine, amount, cap, start_height, end_height, start_offset, end_offset, turbo, fairmint, height, ts, mintable, mints, burned, holders, transactions, etcher, commit_txid) VALUES ",
                 );
                 let mut values: Vec<ToSqlOutput> = Vec::new();
                 let len = items.len();
                 for (index, entry) in items.iter().enumerate() {
-                    sql.push_str("(?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)");
+                    sql.push_str("(?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)");
                     if index != len - 1 {
                         sql.push(',');
                     }
@@ -898,6 +1719,8 @@ impl RunesDB {
                     values.push(entry.burned.to_sql()?);
                     values.push(runes_holders.get(&entry.rune_id).unwrap_or(&0).to_sql()?);
                     values.push(runes_txs.get(&entry.rune_id).unwrap_or(&0).to_sql()?);
+                    values.push(entry.etcher.to_sql()?);
+                    values.push(entry.commit_txid.to_sql()?);
                     used_rune_ids.insert(entry.rune_id.clone());
                 }
                 tx.execute(&sql, params_from_iter(values.iter()))?;
@@ -911,49 +1734,83 @@ impl RunesDB {
         let mut updated_rune_count = 0;
         if !update_rune_entries.is_empty() {
             has_op = true;
-            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET mintable = ?, mints = ?, burned = ?, holders = ?, transactions = ? WHERE rune_id = ?")?;
+            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET mintable = ?, mints = ?, burned = ? WHERE rune_id = ?")?;
             for entry in &update_rune_entries {
                 stmt.execute(params![
                     entry.mintable,
                     entry.mints,
                     entry.burned,
-                    runes_holders.get(&entry.rune_id).unwrap_or(&0),
-                    runes_txs.get(&entry.rune_id).unwrap_or(&0),
                     entry.rune_id,
                 ])?;
-                used_rune_ids.insert(entry.rune_id.clone());
                 updated_rune_count += 1;
             }
         }
 
-        {
-            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET holders = ?, transactions = ? WHERE rune_id = ?")?;
-            for rune_id in need_update_runes {
-                if used_rune_ids.contains(&rune_id) {
-                    continue;
+        let counted_rune_ids = runes_holders.keys().chain(runes_txs.keys()).collect::<HashSet<_>>();
+        if !counted_rune_ids.is_empty() {
+            has_op = true;
+            let mut update = tx.prepare_cached("UPDATE rune_entry SET holders = holders + ?, transactions = transactions + ? WHERE rune_id = ?")?;
+            let mut undo = tx.prepare_cached("INSERT INTO rune_counter_delta (height, rune_id, holders, transactions) VALUES (?, ?, ?, ?)")?;
+            for rune_id in counted_rune_ids {
+                let holders = runes_holders.get(rune_id).unwrap_or(&0);
+                let transactions = runes_txs.get(rune_id).unwrap_or(&0);
+                if !used_rune_ids.contains(rune_id) {
+                    update.execute(params![holders, transactions, rune_id])?;
+                    updated_rune_count += 1;
                 }
-                has_op = true;
-                stmt.execute(params![
-                    runes_holders.get(&rune_id).unwrap_or(&0),
-                    runes_txs.get(&rune_id).unwrap_or(&0),
-                    rune_id,
-                ])?;
-                updated_rune_count += 1;
+                undo.execute(params![height, rune_id, holders, transactions])?;
             }
         }
+        // undo rows are only needed within the reorg window
+        tx.execute("DELETE FROM rune_counter_delta WHERE height + ? <= ?", params![REORG_DEPTH, height])?;
 
         if updated_rune_count > 0 {
             info!("Updating {} rune entries in sqlite, {:?}", updated_rune_count, t.elapsed());
         }
 
 
+        if !events.is_empty() || !transfers.is_empty() {
+            let ts = now_ts();
+            let block_hash = block_hash.to_string();
+            let mut stmt = tx.prepare_cached("INSERT INTO rune_event (height, block_hash, type, data, ts) VALUES (?, ?, ?, ?, ?)")?;
+            for event in events.iter().chain(transfers.iter()) {
+                stmt.execute(params![height, block_hash, event.kind(), serde_json::to_string(event)?, ts])?;
+            }
+        }
+
+        // webhook deliveries are queued with the block, none is lost or sent twice
+        // if the process stops around the commit
+        let webhooks = self.sqlite_webhook_list()?;
+        let mut queued = 0;
+        if !webhooks.is_empty() {
+            let events: Vec<RuneEvent> = events.iter().chain(transfers.iter()).cloned().collect();
+            let deliveries = deliveries(&webhooks, Some((height, block_hash)), &events)?;
+            Self::webhook_delivery_insert(&tx, &deliveries)?;
+            queued = deliveries.len();
+        }
+
+        // commit marker of the block, written in the same transaction as its rows
+        tx.execute(
+            "INSERT OR REPLACE INTO block_commit (height, block_hash, ts) VALUES (?, ?, ?)",
+            params![height, block_hash.to_string(), now_ts()],
+        )?;
+
         tx.commit()?;
 
+        let holders_delta = runes_holders.values().sum::<i64>();
+        if holders_delta != 0 {
+            let holders = (self.statistic_to_value_get(&Statistic::Holders).unwrap_or_default() as i64 + holders_delta).max(0);
+            // written through, the indexer may be buffering the next block already
+            let mut batch = WriteBatch::default();
+            self.statistic_to_value_put_with_batch(&mut batch, &Statistic::Holders, holders as u32);
+            self.write_batch(batch)?;
+        }
+
         if has_op {
-            info!("Sqlite updated, {:?}", now.elapsed());
+            info!(elapsed = ?now.elapsed(), "Sqlite updated");
         }
 
-        Ok(())
+        Ok(queued)
     }
 
 
@@ -969,6 +1826,27 @@ impl RunesDB {
         Ok(entry)
     }
 
+    pub fn sqlite_rune_entry_list_by_height(&self, height: u32) -> anyhow::Result<Vec<RuneEntryForQueryInsert>> {
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        let mut stmt = conn.prepare_cached("SELECT * FROM rune_entry WHERE height = ? ORDER BY number")?;
+        let entries = stmt.query_map(params![height], |row| {
+            Self::rune_entry_to_for_query(row)
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Runes etched from `from` to `to` inclusive, newest first.
+    pub fn sqlite_rune_entry_list_etched(&self, from: u32, to: u32, limit: u32) -> anyhow::Result<Vec<RuneEntryForQueryInsert>> {
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        let mut stmt = conn.prepare_cached("SELECT * FROM rune_entry WHERE height BETWEEN ? AND ? ORDER BY height DESC, number DESC LIMIT ?")?;
+        let entries = stmt.query_map(params![from, to, limit], |row| {
+            Self::rune_entry_to_for_query(row)
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
     pub fn sqlite_rune_entry_get_by_etching_txid(&self, txid: &String) -> anyhow::Result<Option<RuneEntryForQueryInsert>> {
         let conn = self.sqlite.get()?;
         let mut stmt = conn.prepare_cached(
@@ -981,18 +1859,114 @@ impl RunesDB {
         Ok(entry)
     }
 
-    fn rune_entry_to_for_query(row: &Row) -> Result<RuneEntryForQueryInsert, rusqlite::Error> {
-        Ok(RuneEntryForQueryInsert {
-            rune_id: row.get("rune_id")?,
-            etching: row.get("etching")?,
-            number: row.get("number")?,
-            rune: row.get("rune")?,
-            spaced_rune: row.get("spaced_rune")?,
-            symbol: row.get("symbol")?,
-            divisibility: row.get("divisibility")?,
-            premine: row.get("premine")?,
-            amount: row.get("amount")?,
-            cap: row.get("cap")?,
+    /// Height of a transaction that created, spent, etched or burned runes.
+    pub fn sqlite_tx_height(&self, txid: &String) -> anyhow::Result<Option<u32>> {
+        let conn = self.sqlite.get()?;
+        let height = conn.query_row(
+            // language=sqlite
+            "SELECT height FROM rune_balance WHERE txid = ?1 \
+             UNION ALL SELECT spent_height FROM rune_balance WHERE spent_txid = ?1 \
+             UNION ALL SELECT height FROM rune_edict WHERE txid = ?1 \
+             UNION ALL SELECT height FROM cenotaph WHERE txid = ?1 \
+             UNION ALL SELECT height FROM rune_entry WHERE etching = ?1 \
+             LIMIT 1",
+            params![txid],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(height)
+    }
+
+    pub fn sqlite_cenotaph_get(&self, txid: &str) -> anyhow::Result<Option<CenotaphForQueryInsert>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT txid, flaw, description, etching, mint, burned, height, idx, ts FROM cenotaph WHERE txid = ?"
+        )?;
+        let mut rows = stmt.query(params![txid])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        Ok(Some(CenotaphForQueryInsert {
+            txid: row.get(0)?,
+            flaw: row.get(1)?,
+            description: row.get(2)?,
+            etching: row.get(3)?,
+            mint: row.get(4)?,
+            burned: serde_json::from_str(&row.get::<_, String>(5)?)?,
+            height: row.get(6)?,
+            idx: row.get(7)?,
+            ts: row.get(8)?,
+        }))
+    }
+
+    pub fn sqlite_inscription_get(&self, inscription_id: &str) -> anyhow::Result<Option<InscriptionForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT i.*, t.txid AS location_txid, t.vout, t.sat_offset, t.address, t.height AS location_height, t.idx AS location_idx, t.ts AS location_ts FROM inscription i \
+             JOIN inscription_transfer t ON t.id = (SELECT MAX(id) FROM inscription_transfer WHERE inscription_id = i.inscription_id) \
+             WHERE i.inscription_id = ?"
+        )?;
+        Ok(stmt.query_row(params![inscription_id], Self::inscription_to_for_query).optional()?)
+    }
+
+    /// Inscriptions an address holds, the latest received first.
+    pub fn sqlite_inscription_paged_by_address(&self, address: &String, cursor: usize, size: usize) -> anyhow::Result<(bool, Vec<InscriptionForQuery>)> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT i.*, t.txid AS location_txid, t.vout, t.sat_offset, t.address, t.height AS location_height, t.idx AS location_idx, t.ts AS location_ts FROM inscription_transfer t \
+             JOIN inscription i ON i.inscription_id = t.inscription_id \
+             WHERE t.address = ?1 AND t.id = (SELECT MAX(id) FROM inscription_transfer WHERE inscription_id = t.inscription_id) \
+             ORDER BY t.id DESC LIMIT ?2 OFFSET ?3"
+        )?;
+        let mut list: Vec<InscriptionForQuery> = stmt.query_map(params![address, size + 1, cursor], Self::inscription_to_for_query)?.collect::<Result<_, _>>()?;
+        let next = list.len() > size;
+        list.truncate(size);
+        Ok((next, list))
+    }
+
+    fn inscription_to_for_query(row: &Row) -> Result<InscriptionForQuery, rusqlite::Error> {
+        let inscription_id: String = row.get("inscription_id")?;
+        Ok(InscriptionForQuery {
+            inscription: InscriptionForInsert {
+                inscription_id: inscription_id.clone(),
+                number: row.get("number")?,
+                txid: row.get("txid")?,
+                input: row.get("input")?,
+                content_type: row.get("content_type")?,
+                content_length: row.get("content_length")?,
+                content_encoding: row.get("content_encoding")?,
+                metaprotocol: row.get("metaprotocol")?,
+                height: row.get("height")?,
+                idx: row.get("idx")?,
+                ts: row.get("ts")?,
+            },
+            location: InscriptionTransferForInsert {
+                inscription_id,
+                txid: row.get("location_txid")?,
+                vout: row.get("vout")?,
+                sat_offset: row.get("sat_offset")?,
+                address: row.get("address")?,
+                height: row.get("location_height")?,
+                idx: row.get("location_idx")?,
+                ts: row.get("location_ts")?,
+            },
+        })
+    }
+
+    fn rune_entry_to_for_query(row: &Row) -> Result<RuneEntryForQueryInsert, rusqlite::Error> {
+        Ok(RuneEntryForQueryInsert {
+            rune_id: row.get("rune_id")?,
+            etching: row.get("etching")?,
+            number: row.get("number")?,
+            rune: row.get("rune")?,
+            spaced_rune: row.get("spaced_rune")?,
+            symbol: row.get("symbol")?,
+            divisibility: row.get("divisibility")?,
+            premine: row.get("premine")?,
+            amount: row.get("amount")?,
+            cap: row.get("cap")?,
             start_height: row.get("start_height")?,
             end_height: row.get("end_height")?,
             start_offset: row.get("start_offset")?,
@@ -1006,23 +1980,138 @@ impl RunesDB {
             mintable: row.get("mintable")?,
             holders: row.get("holders")?,
             transactions: row.get("transactions")?,
+            etcher: row.get("etcher")?,
+            commit_txid: row.get("commit_txid")?,
         })
     }
 
+    /// Rune entries of a compat list page, in `params.sort` order, largest first.
     pub fn sqlite_rune_entry_list_for_compat(&self, params: &RuneEntryCompatPageParams) -> anyhow::Result<Vec<RuneEntryForQueryInsert>> {
+        let query = RuneEntryPageQuery {
+            offset: params.offset as usize,
+            size: params.limit as usize,
+            filter: params.filter()?,
+            sort: params.sort.as_deref().map(str::parse::<RuneEntrySort>).transpose()?.unwrap_or_default(),
+            desc: true,
+            ..Default::default()
+        };
+        let (_, page) = self.sqlite_rune_entry_paged(&query)?;
+        let rune_ids: Vec<String> = page.into_iter().map(|(_, rune_id)| rune_id).collect();
+        let mut entries = self.sqlite_rune_entry_list_by_ids(&rune_ids.iter().cloned().collect())?;
+        entries.sort_by_key(|x| rune_ids.iter().position(|rune_id| *rune_id == x.rune_id));
+        Ok(entries)
+    }
+
+    /// Rune ids of a listing page in `query.sort` order, with whether a next page
+    /// exists. Keyset pages (`after`) stay fast however deep they go.
+    pub fn sqlite_rune_entry_paged(&self, query: &RuneEntryPageQuery) -> anyhow::Result<(bool, Vec<(RuneEntryPageKey, String)>)> {
         let conn = self.sqlite.get()?;
-        let mut sql = "SELECT * FROM rune_entry".to_string();
+        let (mut conditions, mut values) = Self::rune_entry_filter_conditions(&query.filter);
+        let fts_query = query.filter.keywords.as_deref().and_then(Self::rune_entry_fts_query);
+        let sort = match query.sort {
+            RuneEntrySort::Relevance if fts_query.is_none() => RuneEntrySort::Etching,
+            sort => sort,
+        };
+        let columns = sort.columns().join(", ");
+        let op = if query.desc { "<" } else { ">" };
+        let keyset;
+        if let Some(after) = query.after {
+            if sort == RuneEntrySort::Relevance {
+                bail!("keyset pagination is not supported when sorting by relevance");
+            }
+            keyset = if sort == RuneEntrySort::Etching {
+                values.push(Box::new(after.height));
+                format!("(height, number) {op} (?, ?)")
+            } else {
+                // the sort value of the last rune is looked up by its unique number
+                format!("({columns}, number) {op} (SELECT {columns}, number FROM rune_entry WHERE number = ?)")
+            };
+            conditions.push(&keyset);
+            values.push(Box::new(after.number));
+        }
+        let mut sql = "SELECT height, number, rune_id FROM rune_entry".to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        let direction = if query.desc { " DESC" } else { "" };
+        let order = sort.columns().iter().chain(&["number"]).map(|x| format!("{x}{direction}")).collect::<Vec<_>>().join(", ");
+        sql.push_str(&format!(" ORDER BY {order} LIMIT ? OFFSET ?"));
+        if sort == RuneEntrySort::Relevance {
+            values.push(Box::new(fts_query));
+        }
+        values.push(Box::new(query.size as i64 + 1));
+        values.push(Box::new(if query.after.is_some() { 0 } else { query.offset as i64 }));
 
+        let mut stmt = conn.prepare(&sql)?;
+        let mut list: Vec<(RuneEntryPageKey, String)> = stmt.query_map(params_from_iter(values.iter()), |row| {
+            Ok((RuneEntryPageKey { height: row.get(0)?, number: row.get(1)? }, row.get(2)?))
+        })?.map(|x| x.unwrap()).collect();
+        let next = list.len() > query.size;
+        list.truncate(query.size);
+        Ok((next, list))
+    }
 
-        sql.push_str(" order by");
-        let mut stmt = conn.prepare_cached(
-            // language=sqlite
-            "SELECT * FROM rune_entry"
-        )?;
-        let entries = stmt.query_map([], |row| {
-            Self::rune_entry_to_for_query(row)
+    pub fn sqlite_rune_entry_holders_sum(&self) -> anyhow::Result<u32> {
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        let mut stmt = conn.prepare_cached("SELECT IFNULL(SUM(holders), 0) FROM rune_entry")?;
+        Ok(stmt.query_row([], |row| row.get(0))?)
+    }
+
+    /// Etchings per UTC day over the `days` days up to the latest etching.
+    pub fn sqlite_etchings_per_day(&self, days: u32) -> anyhow::Result<Vec<EtchingsPerDay>> {
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        let mut stmt = conn.prepare_cached("SELECT date(ts, 'unixepoch') AS day, COUNT(*) FROM rune_entry WHERE ts >= (SELECT MAX(ts) FROM rune_entry) / 86400 * 86400 - (? - 1) * 86400 GROUP BY day ORDER BY day")?;
+        let res = stmt.query_map(params![days], |row| {
+            Ok(EtchingsPerDay { day: row.get(0)?, etchings: row.get(1)? })
         })?.map(|x| x.unwrap()).collect();
-        Ok(entries)
+        Ok(res)
+    }
+
+    /// Number of runes `sqlite_rune_entry_paged` lists for the same filter.
+    pub fn sqlite_rune_entry_count(&self, filter: &RuneEntryFilter) -> anyhow::Result<u64> {
+        let conn = self.sqlite.get()?;
+        let (conditions, values) = Self::rune_entry_filter_conditions(filter);
+        let mut sql = "SELECT COUNT(*) FROM rune_entry".to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        let count = conn.query_row(&sql, params_from_iter(values.iter()), |row| row.get(0))?;
+        Ok(count)
+    }
+
+    /// FTS5 query matching `keywords` anywhere in a name or symbol, `None` when it
+    /// is shorter than a trigram.
+    fn rune_entry_fts_query(keywords: &str) -> Option<String> {
+        (keywords.chars().count() >= 3).then(|| format!("\"{}\"", keywords.replace('"', "\"\"")))
+    }
+
+    fn rune_entry_filter_conditions(filter: &RuneEntryFilter) -> (Vec<&str>, Vec<Box<dyn ToSql>>) {
+        let mut conditions = vec![];
+        let mut values: Vec<Box<dyn ToSql>> = vec![];
+        if let Some(keywords) = &filter.keywords {
+            if let Some(fts_query) = Self::rune_entry_fts_query(keywords) {
+                conditions.push("(rowid IN (SELECT rowid FROM rune_entry_fts WHERE rune_entry_fts MATCH ?) OR instr(rune_id, ?) > 0)");
+                values.push(Box::new(fts_query));
+            } else {
+                // too short for trigrams
+                conditions.push("(instr(rune, ?) > 0 OR instr(spaced_rune, ?) > 0 OR symbol = ? OR instr(rune_id, ?) > 0)");
+                values.push(Box::new(keywords.to_uppercase()));
+                values.push(Box::new(keywords.to_uppercase()));
+                values.push(Box::new(keywords.clone()));
+            }
+            values.push(Box::new(keywords.clone()));
+        }
+        for (column, flag) in [("mintable = ?", filter.mintable), ("fairmint = ?", filter.fairmint), ("turbo = ?", filter.turbo)] {
+            if let Some(flag) = flag {
+                conditions.push(column);
+                values.push(Box::new(flag));
+            }
+        }
+        (conditions, values)
     }
 
     pub fn sqlite_rune_entry_list_by_ids(&self, rune_ids: &HashSet<String>) -> anyhow::Result<Vec<RuneEntryForQueryInsert>> {
@@ -1061,6 +2150,206 @@ impl RunesDB {
         Ok(entries)
     }
 
+    /// Every unspent balance as (rune_id, txid, vout, rune_amount).
+    pub fn sqlite_rune_balance_list_unspent(&self) -> anyhow::Result<Vec<(String, String, u32, String)>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT rune_id, txid, vout, rune_amount FROM rune_balance WHERE spent_height = 0"
+        )?;
+        let entries = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Unspent balance per address of a rune, largest first, with the number of
+    /// holders. Amounts are u128 text that SQLite can't sum exactly, so the
+    /// balances are summed and ordered here.
+    pub fn sqlite_rune_holders_paged(&self, rune_id: &String, offset: usize, size: usize) -> anyhow::Result<(u64, Vec<(String, u128)>)> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT address, rune_amount FROM rune_balance WHERE rune_id = ? AND spent_height = 0"
+        )?;
+        let mut balances: HashMap<String, u128> = HashMap::new();
+        for row in stmt.query_map(params![rune_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (address, amount) = row?;
+            *balances.entry(address).or_default() += amount.parse::<u128>()?;
+        }
+        let total = balances.len() as u64;
+        let mut holders: Vec<(String, u128)> = balances.into_iter().collect();
+        holders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok((total, holders.into_iter().skip(offset).take(size).collect()))
+    }
+
+    /// Unspent balance rows of `address`, restricted to outputs holding `rune_id`
+    /// with at least `min_amount` when given. All balances of a matched output
+    /// are returned. `limit` caps the number of outputs.
+    pub fn sqlite_rune_balance_list_unspent_by_address_filtered(
+        &self,
+        address: &String,
+        rune_id: Option<&String>,
+        min_amount: Option<u128>,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<RuneBalanceForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut filter = "address = ?1 AND spent_height = 0".to_string();
+        if rune_id.is_some() {
+            filter.push_str(" AND rune_id = ?2");
+        }
+        if min_amount.is_some() {
+            // amounts are decimal text, compare by length first so the order is numeric
+            filter.push_str(" AND (length(rune_amount) > length(?3) OR (length(rune_amount) = length(?3) AND rune_amount >= ?3))");
+        }
+        let sql = format!(
+            "SELECT * FROM rune_balance WHERE address = ?1 AND spent_height = 0 AND (txid, vout) IN \
+             (SELECT DISTINCT txid, vout FROM rune_balance WHERE {} ORDER BY height, idx, vout LIMIT ?4)",
+            filter
+        );
+        let mut stmt = conn.prepare_cached(&sql)?;
+        let limit = limit.map(|x| x as i64).unwrap_or(-1);
+        let entries = stmt.query_map(params![address, rune_id, min_amount.map(|x| x.to_string()), limit], |row| {
+            Self::rune_balance_to_for_query(row)
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Distinct transactions that created or spent outputs of a rune, newest first,
+    /// as (txid, height). Returns whether there is a next page.
+    pub fn sqlite_rune_tx_paged(&self, rune_id: &String, cursor: usize, size: usize) -> anyhow::Result<(bool, Vec<(String, u32)>)> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT txid, height FROM rune_balance WHERE rune_id = ?1 \
+             UNION \
+             SELECT spent_txid, spent_height FROM rune_balance WHERE rune_id = ?1 AND spent_height > 0 \
+             ORDER BY height DESC, txid LIMIT ?2 OFFSET ?3"
+        )?;
+        let mut list: Vec<(String, u32)> = stmt.query_map(params![rune_id, size + 1, cursor], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?.map(|x| x.unwrap()).collect();
+        let next = list.len() > size;
+        list.truncate(size);
+        Ok((next, list))
+    }
+
+    /// Transactions affecting a rune from `from` to `to` inclusive, oldest first.
+    pub fn sqlite_rune_tx_range(&self, rune_id: &String, from: u32, to: u32, cursor: usize, size: usize) -> anyhow::Result<(bool, Vec<(String, u32)>)> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT txid, height FROM rune_balance WHERE rune_id = ?1 AND height BETWEEN ?2 AND ?3 \
+             UNION \
+             SELECT spent_txid, spent_height FROM rune_balance WHERE rune_id = ?1 AND spent_height BETWEEN ?2 AND ?3 AND spent_height > 0 \
+             ORDER BY height, txid LIMIT ?4 OFFSET ?5"
+        )?;
+        let mut list: Vec<(String, u32)> = stmt.query_map(params![rune_id, from, to, size + 1, cursor], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?.map(|x| x.unwrap()).collect();
+        let next = list.len() > size;
+        list.truncate(size);
+        Ok((next, list))
+    }
+
+    /// Transactions that minted a rune, oldest first, as (txid, height, minter).
+    /// The `mint` flag is set on every row of a minting transaction, so rows of
+    /// transactions that minted another rune are told apart by their outputs
+    /// exceeding their inputs. The minter is the address of the first output.
+    pub fn sqlite_rune_mint_paged(&self, rune_id: &String, cursor: usize, size: usize) -> anyhow::Result<(bool, Vec<RuneMint>)> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT b.txid, b.height, b.address, MIN(b.vout) FROM rune_balance b WHERE b.rune_id = ?1 AND b.mint GROUP BY b.txid \
+             HAVING SUM(CAST(b.rune_amount AS REAL)) > IFNULL((SELECT SUM(CAST(s.rune_amount AS REAL)) FROM rune_balance s WHERE s.spent_txid = b.txid AND s.rune_id = ?1), 0) \
+             ORDER BY b.height, b.idx LIMIT ?2 OFFSET ?3"
+        )?;
+        let mut list: Vec<RuneMint> = stmt.query_map(params![rune_id, size + 1, cursor], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?.map(|x| x.unwrap()).collect();
+        let next = list.len() > size;
+        list.truncate(size);
+        Ok((next, list))
+    }
+
+    /// Activity of an address, newest first, with the total number of entries.
+    pub fn sqlite_rune_activity_by_address(&self, address: &String, offset: usize, limit: usize) -> anyhow::Result<(u64, Vec<RuneActivityForQuery>)> {
+        let conn = self.sqlite.get()?;
+        let total = conn.query_row(
+            // language=sqlite
+            "SELECT (SELECT COUNT(*) FROM rune_balance WHERE address = ?1) + (SELECT COUNT(*) FROM rune_balance WHERE address = ?1 AND spent_height > 0)",
+            params![address],
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT rune_id, txid, vout, rune_amount, CASE WHEN premine THEN 'etching' WHEN mint THEN 'mint' ELSE 'receive' END AS operation, height, idx, ts \
+             FROM rune_balance WHERE address = ?1 \
+             UNION ALL \
+             SELECT rune_id, spent_txid, NULL, rune_amount, 'send', spent_height, NULL, IFNULL(spent_ts, 0) FROM rune_balance WHERE address = ?1 AND spent_height > 0 \
+             ORDER BY height DESC, operation, txid, vout LIMIT ?2 OFFSET ?3"
+        )?;
+        let list = stmt.query_map(params![address, limit, offset], |row| {
+            Ok(RuneActivityForQuery {
+                rune_id: row.get(0)?,
+                txid: row.get(1)?,
+                vout: row.get(2)?,
+                rune_amount: row.get(3)?,
+                operation: row.get(4)?,
+                height: row.get(5)?,
+                idx: row.get(6)?,
+                ts: row.get(7)?,
+            })
+        })?.map(|x| x.unwrap()).collect();
+        Ok((total, list))
+    }
+
+    /// Balance rows of a rune created or spent by any of `txids`.
+    pub fn sqlite_rune_balance_list_by_rune_id_txids(&self, rune_id: &String, txids: &[String]) -> anyhow::Result<Vec<RuneBalanceForQuery>> {
+        if txids.is_empty() {
+            return Ok(vec![]);
+        }
+        let conn = self.sqlite.get()?;
+        let placeholders = txids.iter().map(|_| "?").collect::<Vec<&str>>().join(",");
+        let mut stmt = conn.prepare(
+            &format!("SELECT * FROM rune_balance WHERE rune_id = ? AND (txid IN ({0}) OR spent_txid IN ({0}))", placeholders)
+        )?;
+        let params = std::iter::once(rune_id).chain(txids.iter()).chain(txids.iter());
+        let entries = stmt.query_map(params_from_iter(params), |row| {
+            Self::rune_balance_to_for_query(row)
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Outputs held by `address` that were created at or before `height` and
+    /// not yet spent at that height.
+    pub fn sqlite_rune_balance_list_unspent_by_address_at_height(&self, address: &String, height: u32) -> anyhow::Result<Vec<RuneBalanceForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM rune_balance WHERE address = ? AND height <= ? AND (spent_height = 0 OR spent_height > ?)"
+        )?;
+        let entries = stmt.query_map(params![address, height, height], |row| {
+            Self::rune_balance_to_for_query(row)
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Streams every output created at or before `height` and unspent at that
+    /// height, grouped by rune and then address.
+    pub fn sqlite_rune_balance_for_each_unspent_at_height(&self, height: u32, mut f: impl FnMut(RuneBalanceForQuery) -> anyhow::Result<()>) -> anyhow::Result<()> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM rune_balance WHERE height <= ?1 AND (spent_height = 0 OR spent_height > ?1) ORDER BY rune_id, address, id"
+        )?;
+        let mut rows = stmt.query(params![height])?;
+        while let Some(row) = rows.next()? {
+            f(Self::rune_balance_to_for_query(row)?)?;
+        }
+        Ok(())
+    }
+
     fn rune_balance_to_for_query(row: &Row) -> Result<RuneBalanceForQuery, rusqlite::Error> {
         Ok(RuneBalanceForQuery {
             id: row.get("id")?,
@@ -1084,4 +2373,2064 @@ impl RunesDB {
             spent_vin: row.get("spent_vin")?,
         })
     }
-}
\ No newline at end of file
+
+    pub fn sqlite_api_key_insert(&self, key: &ApiKeyForInsert) -> anyhow::Result<u32> {
+        let conn = self.sqlite.get()?;
+        conn.execute(
+            "INSERT INTO api_key (name, key_hash, key_prefix, tier, rate_limit_per_minute, daily_quota, created_ts) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![key.name, key.key_hash, key.key_prefix, key.tier, key.rate_limit_per_minute, key.daily_quota, key.created_ts],
+        )?;
+        Ok(conn.last_insert_rowid() as _)
+    }
+
+    pub fn sqlite_api_key_list(&self) -> anyhow::Result<Vec<ApiKeyForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM api_key ORDER BY id"
+        )?;
+        let entries = stmt.query_map([], |row| {
+            Ok(ApiKeyForQuery {
+                id: row.get("id")?,
+                name: row.get("name")?,
+                key_hash: row.get("key_hash")?,
+                key_prefix: row.get("key_prefix")?,
+                tier: row.get("tier")?,
+                rate_limit_per_minute: row.get("rate_limit_per_minute")?,
+                daily_quota: row.get("daily_quota")?,
+                created_ts: row.get("created_ts")?,
+                revoked: row.get("revoked")?,
+            })
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    pub fn sqlite_api_key_revoke(&self, id: u32) -> anyhow::Result<bool> {
+        let conn = self.sqlite.get()?;
+        let updated = conn.execute("UPDATE api_key SET revoked = true WHERE id = ? AND revoked = false", params![id])?;
+        Ok(updated > 0)
+    }
+
+    pub fn sqlite_block_stats_insert(&self, stats: &BlockStats) -> anyhow::Result<()> {
+        let conn = self.sqlite.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO block_stats (height, txs, runestones, fetch_us, update_us, sqlite_us, cache_us, total_us, ts) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![stats.height, stats.txs, stats.runestones, stats.fetch_us, stats.update_us, stats.sqlite_us, stats.cache_us, stats.total_us, stats.ts],
+        )?;
+        Ok(())
+    }
+
+    /// Stats of the blocks from `from` to `to`, both inclusive.
+    pub fn sqlite_block_stats_list(&self, from: u32, to: u32) -> anyhow::Result<Vec<BlockStats>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM block_stats WHERE height >= ? AND height <= ? ORDER BY height"
+        )?;
+        let entries = stmt.query_map(params![from, to], |row| {
+            Ok(BlockStats {
+                height: row.get("height")?,
+                txs: row.get("txs")?,
+                runestones: row.get("runestones")?,
+                fetch_us: row.get("fetch_us")?,
+                update_us: row.get("update_us")?,
+                sqlite_us: row.get("sqlite_us")?,
+                cache_us: row.get("cache_us")?,
+                total_us: row.get("total_us")?,
+                ts: row.get("ts")?,
+            })
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    pub fn sqlite_webhook_insert(&self, webhook: &WebhookForInsert) -> anyhow::Result<u32> {
+        let conn = self.sqlite.get()?;
+        conn.execute(
+            "INSERT INTO webhook (url, event, secret, created_ts) VALUES (?, ?, ?, ?)",
+            params![webhook.url, webhook.event, webhook.secret, webhook.created_ts],
+        )?;
+        Ok(conn.last_insert_rowid() as _)
+    }
+
+    pub fn sqlite_webhook_list(&self) -> anyhow::Result<Vec<WebhookForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM webhook WHERE deleted = false ORDER BY id"
+        )?;
+        let entries = stmt.query_map([], |row| {
+            Ok(WebhookForQuery {
+                id: row.get("id")?,
+                url: row.get("url")?,
+                event: row.get("event")?,
+                secret: row.get("secret")?,
+                created_ts: row.get("created_ts")?,
+            })
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    pub fn sqlite_webhook_delete(&self, id: u32) -> anyhow::Result<bool> {
+        let conn = self.sqlite.get()?;
+        let updated = conn.execute("UPDATE webhook SET deleted = true WHERE id = ? AND deleted = false", params![id])?;
+        conn.execute("UPDATE webhook_delivery SET failed = true, error = 'webhook deleted' WHERE webhook_id = ? AND delivered = false AND failed = false", params![id])?;
+        Ok(updated > 0)
+    }
+
+    pub fn sqlite_watch_insert(&self, watch: &WatchForInsert) -> anyhow::Result<u32> {
+        let conn = self.sqlite.get()?;
+        conn.execute(
+            "INSERT INTO watch (address, webhook_id, api_key_id, created_ts) VALUES (?, ?, ?, ?)",
+            params![watch.address, watch.webhook_id, watch.api_key_id, watch.created_ts],
+        )?;
+        Ok(conn.last_insert_rowid() as _)
+    }
+
+    /// Watches of the key `api_key_id`, or every watch if unset.
+    pub fn sqlite_watch_list(&self, api_key_id: Option<u32>) -> anyhow::Result<Vec<WatchForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM watch WHERE deleted = false AND (?1 IS NULL OR api_key_id = ?1) ORDER BY id"
+        )?;
+        let entries = stmt.query_map(params![api_key_id], |row| {
+            Ok(WatchForQuery {
+                id: row.get("id")?,
+                address: row.get("address")?,
+                webhook_id: row.get("webhook_id")?,
+                api_key_id: row.get("api_key_id")?,
+                created_ts: row.get("created_ts")?,
+            })
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Distinct addresses with at least one watch of the key `api_key_id`, or
+    /// of any key if unset.
+    pub fn sqlite_watch_addresses(&self, api_key_id: Option<u32>) -> anyhow::Result<Vec<String>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT DISTINCT address FROM watch WHERE deleted = false AND (?1 IS NULL OR api_key_id = ?1) ORDER BY address"
+        )?;
+        let entries = stmt.query_map(params![api_key_id], |row| row.get(0))?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Deletes the watch and the webhook created with it, if the watch belongs
+    /// to the key `api_key_id` or that is unset.
+    pub fn sqlite_watch_delete(&self, id: u32, api_key_id: Option<u32>) -> anyhow::Result<bool> {
+        let conn = self.sqlite.get()?;
+        let webhook_id: Option<u32> = conn
+            .query_row("SELECT webhook_id FROM watch WHERE id = ?1 AND deleted = false AND (?2 IS NULL OR api_key_id = ?2)", params![id, api_key_id], |row| row.get(0))
+            .optional()?
+            .flatten();
+        let updated = conn.execute("UPDATE watch SET deleted = true WHERE id = ?1 AND deleted = false AND (?2 IS NULL OR api_key_id = ?2)", params![id, api_key_id])?;
+        drop(conn);
+        if let Some(webhook_id) = webhook_id.filter(|_| updated > 0) {
+            self.sqlite_webhook_delete(webhook_id)?;
+        }
+        Ok(updated > 0)
+    }
+
+    pub fn sqlite_webhook_delivery_insert_batch(&self, deliveries: &[WebhookDeliveryForInsert]) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.get()?;
+        let tx = conn.transaction()?;
+        Self::webhook_delivery_insert(&tx, deliveries)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn webhook_delivery_insert(conn: &Connection, deliveries: &[WebhookDeliveryForInsert]) -> anyhow::Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO webhook_delivery (webhook_id, height, payload, next_attempt_ts, created_ts, updated_ts) VALUES (?, ?, ?, ?, ?, ?)")?;
+        for x in deliveries {
+            stmt.execute(params![x.webhook_id, x.height, x.payload, x.created_ts, x.created_ts, x.created_ts])?;
+        }
+        Ok(())
+    }
+
+    pub fn sqlite_webhook_delivery_due(&self, now: u64, limit: u32) -> anyhow::Result<Vec<WebhookDeliveryForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM webhook_delivery WHERE delivered = false AND failed = false AND next_attempt_ts <= ? ORDER BY id LIMIT ?"
+        )?;
+        let entries = stmt.query_map(params![now, limit], Self::webhook_delivery_to_for_query)?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    pub fn sqlite_webhook_delivery_list(&self, webhook_id: u32, limit: u32) -> anyhow::Result<Vec<WebhookDeliveryForQuery>> {
+        let conn = self.sqlite.get()?;
+        let mut stmt = conn.prepare_cached(
+            // language=sqlite
+            "SELECT * FROM webhook_delivery WHERE webhook_id = ? ORDER BY id DESC LIMIT ?"
+        )?;
+        let entries = stmt.query_map(params![webhook_id, limit], Self::webhook_delivery_to_for_query)?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    pub fn sqlite_webhook_delivery_update(&self, delivery: &WebhookDeliveryForQuery) -> anyhow::Result<()> {
+        let conn = self.sqlite.get()?;
+        conn.execute(
+            "UPDATE webhook_delivery SET attempts = ?, next_attempt_ts = ?, status_code = ?, error = ?, delivered = ?, failed = ?, updated_ts = ? WHERE id = ?",
+            params![delivery.attempts, delivery.next_attempt_ts, delivery.status_code, delivery.error, delivery.delivered, delivery.failed, delivery.updated_ts, delivery.id],
+        )?;
+        Ok(())
+    }
+
+    fn webhook_delivery_to_for_query(row: &Row) -> Result<WebhookDeliveryForQuery, rusqlite::Error> {
+        Ok(WebhookDeliveryForQuery {
+            id: row.get("id")?,
+            webhook_id: row.get("webhook_id")?,
+            height: row.get("height")?,
+            payload: row.get("payload")?,
+            attempts: row.get("attempts")?,
+            next_attempt_ts: row.get("next_attempt_ts")?,
+            status_code: row.get("status_code")?,
+            error: row.get("error")?,
+            delivered: row.get("delivered")?,
+            failed: row.get("failed")?,
+            created_ts: row.get("created_ts")?,
+            updated_ts: row.get("updated_ts")?,
+        })
+    }
+
+    /// Rune outputs received (`spent = false`) or spent (`spent = true`) at `height`.
+    /// Outputs that received runes by transfer at `height`, and the
+    /// transactions that created them.
+    pub fn sqlite_transfer_count_at_height(&self, height: u32) -> anyhow::Result<(u32, u32)> {
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        let mut stmt = conn.prepare_cached("SELECT COUNT(*), COUNT(DISTINCT txid) FROM rune_balance WHERE height = ? AND transfer")?;
+        Ok(stmt.query_row(params![height], |row| Ok((row.get(0)?, row.get(1)?)))?)
+    }
+
+    pub fn sqlite_address_transfers_at_height(&self, conn: &Connection, height: u32, spent: bool) -> anyhow::Result<Vec<AddressTransferForQuery>> {
+        let sql = if spent {
+            "SELECT address, rune_id, rune_amount, txid, vout, spent_txid FROM rune_balance WHERE spent_height = ?"
+        } else {
+            "SELECT address, rune_id, rune_amount, txid, vout, spent_txid FROM rune_balance WHERE height = ?"
+        };
+        let mut stmt = conn.prepare_cached(sql)?;
+        let entries = stmt.query_map(params![height], |row| {
+            Ok(AddressTransferForQuery {
+                address: row.get("address")?,
+                rune_id: row.get("rune_id")?,
+                rune_amount: row.get("rune_amount")?,
+                txid: row.get("txid")?,
+                vout: row.get("vout")?,
+                spent_txid: row.get("spent_txid")?,
+            })
+        })?.map(|x| x.unwrap()).collect();
+        Ok(entries)
+    }
+
+    /// Logs a revert event for each committed block from `height` on, newest
+    /// first, and drops their commit markers.
+    fn sqlite_rune_event_revert_from_height(&self, conn: &mut Connection, height: u32) -> anyhow::Result<usize> {
+        let tx = conn.transaction()?;
+        let blocks: Vec<(u32, String)> = {
+            // language=sqlite
+            let mut stmt = tx.prepare_cached("SELECT height, block_hash FROM block_commit WHERE height >= ? ORDER BY height DESC")?;
+            let res = stmt.query_map(params![height], |row| Ok((row.get(0)?, row.get(1)?)))?.map(|x| x.unwrap()).collect();
+            res
+        };
+        {
+            let ts = now_ts();
+            let data = serde_json::to_string(&RuneEvent::Revert)?;
+            let mut stmt = tx.prepare_cached("INSERT INTO rune_event (height, block_hash, type, data, ts) VALUES (?, ?, ?, ?, ?)")?;
+            for (height, block_hash) in &blocks {
+                stmt.execute(params![height, block_hash, RuneEvent::Revert.kind(), data, ts])?;
+            }
+        }
+        tx.execute("DELETE FROM block_commit WHERE height >= ?", params![height])?;
+        tx.commit()?;
+        Ok(blocks.len())
+    }
+
+    /// Events after `since_seq` in log order.
+    pub fn sqlite_rune_event_list(&self, since_seq: u64, limit: u32) -> anyhow::Result<Vec<RuneEventForQuery>> {
+        let conn = self.sqlite.get()?;
+        // language=sqlite
+        let mut stmt = conn.prepare_cached("SELECT seq, height, block_hash, data, ts FROM rune_event WHERE seq > ? ORDER BY seq LIMIT ?")?;
+        let res = stmt.query_map(params![since_seq, limit], |row| {
+            let data: String = row.get(3)?;
+            Ok(RuneEventForQuery {
+                seq: row.get(0)?,
+                height: row.get(1)?,
+                block_hash: row.get(2)?,
+                event: serde_json::from_str(&data).unwrap_or_default(),
+                ts: row.get(4)?,
+            })
+        })?.map(|x| x.unwrap()).collect();
+        Ok(res)
+    }
+
+    /// Highest height in each height keyed sqlite table, empty tables are left out.
+    pub fn sqlite_max_heights(&self) -> anyhow::Result<Vec<(&'static str, u32)>> {
+        let conn = self.sqlite.get()?;
+        let mut heights = vec![];
+        for (table, sql) in [
+            ("rune_entry", "SELECT MAX(height) FROM rune_entry"),
+            ("rune_balance", "SELECT MAX(height) FROM rune_balance"),
+            ("rune_balance spent", "SELECT MAX(spent_height) FROM rune_balance"),
+            ("block_commit", "SELECT MAX(height) FROM block_commit"),
+            ("rune_counter_delta", "SELECT MAX(height) FROM rune_counter_delta"),
+            ("rune_edict", "SELECT MAX(height) FROM rune_edict"),
+            ("cenotaph", "SELECT MAX(height) FROM cenotaph"),
+            ("inscription", "SELECT MAX(height) FROM inscription"),
+            ("inscription_transfer", "SELECT MAX(height) FROM inscription_transfer"),
+        ] {
+            let height: Option<u32> = conn.prepare_cached(sql)?.query_row([], |row| row.get(0))?;
+            if let Some(height) = height {
+                heights.push((table, height));
+            }
+        }
+        Ok(heights)
+    }
+
+    pub fn sqlite_block_commit_latest(&self) -> anyhow::Result<Option<(u32, String)>> {
+        let conn = self.sqlite.get()?;
+        let latest = conn.query_row(
+            "SELECT height, block_hash FROM block_commit ORDER BY height DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?;
+        Ok(latest)
+    }
+
+    /// Brings both stores back to the same block after a crash between the rocksdb
+    /// and the sqlite commit of a block, the diverging blocks are rolled back and
+    /// indexed again.
+    pub fn reconcile_commit(&self) -> anyhow::Result<()> {
+        let Some(rocksdb_height) = self.latest_indexed_height() else {
+            return Ok(());
+        };
+        let Some((sqlite_height, sqlite_hash)) = self.sqlite_block_commit_latest()? else {
+            // indexed before commit markers existed
+            let header = self.height_to_block_header_get(rocksdb_height)
+                .with_context(|| format!("missing block header of the indexed tip {}", rocksdb_height))?;
+            let conn = self.sqlite.get()?;
+            conn.execute(
+                "INSERT INTO block_commit (height, block_hash, ts) VALUES (?, ?, ?)",
+                params![rocksdb_height, header.block_hash().to_string(), now_ts()],
+            )?;
+            return Ok(());
+        };
+        let same_block = self.height_to_block_header_get(sqlite_height)
+            .is_some_and(|header| header.block_hash().to_string() == sqlite_hash);
+        if sqlite_height == rocksdb_height && same_block {
+            return Ok(());
+        }
+        let height = if same_block {
+            sqlite_height.min(rocksdb_height) + 1
+        } else {
+            sqlite_height.min(rocksdb_height)
+        };
+        if rocksdb_height.saturating_sub(height) >= REORG_DEPTH {
+            bail!("rocksdb at {} and sqlite at {} diverged beyond the reorg window, reindex required", rocksdb_height, sqlite_height);
+        }
+        warn!(rocksdb_height, sqlite_height, "Stores diverged, rolling back to {}", height - 1);
+        self.reorg_to_height(height, self.latest_height().unwrap_or(rocksdb_height))
+    }
+}
+
+/// Point reads and writes of the rocksdb CFs and the typed accessors over them.
+/// [`RunesDB`] goes straight to rocksdb, a [`BlockWriter`] keeps the writes of
+/// the block being indexed to itself until it is committed.
+pub trait Store {
+    fn get(&self, cf_name: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    fn multi_get(&self, cf_name: &str, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error>;
+
+    fn put(&self, cf_name: &str, key: &[u8], value: &[u8]) -> Result<(), Error>;
+
+    fn del(&self, cf_name: &str, key: &[u8]) -> Result<(), Error>;
+
+    fn height_to_state_hash_get(&self, height: u32) -> Option<[u8; 32]> {
+        self.get(HEIGHT_TO_STATE_HASH, &height.encode())
+            .map(|opt| opt.map(|bytes| bytes.try_into().unwrap())).unwrap()
+    }
+
+    fn statistic_to_value_put(&self, statistic: &Statistic, value: u32) {
+        self.put(STATISTIC_TO_VALUE, &StatisticKey::from(*statistic).encode(), &value.to_be_bytes()).unwrap()
+    }
+
+    fn statistic_to_value_get(&self, statistic: &Statistic) -> Option<u32> {
+        self.get(STATISTIC_TO_VALUE, &StatisticKey::from(*statistic).encode())
+            .map(|opt| opt.map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    }
+
+    fn statistic_to_value_inc(&self, statistic: &Statistic) {
+        let current = self.statistic_to_value_get(statistic).unwrap_or_default() + 1;
+        self.put(STATISTIC_TO_VALUE, &StatisticKey::from(*statistic).encode(), &current.to_be_bytes()).unwrap()
+    }
+
+    /// Amount totals don't fit the u32 counters, these are stored as u128.
+    fn statistic_to_total_put(&self, statistic: &Statistic, value: u128) {
+        self.put(STATISTIC_TO_VALUE, &StatisticKey::from(*statistic).encode(), &value.to_be_bytes()).unwrap()
+    }
+
+    fn statistic_to_total_get(&self, statistic: &Statistic) -> Option<u128> {
+        self.get(STATISTIC_TO_VALUE, &StatisticKey::from(*statistic).encode())
+            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    }
+
+    /// Indexes an output with runes under its script, `value` in sats.
+    fn spk_to_outpoints_put(&self, script: &Script, outpoint: &OutPoint, height: u32, value: u64) {
+        let script_hash = script_hash(script);
+        let key = ScriptHashOutPointKey { script_hash, outpoint: *outpoint };
+        self.put(SPK_TO_OUTPOINTS, &key.encode(), &[&height.to_be_bytes()[..], &value.to_be_bytes()].concat()).unwrap();
+        self.put(OUTPOINT_TO_SCRIPT_HASH, &outpoint.encode(), &[&script_hash[..], &value.to_be_bytes()].concat()).unwrap()
+    }
+
+    /// Removes a spent output from the outputs of its script, its script hash
+    /// stays for reorgs.
+    fn spk_to_outpoints_del(&self, outpoint: &OutPoint) {
+        if let Some((script_hash, _)) = self.outpoint_to_script_hash_get(outpoint) {
+            self.del(SPK_TO_OUTPOINTS, &ScriptHashOutPointKey { script_hash, outpoint: *outpoint }.encode()).unwrap()
+        }
+    }
+
+    /// Script hash and value of an output with runes.
+    fn outpoint_to_script_hash_get(&self, outpoint: &OutPoint) -> Option<([u8; 32], u64)> {
+        self.get(OUTPOINT_TO_SCRIPT_HASH, &outpoint.encode())
+            .map(|opt| opt.map(|bytes| (bytes[0..32].try_into().unwrap(), u64::from_be_bytes(bytes[32..40].try_into().unwrap())))).unwrap()
+    }
+
+    fn rune_id_to_outpoints_put(&self, rune_id: &RuneId, outpoint: &OutPoint, amount: u128) {
+        self.put(RUNE_ID_TO_OUTPOINTS, &RuneIdOutPointKey { rune_id: *rune_id, outpoint: *outpoint }.encode(), &amount.to_be_bytes()).unwrap()
+    }
+
+    fn rune_id_to_outpoints_del(&self, rune_id: &RuneId, outpoint: &OutPoint) {
+        self.del(RUNE_ID_TO_OUTPOINTS, &RuneIdOutPointKey { rune_id: *rune_id, outpoint: *outpoint }.encode()).unwrap()
+    }
+
+    /// Adds the amounts of a new output to the rune balances of its script.
+    fn script_hash_rune_id_to_balance_add(&self, script_hash: &[u8; 32], balances: &[(RuneId, u128)]) {
+        for (rune_id, amount) in balances {
+            let key = ScriptHashRuneIdKey { script_hash: *script_hash, rune_id: *rune_id };
+            let (total, outputs) = self.script_hash_rune_id_to_balance_get_by_key(&key).unwrap_or_default();
+            self.put(SCRIPT_HASH_RUNE_ID_TO_BALANCE, &key.encode(), &encode_script_balance((total + amount, outputs + 1))).unwrap()
+        }
+    }
+
+    /// Subtracts the amounts of a spent output from the rune balances of its
+    /// script, a rune without outputs left is removed.
+    fn script_hash_rune_id_to_balance_sub(&self, script_hash: &[u8; 32], balances: &[(RuneId, u128)]) {
+        for (rune_id, amount) in balances {
+            let key = ScriptHashRuneIdKey { script_hash: *script_hash, rune_id: *rune_id };
+            let (total, outputs) = self.script_hash_rune_id_to_balance_get_by_key(&key).unwrap_or_default();
+            if outputs <= 1 {
+                self.del(SCRIPT_HASH_RUNE_ID_TO_BALANCE, &key.encode()).unwrap();
+            } else {
+                self.put(SCRIPT_HASH_RUNE_ID_TO_BALANCE, &key.encode(), &encode_script_balance((total.saturating_sub(*amount), outputs - 1))).unwrap()
+            }
+        }
+    }
+
+    fn script_hash_rune_id_to_balance_get_by_key(&self, key: &ScriptHashRuneIdKey) -> Option<(u128, u32)> {
+        self.get(SCRIPT_HASH_RUNE_ID_TO_BALANCE, &key.encode())
+            .map(|opt| opt.map(|bytes| decode_script_balance(&bytes))).unwrap()
+    }
+
+    /// Balance of `rune_id` over the unspent outputs of `script` as (amount, outputs).
+    fn script_hash_rune_id_to_balance_get(&self, script: &Script, rune_id: &RuneId) -> Option<(u128, u32)> {
+        self.script_hash_rune_id_to_balance_get_by_key(&ScriptHashRuneIdKey { script_hash: script_hash(script), rune_id: *rune_id })
+    }
+
+    fn rune_id_to_mints_put(&self, key: &RuneId, value: u128) {
+        self.put(RUNE_ID_TO_MINTS, &key.store_bytes(), &value.to_be_bytes()).unwrap()
+    }
+
+    fn rune_id_to_mints_get(&self, key: &RuneId) -> Option<u128> {
+        self.get(RUNE_ID_TO_MINTS, &key.store_bytes())
+            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    }
+
+    fn rune_id_to_mints_inc(&self, key: &RuneId) -> u128 {
+        let current = self.rune_id_to_mints_get(key).unwrap_or_default() + 1;
+        self.put(RUNE_ID_TO_MINTS, &key.store_bytes(), &current.to_be_bytes()).unwrap();
+        current
+    }
+
+    fn rune_id_to_burned_put(&self, key: &RuneId, value: u128) {
+        self.put(RUNE_ID_TO_BURNED, &key.store_bytes(), &value.to_be_bytes()).unwrap()
+    }
+
+    fn rune_id_to_burned_get(&self, key: &RuneId) -> Option<u128> {
+        self.get(RUNE_ID_TO_BURNED, &key.store_bytes())
+            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    }
+
+    fn rune_id_to_burned_add(&self, key: &RuneId, amount: u128) -> u128 {
+        let current = self.rune_id_to_burned_get(key).unwrap_or_default() + amount;
+        self.put(RUNE_ID_TO_BURNED, &key.store_bytes(), &current.to_be_bytes()).unwrap();
+        current
+    }
+
+    fn height_rune_id_to_mints_get(&self, height: u32, rune_id: &RuneId) -> Option<u128> {
+        self.get(HEIGHT_RUNE_ID_TO_MINTS, &HeightRuneIdKey { height, rune_id: *rune_id }.encode())
+            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    }
+
+    fn height_rune_id_to_mints_inc(&self, height: u32, rune_id: &RuneId) {
+        let current = self.height_rune_id_to_mints_get(height, rune_id).unwrap_or_default() + 1;
+        self.put(HEIGHT_RUNE_ID_TO_MINTS, &HeightRuneIdKey { height, rune_id: *rune_id }.encode(), &current.to_be_bytes()).unwrap()
+    }
+
+    fn height_rune_id_to_burned_put(&self, height: u32, rune_id: &RuneId, value: u128) {
+        self.put(HEIGHT_RUNE_ID_TO_BURNED, &HeightRuneIdKey { height, rune_id: *rune_id }.encode(), &value.to_be_bytes()).unwrap()
+    }
+
+    fn height_rune_id_to_burned_get(&self, height: u32, rune_id: &RuneId) -> Option<u128> {
+        self.get(HEIGHT_RUNE_ID_TO_BURNED, &HeightRuneIdKey { height, rune_id: *rune_id }.encode())
+            .map(|opt| opt.map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    }
+
+    fn outpoint_to_rune_balances_put(&self, key: &OutPoint, value: RuneBalanceEntry) {
+        self.put(OUTPOINT_TO_RUNE_BALANCES, &key.store(), &value.store_bytes()).unwrap()
+    }
+
+    fn outpoint_to_rune_balances_get(&self, key: &OutPoint) -> Option<RuneBalanceEntry> {
+        self.get(OUTPOINT_TO_RUNE_BALANCES, &key.store())
+            .map(|opt| opt.map(|bytes| RuneBalanceEntry::load_bytes(&bytes))).unwrap()
+    }
+
+    fn outpoint_to_rune_balances_multi_get(&self, keys: &[OutPoint]) -> Vec<Option<RuneBalanceEntry>> {
+        let keys: Vec<Vec<u8>> = keys.iter().map(|x| x.encode()).collect();
+        self.multi_get(OUTPOINT_TO_RUNE_BALANCES, &keys).unwrap()
+            .into_iter()
+            .map(|opt| opt.map(|bytes| RuneBalanceEntry::load_bytes(&bytes)))
+            .collect()
+    }
+
+    fn outpoint_to_inscriptions_get(&self, key: &OutPoint) -> Vec<(InscriptionId, u64)> {
+        let value = self.get(OUTPOINT_TO_INSCRIPTIONS, &key.encode()).unwrap().unwrap_or_default();
+        value.chunks(44)
+            .map(|x| (InscriptionId::load(x[0..36].try_into().unwrap()), u64::from_be_bytes(x[36..44].try_into().unwrap())))
+            .collect()
+    }
+
+    /// Replaces the inscriptions of an output, none deletes it.
+    fn outpoint_to_inscriptions_put(&self, height: u32, key: &OutPoint, inscriptions: &[(InscriptionId, u64)]) {
+        let value = inscriptions.iter().map(|(id, offset)| [&id.store()[..], &offset.to_be_bytes()].concat()).collect::<Vec<_>>().concat();
+        self.outpoint_put_with_undo(height, (OUTPOINT_TO_INSCRIPTIONS, HEIGHT_OUTPOINT_TO_INSCRIPTIONS), key, &value);
+    }
+
+    fn outpoint_to_sat_ranges_get(&self, key: &OutPoint) -> Vec<SatRange> {
+        let value = self.get(OUTPOINT_TO_SAT_RANGES, &key.encode()).unwrap().unwrap_or_default();
+        value.chunks(11).map(|x| SatRange::load(x.try_into().unwrap())).collect()
+    }
+
+    /// Replaces the sat ranges of an output, none deletes it.
+    fn outpoint_to_sat_ranges_put(&self, height: u32, key: &OutPoint, ranges: &[SatRange]) {
+        let value = ranges.iter().flat_map(|x| x.store()).collect::<Vec<_>>();
+        self.outpoint_put_with_undo(height, (OUTPOINT_TO_SAT_RANGES, HEIGHT_OUTPOINT_TO_SAT_RANGES), key, &value);
+    }
+
+    /// Writes an outpoint keyed value, empty deletes it. The value before the
+    /// first change at `height` goes to the undo log, empty without one.
+    fn outpoint_put_with_undo(&self, height: u32, (cf_name, undo_cf_name): (&str, &str), key: &OutPoint, value: &[u8]) {
+        let k = key.encode();
+        let undo_key = HeightOutPointKey { height, outpoint: *key }.encode();
+        if self.get(undo_cf_name, &undo_key).unwrap().is_none() {
+            let prev = self.get(cf_name, &k).unwrap().unwrap_or_default();
+            self.put(undo_cf_name, &undo_key, &prev).unwrap();
+        }
+        if value.is_empty() {
+            self.del(cf_name, &k).unwrap();
+        } else {
+            self.put(cf_name, &k, value).unwrap();
+        }
+    }
+
+    /// Writes to the CF of a protocol, none deletes. The value before the first
+    /// change at `height` goes to the undo log.
+    fn protocol_put(&self, height: u32, protocol: &dyn Protocol, key: &[u8], value: Option<&[u8]>) {
+        let undo_key = [&height.encode()[..], &protocol.tag().to_be_bytes(), key].concat();
+        if self.get(HEIGHT_PROTOCOL_KEY_UNDO, &undo_key).unwrap().is_none() {
+            let prev = match self.get(protocol.column_family(), key).unwrap() {
+                Some(prev) => [&[1][..], &prev].concat(),
+                None => vec![0],
+            };
+            self.put(HEIGHT_PROTOCOL_KEY_UNDO, &undo_key, &prev).unwrap();
+        }
+        match value {
+            Some(value) => self.put(protocol.column_family(), key, value).unwrap(),
+            None => self.del(protocol.column_family(), key).unwrap(),
+        }
+    }
+
+    fn rune_id_to_rune_entry_put(&self, key: &RuneId, value: &RuneEntry) {
+        self.put(RUNE_ID_TO_RUNE_ENTRY, &key.store_bytes(), &value.store_bytes()).unwrap()
+    }
+
+    fn rune_id_to_rune_entry_get(&self, key: &RuneId) -> Option<RuneEntry> {
+        self.get(RUNE_ID_TO_RUNE_ENTRY, &key.store_bytes())
+            .map(|opt| opt.map(|bytes| RuneEntry::load_bytes(&bytes))).unwrap()
+    }
+
+    fn rune_id_to_rune_entry_del(&self, key: &RuneId) {
+        self.del(RUNE_ID_TO_RUNE_ENTRY, &key.store_bytes()).unwrap()
+    }
+
+    fn rune_to_rune_id_put(&self, key: &Rune, value: &RuneId) {
+        self.put(RUNE_TO_RUNE_ID, &key.store_bytes(), &value.store_bytes()).unwrap()
+    }
+
+    fn rune_to_rune_id_del(&self, key: &Rune) {
+        self.del(RUNE_TO_RUNE_ID, &key.store_bytes()).unwrap()
+    }
+
+    fn rune_to_rune_id_get(&self, key: &Rune) -> Option<RuneId> {
+        self.get(RUNE_TO_RUNE_ID, &key.store_bytes())
+            .map(|opt| opt.map(|bytes| RuneId::load_bytes(&bytes))).unwrap()
+    }
+
+    fn height_to_block_header_put(&self, key: u32, value: &Header) {
+        self.put(HEIGHT_TO_BLOCK_HEADER, &key.encode(), &value.store_bytes()).unwrap()
+    }
+
+    fn height_to_block_header_get(&self, key: u32) -> Option<Header> {
+        self.get(HEIGHT_TO_BLOCK_HEADER, &key.encode())
+            .map(|opt| opt.map(|bytes| Header::load_bytes(&bytes))).unwrap()
+    }
+
+    fn height_to_statistic_count_put(&self, statistic: &Statistic, height: u32, value: u32) {
+        let key = StatisticHeightKey::new(statistic, height).encode();
+        self.put(HEIGHT_TO_STATISTIC_COUNT, &key, &value.to_be_bytes()).unwrap()
+    }
+
+    fn height_to_statistic_count_inc(&self, statistic: &Statistic, height: u32) {
+        let key = StatisticHeightKey::new(statistic, height).encode();
+        let current = self.height_to_statistic_count_get(statistic, height).unwrap_or_default() + 1;
+        self.put(HEIGHT_TO_STATISTIC_COUNT, &key, &current.to_be_bytes()).unwrap()
+    }
+
+    fn height_to_statistic_count_get(&self, statistic: &Statistic, height: u32) -> Option<u32> {
+        self.get(HEIGHT_TO_STATISTIC_COUNT, &StatisticHeightKey::new(statistic, height).encode())
+            .map(|opt| opt.map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))).unwrap()
+    }
+}
+
+impl Store for RunesDB {
+    fn get(&self, cf_name: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        RunesDB::get(self, cf_name, key)
+    }
+
+    fn multi_get(&self, cf_name: &str, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        RunesDB::multi_get(self, cf_name, keys)
+    }
+
+    fn put(&self, cf_name: &str, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        RunesDB::put(self, cf_name, key, value)
+    }
+
+    fn del(&self, cf_name: &str, key: &[u8]) -> Result<(), Error> {
+        RunesDB::del(self, cf_name, key)
+    }
+}
+
+/// The writes of the block being indexed, owned by the indexer. Its reads see
+/// them, every other reader of the [`RunesDB`] sees the last committed block
+/// until [`BlockWriter::commit`] writes them in one batch. Dropping it without
+/// a commit discards the block.
+pub struct BlockWriter<'a> {
+    db: &'a RunesDB,
+    // only the indexer's updaters share it, so the lock is never contended
+    pending: Mutex<PendingWrites>,
+}
+
+impl<'a> BlockWriter<'a> {
+    /// Writes the block together with its state hash, outpoint undo log and
+    /// header in one batch, so a crash never leaves a partially indexed block behind.
+    #[instrument(skip(self, header, outpoints))]
+    pub fn commit(self, height: u32, header: &Header, outpoints: &HashMap<OutPoint, HashSet<RuneId>>) -> Result<(), Error> {
+        let db = self.db;
+        let pending = self.pending.into_inner().unwrap();
+        let mut batch = WriteBatch::default();
+        batch.put_cf(db.get_cf(HEIGHT_TO_STATE_HASH), height.encode(), db.state_hash(height, &pending));
+        for (cf_name, writes) in pending {
+            let cf = db.get_cf(&cf_name);
+            for (key, value) in writes {
+                match value {
+                    Some(value) => batch.put_cf(cf, key, value),
+                    None => batch.delete_cf(cf, key),
+                }
+            }
+        }
+        db.height_outpoint_to_rune_ids_put_and_del_with_batch(&mut batch, height, outpoints);
+        if let Some(end) = (height + 1).checked_sub(REORG_DEPTH) {
+            for cf_name in [HEIGHT_OUTPOINT_TO_INSCRIPTIONS, HEIGHT_OUTPOINT_TO_SAT_RANGES, HEIGHT_PROTOCOL_KEY_UNDO] {
+                batch.delete_range_cf(db.get_cf(cf_name), 0u32.encode(), end.encode());
+            }
+        }
+        batch.put_cf(db.get_cf(HEIGHT_TO_BLOCK_HEADER), height.encode(), header.store_bytes());
+        db.write_batch(batch)
+    }
+}
+
+impl Store for BlockWriter<'_> {
+    fn get(&self, cf_name: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(value) = self.pending.lock().unwrap().get(cf_name).and_then(|x| x.get(key)) {
+            return Ok(value.clone());
+        }
+        self.db.get(cf_name, key)
+    }
+
+    fn multi_get(&self, cf_name: &str, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut values = vec![None; keys.len()];
+        let mut misses = Vec::with_capacity(keys.len());
+        {
+            let pending = self.pending.lock().unwrap();
+            let pending = pending.get(cf_name);
+            for (i, key) in keys.iter().enumerate() {
+                match pending.and_then(|x| x.get(key)) {
+                    Some(value) => values[i] = value.clone(),
+                    None => misses.push(i),
+                }
+            }
+        }
+        if misses.is_empty() {
+            return Ok(values);
+        }
+        let misses_keys: Vec<Vec<u8>> = misses.iter().map(|i| keys[*i].clone()).collect();
+        for (i, value) in misses.into_iter().zip(self.db.multi_get(cf_name, &misses_keys)?) {
+            values[i] = value;
+        }
+        Ok(values)
+    }
+
+    fn put(&self, cf_name: &str, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.pending.lock().unwrap().entry(cf_name.to_string()).or_default().insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    fn del(&self, cf_name: &str, key: &[u8]) -> Result<(), Error> {
+        self.pending.lock().unwrap().entry(cf_name.to_string()).or_default().insert(key.to_vec(), None);
+        Ok(())
+    }
+}
+
+/// Copies the rune id first mints/burned CFs into their height first replacements
+/// and drops them, a no-op once they are gone.
+fn migrate_legacy_height_keyed_cfs(rocksdb: &mut DB, sqlite: &SqlitePool) -> anyhow::Result<()> {
+    for (legacy, cf_name) in LEGACY_HEIGHT_KEYED_CFS {
+        let Some(legacy_cf) = rocksdb.cf_handle(legacy) else {
+            continue;
+        };
+        let cf = rocksdb.cf_handle(cf_name).unwrap();
+        let t = Instant::now();
+        let mut batch = WriteBatch::default();
+        let mut migrated = 0;
+        let mut totals: BTreeMap<RuneId, u128> = BTreeMap::new();
+        for x in rocksdb.iterator_cf(legacy_cf, IteratorMode::Start) {
+            let (k, v) = x?;
+            // rune id (12 bytes) + height (4 bytes)
+            let key = HeightRuneIdKey {
+                height: u32::decode(&k[12..])?,
+                rune_id: RuneId::decode(&k[..12])?,
+            };
+            *totals.entry(key.rune_id).or_default() += u128::from_be_bytes(v[..].try_into()?);
+            batch.put_cf(cf, key.encode(), v);
+            migrated += 1;
+            if batch.len() >= 100_000 {
+                rocksdb.write(std::mem::take(&mut batch))?;
+            }
+        }
+        rocksdb.write(batch)?;
+        if cf_name == HEIGHT_RUNE_ID_TO_BURNED {
+            rebuild_burned_totals(rocksdb, sqlite, &totals)?;
+        }
+        rocksdb.drop_cf(legacy)?;
+        info!("Migrated {} keys from {} to {}, {:?}", migrated, legacy, cf_name, t.elapsed());
+    }
+    Ok(())
+}
+
+/// The legacy burned totals counted the blocks a rune was burned in, while the
+/// heights held the amounts: the totals are recomputed from the amounts, before
+/// the legacy CF is dropped so that an interrupted migration is redone.
+fn rebuild_burned_totals(rocksdb: &DB, sqlite: &SqlitePool, totals: &BTreeMap<RuneId, u128>) -> anyhow::Result<()> {
+    let entries = rocksdb.cf_handle(RUNE_ID_TO_RUNE_ENTRY).unwrap();
+    let mut batch = WriteBatch::default();
+    for (rune_id, burned) in totals {
+        let k = rune_id.store_bytes();
+        batch.put_cf(rocksdb.cf_handle(RUNE_ID_TO_BURNED).unwrap(), &k, burned.to_be_bytes());
+        if let Some(bytes) = rocksdb.get_cf(entries, &k)? {
+            let mut entry = RuneEntry::load_bytes(&bytes);
+            entry.burned = *burned;
+            batch.put_cf(entries, &k, entry.store_bytes());
+        }
+    }
+    let total = totals.values().sum::<u128>();
+    batch.put_cf(rocksdb.cf_handle(STATISTIC_TO_VALUE).unwrap(), StatisticKey::from(Statistic::Burned).encode(), total.to_be_bytes());
+
+    let mut conn = sqlite.get()?;
+    let has_rune_entry: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'rune_entry')", [], |row| row.get(0))?;
+    if has_rune_entry {
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached("UPDATE rune_entry SET burned = ? WHERE rune_id = ?")?;
+            for (rune_id, burned) in totals {
+                stmt.execute(params![burned.to_string(), rune_id.to_string()])?;
+            }
+        }
+        tx.commit()?;
+    }
+    rocksdb.write(batch)?;
+    info!("Rebuilt the burned totals of {} runes, {}", totals.len(), total);
+    Ok(())
+}
+
+/// SCRIPT_HASH_RUNE_ID_TO_BALANCE values, amount (16) + outputs (4).
+fn encode_script_balance((amount, outputs): (u128, u32)) -> Vec<u8> {
+    [&amount.to_be_bytes()[..], &outputs.to_be_bytes()].concat()
+}
+
+fn decode_script_balance(bytes: &[u8]) -> (u128, u32) {
+    (u128::from_be_bytes(bytes[0..16].try_into().unwrap()), u32::from_be_bytes(bytes[16..20].try_into().unwrap()))
+}
+
+fn now_ts() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// A header of a block at `time`, for tests that commit blocks.
+#[cfg(test)]
+pub(crate) fn test_header(time: u32) -> Header {
+    Header {
+        version: bitcoin::block::Version::ONE,
+        prev_blockhash: BlockHash::all_zeros(),
+        merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+        time,
+        bits: bitcoin::CompactTarget::from_consensus(0),
+        nonce: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use itertools::Itertools;
+    use ordinals::Terms;
+
+    use crate::db::model::{RuneBalanceKey, RuneEdictForInsert};
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn commit_block_is_atomic() {
+        let path = TempDir::new("commit-block");
+        let db = RunesDB::new(&path);
+        let id = RuneId { block: 840000, tx: 1 };
+
+        let writer = db.block_writer();
+        writer.rune_id_to_mints_put(&id, 7);
+        assert_eq!(writer.rune_id_to_mints_get(&id), Some(7));
+        // readers of the db see the last committed block only
+        assert_eq!(db.rune_id_to_mints_get(&id), None);
+        assert!(db.latest_indexed_height().is_none());
+
+        writer.commit(840000, &test_header(0), &HashMap::new()).unwrap();
+        assert_eq!(db.latest_indexed_height(), Some(840000));
+        assert_eq!(db.rune_id_to_mints_get(&id), Some(7));
+        assert!(db.rocksdb.get_cf(db.get_cf(RUNE_ID_TO_MINTS), id.store_bytes()).unwrap().is_some());
+    }
+
+    #[test]
+    fn compact_rocksdb() {
+        let path = TempDir::new("compact");
+        let db = RunesDB::new(&path);
+        db.rune_id_to_mints_put(&RuneId { block: 1, tx: 0 }, 1);
+        db.flush_rocksdb().unwrap();
+
+        assert_eq!(db.compact_rocksdb(Some(RUNE_ID_TO_MINTS)).unwrap(), vec![RUNE_ID_TO_MINTS]);
+        let stats = db.rocksdb_cf_stats().unwrap();
+        assert_eq!(stats.len(), COLUMN_FAMILIES.len());
+        let mints = stats.iter().find(|x| x.name == RUNE_ID_TO_MINTS).unwrap();
+        assert_eq!((mints.estimated_keys, mints.live_files), (1, 1));
+        assert!(mints.sst_size > 0);
+        assert_eq!(db.compact_rocksdb(None).unwrap().len(), COLUMN_FAMILIES.len());
+        assert!(db.compact_rocksdb(Some("UNKNOWN")).is_err());
+        assert_eq!(db.rune_id_to_mints_get(&RuneId { block: 1, tx: 0 }), Some(1));
+    }
+
+    #[test]
+    fn scans_across_prefixes_of_flushed_files() {
+        let path = TempDir::new("prefix-scans");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let id = RuneId { block: 1, tx: 0 };
+        let flush = |cf_name| db.rocksdb.flush_cf(db.get_cf(cf_name)).unwrap();
+        // one sst file per height, none holds the prefix of height 20
+        for height in [30, 10] {
+            db.height_rune_id_to_burned_put(height, &id, height as u128);
+            db.height_to_statistic_count_put(&Statistic::Runes, height, 1);
+            db.height_to_statistic_count_put(&Statistic::RuneTransactions, height, 2);
+            flush(HEIGHT_RUNE_ID_TO_BURNED);
+            flush(HEIGHT_TO_STATISTIC_COUNT);
+        }
+
+        assert_eq!(db.height_rune_id_sum_from_height(HEIGHT_RUNE_ID_TO_BURNED, 20).unwrap(), HashMap::from([(id, 30)]));
+        assert_eq!(db.height_rune_id_list_at_height(HEIGHT_RUNE_ID_TO_BURNED, 10).unwrap(), vec![(id, 10)]);
+        assert!(db.height_rune_id_list_at_height(HEIGHT_RUNE_ID_TO_BURNED, 20).unwrap().is_empty());
+        assert_eq!(db.height_to_statistic_count_sum_to_height(&Statistic::Runes, 30), 2);
+        assert_eq!(db.height_to_statistic_count_sum_from_height(&Statistic::RuneTransactions, 20), 2);
+        assert_eq!(db.reorg_plan(20).unwrap().height_burned, 1);
+    }
+
+    #[test]
+    fn secondary_catches_up_with_primary() {
+        let path = TempDir::new("secondary");
+        let primary = RunesDB::new(&path);
+        primary.rune_id_to_mints_put(&RuneId { block: 1, tx: 0 }, 1);
+
+        let secondary = RunesDB::new_secondary(path.to_path_buf(), path.join("secondary"), &SqliteOptions::default(), &RocksdbOptions::default());
+        assert_eq!(secondary.rune_id_to_mints_get(&RuneId { block: 1, tx: 0 }), Some(1));
+        primary.rune_id_to_mints_put(&RuneId { block: 1, tx: 0 }, 2);
+        assert_eq!(secondary.rune_id_to_mints_get(&RuneId { block: 1, tx: 0 }), Some(1));
+        secondary.catch_up_with_primary().unwrap();
+        assert_eq!(secondary.rune_id_to_mints_get(&RuneId { block: 1, tx: 0 }), Some(2));
+    }
+
+    #[test]
+    fn block_stats_range() {
+        let path = TempDir::new("block-stats");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        for height in 100..105 {
+            db.sqlite_block_stats_insert(&BlockStats { height, txs: height, total_us: 10, ..Default::default() }).unwrap();
+        }
+        db.sqlite_block_stats_insert(&BlockStats { height: 102, txs: 1, total_us: 20, ..Default::default() }).unwrap();
+
+        let stats = db.sqlite_block_stats_list(101, 103).unwrap();
+        assert_eq!(stats.iter().map(|x| (x.height, x.txs)).collect_vec(), vec![(101, 101), (102, 1), (103, 103)]);
+        assert_eq!(stats[1].total_us, 20);
+    }
+
+    #[test]
+    fn block_writer_multi_get_sees_its_writes() {
+        let path = TempDir::new("multi-get");
+        let db = RunesDB::new(&path);
+        let outpoint = |vout| OutPoint { txid: bitcoin::Txid::all_zeros(), vout };
+
+        db.outpoint_to_rune_balances_put(&outpoint(0), (1, 0, vec![1]));
+        db.outpoint_to_rune_balances_put(&outpoint(1), (1, 0, vec![2]));
+        let writer = db.block_writer();
+        writer.outpoint_to_rune_balances_put(&outpoint(1), (1, 2, vec![2]));
+        writer.outpoint_to_rune_balances_put(&outpoint(2), (2, 0, vec![3]));
+
+        let outpoints = [outpoint(0), outpoint(1), outpoint(2), outpoint(3)];
+        assert_eq!(writer.outpoint_to_rune_balances_multi_get(&outpoints), vec![
+            Some((1, 0, vec![1])),
+            Some((1, 2, vec![2])),
+            Some((2, 0, vec![3])),
+            None,
+        ]);
+        assert_eq!(db.outpoint_to_rune_balances_multi_get(&outpoints), vec![
+            Some((1, 0, vec![1])),
+            Some((1, 0, vec![2])),
+            None,
+            None,
+        ]);
+    }
+
+    #[test]
+    fn state_hash_chains_blocks_regardless_of_write_order() {
+        let path = TempDir::new("state-hash");
+        let (a, b) = (RunesDB::new(path.join("a")), RunesDB::new(path.join("b")));
+        a.init_sqlite().unwrap();
+        b.init_sqlite().unwrap();
+        let outpoint = |vout| OutPoint { txid: bitcoin::Txid::all_zeros(), vout };
+
+        let writer = a.block_writer();
+        writer.outpoint_to_rune_balances_put(&outpoint(0), (100, 0, vec![1]));
+        writer.outpoint_to_rune_balances_put(&outpoint(1), (100, 0, vec![2]));
+        writer.outpoint_to_rune_balances_put(&outpoint(3), (100, 0, vec![4]));
+        writer.commit(100, &test_header(100), &HashMap::new()).unwrap();
+        let writer = b.block_writer();
+        writer.outpoint_to_rune_balances_put(&outpoint(1), (100, 0, vec![2]));
+        writer.outpoint_to_rune_balances_put(&outpoint(0), (100, 0, vec![1]));
+        writer.outpoint_to_rune_balances_put(&outpoint(3), (100, 0, vec![4]));
+        writer.commit(100, &test_header(100), &HashMap::new()).unwrap();
+        assert!(a.height_to_state_hash_get(100).is_some());
+        assert_eq!(a.height_to_state_hash_get(100), b.height_to_state_hash_get(100));
+
+        // the same writes on top of a different state hash differently
+        for (db, spent_height) in [(&a, 101), (&b, 0)] {
+            let writer = db.block_writer();
+            writer.outpoint_to_rune_balances_put(&outpoint(0), (100, spent_height, vec![1]));
+            writer.commit(101, &test_header(101), &HashMap::new()).unwrap();
+            let writer = db.block_writer();
+            writer.outpoint_to_rune_balances_put(&outpoint(2), (102, 0, vec![3]));
+            writer.commit(102, &test_header(102), &HashMap::new()).unwrap();
+        }
+        assert_ne!(a.height_to_state_hash_get(101), b.height_to_state_hash_get(101));
+        assert_ne!(a.height_to_state_hash_get(102), b.height_to_state_hash_get(102));
+
+        a.reorg_to_height(101, 102).unwrap();
+        assert_eq!(a.height_to_state_hash_get(101), None);
+        assert!(a.height_to_state_hash_get(100).is_some());
+    }
+
+    #[test]
+    fn response_cache_is_about_the_indexed_state() {
+        let path = TempDir::new("response-cache");
+        let db = RunesDB::new(&path);
+        let key = [1; 32];
+        db.response_cache_put(&key, b"{}").unwrap();
+        assert_eq!(db.response_cache_get(&key).unwrap(), None);
+
+        let writer = db.block_writer();
+        writer.commit(100, &test_header(100), &HashMap::new()).unwrap();
+        db.response_cache_put(&key, b"{}").unwrap();
+        assert_eq!(db.response_cache_get(&key).unwrap(), Some(b"{}".to_vec()));
+
+        let writer = db.block_writer();
+        writer.commit(101, &test_header(101), &HashMap::new()).unwrap();
+        assert_eq!(db.response_cache_get(&key).unwrap(), None);
+        db.response_cache_put(&key, b"[]").unwrap();
+        db.response_cache_clear().unwrap();
+        assert_eq!(db.response_cache_get(&key).unwrap(), None);
+    }
+
+    fn test_rune_entry(id: &RuneId, number: u64, rune: u128) -> RuneEntry {
+        RuneEntry {
+            block: id.block,
+            burned: 0,
+            divisibility: 0,
+            etching: bitcoin::Txid::all_zeros(),
+            terms: None,
+            mints: 0,
+            number,
+            premine: 0,
+            spaced_rune: ordinals::SpacedRune { rune: Rune(rune), spacers: 0 },
+            symbol: None,
+            timestamp: 0,
+            turbo: false,
+        }
+    }
+
+    #[test]
+    fn legacy_burned_counts_are_rebuilt() {
+        let path = TempDir::new("legacy-burned");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        // burned 10 at 101 and 5 at 102, the legacy total counting 2 blocks
+        let id = RuneId { block: 100, tx: 1 };
+        let mut entry = test_rune_entry(&id, 0, 1000);
+        entry.burned = 2;
+        db.rune_id_to_rune_entry_put(&id, &entry);
+        db.rune_to_rune_id_put(&entry.spaced_rune.rune, &id);
+        db.sqlite.get().unwrap().execute(
+            "INSERT INTO rune_entry (rune_id, etching, number, rune, spaced_rune, divisibility, height, ts, burned) VALUES (?, '', 0, '', '', 0, 100, 0, '2')",
+            [id.to_string()],
+        ).unwrap();
+        db.height_to_statistic_count_put(&Statistic::Runes, 100, 1);
+        db.rune_id_to_burned_put(&id, 2);
+        db.statistic_to_total_put(&Statistic::Burned, 2);
+        for height in 100..=102 {
+            db.height_to_block_header_put(height, &test_header(height));
+        }
+        drop(db);
+
+        let rocksdb_path = path.join("rocksdb");
+        let mut opts = Options::default();
+        opts.create_missing_column_families(true);
+        let cfs = DB::list_cf(&opts, &rocksdb_path).unwrap().into_iter().chain(["RUNE_ID_HEIGHT_TO_BURNED".to_string()]);
+        let rocksdb = DB::open_cf(&opts, &rocksdb_path, cfs).unwrap();
+        for (height, burned) in [(101u32, 10u128), (102, 5)] {
+            let key = [id.store_bytes(), height.to_be_bytes().to_vec()].concat();
+            rocksdb.put_cf(rocksdb.cf_handle("RUNE_ID_HEIGHT_TO_BURNED").unwrap(), key, burned.to_be_bytes()).unwrap();
+        }
+        drop(rocksdb);
+
+        let db = RunesDB::new(&path);
+        let burned = || (db.rune_id_to_burned_get(&id), db.rune_id_to_rune_entry_get(&id).unwrap().burned, db.network_stats().burned);
+        let sqlite_burned = || db.sqlite_rune_entry_get_by_id(id.to_string()).unwrap().unwrap().burned;
+        assert_eq!(db.height_rune_id_to_burned_get(102, &id), Some(5));
+        assert_eq!(burned(), (Some(15), 15, 15));
+        assert_eq!(sqlite_burned(), "15");
+
+        db.reorg_to_height(102, 102).unwrap();
+        assert_eq!(burned(), (Some(10), 10, 10));
+        assert_eq!(sqlite_burned(), "10");
+    }
+
+    #[test]
+    fn reorg_range_deletes_and_rolls_back_counters() {
+        let path = TempDir::new("reorg-range");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let kept = RuneId { block: 100, tx: 1 };
+        let mut kept_entry = test_rune_entry(&kept, 0, 1000);
+        db.rune_id_to_rune_entry_put(&kept, &kept_entry);
+        db.rune_to_rune_id_put(&kept_entry.spaced_rune.rune, &kept);
+        db.height_to_statistic_count_put(&Statistic::Runes, 100, 1);
+        db.height_to_block_header_put(100, &test_header(100));
+
+        db.height_rune_id_to_mints_inc(101, &kept);
+        db.height_rune_id_to_mints_inc(101, &kept);
+        db.height_to_block_header_put(101, &test_header(101));
+
+        let etched = RuneId { block: 102, tx: 0 };
+        let etched_entry = test_rune_entry(&etched, 1, 2000);
+        db.rune_id_to_rune_entry_put(&etched, &etched_entry);
+        db.rune_to_rune_id_put(&etched_entry.spaced_rune.rune, &etched);
+        db.height_to_statistic_count_put(&Statistic::Runes, 102, 1);
+        db.height_rune_id_to_mints_inc(102, &kept);
+        db.height_rune_id_to_burned_put(102, &kept, 10);
+        db.height_to_block_header_put(102, &test_header(102));
+
+        kept_entry.mints = 3;
+        kept_entry.burned = 10;
+        db.rune_id_to_rune_entry_put(&kept, &kept_entry);
+        db.rune_id_to_mints_put(&kept, 3);
+        db.rune_id_to_burned_put(&kept, 10);
+        db.statistic_to_total_put(&Statistic::Mints, 3);
+        db.statistic_to_total_put(&Statistic::Burned, 10);
+        db.height_to_statistic_count_put(&Statistic::RuneTransactions, 101, 2);
+        db.height_to_statistic_count_put(&Statistic::RuneTransactions, 102, 2);
+        db.statistic_to_value_put(&Statistic::RuneTransactions, 4);
+
+        let plan = db.reorg_plan(102).unwrap();
+        assert_eq!((plan.block_headers, plan.rune_entries, plan.height_mints, plan.height_burned), (1, 1, 1, 1));
+        assert_eq!(db.latest_indexed_height(), Some(102));
+        let entries = db.rune_entries_at_height(101).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].0, entries[0].1.mints, entries[0].1.burned), (kept, 2, 0));
+
+        db.reorg_to_height(102, 102).unwrap();
+
+        assert_eq!(db.latest_indexed_height(), Some(101));
+        assert_eq!(db.height_to_statistic_count_get(&Statistic::Runes, 102), None);
+        assert_eq!(db.statistic_to_value_get(&Statistic::Runes), Some(1));
+        assert_eq!(db.height_rune_id_to_mints_get(101, &kept), Some(2));
+        assert_eq!(db.height_rune_id_to_mints_get(102, &kept), None);
+        assert_eq!(db.height_rune_id_to_burned_get(102, &kept), None);
+        assert!(db.rune_id_to_rune_entry_get(&etched).is_none());
+        assert!(db.rune_to_rune_id_get(&etched_entry.spaced_rune.rune).is_none());
+
+        let entry = db.rune_id_to_rune_entry_get(&kept).unwrap();
+        assert_eq!((entry.mints, entry.burned), (2, 0));
+        assert_eq!(db.rune_id_to_mints_get(&kept), Some(2));
+        assert_eq!(db.rune_id_to_burned_get(&kept), Some(0));
+        assert_eq!(db.network_stats(), NetworkStats { mints: 2, burned: 0, transactions: 2, holders: 0 });
+    }
+
+    #[test]
+    fn script_hash_index_follows_spends_and_reorgs() {
+        let path = TempDir::new("script-hash-index");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let id = RuneId { block: 100, tx: 1 };
+        let script = ScriptBuf::from_hex("51").unwrap();
+        let created = OutPoint { txid: bitcoin::Txid::all_zeros(), vout: 0 };
+        let change = OutPoint { txid: bitcoin::Txid::all_zeros(), vout: 1 };
+
+        let writer = db.block_writer();
+        writer.outpoint_to_rune_balances_put(&created, (100, 0, vec![]));
+        writer.spk_to_outpoints_put(&script, &created, 100, 546);
+        writer.commit(100, &test_header(100), &HashMap::from([(created, HashSet::from([id]))])).unwrap();
+
+        let writer = db.block_writer();
+        writer.outpoint_to_rune_balances_put(&created, (100, 101, vec![]));
+        writer.spk_to_outpoints_del(&created);
+        writer.outpoint_to_rune_balances_put(&change, (101, 0, vec![]));
+        writer.spk_to_outpoints_put(&script, &change, 101, 1000);
+        writer.commit(101, &test_header(101), &HashMap::from([(created, HashSet::from([id])), (change, HashSet::from([id]))])).unwrap();
+
+        assert_eq!(db.spk_to_outpoints_list(&script).unwrap(), vec![(change, 101, 1000)]);
+        assert!(db.spk_to_outpoints_list(&ScriptBuf::from_hex("52").unwrap()).unwrap().is_empty());
+
+        db.reorg_to_height(101, 101).unwrap();
+        assert_eq!(db.spk_to_outpoints_list(&script).unwrap(), vec![(created, 100, 546)]);
+        assert!(db.outpoint_to_script_hash_get(&change).is_none());
+        drop(db);
+        std::fs::remove_dir_all(&path).unwrap();
+
+        // a database indexed before the index existed is backfilled from sqlite
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        db.height_to_block_header_put(101, &test_header(101));
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (vout, rune_id, spent_height) in [(0, "100:1", 101), (1, "100:1", 0), (1, "100:2", 0)] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, spent_height) VALUES (?, ?, 546, ?, '1', '51', 100, 0, 0, ?)",
+                    params![bitcoin::Txid::all_zeros().to_string(), vout, rune_id, spent_height],
+                ).unwrap();
+            }
+        }
+        db.init_script_hash_index(Chain::Regtest).unwrap();
+        assert_eq!(db.spk_to_outpoints_list(&script).unwrap(), vec![(change, 100, 546)]);
+        assert_eq!(db.outpoint_to_script_hash_get(&created), Some((script_hash(&script), 546)));
+        assert_eq!(db.statistic_to_value_get(&Statistic::ScriptHashIndex), Some(1));
+    }
+
+    #[test]
+    fn rune_outpoints_index_follows_spends_and_reorgs() {
+        let path = TempDir::new("rune-outpoints");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        db.init_rune_outpoints_index().unwrap();
+        let (a, b) = (RuneId { block: 100, tx: 1 }, RuneId { block: 100, tx: 2 });
+        let created = OutPoint { txid: bitcoin::Txid::all_zeros(), vout: 0 };
+        let change = OutPoint { txid: bitcoin::Txid::all_zeros(), vout: 1 };
+        let balances = |balances: &[(RuneId, u128)]| {
+            let mut buffer = vec![];
+            for (id, amount) in balances {
+                RuneUpdater::encode_rune_balance(*id, *amount, &mut buffer);
+            }
+            buffer
+        };
+
+        let writer = db.block_writer();
+        writer.outpoint_to_rune_balances_put(&created, (100, 0, balances(&[(a, 10), (b, 5)])));
+        writer.rune_id_to_outpoints_put(&a, &created, 10);
+        writer.rune_id_to_outpoints_put(&b, &created, 5);
+        writer.commit(100, &test_header(100), &HashMap::from([(created, HashSet::from([a, b]))])).unwrap();
+
+        let writer = db.block_writer();
+        writer.outpoint_to_rune_balances_put(&created, (100, 101, balances(&[(a, 10), (b, 5)])));
+        writer.rune_id_to_outpoints_del(&a, &created);
+        writer.rune_id_to_outpoints_del(&b, &created);
+        writer.outpoint_to_rune_balances_put(&change, (101, 0, balances(&[(a, 10)])));
+        writer.rune_id_to_outpoints_put(&a, &change, 10);
+        writer.commit(101, &test_header(101), &HashMap::from([(created, HashSet::from([a, b])), (change, HashSet::from([a]))])).unwrap();
+
+        assert_eq!(db.rune_id_to_outpoints_list(&a).unwrap(), vec![(change, 10)]);
+        assert!(db.rune_id_to_outpoints_list(&b).unwrap().is_empty());
+
+        db.reorg_to_height(101, 101).unwrap();
+        assert_eq!(db.rune_id_to_outpoints_list(&a).unwrap(), vec![(created, 10)]);
+        assert_eq!(db.rune_id_to_outpoints_supply(&b).unwrap(), 5);
+
+        // rebuilt from the unspent balances when the index is missing
+        db.del(RUNE_ID_TO_OUTPOINTS, &RuneIdOutPointKey { rune_id: a, outpoint: created }.encode()).unwrap();
+        db.init_rune_outpoints_index().unwrap();
+        assert!(db.rune_id_to_outpoints_list(&a).unwrap().is_empty());
+        db.del(STATISTIC_TO_VALUE, &StatisticKey::from(Statistic::RuneOutPointsIndex).encode()).unwrap();
+        db.init_rune_outpoints_index().unwrap();
+        assert_eq!(db.rune_id_to_outpoints_list(&a).unwrap(), vec![(created, 10)]);
+    }
+
+    #[test]
+    fn script_balances_follow_spends_and_reorgs() {
+        let path = TempDir::new("script-balances");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        db.init_script_hash_balance_index().unwrap();
+        let (a, b) = (RuneId { block: 100, tx: 1 }, RuneId { block: 100, tx: 2 });
+        let (script, other) = (ScriptBuf::from_hex("51").unwrap(), ScriptBuf::from_hex("52").unwrap());
+        let outpoint = |vout| OutPoint { txid: bitcoin::Txid::all_zeros(), vout };
+        let create = |writer: &BlockWriter, vout, height, script: &ScriptBuf, balances: &[(RuneId, u128)]| {
+            let mut buffer = vec![];
+            for (id, amount) in balances {
+                RuneUpdater::encode_rune_balance(*id, *amount, &mut buffer);
+            }
+            writer.outpoint_to_rune_balances_put(&outpoint(vout), (height, 0, buffer));
+            writer.spk_to_outpoints_put(script, &outpoint(vout), height, 546);
+            writer.script_hash_rune_id_to_balance_add(&script_hash(script), balances);
+        };
+
+        let writer = db.block_writer();
+        create(&writer, 0, 100, &script, &[(a, 10), (b, 5)]);
+        create(&writer, 1, 100, &script, &[(a, 3)]);
+        writer.commit(100, &test_header(100), &HashMap::from([(outpoint(0), HashSet::from([a, b])), (outpoint(1), HashSet::from([a]))])).unwrap();
+        assert_eq!(db.script_hash_rune_id_to_balance_list(&script).unwrap(), vec![(a, 13, 2), (b, 5, 1)]);
+
+        // outpoint 0 moves to the other script
+        let writer = db.block_writer();
+        let mut entry = writer.outpoint_to_rune_balances_get(&outpoint(0)).unwrap();
+        entry.1 = 101;
+        writer.outpoint_to_rune_balances_put(&outpoint(0), entry);
+        writer.spk_to_outpoints_del(&outpoint(0));
+        writer.script_hash_rune_id_to_balance_sub(&script_hash(&script), &[(a, 10), (b, 5)]);
+        create(&writer, 2, 101, &other, &[(a, 10), (b, 5)]);
+        writer.commit(101, &test_header(101), &HashMap::from([(outpoint(0), HashSet::from([a, b])), (outpoint(2), HashSet::from([a, b]))])).unwrap();
+        assert_eq!(db.script_hash_rune_id_to_balance_list(&script).unwrap(), vec![(a, 3, 1)]);
+        assert_eq!(db.script_hash_rune_id_to_balance_get(&other, &b), Some((5, 1)));
+
+        db.reorg_to_height(101, 101).unwrap();
+        assert_eq!(db.script_hash_rune_id_to_balance_list(&script).unwrap(), vec![(a, 13, 2), (b, 5, 1)]);
+        assert!(db.script_hash_rune_id_to_balance_list(&other).unwrap().is_empty());
+
+        // rebuilt from the unspent outputs of each script when the index is missing
+        db.del(SCRIPT_HASH_RUNE_ID_TO_BALANCE, &ScriptHashRuneIdKey { script_hash: script_hash(&script), rune_id: b }.encode()).unwrap();
+        db.init_script_hash_balance_index().unwrap();
+        assert_eq!(db.script_hash_rune_id_to_balance_get(&script, &b), None);
+        db.del(STATISTIC_TO_VALUE, &StatisticKey::from(Statistic::ScriptHashBalanceIndex).encode()).unwrap();
+        db.init_script_hash_balance_index().unwrap();
+        assert_eq!(db.script_hash_rune_id_to_balance_list(&script).unwrap(), vec![(a, 13, 2), (b, 5, 1)]);
+    }
+
+    #[test]
+    fn prune_spent_deletes_deep_spends() {
+        let path = TempDir::new("prune-spent");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let outpoint = |vout| OutPoint { txid: bitcoin::Txid::all_zeros(), vout };
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (vout, spent_height) in [(0, 100), (1, 150), (2, 0)] {
+                db.outpoint_to_rune_balances_put(&outpoint(vout), (90, spent_height, vec![]));
+                db.spk_to_outpoints_put(&ScriptBuf::from_hex("51").unwrap(), &outpoint(vout), 90, 546);
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, spent_height) VALUES (?, ?, 546, '1:0', '1', '51', 90, 0, 0, ?)",
+                    params![bitcoin::Txid::all_zeros().to_string(), vout, spent_height],
+                ).unwrap();
+            }
+        }
+
+        assert_eq!(db.prune_spent(50, 100).unwrap(), 0);
+        assert_eq!(db.prune_spent(200, 100).unwrap(), 1);
+        assert!(db.outpoint_to_rune_balances_get(&outpoint(0)).is_none());
+        assert!(db.outpoint_to_script_hash_get(&outpoint(0)).is_none());
+        assert!(db.outpoint_to_rune_balances_get(&outpoint(1)).is_some());
+        assert_eq!(db.prune_spent(200, 100).unwrap(), 0);
+        assert_eq!(db.prune_spent(250, 100).unwrap(), 1);
+        let rows: u32 = db.sqlite.get().unwrap().query_row("SELECT COUNT(*) FROM rune_balance", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 1);
+        assert!(db.outpoint_to_rune_balances_get(&outpoint(2)).is_some());
+    }
+
+    #[test]
+    fn reorg_skips_pruned_outpoints() {
+        let path = TempDir::new("reorg-pruned");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let outpoint = |vout| OutPoint { txid: bitcoin::Txid::all_zeros(), vout };
+        let writer = db.block_writer();
+        writer.outpoint_to_rune_balances_put(&outpoint(0), (100, 0, vec![]));
+        writer.commit(100, &test_header(100), &HashMap::new()).unwrap();
+        let writer = db.block_writer();
+        writer.outpoint_to_rune_balances_put(&outpoint(0), (100, 101, vec![]));
+        writer.outpoint_to_rune_balances_put(&outpoint(1), (101, 0, vec![]));
+        let id = RuneId { block: 1, tx: 0 };
+        writer.commit(101, &test_header(101), &HashMap::from([(outpoint(0), HashSet::from([id])), (outpoint(1), HashSet::from([id]))])).unwrap();
+
+        // the spent outpoint is pruned, the outputs of the block are still undone
+        db.del(OUTPOINT_TO_RUNE_BALANCES, &outpoint(0).encode()).unwrap();
+        db.reorg_to_height(101, 101).unwrap();
+        assert!(db.outpoint_to_rune_balances_get(&outpoint(1)).is_none());
+    }
+
+    #[test]
+    fn block_runes_at_height() {
+        let path = TempDir::new("block-runes");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let a = RuneId { block: 100, tx: 1 };
+        let b = RuneId { block: 100, tx: 2 };
+        db.height_rune_id_to_mints_inc(101, &b);
+        db.height_rune_id_to_mints_inc(101, &a);
+        db.height_rune_id_to_mints_inc(101, &a);
+        db.height_rune_id_to_mints_inc(102, &a);
+        db.height_rune_id_to_burned_put(100, &a, 5);
+        assert_eq!(db.height_rune_id_list_at_height(HEIGHT_RUNE_ID_TO_MINTS, 101).unwrap(), vec![(a, 2), (b, 1)]);
+        assert_eq!(db.height_rune_id_list_at_height(HEIGHT_RUNE_ID_TO_BURNED, 101).unwrap(), vec![]);
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (rune_id, number, height) in [("100:2", 1, 100), ("100:1", 0, 100), ("101:1", 2, 101)] {
+                conn.execute(
+                    "INSERT INTO rune_entry(rune_id, etching, number, rune, spaced_rune, divisibility, height, ts) VALUES (?, '', ?, ?, ?, 0, ?, 0)",
+                    params![rune_id, number, rune_id, rune_id, height],
+                ).unwrap();
+            }
+            for (txid, vout, transfer) in [("a", 0, true), ("a", 1, true), ("b", 0, true), ("c", 0, false)] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, transfer) VALUES (?, ?, 546, '100:1', '10', 'bc1qtest', 101, 0, 0, ?)",
+                    params![txid, vout, transfer],
+                ).unwrap();
+            }
+        }
+        assert_eq!(db.sqlite_rune_entry_list_by_height(100).unwrap().iter().map(|x| x.rune_id.as_str()).collect_vec(), vec!["100:1", "100:2"]);
+        assert_eq!(db.sqlite_transfer_count_at_height(101).unwrap(), (3, 2));
+        assert_eq!(db.sqlite_transfer_count_at_height(102).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn mintable_follows_the_tip() {
+        let path = TempDir::new("mintable");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let id = RuneId { block: 100, tx: 1 };
+        let terms = Terms { amount: Some(1), cap: Some(10), height: (Some(105), None), offset: (None, Some(10)) };
+        let entry = RuneEntry { block: 100, terms: Some(terms), ..Default::default() };
+        db.rune_id_to_rune_entry_put(&id, &entry);
+        db.sqlite_rune_entry_insert_all(&[RuneEntryForQueryInsert::new(&id, &entry, 100, None, None)]).unwrap();
+        let mintable = || db.sqlite_rune_entry_get_by_id(id.to_string()).unwrap().unwrap().mintable;
+        assert!(!mintable());
+
+        assert_eq!(db.refresh_mintable(104).unwrap(), 0);
+        assert_eq!(db.refresh_mintable(105).unwrap(), 1);
+        assert!(mintable());
+        assert_eq!(db.refresh_mintable(109).unwrap(), 0);
+        assert_eq!(db.refresh_mintable(112).unwrap(), 1);
+        assert!(!mintable());
+        // a tip moving back crosses the boundary again
+        assert_eq!(db.refresh_mintable(108).unwrap(), 1);
+        assert!(mintable());
+    }
+
+    #[test]
+    fn etched_in_range_and_etcher_column() {
+        let path = TempDir::new("etched");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        db.sqlite.get().unwrap().execute_batch("ALTER TABLE rune_entry DROP COLUMN etcher; ALTER TABLE rune_entry DROP COLUMN commit_txid;").unwrap();
+        db.init_sqlite().unwrap();
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (rune_id, number, height, etcher) in [("100:1", 0, 100, Some("bc1pa")), ("101:1", 1, 101, None), ("101:2", 2, 101, Some("bc1pb")), ("103:1", 3, 103, None)] {
+                conn.execute(
+                    "INSERT INTO rune_entry(rune_id, etching, number, rune, spaced_rune, divisibility, height, ts, etcher) VALUES (?, '', ?, ?, ?, 0, ?, 0, ?)",
+                    params![rune_id, number, rune_id, rune_id, height, etcher],
+                ).unwrap();
+            }
+        }
+        let etched = db.sqlite_rune_entry_list_etched(101, 102, 10).unwrap();
+        assert_eq!(etched.iter().map(|x| (x.rune_id.as_str(), x.etcher.as_deref())).collect_vec(), vec![("101:2", Some("bc1pb")), ("101:1", None)]);
+        assert_eq!(db.sqlite_rune_entry_list_etched(100, 103, 2).unwrap().iter().map(|x| x.rune_id.as_str()).collect_vec(), vec!["103:1", "101:2"]);
+
+        let id = RuneId { block: 104, tx: 1 };
+        let entry = RuneEntry { block: 104, ..Default::default() };
+        let commit_txid = Txid::all_zeros().to_string();
+        db.sqlite_rune_entry_insert_all(&[RuneEntryForQueryInsert::new(&id, &entry, 104, Some("bc1pc".into()), Some(commit_txid.clone()))]).unwrap();
+        let etched = db.sqlite_rune_entry_list_etched(104, 104, 10).unwrap();
+        assert_eq!(etched.iter().map(|x| (x.etcher.as_deref(), x.commit_txid.as_deref())).collect_vec(), vec![(Some("bc1pc"), Some(commit_txid.as_str()))]);
+    }
+
+    #[test]
+    fn event_log_appends_reverts() {
+        let path = TempDir::new("event-log");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let mint = RuneEvent::Mint { rune_id: "100:1".into(), txid: "a".into(), amount: "10".into() };
+        let burn = RuneEvent::Burn { rune_id: "100:1".into(), txid: "b".into(), amount: "1".into() };
+        for (height, events) in [(100, vec![mint]), (101, vec![]), (102, vec![burn.clone(), burn])] {
+            db.height_to_block_header_put(height, &test_header(height));
+            db.to_sqlite(height, &test_header(height).block_hash(), RuneEntryForTemp::default(), RuneBalanceForTemp::default(), &events).unwrap();
+        }
+        let events = db.sqlite_rune_event_list(0, 10).unwrap();
+        assert_eq!(events.iter().map(|x| (x.seq, x.height, x.event["type"].as_str().unwrap())).collect_vec(), vec![(1, 100, "mint"), (2, 102, "burn"), (3, 102, "burn")]);
+        assert_eq!(events[0].event["amount"], "10");
+        assert_eq!(db.sqlite_rune_event_list(1, 1).unwrap()[0].seq, 2);
+
+        db.reorg_to_height(101, 102).unwrap();
+
+        let reverts = db.sqlite_rune_event_list(3, 10).unwrap();
+        assert_eq!(reverts.iter().map(|x| (x.seq, x.height, x.event["type"].as_str().unwrap())).collect_vec(), vec![(4, 102, "revert"), (5, 101, "revert")]);
+        assert_eq!(reverts[0].block_hash, test_header(102).block_hash().to_string());
+        assert_eq!(db.sqlite_block_commit_latest().unwrap().map(|x| x.0), Some(100));
+    }
+
+    #[test]
+    fn incremental_counters_and_reorg_undo() {
+        let path = TempDir::new("counters");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        db.init_counter_undo();
+        db.sqlite.get().unwrap().execute(
+            "INSERT INTO rune_entry(rune_id, etching, number, rune, spaced_rune, divisibility, height, ts) VALUES ('100:1', '', 0, 'A', 'A', 0, 100, 0)",
+            [],
+        ).unwrap();
+
+        let output = |txid: &str, vout: u32, address: &str, height: u32| RuneBalanceForInsert {
+            txid: txid.into(),
+            vout,
+            value: 546,
+            rune_id: "100:1".into(),
+            rune_amount: "10".into(),
+            address: address.into(),
+            premine: false,
+            mint: false,
+            burn: false,
+            cenotaph: false,
+            transfer: true,
+            height,
+            idx: 0,
+            ts: 0,
+            spent_height: 0,
+            spent_txid: None,
+            spent_vin: None,
+            spent_ts: None,
+        };
+        let key = |txid: &str, vout: u32| RuneBalanceKey { txid: txid.into(), vout, rune_id: "100:1".into() };
+        let counters = || {
+            let entry = db.sqlite_rune_entry_list_by_height(100).unwrap().remove(0);
+            (entry.holders, entry.transactions, db.network_stats().holders)
+        };
+
+        let mut balances = RuneBalanceForTemp::default();
+        balances.insert(key("a", 0), output("a", 0, "alice", 101));
+        balances.insert(key("a", 1), output("a", 1, "bob", 101));
+        db.height_to_block_header_put(101, &test_header(101));
+        db.to_sqlite(101, &test_header(101).block_hash(), RuneEntryForTemp::default(), balances, &[]).unwrap();
+        assert_eq!(counters(), (2, 1, 2));
+
+        // alice sends to bob, bob's second output doesn't make him a second holder
+        let mut balances = RuneBalanceForTemp::default();
+        balances.insert(key("b", 0), output("b", 0, "bob", 102));
+        balances.try_update(&key("a", 0), RuneBalanceForUpdate {
+            txid: "a".into(),
+            vout: 0,
+            rune_id: "100:1".into(),
+            spent_height: 102,
+            spent_txid: "b".into(),
+            spent_vin: 0,
+            spent_ts: 0,
+        });
+        db.height_to_block_header_put(102, &test_header(102));
+        db.to_sqlite(102, &test_header(102).block_hash(), RuneEntryForTemp::default(), balances, &[]).unwrap();
+        assert_eq!(counters(), (1, 2, 1));
+
+        db.reorg_to_height(102, 102).unwrap();
+        assert_eq!(counters(), (2, 1, 2));
+        let deltas: u32 = db.sqlite.get().unwrap().query_row("SELECT COUNT(*) FROM rune_counter_delta", [], |row| row.get(0)).unwrap();
+        assert_eq!(deltas, 1);
+    }
+
+    #[test]
+    fn edicts_are_inserted_and_reverted() {
+        let path = TempDir::new("edicts");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let edict = |txid: &str, edict: u32, height: u32| RuneEdictForInsert {
+            txid: txid.into(),
+            edict,
+            rune_id: "100:1".into(),
+            amount: "0".into(),
+            allocated: "10".into(),
+            output: 2,
+            height,
+            idx: 1,
+            ts: 0,
+        };
+        let edicts = || -> Vec<(String, u32, u32)> {
+            let conn = db.sqlite.get().unwrap();
+            let mut stmt = conn.prepare("SELECT txid, edict, height FROM rune_edict ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+            rows.map(|x| x.unwrap()).collect()
+        };
+
+        for (height, txid) in [(101, "a"), (102, "b")] {
+            let balances = RuneBalanceForTemp { edicts: vec![edict(txid, 0, height), edict(txid, 1, height)], ..Default::default() };
+            db.height_to_block_header_put(height, &test_header(height));
+            db.to_sqlite(height, &test_header(height).block_hash(), RuneEntryForTemp::default(), balances, &[]).unwrap();
+        }
+        assert_eq!(edicts(), vec![("a".into(), 0, 101), ("a".into(), 1, 101), ("b".into(), 0, 102), ("b".into(), 1, 102)]);
+
+        db.reorg_to_height(102, 102).unwrap();
+        assert_eq!(edicts(), vec![("a".into(), 0, 101), ("a".into(), 1, 101)]);
+    }
+
+    #[test]
+    fn cenotaphs_are_stored_and_reverted() {
+        let path = TempDir::new("cenotaphs");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let cenotaph = CenotaphForQueryInsert {
+            txid: "a".into(),
+            flaw: Some("edict-output".into()),
+            description: Some(ordinals::Flaw::EdictOutput.to_string()),
+            etching: None,
+            mint: Some("100:1".into()),
+            burned: BTreeMap::from([("100:1".into(), "10".into()), ("100:2".into(), "5".into())]),
+            height: 101,
+            idx: 1,
+            ts: 0,
+        };
+
+        let balances = RuneBalanceForTemp { cenotaphs: vec![cenotaph.clone()], ..Default::default() };
+        db.height_to_block_header_put(101, &test_header(101));
+        db.to_sqlite(101, &test_header(101).block_hash(), RuneEntryForTemp::default(), balances, &[]).unwrap();
+        assert_eq!(db.sqlite_cenotaph_get("a").unwrap(), Some(cenotaph));
+        assert_eq!(db.sqlite_cenotaph_get("b").unwrap(), None);
+        assert_eq!(db.sqlite_tx_height(&"a".to_string()).unwrap(), Some(101));
+
+        db.reorg_to_height(101, 101).unwrap();
+        assert_eq!(db.sqlite_cenotaph_get("a").unwrap(), None);
+    }
+
+    #[test]
+    fn inscriptions_move_and_revert() {
+        let path = TempDir::new("inscriptions");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let (reveal, transfer) = (Txid::from_byte_array([1; 32]), Txid::from_byte_array([2; 32]));
+        let id = InscriptionId { txid: reveal, index: 0 };
+        let (genesis, moved) = (OutPoint { txid: reveal, vout: 0 }, OutPoint { txid: transfer, vout: 1 });
+        let transfer_row = |outpoint: &OutPoint, sat_offset: u64, address: &str, height: u32| InscriptionTransferForInsert {
+            inscription_id: id.to_string(),
+            txid: outpoint.txid.to_string(),
+            vout: Some(outpoint.vout),
+            sat_offset,
+            address: Some(address.into()),
+            height,
+            idx: 1,
+            ts: 0,
+        };
+        let inscription = InscriptionForInsert {
+            inscription_id: id.to_string(),
+            number: 0,
+            txid: reveal.to_string(),
+            input: 0,
+            content_type: Some("text/plain".into()),
+            content_length: 5,
+            content_encoding: None,
+            metaprotocol: None,
+            height: 101,
+            idx: 1,
+            ts: 0,
+        };
+
+        db.outpoint_to_inscriptions_put(101, &genesis, &[(id, 0)]);
+        db.height_to_statistic_count_put(&Statistic::Inscriptions, 101, 1);
+        db.statistic_to_value_put(&Statistic::Inscriptions, 1);
+        let balances = RuneBalanceForTemp {
+            inscriptions: vec![inscription.clone()],
+            inscription_transfers: vec![transfer_row(&genesis, 0, "a", 101)],
+            ..Default::default()
+        };
+        db.height_to_block_header_put(101, &test_header(101));
+        db.to_sqlite(101, &test_header(101).block_hash(), RuneEntryForTemp::default(), balances, &[]).unwrap();
+
+        db.outpoint_to_inscriptions_put(102, &genesis, &[]);
+        db.outpoint_to_inscriptions_put(102, &moved, &[(id, 5)]);
+        let balances = RuneBalanceForTemp { inscription_transfers: vec![transfer_row(&moved, 5, "b", 102)], ..Default::default() };
+        db.height_to_block_header_put(102, &test_header(102));
+        db.to_sqlite(102, &test_header(102).block_hash(), RuneEntryForTemp::default(), balances, &[]).unwrap();
+
+        assert_eq!(db.outpoint_to_inscriptions_get(&genesis), vec![]);
+        assert_eq!(db.outpoint_to_inscriptions_get(&moved), vec![(id, 5)]);
+        let found = db.sqlite_inscription_get(&id.to_string()).unwrap().unwrap();
+        assert_eq!(found.inscription, inscription);
+        assert_eq!(found.location, transfer_row(&moved, 5, "b", 102));
+        assert_eq!(db.sqlite_inscription_paged_by_address(&"a".to_string(), 0, 10).unwrap(), (false, vec![]));
+        assert_eq!(db.sqlite_inscription_paged_by_address(&"b".to_string(), 0, 10).unwrap(), (false, vec![found]));
+
+        db.reorg_to_height(102, 102).unwrap();
+        assert_eq!(db.outpoint_to_inscriptions_get(&genesis), vec![(id, 0)]);
+        assert_eq!(db.outpoint_to_inscriptions_get(&moved), vec![]);
+        assert_eq!(db.sqlite_inscription_get(&id.to_string()).unwrap().unwrap().location, transfer_row(&genesis, 0, "a", 101));
+        assert_eq!(db.statistic_to_value_get(&Statistic::Inscriptions), Some(1));
+
+        db.reorg_to_height(101, 101).unwrap();
+        assert_eq!(db.outpoint_to_inscriptions_get(&genesis), vec![]);
+        assert_eq!(db.sqlite_inscription_get(&id.to_string()).unwrap(), None);
+        assert_eq!(db.statistic_to_value_get(&Statistic::Inscriptions), Some(0));
+    }
+
+    #[test]
+    fn maintenance_vacuums_and_analyzes() {
+        let path = TempDir::new("maintenance");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let conn = db.sqlite.get().unwrap();
+        let freelist = || conn.query_row("PRAGMA freelist_count", [], |row| row.get::<_, u32>(0)).unwrap();
+        conn.execute_batch("
+            WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 5000)
+            INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts)
+            SELECT printf('%064d', i), 0, 546, '1:0', '10', 'bc1qtest', 1, 0, 0 FROM n;
+            DELETE FROM rune_balance;
+        ").unwrap();
+        assert!(freelist() > 0);
+
+        assert!(db.sqlite_maintenance(u32::MAX).unwrap() > 0);
+        assert_eq!(freelist(), 0);
+        let analyzed: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'sqlite_stat1')", [], |row| row.get(0)).unwrap();
+        assert!(analyzed);
+    }
+
+    #[test]
+    fn watch_list_and_delete() {
+        let path = TempDir::new("watch");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let webhook_id = db.sqlite_webhook_insert(&WebhookForInsert {
+            url: "http://localhost:1/hook".into(),
+            event: "watch:bc1qb".into(),
+            secret: "secret".into(),
+            created_ts: 0,
+        }).unwrap();
+        for (address, webhook_id, api_key_id) in [("bc1qb", Some(webhook_id), 1), ("bc1qa", None, 2), ("bc1qb", None, 2)] {
+            db.sqlite_watch_insert(&WatchForInsert { address: address.into(), webhook_id, api_key_id, created_ts: 0 }).unwrap();
+        }
+        assert_eq!(db.sqlite_watch_list(None).unwrap().len(), 3);
+        assert_eq!(db.sqlite_watch_list(Some(2)).unwrap().iter().map(|x| x.id).collect_vec(), vec![2, 3]);
+        assert_eq!(db.sqlite_watch_addresses(None).unwrap(), vec!["bc1qa", "bc1qb"]);
+        assert_eq!(db.sqlite_watch_addresses(Some(1)).unwrap(), vec!["bc1qb"]);
+
+        // only the key that created it or an admin deletes a watch
+        assert!(!db.sqlite_watch_delete(1, Some(2)).unwrap());
+        assert!(db.sqlite_watch_delete(1, Some(1)).unwrap());
+        assert!(!db.sqlite_watch_delete(1, None).unwrap());
+        assert!(db.sqlite_webhook_list().unwrap().is_empty());
+        assert_eq!(db.sqlite_watch_list(None).unwrap().iter().map(|x| x.id).collect_vec(), vec![2, 3]);
+        assert_eq!(db.sqlite_watch_addresses(None).unwrap(), vec!["bc1qa", "bc1qb"]);
+        assert!(db.sqlite_watch_delete(2, None).unwrap());
+    }
+
+    #[test]
+    fn network_stats_init_and_etchings_per_day() {
+        let path = TempDir::new("network-stats");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let day = 86400;
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (rune_id, holders, ts) in [("1:0", 2, 10 * day), ("2:0", 3, 12 * day + 5), ("3:0", 0, 12 * day + 7), ("4:0", 1, 13 * day)] {
+                conn.execute(
+                    "INSERT INTO rune_entry(rune_id, etching, number, rune, spaced_rune, divisibility, height, ts, holders) VALUES (?, '', 0, ?, ?, 0, 0, ?, ?)",
+                    params![rune_id, rune_id, rune_id, ts, holders],
+                ).unwrap();
+            }
+            for (txid, spent_txid) in [("a", Some("b")), ("b", None), ("c", Some("d"))] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, spent_height, spent_txid) VALUES (?, 0, 546, '1:0', '10', 'bc1qtest', 1, 0, 0, ?, ?)",
+                    params![txid, spent_txid.map_or(0, |_| 2), spent_txid],
+                ).unwrap();
+            }
+        }
+        let per_day = db.sqlite_etchings_per_day(3).unwrap();
+        assert_eq!(per_day.iter().map(|x| (x.day.as_str(), x.etchings)).collect_vec(), vec![("1970-01-13", 2), ("1970-01-14", 1)]);
+
+        // nothing indexed yet
+        db.init_network_stats().unwrap();
+        assert_eq!(db.statistic_to_value_get(&Statistic::Holders), None);
+
+        db.height_to_block_header_put(1, &test_header(1));
+        db.rune_id_to_mints_put(&RuneId { block: 1, tx: 0 }, 5);
+        db.rune_id_to_mints_put(&RuneId { block: 2, tx: 0 }, 7);
+        db.rune_id_to_burned_put(&RuneId { block: 1, tx: 0 }, 21);
+        db.init_network_stats().unwrap();
+        assert_eq!(db.network_stats(), NetworkStats { mints: 12, burned: 21, transactions: 4, holders: 6 });
+
+        // kept once initialized
+        db.statistic_to_value_put(&Statistic::Holders, 7);
+        db.init_network_stats().unwrap();
+        assert_eq!(db.network_stats().holders, 7);
+    }
+
+    #[test]
+    fn unspent_by_address_at_height() {
+        let path = TempDir::new("unspent-at-height");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let address = "bc1qtest".to_string();
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (txid, height, spent_height) in [("a", 100, 0), ("b", 100, 105), ("c", 103, 0), ("d", 101, 102)] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, spent_height) VALUES (?, 0, 546, '1:0', '10', ?, ?, 0, 0, ?)",
+                    params![txid, address, height, spent_height],
+                ).unwrap();
+            }
+        }
+
+        let txids = |height| {
+            db.sqlite_rune_balance_list_unspent_by_address_at_height(&address, height).unwrap()
+                .into_iter().map(|x| x.txid).sorted().collect::<Vec<_>>()
+        };
+        assert_eq!(txids(99), Vec::<String>::new());
+        assert_eq!(txids(101), vec!["a", "b", "d"]);
+        assert_eq!(txids(102), vec!["a", "b"]);
+        assert_eq!(txids(104), vec!["a", "b", "c"]);
+        assert_eq!(txids(105), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn rune_mint_paged() {
+        let path = TempDir::new("rune-mint-paged");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            // b mints to two outputs, c only moves 1:0 while minting another rune
+            for (txid, vout, amount, address, height, idx, mint, spent_txid) in [
+                ("a", 0, "10", "bc1qa", 100, 1, true, Some("c")),
+                ("b", 1, "4", "bc1qc", 101, 2, true, None),
+                ("b", 0, "6", "bc1qb", 101, 2, true, None),
+                ("c", 0, "10", "bc1qd", 101, 1, true, None),
+                ("d", 0, "10", "bc1qe", 102, 1, true, None),
+            ] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, mint, height, idx, ts, spent_txid) VALUES (?, ?, 546, '1:0', ?, ?, ?, ?, ?, 0, ?)",
+                    params![txid, vout, amount, address, mint, height, idx, spent_txid],
+                ).unwrap();
+            }
+        }
+
+        let rune_id = "1:0".to_string();
+        let (next, list) = db.sqlite_rune_mint_paged(&rune_id, 0, 2).unwrap();
+        assert!(next);
+        assert_eq!(list, vec![("a".to_string(), 100, "bc1qa".to_string()), ("b".to_string(), 101, "bc1qb".to_string())]);
+        let (next, list) = db.sqlite_rune_mint_paged(&rune_id, 2, 2).unwrap();
+        assert!(!next);
+        assert_eq!(list, vec![("d".to_string(), 102, "bc1qe".to_string())]);
+    }
+
+    #[test]
+    fn rune_holders_paged() {
+        let path = TempDir::new("rune-holders-paged");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (txid, amount, address, spent_height) in [
+                ("a", "10", "bc1qa", 0),
+                ("b", "15", "bc1qa", 0),
+                ("c", "20", "bc1qb", 0),
+                ("d", "100", "bc1qc", 101),
+                ("e", "340282366920938463463374607431768211455", "bc1qd", 0),
+            ] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, spent_height) VALUES (?, 0, 546, '1:0', ?, ?, 100, 0, 0, ?)",
+                    params![txid, amount, address, spent_height],
+                ).unwrap();
+            }
+        }
+
+        let rune_id = "1:0".to_string();
+        let (total, list) = db.sqlite_rune_holders_paged(&rune_id, 0, 2).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(list, vec![("bc1qd".to_string(), u128::MAX), ("bc1qa".to_string(), 25)]);
+        let (_, list) = db.sqlite_rune_holders_paged(&rune_id, 2, 2).unwrap();
+        assert_eq!(list, vec![("bc1qb".to_string(), 20)]);
+    }
+
+    #[test]
+    fn rune_holders_ordered_exactly() {
+        let path = TempDir::new("rune-holders-ordered-exactly");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            // the three balances are the same double, they differ in the low bits only
+            for (txid, amount, address) in [
+                ("a", "1152921504606846976", "bc1qa"),
+                ("b", "1152921504606846975", "bc1qb"),
+                ("c", "2", "bc1qb"),
+                ("d", "1152921504606846975", "bc1qc"),
+            ] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, spent_height) VALUES (?, 0, 546, '1:0', ?, ?, 100, 0, 0, 0)",
+                    params![txid, amount, address],
+                ).unwrap();
+            }
+        }
+
+        let (total, list) = db.sqlite_rune_holders_paged(&"1:0".to_string(), 0, 10).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(list, vec![
+            ("bc1qb".to_string(), (1 << 60) + 1),
+            ("bc1qa".to_string(), 1 << 60),
+            ("bc1qc".to_string(), (1 << 60) - 1),
+        ]);
+    }
+
+    #[test]
+    fn rune_tx_paged() {
+        let path = TempDir::new("rune-tx-paged");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (txid, height, spent_txid, spent_height) in [("a", 100, Some("b"), 101), ("b", 101, None, 0), ("c", 102, None, 0)] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts, spent_height, spent_txid) VALUES (?, 0, 546, '1:0', '10', 'bc1qtest', ?, 0, 0, ?, ?)",
+                    params![txid, height, spent_height, spent_txid],
+                ).unwrap();
+            }
+        }
+
+        let rune_id = "1:0".to_string();
+        let (next, list) = db.sqlite_rune_tx_paged(&rune_id, 0, 2).unwrap();
+        assert!(next);
+        assert_eq!(list, vec![("c".to_string(), 102), ("b".to_string(), 101)]);
+        let (next, list) = db.sqlite_rune_tx_paged(&rune_id, 2, 2).unwrap();
+        assert!(!next);
+        assert_eq!(list, vec![("a".to_string(), 100)]);
+
+        let (next, list) = db.sqlite_rune_tx_range(&rune_id, 101, 102, 0, 10).unwrap();
+        assert!(!next);
+        assert_eq!(list, vec![("b".to_string(), 101), ("c".to_string(), 102)]);
+        let (next, list) = db.sqlite_rune_tx_range(&rune_id, 100, 101, 0, 1).unwrap();
+        assert!(next);
+        assert_eq!(list, vec![("a".to_string(), 100)]);
+
+        assert_eq!(db.sqlite_tx_height(&"a".to_string()).unwrap(), Some(100));
+        assert_eq!(db.sqlite_tx_height(&"b".to_string()).unwrap(), Some(101));
+        assert_eq!(db.sqlite_tx_height(&"d".to_string()).unwrap(), None);
+
+        let rows = db.sqlite_rune_balance_list_by_rune_id_txids(&rune_id, &["b".to_string()]).unwrap();
+        assert_eq!(rows.iter().map(|x| x.txid.as_str()).sorted().collect::<Vec<_>>(), vec!["a", "b"]);
+
+        let (total, list) = db.sqlite_rune_activity_by_address(&"bc1qtest".to_string(), 0, 10).unwrap();
+        assert_eq!(total, 4);
+        assert_eq!(
+            list.iter().map(|x| (x.txid.as_str(), x.operation.as_str(), x.height, x.vout)).collect_vec(),
+            vec![("c", "receive", 102, Some(0)), ("b", "receive", 101, Some(0)), ("b", "send", 101, None), ("a", "receive", 100, Some(0))],
+        );
+        let (_, list) = db.sqlite_rune_activity_by_address(&"bc1qtest".to_string(), 3, 10).unwrap();
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn unspent_by_address_filtered() {
+        let path = TempDir::new("unspent-filtered");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        let address = "bc1qtest".to_string();
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (txid, rune_id, amount, height) in [("a", "1:0", "9", 100), ("a", "2:0", "5", 100), ("b", "1:0", "100", 101), ("c", "2:0", "7", 102)] {
+                conn.execute(
+                    "INSERT INTO rune_balance(txid, vout, value, rune_id, rune_amount, address, height, idx, ts) VALUES (?, 0, 546, ?, ?, ?, ?, 0, 0)",
+                    params![txid, rune_id, amount, address, height],
+                ).unwrap();
+            }
+        }
+
+        let rows = |rune_id: Option<&str>, min_amount, limit| {
+            db.sqlite_rune_balance_list_unspent_by_address_filtered(&address, rune_id.map(|x| x.to_string()).as_ref(), min_amount, limit).unwrap()
+                .into_iter().map(|x| format!("{}/{}", x.txid, x.rune_id)).sorted().collect::<Vec<_>>()
+        };
+        assert_eq!(rows(None, None, None).len(), 4);
+        // every balance of a matched output is returned
+        assert_eq!(rows(Some("1:0"), None, None), vec!["a/1:0", "a/2:0", "b/1:0"]);
+        assert_eq!(rows(Some("1:0"), Some(10), None), vec!["b/1:0"]);
+        assert_eq!(rows(Some("1:0"), Some(9), Some(1)), vec!["a/1:0", "a/2:0"]);
+        assert_eq!(rows(None, Some(6), None), vec!["a/1:0", "a/2:0", "b/1:0", "c/2:0"]);
+        assert_eq!(rows(Some("2:0"), Some(6), None), vec!["c/2:0"]);
+    }
+
+    #[test]
+    fn rune_entry_count() {
+        let path = TempDir::new("rune-entry-count");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (rune_id, rune, spaced_rune) in [("1:0", "UNCOMMONGOODS", "UNCOMMON•GOODS"), ("840000:3", "ZZZ", "Z•ZZ")] {
+                conn.execute(
+                    "INSERT INTO rune_entry(rune_id, etching, number, rune, spaced_rune, divisibility, height, ts) VALUES (?, '', 0, ?, ?, 0, 0, 0)",
+                    params![rune_id, rune, spaced_rune],
+                ).unwrap();
+            }
+        }
+
+        let keywords = |keywords: &str| RuneEntryFilter { keywords: Some(keywords.into()), ..Default::default() };
+        assert_eq!(db.sqlite_rune_entry_count(&RuneEntryFilter::default()).unwrap(), 2);
+        assert_eq!(db.sqlite_rune_entry_count(&keywords("goods")).unwrap(), 1);
+        assert_eq!(db.sqlite_rune_entry_count(&keywords("Z•Z")).unwrap(), 1);
+        assert_eq!(db.sqlite_rune_entry_count(&keywords("840000")).unwrap(), 1);
+        assert_eq!(db.sqlite_rune_entry_count(&keywords("QQ")).unwrap(), 0);
+
+        db.sqlite.get().unwrap().execute("UPDATE rune_entry SET mintable = true, turbo = true WHERE rune_id = '1:0'", []).unwrap();
+        let mintable = RuneEntryFilter { mintable: Some(true), ..Default::default() };
+        assert_eq!(db.sqlite_rune_entry_count(&mintable).unwrap(), 1);
+        assert_eq!(db.sqlite_rune_entry_count(&RuneEntryFilter { fairmint: Some(true), ..mintable.clone() }).unwrap(), 0);
+        assert_eq!(db.sqlite_rune_entry_count(&RuneEntryFilter { turbo: Some(false), ..Default::default() }).unwrap(), 1);
+        let query = RuneEntryPageQuery { size: 10, filter: mintable, ..Default::default() };
+        assert_eq!(db.sqlite_rune_entry_paged(&query).unwrap().1.into_iter().map(|x| x.1).collect_vec(), vec!["1:0"]);
+
+        db.sqlite.get().unwrap().execute("UPDATE rune_entry SET symbol = '$' WHERE rune_id = '840000:3'", []).unwrap();
+        assert_eq!(db.sqlite_rune_entry_count(&keywords("$")).unwrap(), 1);
+        assert_eq!(db.sqlite_rune_entry_count(&keywords("uncommon•goods")).unwrap(), 1);
+    }
+
+    #[test]
+    fn rune_entry_paged() {
+        let path = TempDir::new("rune-entry-paged");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (rune_id, number, height) in [("1:0", 0, 1), ("840000:3", 1, 840000), ("840000:7", 2, 840000), ("840001:1", 3, 840001)] {
+                conn.execute(
+                    "INSERT INTO rune_entry(rune_id, etching, number, rune, spaced_rune, divisibility, height, ts) VALUES (?, '', ?, ?, ?, 0, ?, 0)",
+                    params![rune_id, number, format!("RUNE{number}"), format!("RUNE•{number}"), height],
+                ).unwrap();
+            }
+        }
+
+        let ids = |list: Vec<(RuneEntryPageKey, String)>| list.into_iter().map(|x| x.1).collect_vec();
+        let mut query = RuneEntryPageQuery { size: 2, ..Default::default() };
+        let (next, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert!(next);
+        assert_eq!(list[1].0, RuneEntryPageKey { height: 840000, number: 1 });
+        assert_eq!(ids(list), vec!["1:0", "840000:3"]);
+
+        query.after = Some("840000:1".parse().unwrap());
+        let (next, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert!(!next);
+        assert_eq!(ids(list), vec!["840000:7", "840001:1"]);
+
+        query.desc = true;
+        let (next, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert!(!next);
+        assert_eq!(ids(list), vec!["1:0"]);
+
+        let query = RuneEntryPageQuery { offset: 1, size: 2, filter: RuneEntryFilter { keywords: Some("rune•".into()), ..Default::default() }, ..Default::default() };
+        let (next, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert!(next);
+        assert_eq!(ids(list), vec!["840000:3", "840000:7"]);
+
+        {
+            let conn = db.sqlite.get().unwrap();
+            for (rune_id, holders, mints) in [("1:0", 5, "900"), ("840000:3", 9, "1000"), ("840000:7", 5, "25"), ("840001:1", 1, "0")] {
+                conn.execute("UPDATE rune_entry SET holders = ?, mints = ? WHERE rune_id = ?", params![holders, mints, rune_id]).unwrap();
+            }
+        }
+
+        let mut query = RuneEntryPageQuery { size: 2, sort: RuneEntrySort::Holders, desc: true, ..Default::default() };
+        let (next, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert!(next);
+        assert_eq!(ids(list), vec!["840000:3", "840000:7"]);
+        query.after = Some(RuneEntryPageKey { height: 840000, number: 2 });
+        let (_, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert_eq!(ids(list), vec!["1:0", "840001:1"]);
+
+        let query = RuneEntryPageQuery { size: 4, sort: RuneEntrySort::Mints, ..Default::default() };
+        let (_, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert_eq!(ids(list), vec!["840001:1", "840000:7", "1:0", "840000:3"]);
+
+        let compat = RuneEntryCompatPageParams { offset: 1, limit: 2, mint_type: None, search: None, sort: Some("holders".into()) };
+        let list = db.sqlite_rune_entry_list_for_compat(&compat).unwrap();
+        assert_eq!(list.iter().map(|x| x.rune_id.as_str()).collect_vec(), vec!["840000:7", "1:0"]);
+
+        db.sqlite.get().unwrap().execute("UPDATE rune_entry SET rune = 'RUNE3RUNE', spaced_rune = 'RUNE3•RUNE' WHERE rune_id = '840001:1'", []).unwrap();
+        let mut query = RuneEntryPageQuery { size: 4, sort: RuneEntrySort::Relevance, ..Default::default() };
+        query.filter.keywords = Some("rune".into());
+        let (_, list) = db.sqlite_rune_entry_paged(&query).unwrap();
+        assert_eq!(list[0].1, "840001:1");
+        assert_eq!(list.len(), 4);
+        query.after = Some(RuneEntryPageKey { height: 1, number: 0 });
+        assert!(db.sqlite_rune_entry_paged(&query).is_err());
+    }
+}
@@ -1,9 +1,11 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
 use ordinals::RuneId;
 
+use crate::entry::RuneEntry;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuneEntryForQueryInsert {
     pub rune_id: String,
@@ -29,6 +31,41 @@ pub struct RuneEntryForQueryInsert {
     pub transactions: u32,
     pub height: u32,
     pub ts: u32,
+    pub etcher: Option<String>,
+    pub commit_txid: Option<String>,
+}
+
+impl RuneEntryForQueryInsert {
+    /// The row of a newly etched rune, `mintable` as of `latest_height`.
+    pub fn new(id: &RuneId, entry: &RuneEntry, latest_height: u32, etcher: Option<String>, commit_txid: Option<String>) -> Self {
+        RuneEntryForQueryInsert {
+            rune_id: id.to_string(),
+            etching: entry.etching.to_string(),
+            number: entry.number,
+            rune: entry.spaced_rune.rune.to_string(),
+            spaced_rune: entry.spaced_rune.to_string(),
+            symbol: entry.symbol.map(|s| s.to_string()),
+            divisibility: entry.divisibility,
+            premine: entry.premine.to_string(),
+            amount: entry.terms.and_then(|t| t.amount).map(|a| a.to_string()),
+            cap: entry.terms.and_then(|t| t.cap).map(|c| c.to_string()),
+            start_height: entry.terms.and_then(|t| t.height.0).map(|s| s as _),
+            end_height: entry.terms.and_then(|t| t.height.1).map(|e| e as _),
+            start_offset: entry.terms.and_then(|t| t.offset.0).map(|s| s as _),
+            end_offset: entry.terms.and_then(|t| t.offset.1).map(|e| e as _),
+            mints: entry.mints.to_string(),
+            turbo: entry.turbo,
+            burned: entry.burned.to_string(),
+            mintable: entry.mintable(latest_height as _).unwrap_or(0) > 0,
+            fairmint: entry.fairmint(),
+            holders: 0,
+            transactions: 0,
+            height: id.block as _,
+            ts: entry.timestamp as _,
+            etcher,
+            commit_txid,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,6 +151,68 @@ pub struct RuneBalanceForInsert {
     pub spent_ts: Option<u32>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuneEdictForInsert {
+    pub txid: String,
+    pub edict: u32,
+    pub rune_id: String,
+    pub amount: String,
+    pub allocated: String,
+    pub output: u32,
+    pub height: u32,
+    pub idx: u32,
+    pub ts: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CenotaphForQueryInsert {
+    pub txid: String,
+    pub flaw: Option<String>,
+    pub description: Option<String>,
+    pub etching: Option<String>,
+    pub mint: Option<String>,
+    pub burned: BTreeMap<String, String>,
+    pub height: u32,
+    pub idx: u32,
+    pub ts: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InscriptionForInsert {
+    pub inscription_id: String,
+    pub number: u32,
+    pub txid: String,
+    pub input: u32,
+    pub content_type: Option<String>,
+    pub content_length: u64,
+    pub content_encoding: Option<String>,
+    pub metaprotocol: Option<String>,
+    pub height: u32,
+    pub idx: u32,
+    pub ts: u32,
+}
+
+/// Where a transaction left an inscription, vout and address are unset when
+/// its sat went to the fees, sat_offset is then the offset into the fees.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InscriptionTransferForInsert {
+    pub inscription_id: String,
+    pub txid: String,
+    pub vout: Option<u32>,
+    pub sat_offset: u64,
+    pub address: Option<String>,
+    pub height: u32,
+    pub idx: u32,
+    pub ts: u32,
+}
+
+/// An inscription with its latest transfer as the location.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InscriptionForQuery {
+    pub inscription: InscriptionForInsert,
+    pub location: InscriptionTransferForInsert,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuneBalanceForUpdate {
     pub txid: String,
@@ -125,6 +224,112 @@ pub struct RuneBalanceForUpdate {
     pub spent_ts: u32,
 }
 
+/// Keyset position in the rune listing, the last (height, number) of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuneEntryPageKey {
+    pub height: u32,
+    pub number: u64,
+}
+
+impl std::fmt::Display for RuneEntryPageKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.height, self.number)
+    }
+}
+
+impl std::str::FromStr for RuneEntryPageKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((height, number)) = s.split_once(':') else {
+            anyhow::bail!("invalid page key `{s}`, expected `height:number`");
+        };
+        Ok(RuneEntryPageKey { height: height.parse()?, number: number.parse()? })
+    }
+}
+
+/// Ordering of the rune listing, ties are broken by rune number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuneEntrySort {
+    #[default]
+    Etching,
+    Holders,
+    Mints,
+    Supply,
+    Timestamp,
+    Transactions,
+    /// Full-text rank of the keywords, best match first.
+    Relevance,
+}
+
+impl RuneEntrySort {
+    /// Sort expressions, matching the `rune_entry` indexes so SQLite can use them.
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            RuneEntrySort::Etching => &["height"],
+            RuneEntrySort::Holders => &["holders"],
+            // mints is a decimal string, shorter means smaller
+            RuneEntrySort::Mints => &["length(mints)", "mints"],
+            RuneEntrySort::Supply => &["CAST(premine AS REAL) + CAST(mints AS REAL) * CAST(IFNULL(amount, '0') AS REAL)"],
+            RuneEntrySort::Timestamp => &["ts"],
+            RuneEntrySort::Transactions => &["transactions"],
+            // binds the fts query, runes only matched by id rank last
+            RuneEntrySort::Relevance => &["IFNULL((SELECT rank FROM rune_entry_fts WHERE rune_entry_fts MATCH ? AND rowid = rune_entry.rowid), 0)"],
+        }
+    }
+}
+
+impl std::str::FromStr for RuneEntrySort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "etching" => RuneEntrySort::Etching,
+            "holders" => RuneEntrySort::Holders,
+            "mints" => RuneEntrySort::Mints,
+            "supply" => RuneEntrySort::Supply,
+            "timestamp" => RuneEntrySort::Timestamp,
+            "transactions" => RuneEntrySort::Transactions,
+            "relevance" => RuneEntrySort::Relevance,
+            _ => anyhow::bail!("invalid sort `{s}`, expected one of etching, holders, mints, supply, timestamp, transactions, relevance"),
+        })
+    }
+}
+
+/// Conditions shared by the rune listing and its count.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RuneEntryFilter {
+    pub keywords: Option<String>,
+    pub mintable: Option<bool>,
+    pub fairmint: Option<bool>,
+    pub turbo: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuneEntryPageQuery {
+    /// Start after this key, takes precedence over `offset`.
+    pub after: Option<RuneEntryPageKey>,
+    pub offset: usize,
+    pub size: usize,
+    pub filter: RuneEntryFilter,
+    pub sort: RuneEntrySort,
+    pub desc: bool,
+}
+
+/// One balance change of an address: the output receiving runes, or the input
+/// spending them (`operation` = `send`, without vout and tx index).
+#[derive(Debug, Clone)]
+pub struct RuneActivityForQuery {
+    pub rune_id: String,
+    pub txid: String,
+    pub vout: Option<u32>,
+    pub rune_amount: String,
+    pub operation: String,
+    pub height: u32,
+    pub idx: Option<u32>,
+    pub ts: u32,
+}
+
 pub struct RuneEntryCompatPageParams{
     pub offset: u64,
     pub limit: u64,
@@ -133,6 +338,24 @@ pub struct RuneEntryCompatPageParams{
     pub sort: Option<String>,
 }
 
+impl RuneEntryCompatPageParams {
+    /// Filter of the page, `mint_type` is `mintable`, `fairmint` or `turbo`.
+    pub fn filter(&self) -> anyhow::Result<RuneEntryFilter> {
+        let mut filter = RuneEntryFilter {
+            keywords: self.search.clone(),
+            ..Default::default()
+        };
+        match self.mint_type.as_deref() {
+            None | Some("all") => {}
+            Some("mintable") => filter.mintable = Some(true),
+            Some("fairmint") => filter.fairmint = Some(true),
+            Some("turbo") => filter.turbo = Some(true),
+            Some(x) => anyhow::bail!("invalid type `{x}`, expected one of all, mintable, fairmint, turbo"),
+        }
+        Ok(filter)
+    }
+}
+
 
 #[derive(Debug, Clone, Default)]
 pub struct RuneEntryForTemp {
@@ -170,6 +393,10 @@ pub struct RuneBalanceForTemp {
     pub inserts: HashMap<RuneBalanceKey, RuneBalanceForInsert>,
     pub updates: HashMap<RuneBalanceKey, RuneBalanceForUpdate>,
     pub tx_ops: HashMap<String, HashSet<RuneOpType>>,
+    pub edicts: Vec<RuneEdictForInsert>,
+    pub cenotaphs: Vec<CenotaphForQueryInsert>,
+    pub inscriptions: Vec<InscriptionForInsert>,
+    pub inscription_transfers: Vec<InscriptionTransferForInsert>,
 }
 
 impl RuneBalanceForTemp {
@@ -215,4 +442,177 @@ impl RuneBalanceForTemp {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyForQuery {
+    pub id: u32,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub key_prefix: String,
+    pub tier: String,
+    pub rate_limit_per_minute: Option<u32>,
+    pub daily_quota: Option<u64>,
+    pub created_ts: u64,
+    pub revoked: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyForInsert {
+    pub name: String,
+    pub key_hash: String,
+    pub key_prefix: String,
+    pub tier: String,
+    pub rate_limit_per_minute: Option<u32>,
+    pub daily_quota: Option<u64>,
+    pub created_ts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookForQuery {
+    pub id: u32,
+    pub url: String,
+    pub event: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub created_ts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookForInsert {
+    pub url: String,
+    pub event: String,
+    pub secret: String,
+    pub created_ts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchForQuery {
+    pub id: u32,
+    pub address: String,
+    pub webhook_id: Option<u32>,
+    pub api_key_id: Option<u32>,
+    pub created_ts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchForInsert {
+    pub address: String,
+    pub webhook_id: Option<u32>,
+    pub api_key_id: u32,
+    pub created_ts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeliveryForQuery {
+    pub id: u32,
+    pub webhook_id: u32,
+    pub height: u32,
+    pub payload: String,
+    pub attempts: u32,
+    pub next_attempt_ts: u64,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+    pub failed: bool,
+    pub created_ts: u64,
+    pub updated_ts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeliveryForInsert {
+    pub webhook_id: u32,
+    pub height: u32,
+    pub payload: String,
+    pub created_ts: u64,
+}
+
+/// Runes received or spent by an address in a block, read back from rune_balance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressTransferForQuery {
+    pub address: String,
+    pub rune_id: String,
+    pub rune_amount: String,
+    pub txid: String,
+    pub vout: u32,
+    pub spent_txid: Option<String>,
+}
+
+/// A row of the `rune_event` log, `event` is the stored
+/// [`crate::webhook::RuneEvent`] JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuneEventForQuery {
+    pub seq: u64,
+    pub height: u32,
+    pub block_hash: String,
+    pub event: serde_json::Value,
+    pub ts: u64,
+}
+
+/// Rows and keys [`crate::db::RunesDB::reorg_to_height`] would delete or reset.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReorgPlan {
+    pub height: u32,
+    pub block_headers: u64,
+    pub rune_entries: u64,
+    pub height_mints: u64,
+    pub height_burned: u64,
+    pub outpoints_deleted: u64,
+    pub outpoints_unspent: u64,
+    pub sqlite_rune_balances_deleted: u64,
+    pub sqlite_rune_balances_unspent: u64,
+    pub sqlite_rune_entries_deleted: u64,
+}
+
+impl std::fmt::Display for ReorgPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Rollback to height {}:", self.height)?;
+        writeln!(f, "  rocksdb block headers deleted: {}", self.block_headers)?;
+        writeln!(f, "  rocksdb rune entries deleted: {}", self.rune_entries)?;
+        writeln!(f, "  rocksdb height mints/burned deleted: {}/{}", self.height_mints, self.height_burned)?;
+        writeln!(f, "  rocksdb outpoints deleted/unspent: {}/{}", self.outpoints_deleted, self.outpoints_unspent)?;
+        writeln!(f, "  sqlite rune_balance deleted/unspent: {}/{}", self.sqlite_rune_balances_deleted, self.sqlite_rune_balances_unspent)?;
+        write!(f, "  sqlite rune_entry deleted: {}", self.sqlite_rune_entries_deleted)
+    }
+}
+
+/// Indexing time of a block per stage, in microseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockStats {
+    pub height: u32,
+    pub txs: u32,
+    pub runestones: u32,
+    pub fetch_us: u64,
+    pub update_us: u64,
+    pub sqlite_us: u64,
+    pub cache_us: u64,
+    pub total_us: u64,
+    pub ts: u64,
+}
+
+/// Network wide totals kept in `STATISTIC_TO_VALUE`, holders are summed over
+/// runes so an address holding two runes counts twice.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub mints: u128,
+    pub burned: u128,
+    pub transactions: u32,
+    pub holders: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EtchingsPerDay {
+    /// UTC, `YYYY-MM-DD`
+    pub day: String,
+    pub etchings: u32,
+}
+
+/// Storage of one rocksdb column family, sizes in bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ColumnFamilyStats {
+    pub name: String,
+    pub sst_size: u64,
+    pub memtable_size: u64,
+    pub estimated_keys: u64,
+    pub live_files: u64,
+}
@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::anyhow;
+use log::error;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::db::RunesDB;
+
+/// Jobs queued before `submit` waits. A block job is committed to rocksdb
+/// before it is queued, so this stays well below the reorg window that
+/// [`RunesDB::reconcile_commit`] can catch sqlite up by after a crash.
+const CHANNEL_CAPACITY: usize = 4;
+
+type Job = Box<dyn FnOnce(&RunesDB) -> anyhow::Result<()> + Send>;
+
+/// Runs every sqlite write on one thread, in the order they were queued, so
+/// writers never wait on each other for the sqlite write lock and the indexer
+/// doesn't wait for a block's rows before indexing the next block.
+pub struct SqliteWriter {
+    sender: mpsc::Sender<Job>,
+    failed: Arc<Mutex<Option<String>>>,
+}
+
+impl SqliteWriter {
+    pub fn new(runes_db: Arc<RunesDB>) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<Job>(CHANNEL_CAPACITY);
+        let failed = Arc::new(Mutex::new(None));
+        let writer_failed = Arc::clone(&failed);
+        thread::Builder::new()
+            .name("sqlite-writer".to_string())
+            .spawn(move || {
+                while let Some(job) = receiver.blocking_recv() {
+                    // later jobs may depend on the failed one, nothing more is written
+                    if let Err(e) = job(&runes_db) {
+                        error!("SQLite writer stopped: {:?}", e);
+                        *writer_failed.lock().unwrap() = Some(e.to_string());
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn the sqlite writer");
+        SqliteWriter { sender, failed }
+    }
+
+    fn stopped(&self) -> anyhow::Error {
+        match self.failed.lock().unwrap().as_ref() {
+            Some(e) => anyhow!("sqlite writer stopped: {}", e),
+            None => anyhow!("sqlite writer stopped"),
+        }
+    }
+
+    /// Queues a job without waiting for it to run, only for a free slot in the
+    /// queue. A failing job stops the writer, and the next call returns its
+    /// error.
+    pub async fn submit(&self, job: impl FnOnce(&RunesDB) -> anyhow::Result<()> + Send + 'static) -> anyhow::Result<()> {
+        self.sender.send(Box::new(job)).await.map_err(|_| self.stopped())
+    }
+
+    /// Runs a job after the queued ones and returns its result, its errors
+    /// leave the writer running.
+    pub async fn call<T: Send + 'static>(&self, job: impl FnOnce(&RunesDB) -> anyhow::Result<T> + Send + 'static) -> anyhow::Result<T> {
+        let (sender, receiver) = oneshot::channel();
+        self.submit(move |runes_db| {
+            let _ = sender.send(job(runes_db));
+            Ok(())
+        }).await?;
+        receiver.await.map_err(|_| self.stopped())?
+    }
+
+    /// Waits for the queued jobs.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        self.call(|_| Ok(())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn jobs_run_in_order_and_failures_stop_the_writer() {
+        let path = TempDir::new("sqlite-writer");
+        let db = Arc::new(RunesDB::new(&path));
+        db.init_sqlite().unwrap();
+        let writer = SqliteWriter::new(Arc::clone(&db));
+
+        let order = Arc::new(Mutex::new(vec![]));
+        for i in 0..10 {
+            let order = Arc::clone(&order);
+            writer.submit(move |_| {
+                order.lock().unwrap().push(i);
+                Ok(())
+            }).await.unwrap();
+        }
+        let count: u32 = writer.call(|db| Ok(db.sqlite.get()?.query_row("SELECT COUNT(*) FROM rune_entry", [], |row| row.get(0))?)).await.unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(*order.lock().unwrap(), (0..10).collect::<Vec<_>>());
+
+        assert!(writer.call(|_| -> anyhow::Result<()> { Err(anyhow!("rejected")) }).await.is_err());
+        writer.flush().await.unwrap();
+
+        writer.submit(|_| Err(anyhow!("disk full"))).await.unwrap();
+        assert_eq!(writer.flush().await.unwrap_err().to_string(), "sqlite writer stopped: disk full");
+        assert!(writer.submit(|_| Ok(())).await.is_err());
+    }
+}
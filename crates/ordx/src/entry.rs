@@ -563,6 +563,16 @@ pub enum Statistic {
     IndexTransactions = 12,
     IndexSpentSats = 13,
     InitialSyncTime = 14,
+    Mints = 16,
+    Burned = 17,
+    RuneTransactions = 18,
+    Holders = 19,
+    CounterUndoHeight = 20,
+    ScriptHashIndex = 21,
+    RuneOutPointsIndex = 22,
+    ScriptHashBalanceIndex = 23,
+    MintableHeight = 24,
+    Inscriptions = 25,
     LatestHeight = u8::MAX as _,
 }
 
@@ -0,0 +1,336 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::bail;
+use log::info;
+use serde::Serialize;
+
+use crate::db::model::RuneBalanceForQuery;
+use crate::db::RunesDB;
+
+pub const USAGE: &str = "Usage: ordx export [--height <height>] [--format csv|jsonl] [--out <dir>]";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            _ => bail!("unknown format `{s}`\n{USAGE}"),
+        }
+    }
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Options of the `export` subcommand, which writes `rune_entries`, `holders`
+/// and `outpoints` files as of `height` into `out`.
+#[derive(Debug, PartialEq)]
+pub struct ExportOptions {
+    /// the indexed height if none
+    pub height: Option<u32>,
+    pub format: ExportFormat,
+    pub out: PathBuf,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            height: None,
+            format: ExportFormat::default(),
+            out: PathBuf::from("."),
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut options = ExportOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+            if !matches!(flag, "--height" | "--format" | "--out") {
+                bail!("unknown argument `{arg}`\n{USAGE}");
+            }
+            let Some(value) = args.next() else {
+                bail!("{flag} requires a value\n{USAGE}");
+            };
+            match flag {
+                "--height" => options.height = Some(value.parse()?),
+                "--format" => options.format = value.parse()?,
+                _ => options.out = PathBuf::from(value),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// A record with a fixed column order, written as a CSV line or a JSON line.
+trait ExportRow: Serialize {
+    const HEADER: &'static [&'static str];
+
+    fn fields(&self) -> Vec<String>;
+}
+
+#[derive(Debug, Serialize)]
+struct RuneEntryRow {
+    rune_id: String,
+    spaced_rune: String,
+    number: u64,
+    block: u64,
+    etching: String,
+    divisibility: u8,
+    symbol: Option<char>,
+    premine: String,
+    mints: String,
+    burned: String,
+    supply: String,
+    turbo: bool,
+    timestamp: u64,
+}
+
+impl ExportRow for RuneEntryRow {
+    const HEADER: &'static [&'static str] = &["rune_id", "spaced_rune", "number", "block", "etching", "divisibility", "symbol", "premine", "mints", "burned", "supply", "turbo", "timestamp"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.rune_id.clone(),
+            self.spaced_rune.clone(),
+            self.number.to_string(),
+            self.block.to_string(),
+            self.etching.clone(),
+            self.divisibility.to_string(),
+            self.symbol.map(String::from).unwrap_or_default(),
+            self.premine.clone(),
+            self.mints.clone(),
+            self.burned.clone(),
+            self.supply.clone(),
+            self.turbo.to_string(),
+            self.timestamp.to_string(),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HolderRow {
+    rune_id: String,
+    address: String,
+    amount: String,
+    outputs: u32,
+}
+
+impl ExportRow for HolderRow {
+    const HEADER: &'static [&'static str] = &["rune_id", "address", "amount", "outputs"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.rune_id.clone(), self.address.clone(), self.amount.clone(), self.outputs.to_string()]
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OutpointRow {
+    txid: String,
+    vout: u32,
+    value: u64,
+    address: String,
+    rune_id: String,
+    amount: String,
+    height: u32,
+}
+
+impl ExportRow for OutpointRow {
+    const HEADER: &'static [&'static str] = &["txid", "vout", "value", "address", "rune_id", "amount", "height"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.txid.clone(),
+            self.vout.to_string(),
+            self.value.to_string(),
+            self.address.clone(),
+            self.rune_id.clone(),
+            self.amount.clone(),
+            self.height.to_string(),
+        ]
+    }
+}
+
+struct ExportWriter<W: Write> {
+    format: ExportFormat,
+    writer: W,
+    rows: u64,
+}
+
+impl ExportWriter<BufWriter<File>> {
+    fn create(dir: &Path, name: &str, format: ExportFormat) -> anyhow::Result<Self> {
+        let path = dir.join(format!("{}.{}", name, format.extension()));
+        info!("Writing {:?}", path);
+        Ok(ExportWriter { format, writer: BufWriter::new(File::create(path)?), rows: 0 })
+    }
+}
+
+impl<W: Write> ExportWriter<W> {
+    fn write<T: ExportRow>(&mut self, row: &T) -> anyhow::Result<()> {
+        match self.format {
+            ExportFormat::Csv => {
+                if self.rows == 0 {
+                    writeln!(self.writer, "{}", T::HEADER.join(","))?;
+                }
+                let line = row.fields().iter().map(|x| csv_field(x)).collect::<Vec<_>>().join(",");
+                writeln!(self.writer, "{}", line)?;
+            }
+            ExportFormat::Jsonl => {
+                serde_json::to_writer(&mut self.writer, row)?;
+                writeln!(self.writer)?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> anyhow::Result<u64> {
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+}
+
+fn csv_field(x: &str) -> String {
+    if x.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", x.replace('"', "\"\""))
+    } else {
+        x.to_string()
+    }
+}
+
+/// Sums consecutive outputs of the same rune and address into holder rows.
+#[derive(Default)]
+struct HolderAccumulator {
+    current: Option<(String, String, u128, u32)>,
+}
+
+impl HolderAccumulator {
+    fn push(&mut self, balance: &RuneBalanceForQuery) -> anyhow::Result<Option<HolderRow>> {
+        let amount = u128::from_str(&balance.rune_amount)?;
+        if let Some((rune_id, address, total, outputs)) = self.current.as_mut() {
+            if *rune_id == balance.rune_id && *address == balance.address {
+                *total += amount;
+                *outputs += 1;
+                return Ok(None);
+            }
+        }
+        let done = self.current.replace((balance.rune_id.clone(), balance.address.clone(), amount, 1));
+        Ok(done.map(Self::row))
+    }
+
+    fn finish(self) -> Option<HolderRow> {
+        self.current.map(Self::row)
+    }
+
+    fn row((rune_id, address, amount, outputs): (String, String, u128, u32)) -> HolderRow {
+        HolderRow { rune_id, address, amount: amount.to_string(), outputs }
+    }
+}
+
+pub fn run(runes_db: &RunesDB, options: &ExportOptions) -> anyhow::Result<()> {
+    let Some(indexed_height) = runes_db.latest_indexed_height() else {
+        bail!("nothing is indexed yet");
+    };
+    let height = options.height.unwrap_or(indexed_height);
+    if height > indexed_height {
+        bail!("height {} is above the indexed height {}", height, indexed_height);
+    }
+    fs::create_dir_all(&options.out)?;
+    info!("Exporting at height {} to {:?}", height, options.out);
+
+    let mut entries = ExportWriter::create(&options.out, "rune_entries", options.format)?;
+    for (rune_id, entry) in runes_db.rune_entries_at_height(height)? {
+        entries.write(&RuneEntryRow {
+            rune_id: rune_id.to_string(),
+            spaced_rune: entry.spaced_rune.to_string(),
+            number: entry.number,
+            block: entry.block,
+            etching: entry.etching.to_string(),
+            divisibility: entry.divisibility,
+            symbol: entry.symbol,
+            premine: entry.premine.to_string(),
+            mints: entry.mints.to_string(),
+            burned: entry.burned.to_string(),
+            supply: entry.supply().to_string(),
+            turbo: entry.turbo,
+            timestamp: entry.timestamp,
+        })?;
+    }
+    info!("Rune entries: {}", entries.finish()?);
+
+    let mut holders = ExportWriter::create(&options.out, "holders", options.format)?;
+    let mut outpoints = ExportWriter::create(&options.out, "outpoints", options.format)?;
+    let mut accumulator = HolderAccumulator::default();
+    runes_db.sqlite_rune_balance_for_each_unspent_at_height(height, |balance| {
+        if let Some(row) = accumulator.push(&balance)? {
+            holders.write(&row)?;
+        }
+        outpoints.write(&OutpointRow {
+            txid: balance.txid,
+            vout: balance.vout,
+            value: balance.value,
+            address: balance.address,
+            rune_id: balance.rune_id,
+            amount: balance.rune_amount,
+            height: balance.height,
+        })
+    })?;
+    if let Some(row) = accumulator.finish() {
+        holders.write(&row)?;
+    }
+    info!("Holders: {}, outpoints: {}", holders.finish()?, outpoints.finish()?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(x: &str) -> Vec<String> {
+        x.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(ExportOptions::parse(&[]).unwrap(), ExportOptions::default());
+        assert_eq!(
+            ExportOptions::parse(&args("--height 840000 --format jsonl --out /tmp/export")).unwrap(),
+            ExportOptions { height: Some(840000), format: ExportFormat::Jsonl, out: PathBuf::from("/tmp/export") }
+        );
+        assert!(ExportOptions::parse(&args("--format xml")).is_err());
+        assert!(ExportOptions::parse(&args("--height")).is_err());
+        assert!(ExportOptions::parse(&args("--all")).is_err());
+    }
+
+    #[test]
+    fn csv_and_jsonl_rows() {
+        let row = HolderRow { rune_id: "840000:3".into(), address: "a,\"b\"".into(), amount: "21".into(), outputs: 2 };
+        let mut csv = ExportWriter { format: ExportFormat::Csv, writer: vec![], rows: 0 };
+        csv.write(&row).unwrap();
+        assert_eq!(String::from_utf8(csv.writer).unwrap(), "rune_id,address,amount,outputs\n840000:3,\"a,\"\"b\"\"\",21,2\n");
+
+        let mut jsonl = ExportWriter { format: ExportFormat::Jsonl, writer: vec![], rows: 0 };
+        jsonl.write(&row).unwrap();
+        assert_eq!(String::from_utf8(jsonl.writer).unwrap(), "{\"rune_id\":\"840000:3\",\"address\":\"a,\\\"b\\\"\",\"amount\":\"21\",\"outputs\":2}\n");
+    }
+}
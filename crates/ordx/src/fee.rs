@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use moka::future::Cache;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::rpc::AsyncClient;
+use crate::settings::Settings;
+
+/// Confirmation targets reported by `/fees`.
+pub const FEE_TARGETS: [u16; 4] = [1, 3, 6, 144];
+
+const ESTIMATE_TIME_TO_LIVE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FeeEstimate {
+    pub target: u16,
+    /// sat/vB
+    pub fee_rate: f64,
+    /// The node had no estimate and the configured fallback is used.
+    pub fallback: bool,
+}
+
+/// `estimatesmartfee` with a short lived cache, the node only updates its
+/// estimates once per block.
+pub struct FeeEstimator {
+    // none when serving without bitcoind, every estimate is the fallback then
+    client: Option<AsyncClient>,
+    cache: Cache<u16, Option<f64>>,
+    fallback_fee_rate: f64,
+    default_target: u16,
+}
+
+impl FeeEstimator {
+    pub fn new(settings: &Settings, client: Option<AsyncClient>) -> Self {
+        FeeEstimator {
+            client,
+            cache: Cache::builder().time_to_live(ESTIMATE_TIME_TO_LIVE).build(),
+            fallback_fee_rate: settings.fallback_fee_rate,
+            default_target: settings.fee_conf_target,
+        }
+    }
+
+    pub async fn estimate(&self, target: u16) -> FeeEstimate {
+        let Some(client) = &self.client else {
+            return FeeEstimate { target, fee_rate: self.fallback_fee_rate, fallback: true };
+        };
+        let fee_rate = self.cache.try_get_with(target, client.estimate_smart_fee(target)).await;
+        match fee_rate {
+            Ok(Some(fee_rate)) => FeeEstimate { target, fee_rate, fallback: false },
+            Ok(None) => FeeEstimate { target, fee_rate: self.fallback_fee_rate, fallback: true },
+            Err(e) => {
+                warn!("estimatesmartfee({}) failed: {}", target, e);
+                FeeEstimate { target, fee_rate: self.fallback_fee_rate, fallback: true }
+            }
+        }
+    }
+
+    /// Fee rate the PSBT builders use when the request does not set one.
+    pub async fn default_fee_rate(&self) -> f64 {
+        self.estimate(self.default_target).await.fee_rate
+    }
+}
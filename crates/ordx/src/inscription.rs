@@ -0,0 +1,374 @@
+//! Ord style inscriptions: envelopes in the tapscript of an input, numbered in
+//! reveal order and tracked by the offset of their sat in the outputs that
+//! hold them.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::{OP_ENDIF, OP_IF, OP_PUSHNUM_1, OP_PUSHNUM_16, OP_PUSHNUM_NEG1};
+use bitcoin::script::Instruction;
+use bitcoin::{OutPoint, Script, Transaction, Txid};
+use futures_util::future::join_all;
+use tracing::instrument;
+
+use crate::chain::Chain;
+use crate::db::model::{InscriptionForInsert, InscriptionTransferForInsert};
+use crate::db::{BlockWriter, Store};
+use crate::entry::{Entry, Statistic};
+use crate::rpc::{with_retry, AsyncClient, Prevouts};
+use crate::updater::{script_address, Result};
+
+const PROTOCOL_ID: &[u8] = b"ord";
+
+const CONTENT_TYPE_TAG: u8 = 1;
+const POINTER_TAG: u8 = 2;
+const METAPROTOCOL_TAG: u8 = 7;
+const CONTENT_ENCODING_TAG: u8 = 9;
+
+/// The reveal txid and the index of the envelope among the envelopes of the
+/// transaction, `{txid}i{index}`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct InscriptionId {
+    pub txid: Txid,
+    pub index: u32,
+}
+
+impl Display for InscriptionId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}i{}", self.txid, self.index)
+    }
+}
+
+impl FromStr for InscriptionId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((txid, index)) = s.split_once('i') else {
+            bail!("invalid inscription id: {}", s);
+        };
+        Ok(InscriptionId {
+            txid: txid.parse()?,
+            index: index.parse()?,
+        })
+    }
+}
+
+pub type InscriptionIdValue = [u8; 36];
+
+impl Entry for InscriptionId {
+    type Value = InscriptionIdValue;
+
+    fn load(value: Self::Value) -> Self {
+        InscriptionId {
+            txid: Txid::from_byte_array(value[0..32].try_into().unwrap()),
+            index: u32::from_be_bytes(value[32..36].try_into().unwrap()),
+        }
+    }
+
+    fn store(self) -> Self::Value {
+        let mut value = [0; 36];
+        value[0..32].copy_from_slice(&self.txid.to_byte_array());
+        value[32..36].copy_from_slice(&self.index.to_be_bytes());
+        value
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct Inscription {
+    pub body: Option<Vec<u8>>,
+    pub content_type: Option<Vec<u8>>,
+    pub content_encoding: Option<Vec<u8>>,
+    pub metaprotocol: Option<Vec<u8>>,
+    pub pointer: Option<u64>,
+}
+
+impl Inscription {
+    /// The inscriptions of the envelopes in `tapscript`, `OP_FALSE OP_IF "ord"`
+    /// followed by pushes up to `OP_ENDIF`. Envelopes with other opcodes are
+    /// skipped.
+    pub fn from_tapscript(tapscript: &Script) -> Vec<Inscription> {
+        let mut inscriptions = Vec::new();
+        let mut instructions = tapscript.instructions().peekable();
+        // ignore errors, since the extracted script may not be valid
+        while let Some(Ok(instruction)) = instructions.next() {
+            if !matches!(instruction, Instruction::PushBytes(x) if x.is_empty()) {
+                continue;
+            }
+            if !matches!(instructions.peek(), Some(Ok(Instruction::Op(op))) if *op == OP_IF) {
+                continue;
+            }
+            instructions.next();
+            if !matches!(instructions.peek(), Some(Ok(Instruction::PushBytes(x))) if x.as_bytes() == PROTOCOL_ID) {
+                continue;
+            }
+            instructions.next();
+            let mut payload = Vec::new();
+            loop {
+                match instructions.next() {
+                    Some(Ok(Instruction::Op(op))) if op == OP_ENDIF => {
+                        inscriptions.push(Self::from_payload(payload));
+                        break;
+                    }
+                    Some(Ok(Instruction::PushBytes(x))) => payload.push(x.as_bytes().to_vec()),
+                    Some(Ok(Instruction::Op(op))) if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) => {
+                        payload.push(vec![op.to_u8() - OP_PUSHNUM_1.to_u8() + 1]);
+                    }
+                    Some(Ok(Instruction::Op(op))) if op == OP_PUSHNUM_NEG1 => payload.push(vec![0x81]),
+                    _ => break,
+                }
+            }
+        }
+        inscriptions
+    }
+
+    /// Tag and value pushes alternate up to an empty tag, the pushes after it
+    /// are the body. The first value of a tag counts, unknown tags are ignored.
+    fn from_payload(payload: Vec<Vec<u8>>) -> Inscription {
+        let mut inscription = Inscription::default();
+        let mut pushes = payload.into_iter();
+        while let Some(tag) = pushes.next() {
+            if tag.is_empty() {
+                inscription.body = Some(pushes.flatten().collect());
+                break;
+            }
+            let Some(value) = pushes.next() else {
+                break;
+            };
+            match tag.as_slice() {
+                [CONTENT_TYPE_TAG] => {
+                    inscription.content_type.get_or_insert(value);
+                }
+                [POINTER_TAG] => {
+                    inscription.pointer = inscription.pointer.or_else(|| Self::pointer(&value));
+                }
+                [METAPROTOCOL_TAG] => {
+                    inscription.metaprotocol.get_or_insert(value);
+                }
+                [CONTENT_ENCODING_TAG] => {
+                    inscription.content_encoding.get_or_insert(value);
+                }
+                _ => {}
+            }
+        }
+        inscription
+    }
+
+    /// Little endian, trailing zeros beyond 8 bytes are allowed.
+    fn pointer(value: &[u8]) -> Option<u64> {
+        if value.iter().skip(8).any(|x| *x != 0) {
+            return None;
+        }
+        let mut bytes = [0; 8];
+        let len = value.len().min(8);
+        bytes[..len].copy_from_slice(&value[..len]);
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    fn content_string(value: &Option<Vec<u8>>) -> Option<String> {
+        value.as_ref().map(|x| String::from_utf8_lossy(x).into_owned())
+    }
+}
+
+/// Indexes the inscriptions revealed in a block and moves the ones spent. New
+/// inscriptions sit on the first sat of their input unless the pointer names
+/// an output sat, inscriptions whose sat goes to the fees are lost.
+pub struct InscriptionUpdater<'a> {
+    pub block_time: u32,
+    pub client: &'a AsyncClient,
+    // outputs spent in the block, input values are looked up here instead of
+    // with getrawtransaction when set
+    pub prevouts: Option<&'a Prevouts>,
+    pub height: u32,
+    pub chain: Chain,
+    // number of the next inscription
+    pub next_number: u32,
+    // inscriptions revealed in this block
+    pub revealed: u32,
+    pub writer: &'a BlockWriter<'a>,
+    pub inscriptions: Vec<InscriptionForInsert>,
+    pub transfers: Vec<InscriptionTransferForInsert>,
+}
+
+impl<'a> InscriptionUpdater<'a> {
+    #[instrument(level = "trace", skip(self, tx), fields(txid = %tx.txid()))]
+    pub async fn index_inscriptions(&mut self, tx_index: u32, tx: &Transaction) -> Result<()> {
+        if tx.is_coinbase() {
+            return Ok(());
+        }
+        let txid = tx.txid();
+        let inscribed = tx.input.iter()
+            .map(|input| self.writer.outpoint_to_inscriptions_get(&input.previous_output))
+            .collect::<Vec<_>>();
+        let envelopes = tx.input.iter()
+            .map(|input| input.witness.tapscript().map(Inscription::from_tapscript).unwrap_or_default())
+            .collect::<Vec<_>>();
+        if inscribed.iter().all(Vec::is_empty) && envelopes.iter().all(Vec::is_empty) {
+            return Ok(());
+        }
+
+        let input_values = self.input_values(tx).await?;
+        let output_value = tx.output.iter().map(|x| x.value.to_sat()).sum::<u64>();
+        // inscriptions by their offset in the sats of the inputs
+        let mut floating = Vec::new();
+        let mut offset = 0;
+        let mut index = 0;
+        for (vin, input) in tx.input.iter().enumerate() {
+            if !inscribed[vin].is_empty() {
+                self.writer.outpoint_to_inscriptions_put(self.height, &input.previous_output, &[]);
+            }
+            for (id, sat_offset) in &inscribed[vin] {
+                floating.push((offset + sat_offset, *id));
+            }
+            for inscription in &envelopes[vin] {
+                let id = InscriptionId { txid, index };
+                index += 1;
+                self.inscriptions.push(InscriptionForInsert {
+                    inscription_id: id.to_string(),
+                    number: self.next_number + self.revealed,
+                    txid: txid.to_string(),
+                    input: vin as u32,
+                    content_type: Inscription::content_string(&inscription.content_type),
+                    content_length: inscription.body.as_ref().map(|x| x.len() as u64).unwrap_or_default(),
+                    content_encoding: Inscription::content_string(&inscription.content_encoding),
+                    metaprotocol: Inscription::content_string(&inscription.metaprotocol),
+                    height: self.height,
+                    idx: tx_index,
+                    ts: self.block_time,
+                });
+                self.revealed += 1;
+                floating.push((inscription.pointer.filter(|x| *x < output_value).unwrap_or(offset), id));
+            }
+            offset += input_values[vin];
+        }
+
+        floating.sort_by_key(|(offset, _)| *offset);
+        let mut outputs: BTreeMap<u32, Vec<(InscriptionId, u64)>> = BTreeMap::new();
+        let mut vout = 0;
+        let mut start = 0;
+        for (offset, id) in floating {
+            while vout < tx.output.len() && offset >= start + tx.output[vout].value.to_sat() {
+                start += tx.output[vout].value.to_sat();
+                vout += 1;
+            }
+            let mut transfer = InscriptionTransferForInsert {
+                inscription_id: id.to_string(),
+                txid: txid.to_string(),
+                vout: None,
+                sat_offset: offset - start,
+                address: None,
+                height: self.height,
+                idx: tx_index,
+                ts: self.block_time,
+            };
+            if let Some(output) = tx.output.get(vout) {
+                transfer.vout = Some(vout as u32);
+                transfer.address = Some(script_address(&output.script_pubkey, self.chain));
+                outputs.entry(vout as u32).or_default().push((id, offset - start));
+            }
+            self.transfers.push(transfer);
+        }
+        for (vout, inscriptions) in outputs {
+            self.writer.outpoint_to_inscriptions_put(self.height, &OutPoint { txid, vout }, &inscriptions);
+        }
+        Ok(())
+    }
+
+    pub fn update(&self) -> Result {
+        if self.revealed > 0 {
+            self.writer.height_to_statistic_count_put(&Statistic::Inscriptions, self.height, self.revealed);
+            self.writer.statistic_to_value_put(&Statistic::Inscriptions, self.next_number + self.revealed);
+        }
+        Ok(())
+    }
+
+    /// Values of the outputs `tx` spends, in input order.
+    async fn input_values(&self, tx: &Transaction) -> Result<Vec<u64>> {
+        if let Some(prevouts) = self.prevouts {
+            return tx.input.iter()
+                .map(|input| prevouts.get(&input.previous_output).map(|x| x.value).ok_or_else(|| anyhow!("no prevout for input: {}", input.previous_output)))
+                .collect();
+        }
+        let client = self.client;
+        let txids = tx.input.iter().map(|input| input.previous_output.txid).collect::<HashSet<_>>().into_iter().collect::<Vec<_>>();
+        let infos = join_all(txids.iter().map(|txid| with_retry(|| client.get_raw_transaction_info(*txid), 5, Duration::from_millis(100)))).await;
+        let mut values = HashMap::new();
+        for (txid, info) in txids.into_iter().zip(infos) {
+            let Some(info) = info? else {
+                bail!("can't get input transaction: {}", txid);
+            };
+            values.insert(txid, info.vout.iter().map(|x| x.value.to_sat()).collect::<Vec<_>>());
+        }
+        tx.input.iter()
+            .map(|input| {
+                let outpoint = input.previous_output;
+                values[&outpoint.txid].get(outpoint.vout as usize).copied().ok_or_else(|| anyhow!("no output for input: {}", outpoint))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::script::{Builder, PushBytesBuf};
+
+    use super::*;
+
+    fn envelope(pushes: &[&[u8]]) -> Builder {
+        let mut builder = Builder::new().push_opcode(bitcoin::opcodes::OP_FALSE).push_opcode(OP_IF).push_slice(b"ord");
+        for push in pushes {
+            builder = builder.push_slice(PushBytesBuf::try_from(push.to_vec()).unwrap());
+        }
+        builder.push_opcode(OP_ENDIF)
+    }
+
+    #[test]
+    fn envelopes_and_ids() {
+        let script = envelope(&[&[1], b"text/plain", &[2], &[0x10, 0x27], &[], b"hello", b" world"]).into_script();
+        assert_eq!(Inscription::from_tapscript(&script), vec![Inscription {
+            body: Some(b"hello world".to_vec()),
+            content_type: Some(b"text/plain".to_vec()),
+            pointer: Some(10_000),
+            ..Default::default()
+        }]);
+
+        // two envelopes, the second with a duplicate tag and an unknown one
+        let script = envelope(&[&[], b"a"])
+            .push_opcode(bitcoin::opcodes::OP_FALSE)
+            .push_opcode(OP_IF)
+            .push_slice(b"ord")
+            .push_opcode(OP_PUSHNUM_1)
+            .push_slice(b"x")
+            .push_opcode(OP_PUSHNUM_1)
+            .push_slice(b"y")
+            .push_slice([99])
+            .push_slice(b"z")
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let inscriptions = Inscription::from_tapscript(&script);
+        assert_eq!(inscriptions.len(), 2);
+        assert_eq!(inscriptions[0].body, Some(b"a".to_vec()));
+        assert_eq!(inscriptions[1].content_type, Some(b"x".to_vec()));
+        assert_eq!(inscriptions[1].body, None);
+
+        // other opcodes in the envelope or another protocol id
+        let script = Builder::new().push_opcode(bitcoin::opcodes::OP_FALSE).push_opcode(OP_IF).push_slice(b"ord")
+            .push_opcode(bitcoin::opcodes::all::OP_DROP).push_opcode(OP_ENDIF).into_script();
+        assert!(Inscription::from_tapscript(&script).is_empty());
+        let script = Builder::new().push_opcode(bitcoin::opcodes::OP_FALSE).push_opcode(OP_IF).push_slice(b"brc")
+            .push_opcode(OP_ENDIF).into_script();
+        assert!(Inscription::from_tapscript(&script).is_empty());
+
+        assert_eq!(Inscription::pointer(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]), Some(1));
+        assert_eq!(Inscription::pointer(&[1, 0, 0, 0, 0, 0, 0, 0, 1]), None);
+
+        let id: InscriptionId = "1111111111111111111111111111111111111111111111111111111111111111i2".parse().unwrap();
+        assert_eq!(id.index, 2);
+        assert_eq!(id.to_string().parse::<InscriptionId>().unwrap(), id);
+        assert_eq!(InscriptionId::load(id.store()), id);
+        assert!("1111111111111111111111111111111111111111111111111111111111111111".parse::<InscriptionId>().is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::bail;
+use bitcoincore_rpc::{Client, RpcApi};
+use log::{info, warn};
+
+use crate::db::{RunesDB, Store};
+use crate::entry::Statistic;
+use crate::updater::REORG_DEPTH;
+
+/// The first height the local stores disagree from: rows and counts above the
+/// indexed height, or a header that doesn't link to the one below it.
+pub fn local_rollback_height(runes_db: &RunesDB) -> anyhow::Result<Option<u32>> {
+    let Some(indexed_height) = runes_db.latest_indexed_height() else {
+        return Ok(None);
+    };
+    let mut rollback_height = None;
+    for statistic in [Statistic::Runes, Statistic::ReservedRunes, Statistic::RuneTransactions, Statistic::Inscriptions, Statistic::SatRanges] {
+        if let Some(height) = runes_db.height_to_statistic_count_latest_height(&statistic)?.filter(|x| *x > indexed_height) {
+            warn!("Statistic {} counted at {}, above the indexed height {}", statistic.key(), height, indexed_height);
+            rollback_height = Some(indexed_height + 1);
+        }
+    }
+    for (table, height) in runes_db.sqlite_max_heights()? {
+        if height > indexed_height {
+            warn!("{} has rows at {}, above the indexed height {}", table, height, indexed_height);
+            rollback_height = Some(indexed_height + 1);
+        }
+    }
+    let mut header = runes_db.height_to_block_header_get(indexed_height);
+    for height in (indexed_height.saturating_sub(REORG_DEPTH)..indexed_height).rev() {
+        let Some(prev) = runes_db.height_to_block_header_get(height) else {
+            break;
+        };
+        if header.is_some_and(|x| x.prev_blockhash != prev.block_hash()) {
+            warn!("Header at {} doesn't link to the one at {}", height + 1, height);
+            rollback_height = Some(height + 1);
+        }
+        header = Some(prev);
+    }
+    Ok(rollback_height)
+}
+
+/// The first indexed height whose block bitcoind doesn't have on its active
+/// chain, walking back from the tip through the reorg window.
+pub fn tip_rollback_height(runes_db: &RunesDB, client: &Client) -> anyhow::Result<Option<u32>> {
+    let Some(indexed_height) = runes_db.latest_indexed_height() else {
+        return Ok(None);
+    };
+    let mut rollback_height = None;
+    for height in (indexed_height.saturating_sub(REORG_DEPTH)..=indexed_height).rev() {
+        let Some(header) = runes_db.height_to_block_header_get(height) else {
+            break;
+        };
+        if client.get_block_hash(height.into())? == header.block_hash() {
+            return Ok(rollback_height);
+        }
+        rollback_height = Some(height);
+    }
+    match rollback_height {
+        Some(height) => bail!("indexed blocks down to {} are not on bitcoind's chain, beyond the reorg window", height),
+        None => Ok(None),
+    }
+}
+
+/// Checks at startup that the stores agree on the indexed height and the tip
+/// is on bitcoind's chain, and rolls back to the last consistent height if
+/// not. Returns the height indexing resumes from after a rollback.
+///
+/// A failing bitcoind call skips the tip check, the indexer compares the tip
+/// with the next block anyway.
+pub fn check(runes_db: &RunesDB, client: &Client) -> anyhow::Result<Option<u32>> {
+    let local = local_rollback_height(runes_db)?;
+    let tip = tip_rollback_height(runes_db, client).unwrap_or_else(|e| {
+        warn!("Skipping the tip check against bitcoind: {}", e);
+        None
+    });
+    let Some(height) = local.into_iter().chain(tip).min() else {
+        return Ok(None);
+    };
+    let indexed_height = runes_db.latest_indexed_height().unwrap_or_default();
+    if indexed_height.saturating_sub(height) >= REORG_DEPTH {
+        bail!("index inconsistent from {}, beyond the reorg window of the indexed height {}, rollback or reindex required", height, indexed_height);
+    }
+    warn!("Index inconsistent from {}, rolling back", height);
+    let latest_height = runes_db.latest_height().unwrap_or(indexed_height).max(indexed_height);
+    runes_db.reorg_to_height(height, latest_height)?;
+    info!("Rolled back, indexed height: {:?}", runes_db.latest_indexed_height());
+    Ok(Some(height))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::block::{Header, Version};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, CompactTarget, TxMerkleNode};
+    use rusqlite::params;
+
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn header(prev_blockhash: BlockHash, time: u32) -> Header {
+        Header {
+            version: Version::ONE,
+            prev_blockhash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn rows_above_the_tip_and_broken_links() {
+        let path = TempDir::new("integrity");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        assert_eq!(local_rollback_height(&db).unwrap(), None);
+
+        let mut prev = BlockHash::all_zeros();
+        for height in 100..=105 {
+            let x = header(prev, height);
+            db.height_to_block_header_put(height, &x);
+            prev = x.block_hash();
+        }
+        assert_eq!(local_rollback_height(&db).unwrap(), None);
+
+        db.height_to_statistic_count_put(&Statistic::RuneTransactions, 106, 1);
+        assert_eq!(local_rollback_height(&db).unwrap(), Some(106));
+        db.reorg_to_height(106, 106).unwrap();
+        assert_eq!(local_rollback_height(&db).unwrap(), None);
+
+        db.sqlite.get().unwrap().execute("INSERT INTO block_commit (height, block_hash, ts) VALUES (?, ?, ?)", params![107, "", 0]).unwrap();
+        assert_eq!(local_rollback_height(&db).unwrap(), Some(106));
+        db.reorg_to_height(106, 107).unwrap();
+        assert_eq!(local_rollback_height(&db).unwrap(), None);
+
+        db.height_to_block_header_put(103, &header(BlockHash::all_zeros(), 0));
+        assert_eq!(local_rollback_height(&db).unwrap(), Some(103));
+    }
+}
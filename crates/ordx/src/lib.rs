@@ -9,3 +9,26 @@ pub mod db;
 pub mod rpc;
 pub mod api;
 pub mod cache;
+pub mod telemetry;
+pub mod log_file;
+pub mod verify;
+pub mod webhook;
+pub mod fee;
+pub mod mempool;
+pub mod control;
+pub mod rollback;
+pub mod reindex;
+pub mod export;
+pub mod watch;
+pub mod p2p;
+pub mod maintenance;
+pub mod backup;
+pub mod integrity;
+pub mod rebuild;
+pub mod compare;
+pub mod inscription;
+pub mod protocol;
+pub mod sat;
+
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,137 @@
+//! Log files rotated by size and time, for deployments that don't capture
+//! stderr.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// When log files are rotated besides reaching their maximum size.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// Only by size.
+    Never,
+    Hourly,
+    #[default]
+    Daily,
+}
+
+impl LogRotation {
+    /// The period `time` falls in, files are rotated when it changes.
+    fn period(self, time: SystemTime) -> u64 {
+        let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        match self {
+            LogRotation::Never => 0,
+            LogRotation::Hourly => secs / 3600,
+            LogRotation::Daily => secs / 86400,
+        }
+    }
+}
+
+/// Appends to `path`, moving it to `<path>.1` and the older files one number up
+/// to `<path>.<max_files>` when it would outgrow `max_size` (unlimited when 0)
+/// or its rotation period ends.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: LogRotation,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+    period: u64,
+}
+
+impl RotatingFile {
+    pub fn open(dir: impl AsRef<Path>, name: &str, rotation: LogRotation, max_size: u64, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let path = dir.as_ref().join(name);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        // the file of a previous run belongs to the period it was last written in
+        let period = rotation.period(metadata.modified().unwrap_or_else(|_| SystemTime::now()));
+        Ok(Self { path, rotation, max_size, max_files, file, size: metadata.len(), period })
+    }
+
+    fn rotated(&self, number: usize) -> PathBuf {
+        let mut path = OsString::from(&self.path);
+        path.push(format!(".{}", number));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for number in (1..self.max_files).rev() {
+            let from = self.rotated(number);
+            if from.exists() {
+                fs::rename(from, self.rotated(number + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Writes `buf` whole into the file current at `now`, so that records
+    /// written in one call never straddle two files.
+    fn write_at(&mut self, buf: &[u8], now: SystemTime) -> io::Result<usize> {
+        let period = self.rotation.period(now);
+        let full = self.max_size > 0 && self.size + buf.len() as u64 > self.max_size;
+        if self.size > 0 && (period != self.period || full) {
+            self.rotate()?;
+        }
+        self.period = period;
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, SystemTime::now())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn rotates_by_size_and_period() {
+        let dir = TempDir::new("log-file");
+        let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+        let hour = |x: u64| UNIX_EPOCH + Duration::from_secs(x * 3600);
+        let mut file = RotatingFile::open(&dir, "ordx.log", LogRotation::Hourly, 8, 2).unwrap();
+
+        file.write_at(b"a\n", hour(1)).unwrap();
+        file.write_at(b"b\n", hour(1)).unwrap();
+        assert_eq!(read("ordx.log").as_deref(), Some("a\nb\n"));
+        file.write_at(b"c\n", hour(2)).unwrap();
+        assert_eq!((read("ordx.log").as_deref(), read("ordx.log.1").as_deref()), (Some("c\n"), Some("a\nb\n")));
+
+        // records aren't split, the one that doesn't fit starts the next file
+        file.write_at(b"ddddddd\n", hour(2)).unwrap();
+        assert_eq!(read("ordx.log").as_deref(), Some("ddddddd\n"));
+        assert_eq!((read("ordx.log.1").as_deref(), read("ordx.log.2").as_deref()), (Some("c\n"), Some("a\nb\n")));
+        file.write_at(b"e\n", hour(2)).unwrap();
+        assert_eq!(read("ordx.log").as_deref(), Some("e\n"));
+        assert_eq!((read("ordx.log.1").as_deref(), read("ordx.log.2").as_deref()), (Some("ddddddd\n"), Some("c\n")));
+        assert_eq!(read("ordx.log.3"), None);
+    }
+}
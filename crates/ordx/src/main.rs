@@ -1,26 +1,60 @@
 use std::cmp::max;
 use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
-use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use anyhow::Context;
+use axum::Router;
 use bitcoin::constants::SUBSIDY_HALVING_INTERVAL;
 use bitcoin::hashes::Hash;
-use bitcoin::Txid;
-use bitcoincore_rpc::RpcApi;
+use bitcoin::{Block, BlockHash, Txid};
 use log::{info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{oneshot, watch, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{info_span, Instrument};
 
 use ordinals::{Height, Rune, RuneId, SpacedRune, Terms};
-use ordx::api::create_server;
-use ordx::cache::create_cache;
+use ordx::api::{create_router, nest_networks, serve};
+use ordx::backup;
+use ordx::backup::BackupOptions;
+use ordx::cache::{create_cache, create_commit_tx_cache};
 use ordx::chain::Chain;
-use ordx::db::model::{RuneBalanceForTemp, RuneEntryForTemp};
-use ordx::db::RunesDB;
+use ordx::compare;
+use ordx::compare::CompareOptions;
+use ordx::control::IndexerControl;
+use ordx::export;
+use ordx::export::ExportOptions;
+use ordx::fee::FeeEstimator;
+use ordx::inscription::InscriptionUpdater;
+use ordx::protocol::ProtocolUpdater;
+use ordx::sat;
+use ordx::sat::SatUpdater;
+use ordx::integrity;
+use ordx::maintenance::{Maintenance, RocksdbMaintenance};
+use ordx::mempool::MempoolTracker;
+use ordx::p2p::P2pBlockSource;
+use ordx::db::model::{BlockStats, RuneBalanceForTemp, RuneEntryForTemp};
+use ordx::db::writer::SqliteWriter;
+use ordx::db::{RunesDB, Store};
 use ordx::entry::{RuneEntry, Statistic};
-use ordx::rpc::{create_bitcoincore_rpc_client, with_retry};
-use ordx::settings::Settings;
-use ordx::updater::RuneUpdater;
+use ordx::rebuild;
+use ordx::rebuild::RebuildOptions;
+use ordx::reindex;
+use ordx::reindex::ReindexStatus;
+use ordx::rollback;
+use ordx::rollback::RollbackOptions;
+use ordx::rpc::{create_bitcoincore_rpc_client, with_retry, AsyncClient};
+use ordx::settings::{Mode, Settings};
+use ordx::telemetry;
+use ordx::updater::{RuneUpdater, REORG_DEPTH};
+use ordx::verify;
+use ordx::verify::VerifyOptions;
+use ordx::watch::Watches;
+use ordx::webhook::Webhooks;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -31,35 +65,320 @@ async fn main() -> anyhow::Result<()> {
         warn!("Waiting index to finish...");
     })
         .expect("Error setting Ctrl-C handler");
+    // SIGHUP reloads settings, see api::reload, so ctrlc only handles SIGINT
+    let mut terminate = signal(SignalKind::terminate())?;
+    let shutdown_handler = Arc::clone(&shutdown);
+    tokio::spawn(async move {
+        terminate.recv().await;
+        shutdown_handler.store(true, Ordering::Relaxed);
+        warn!("Waiting index to finish...");
+    });
 
     let settings = Arc::new(Settings::load());
-    env_logger::init();
+    telemetry::init(&settings)?;
     info!("{}", &settings);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut networks = settings.network_settings()?;
+    // subcommands run on one network, NETWORK picks its section
+    if networks.len() > 1 && !args.is_empty() {
+        let chain: Chain = settings.network.as_ref().context("NETWORK is required to pick the network of a subcommand")?.parse()?;
+        networks.retain(|x| x.network.as_ref().and_then(|x| x.parse::<Chain>().ok()) == Some(chain));
+        if networks.is_empty() {
+            anyhow::bail!("network {} has no section", chain);
+        }
+    }
+
+    let server = Arc::new(Server::new());
+    let result = if networks.len() == 1 {
+        let network = Arc::new(networks.remove(0));
+        run(network, &args, shutdown, Arc::clone(&server), Api::Own).await
+    } else {
+        run_networks(settings, networks, shutdown, server).await
+    };
+    warn!("Shutting down...");
+    telemetry::shutdown();
+    result
+}
+
+/// The API server of the process, shared by its networks. The first of them
+/// to shut down stops it, before closing its database.
+struct Server {
+    shutdown: watch::Sender<bool>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Server {
+    fn new() -> Self {
+        Server {
+            shutdown: watch::channel(false).0,
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Tells the server and the background tasks of the networks to stop.
+    fn subscribe(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    async fn start(&self, settings: Arc<Settings>, app: Router) {
+        let shutdown = self.subscribe();
+        *self.handle.lock().await = Some(tokio::spawn(async move {
+            serve(&settings, app, shutdown).await.unwrap();
+        }));
+    }
+
+    async fn stop(&self, settings: &Settings) {
+        warn!("Shutting down server...");
+        self.shutdown.send_replace(true);
+        let Some(mut handle) = self.handle.lock().await.take() else {
+            return;
+        };
+        let drain_timeout = Duration::from_secs(settings.api_shutdown_timeout_secs);
+        match tokio::time::timeout(drain_timeout, &mut handle).await {
+            Ok(result) => warn!("Server shutdown: {:?}", result),
+            Err(_) => {
+                warn!("Server did not drain in {:?}, aborting", drain_timeout);
+                handle.abort();
+            }
+        }
+    }
+}
+
+/// Where the API of a network goes: a server of its own, or to
+/// `run_networks` to be nested with the others.
+enum Api {
+    Own,
+    Nested(oneshot::Sender<(Chain, Router)>),
+}
+
+impl Api {
+    async fn start(self, server: &Server, settings: Arc<Settings>, chain: Chain, app: Router) -> anyhow::Result<()> {
+        match self {
+            Api::Own => server.start(settings, app).await,
+            Api::Nested(routers) => routers.send((chain, app)).map_err(|_| anyhow::anyhow!("the server of the networks is gone"))?,
+        }
+        Ok(())
+    }
+}
+
+/// Runs several networks in one process, each indexing on its own while one
+/// server nests their APIs under their names. A failing network stops the
+/// others.
+async fn run_networks(settings: Arc<Settings>, networks: Vec<Settings>, shutdown: Arc<AtomicBool>, server: Arc<Server>) -> anyhow::Result<()> {
+    let mut routers = Vec::new();
+    let mut indexers = JoinSet::new();
+    for network in networks {
+        let (api, router) = oneshot::channel();
+        routers.push(router);
+        let span = info_span!("network", name = network.network.clone().unwrap_or_default());
+        let (shutdown, server) = (Arc::clone(&shutdown), Arc::clone(&server));
+        // each network gets its own task, a busy block loop doesn't starve the others
+        indexers.spawn(async move {
+            run(Arc::new(network), &[], shutdown, server, Api::Nested(api)).await
+        }.instrument(span));
+    }
+
+    // the indexers run until every API is up, a network that fails before
+    // sends none
+    let mut result = Ok(());
+    let mut apis = Vec::new();
+    for router in routers {
+        tokio::select! {
+            api = router => match api {
+                Ok(api) => apis.push(api),
+                Err(_) => break,
+            },
+            Some(Err(e)) = join_next(&mut indexers) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    if result.is_ok() && apis.len() == settings.networks.len() {
+        server.start(Arc::clone(&settings), nest_networks(apis)).await;
+    } else {
+        shutdown.store(true, Ordering::Relaxed);
+    }
+
+    while let Some(indexed) = join_next(&mut indexers).await {
+        if let Err(e) = indexed {
+            warn!("Network failed, stopping the others: {:?}", e);
+            shutdown.store(true, Ordering::Relaxed);
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+    server.stop(&settings).await;
+    result
+}
+
+/// Waits for the next network to finish, a panicked one counts as failed.
+async fn join_next(indexers: &mut JoinSet<anyhow::Result<()>>) -> Option<anyhow::Result<()>> {
+    let joined = indexers.join_next().await?;
+    Some(joined.context("network task panicked").and_then(|x| x))
+}
+
+/// Indexes one network and serves its API, or runs a subcommand on it.
+async fn run(settings: Arc<Settings>, args: &[String], shutdown: Arc<AtomicBool>, server: Arc<Server>, api: Api) -> anyhow::Result<()> {
+    let data_dir = settings.data_dir.clone().unwrap_or("./data".to_string());
+    if settings.network.as_ref().is_some_and(|x| x.parse::<Chain>().ok() == Some(Chain::Custom)) {
+        Chain::set_custom(settings.custom_chain()?)?;
+    }
+
+    if settings.mode == Mode::Serve {
+        let chain: Chain = settings.network.as_ref().context("NETWORK is required")?.parse()?;
+        let db_path = chain.join_with_data_dir(&data_dir);
+        let secondary_path = settings.rocksdb_secondary_path.as_ref().map(PathBuf::from).unwrap_or_else(|| db_path.join("rocksdb-secondary"));
+        let runes_db = Arc::new(RunesDB::new_secondary(db_path.clone(), secondary_path, &settings.sqlite_options()?, &settings.rocksdb_options()?));
+        return serve_replica(settings, chain, db_path, runes_db, None, shutdown, server, api).await;
+    }
+
     let (rpc_client, chain) = create_bitcoincore_rpc_client(settings.clone())?;
 
-    let db_path = chain.join_with_data_dir(settings.data_dir.clone().unwrap_or("./data".to_string()).as_str());
-    let runes_db = Arc::new(RunesDB::new(db_path));
+    let db_path = chain.join_with_data_dir(&data_dir);
+    let full_reindex = args.first().is_some_and(|x| x == "reindex");
+    if full_reindex {
+        reindex::parse_args(&args[1..])?;
+        warn!("Full reindex, deleting the index at {:?}", db_path);
+        reindex::wipe(&db_path)?;
+    }
+    let rebuild_options = match args.first() {
+        Some(x) if x == "rebuild-sqlite" => Some(RebuildOptions::parse(&args[1..])?),
+        _ => None,
+    };
+    if rebuild_options.as_ref().is_some_and(|x| x.fresh) {
+        rebuild::set_aside(&db_path)?;
+    }
+
+    if let Some(secondary_path) = settings.rocksdb_secondary_path.clone() {
+        let runes_db = Arc::new(RunesDB::new_secondary(db_path.clone(), PathBuf::from(secondary_path), &settings.sqlite_options()?, &settings.rocksdb_options()?));
+        return serve_replica(settings, chain, db_path, runes_db, Some(rpc_client), shutdown, server, api).await;
+    }
+
+    let runes_db = Arc::new(RunesDB::open(&db_path, &settings.sqlite_options()?, &settings.rocksdb_options()?));
     runes_db.init_sqlite()?;
+    runes_db.init_rune_outpoints_index()?;
 
-    let cache = Arc::new(create_cache(&settings));
+    if args.first().is_some_and(|x| x == "verify") {
+        let options = VerifyOptions::parse(&args[1..])?;
+        let report = verify::run(&runes_db, &rpc_client.blocking(), &options)?;
+        runes_db.close()?;
+        if !report.is_consistent() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if rebuild_options.is_some() {
+        rebuild::run(&runes_db, &rpc_client.blocking(), chain)?;
+        runes_db.close()?;
+        return Ok(());
+    }
+
+    runes_db.reconcile_commit()?;
+    runes_db.init_network_stats()?;
+    runes_db.init_counter_undo();
+    runes_db.init_script_hash_index(chain)?;
+    runes_db.init_script_hash_balance_index()?;
 
-    let first_rune_height = {
-        if chain == Chain::Testnet {
-            // testnet first rune height
-            2583205
-        } else {
-            Rune::first_rune_height(chain.network())
+    if args.first().is_some_and(|x| x == "rollback") {
+        let options = RollbackOptions::parse(&args[1..])?;
+        rollback::run(&runes_db, &options, settings.prune_spent_after_blocks()?)?;
+        runes_db.close()?;
+        return Ok(());
+    }
+
+    if args.first().is_some_and(|x| x == "export") {
+        let options = ExportOptions::parse(&args[1..])?;
+        export::run(&runes_db, &options)?;
+        runes_db.close()?;
+        return Ok(());
+    }
+
+    if args.first().is_some_and(|x| x == "compare") {
+        let options = CompareOptions::parse(&args[1..])?;
+        let report = compare::run(&runes_db, &options)?;
+        runes_db.close()?;
+        if !report.discrepancies.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.first().is_some_and(|x| x == "backup") {
+        let options = BackupOptions::parse(&args[1..])?;
+        backup::run(&runes_db, &options.out)?;
+        runes_db.close()?;
+        return Ok(());
+    }
+
+    integrity::check(&runes_db, &rpc_client.blocking())?;
+
+    let sqlite_writer = Arc::new(SqliteWriter::new(Arc::clone(&runes_db)));
+    let cache = Arc::new(create_cache(&settings).with_persistent(Arc::clone(&runes_db), settings.persistent_cache_methods()));
+    let commit_tx_cache = create_commit_tx_cache(&settings);
+    let balance_scripts = settings.light_mode_scripts(chain)?;
+    let block_filter_scripts = settings.light_mode_block_filter_scripts(chain)?;
+    let prune_spent_after_blocks = settings.prune_spent_after_blocks()?;
+    let protocols = settings.protocols()?;
+    // blocks with prevouts only come from getblock
+    let p2p = match &settings.p2p_peers {
+        Some(peers) if settings.bitcoin_rpc_txindex => Some(P2pBlockSource::new(chain, peers)?),
+        Some(_) => {
+            warn!("P2P_PEERS is ignored without txindex");
+            None
         }
+        None => None,
     };
+    if let Some(scripts) = &balance_scripts {
+        info!("Light mode, balance rows only for {} scripts", scripts.len());
+    }
+    if block_filter_scripts.is_some() {
+        warn!("Light mode skips blocks by their filters, their etchings and mints are not indexed");
+    }
+
+    let first_rune_height = settings.first_rune_height(chain);
+    sat::check_index(&runes_db, settings.index_sats, first_rune_height)?;
+    // headers are synced from below the indexed tip, from genesis on an empty database
+    if let Some(p2p) = &p2p {
+        let anchor = runes_db.latest_indexed_height()
+            .map(|x| x.saturating_sub(REORG_DEPTH).max(first_rune_height))
+            .and_then(|x| runes_db.height_to_block_header_get(x).map(|header| (x, header.block_hash())));
+        if let Some((height, hash)) = anchor {
+            p2p.anchor(height, hash).await;
+        }
+    }
 
-    let started_height = runes_db.latest_indexed_height().map(|x| x + 1).unwrap_or(first_rune_height);
+    let mut started_height = runes_db.latest_indexed_height().map(|x| x + 1).unwrap_or(first_rune_height);
+    let reindex_status = Arc::new(if full_reindex {
+        ReindexStatus::start(&db_path, first_rune_height)
+    } else {
+        ReindexStatus::load(&db_path)
+    });
 
     let server_db = Arc::clone(&runes_db);
+    let server_sqlite_writer = Arc::clone(&sqlite_writer);
     let server_settings = Arc::clone(&settings);
     let server_cache = Arc::clone(&cache);
-    let server_handle = Box::new(tokio::spawn(async move {
-        create_server(server_settings, server_db, server_cache).await.unwrap();
-    }));
+    let server_fee_estimator = Arc::new(FeeEstimator::new(&settings, Some(rpc_client.clone())));
+    let server_shutdown_rx = server.subscribe();
+    let webhooks = Arc::new(Webhooks::new(&settings, Arc::clone(&runes_db), Arc::clone(&sqlite_writer)));
+    tokio::spawn(Arc::clone(&webhooks).run(server_shutdown_rx.clone()));
+    let maintenance = Arc::new(Maintenance::new(&settings, Arc::clone(&runes_db), Arc::clone(&sqlite_writer)));
+    tokio::spawn(maintenance.run(server_shutdown_rx.clone()));
+    let rocksdb_maintenance = Arc::new(RocksdbMaintenance::new(&settings, Arc::clone(&runes_db))?);
+    tokio::spawn(rocksdb_maintenance.run(server_shutdown_rx.clone()));
+    let watches = Arc::new(Watches::new(Arc::clone(&runes_db), chain));
+    let server_watches = Arc::clone(&watches);
+    let server_mempool = Arc::new(MempoolTracker::new(&settings, Some(rpc_client.clone()), Arc::clone(&runes_db), Some(Arc::clone(&webhooks)), Arc::clone(&watches)));
+    tokio::spawn(Arc::clone(&server_mempool).run(server_shutdown_rx.clone()));
+    let server_rpc_client = Some(rpc_client.clone());
+    let indexer_control = Arc::new(IndexerControl::new(first_rune_height));
+    let server_indexer_control = Arc::clone(&indexer_control);
+    let server_reindex_status = Arc::clone(&reindex_status);
+    let app = create_router(Arc::clone(&server_settings), chain, server_db, server_sqlite_writer, server_cache, server_rpc_client, server_fee_estimator, server_mempool, server_watches, server_indexer_control, server_reindex_status)?;
+    api.start(&server, server_settings, chain, app).await?;
     // Create the first rune if it doesn't exist
     if chain == Chain::Mainnet {
         let id = RuneId { block: 1, tx: 0 };
@@ -93,7 +412,7 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    let start_timestamp = Instant::now();
+    let mut start_timestamp = Instant::now();
 
     let reorg_height = AtomicU32::new(0);
     let index_height = AtomicU32::new(started_height);
@@ -101,70 +420,98 @@ async fn main() -> anyhow::Result<()> {
     loop {
         info!("================================================================================");
         if shutdown.load(Ordering::Relaxed) {
-            runes_db.flush_rocksdb();
-            warn!("Shutting down server...");
-            server_handle.abort();
-            let is_cancelled = server_handle.await.unwrap_err().is_cancelled();
-            warn!("Server shutdown: {}", is_cancelled);
+            server.stop(&settings).await;
+            sqlite_writer.flush().await?;
+            runes_db.close()?;
             break;
         }
+        if let Some(height) = indexer_control.take_reindex() {
+            warn!("Reindex requested, resetting to height: {}", height);
+            index_height.store(height, Ordering::Relaxed);
+            reorg_height.store(height, Ordering::Relaxed);
+            // the eta is measured from where indexing restarts
+            started_height = height;
+            start_timestamp = Instant::now();
+        }
         let index_timestamp = Instant::now();
-        let block = with_retry(|| {
-            let latest_height: u32 = rpc_client.get_block_count()? as _;
-            runes_db.statistic_to_value_put(&Statistic::LatestHeight, latest_height);
-            let h = index_height.load(Ordering::Relaxed);
-            if latest_height < h {
-                thread::sleep(Duration::from_secs(1));
-                return Ok(None);
-            }
+        let block = {
+            let (rpc_client, runes_db, index_height, reorg_height, p2p, block_filter_scripts) = (&rpc_client, &runes_db, &index_height, &reorg_height, &p2p, &block_filter_scripts);
+            let txindex = settings.bitcoin_rpc_txindex;
+            with_retry(|| async move {
+                let h = index_height.load(Ordering::Relaxed);
+                let latest_height: u32 = match p2p {
+                    Some(p2p) => p2p.get_block_count(h).await?,
+                    None => rpc_client.get_block_count().await? as _,
+                };
+                runes_db.statistic_to_value_put(&Statistic::LatestHeight, latest_height);
+                if latest_height < h {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    return Ok(None);
+                }
 
-            let block_hash = rpc_client.get_block_hash(h.into())?;
-            let block = rpc_client.get_block(&block_hash)?;
-
-            let bitcoind_prev_blockhash = block.header.prev_blockhash;
-            let mut prev_height = h - 1;
-            let mut first_check = true;
-            loop {
-                if prev_height > first_rune_height {
-                    let header = runes_db.height_to_block_header_get(prev_height);
-                    match header {
-                        None => {
-                            let sh = runes_db.latest_indexed_height().unwrap_or(first_rune_height);
-                            let to_height = sh.max(first_rune_height);
-                            index_height.store(to_height, Ordering::Relaxed);
-                            reorg_height.store(to_height, Ordering::Relaxed);
-                            warn!("No header found for height: {}, resetting to: {}", prev_height, to_height);
-                            return Ok(None);
-                        }
-                        Some(v) => {
-                            if first_check {
-                                first_check = false;
-                                if v.block_hash() == bitcoind_prev_blockhash {
-                                    break;
+                let block_hash = get_block_hash(rpc_client, p2p, h).await?;
+                let skip = match block_filter_scripts {
+                    Some(scripts) => !rpc_client.block_filter_matches(block_hash, scripts).await?,
+                    None => false,
+                };
+                let (block, prevouts) = if skip {
+                    // indexed without transactions, only the header is kept
+                    (Block { header: rpc_client.get_block_header(block_hash).await?, txdata: vec![] }, None)
+                } else if let Some(p2p) = p2p {
+                    (p2p.get_block(h, block_hash).await?, None)
+                } else if txindex {
+                    (rpc_client.get_block(block_hash).await?, None)
+                } else {
+                    let (block, prevouts) = rpc_client.get_block_with_prevouts(block_hash).await?;
+                    (block, Some(prevouts))
+                };
+
+                let bitcoind_prev_blockhash = block.header.prev_blockhash;
+                let mut prev_height = h - 1;
+                let mut first_check = true;
+                loop {
+                    if prev_height > first_rune_height {
+                        let header = runes_db.height_to_block_header_get(prev_height);
+                        match header {
+                            None => {
+                                let sh = runes_db.latest_indexed_height().unwrap_or(first_rune_height);
+                                let to_height = sh.max(first_rune_height);
+                                index_height.store(to_height, Ordering::Relaxed);
+                                reorg_height.store(to_height, Ordering::Relaxed);
+                                warn!("No header found for height: {}, resetting to: {}", prev_height, to_height);
+                                return Ok(None);
+                            }
+                            Some(v) => {
+                                if first_check {
+                                    first_check = false;
+                                    if v.block_hash() == bitcoind_prev_blockhash {
+                                        break;
+                                    } else {
+                                        prev_height = max(first_rune_height, prev_height - 1);
+                                    }
                                 } else {
+                                    let block_hash = get_block_hash(rpc_client, p2p, prev_height).await?;
+                                    if block_hash == v.block_hash() {
+                                        let to_height = prev_height + 1;
+                                        index_height.store(max(first_rune_height, to_height), Ordering::Relaxed);
+                                        reorg_height.store(max(first_rune_height, to_height), Ordering::Relaxed);
+                                        warn!("Block hash mismatch, resetting to: {}", to_height);
+                                        return Ok(None);
+                                    }
                                     prev_height = max(first_rune_height, prev_height - 1);
                                 }
-                            } else {
-                                let block_hash = rpc_client.get_block_hash(prev_height.into())?;
-                                if block_hash == v.block_hash() {
-                                    let to_height = prev_height + 1;
-                                    index_height.store(max(first_rune_height, to_height), Ordering::Relaxed);
-                                    reorg_height.store(max(first_rune_height, to_height), Ordering::Relaxed);
-                                    warn!("Block hash mismatch, resetting to: {}", to_height);
-                                    return Ok(None);
-                                }
-                                prev_height = max(first_rune_height, prev_height - 1);
                             }
                         }
+                    } else {
+                        break;
                     }
-                } else {
-                    break;
                 }
-            }
-            Ok(Some((block, h, latest_height)))
-        }, 10, Duration::from_millis(100)).await;
+                Ok(Some((block, prevouts, h, latest_height)))
+            }, 10, Duration::from_millis(100)).await
+        };
         match block {
-            Ok(Some((block, block_height, latest_height))) => {
+            Ok(Some((block, prevouts, block_height, latest_height))) => {
+                let fetch_elapsed = index_timestamp.elapsed();
                 let curr_reorg_height = reorg_height.load(Ordering::Relaxed);
                 if curr_reorg_height != 0 {
                     if block_height > curr_reorg_height {
@@ -173,60 +520,170 @@ async fn main() -> anyhow::Result<()> {
                     }
                     warn!("Reorg detected, resetting to height: {}", curr_reorg_height);
                     let start = Instant::now();
-                    runes_db.reorg_to_height(curr_reorg_height, latest_height)?;
+                    sqlite_writer.call(move |db| db.reorg_to_height(curr_reorg_height, latest_height)).await?;
+                    // commit txs may have been reorged to another height
+                    commit_tx_cache.invalidate_all();
                     let elapsed = start.elapsed();
                     warn!("Reorg done, {:?}", elapsed);
                     reorg_height.store(0, Ordering::Relaxed);
                 }
                 let updater_timestamp = Instant::now();
-                let runes_num_before = runes_db.statistic_to_value_get(&Statistic::Runes).unwrap_or_default();
+                let block_writer = runes_db.block_writer();
+                let runes_num_before = block_writer.statistic_to_value_get(&Statistic::Runes).unwrap_or_default();
                 let mut outpoint_to_rune_ids = HashMap::new();
                 let mut rune_entry_temp = RuneEntryForTemp::default();
                 let mut rune_balance_temp = RuneBalanceForTemp::default();
                 let mut rune_updater = RuneUpdater {
                     block_time: block.header.time,
-                    network: chain.network(),
+                    chain,
                     burned: HashMap::new(),
+                    events: Vec::new(),
                     client: &rpc_client,
+                    commit_tx_cache: &commit_tx_cache,
+                    prevouts: prevouts.as_ref(),
+                    balance_scripts: balance_scripts.as_ref(),
                     height: block_height,
                     latest_height,
-                    minimum: Rune::minimum_at_height(
-                        chain.network(),
-                        Height(block_height),
-                    ),
+                    minimum: chain.minimum_rune_at_height(Height(block_height)),
                     runes: runes_num_before,
-                    runes_db: &runes_db,
+                    runestones: 0,
+                    transactions: 0,
+                    mints: 0,
+                    writer: &block_writer,
                     outpoint_to_rune_ids: &mut outpoint_to_rune_ids,
                     rune_entry_temp: &mut rune_entry_temp,
                     rune_balance_temp: &mut rune_balance_temp,
                 };
-                for (i, tx) in block.txdata.iter().enumerate() {
-                    rune_updater.index_runes(u32::try_from(i)?, tx).await?;
-                }
-                rune_updater.update()?;
-                let runes_num_total = rune_updater.runes_num();
-
-                let changed_count = runes_num_total - runes_num_before;
-                if changed_count > 0 {
-                    info!("Runes added: {}, total: {}", changed_count, rune_updater.runes_num());
-                    runes_db.height_to_statistic_count_put(&Statistic::Runes, block_height, changed_count);
+                let mut inscription_updater = settings.index_inscriptions.then(|| InscriptionUpdater {
+                    block_time: block.header.time,
+                    client: &rpc_client,
+                    prevouts: prevouts.as_ref(),
+                    height: block_height,
+                    chain,
+                    next_number: block_writer.statistic_to_value_get(&Statistic::Inscriptions).unwrap_or_default(),
+                    revealed: 0,
+                    writer: &block_writer,
+                    inscriptions: Vec::new(),
+                    transfers: Vec::new(),
+                });
+                let mut sat_updater = settings.index_sats.then(|| SatUpdater::new(chain, block_height, &block_writer));
+                let protocol_updater = ProtocolUpdater {
+                    height: block_height,
+                    protocols: &protocols,
+                    writer: &block_writer,
+                };
+                let block_span = info_span!("index_block", height = block_height, txs = block.txdata.len());
+                async {
+                    for (i, tx) in block.txdata.iter().enumerate() {
+                        rune_updater.index_runes(u32::try_from(i)?, tx).await?;
+                        if let Some(inscription_updater) = &mut inscription_updater {
+                            inscription_updater.index_inscriptions(u32::try_from(i)?, tx).await?;
+                        }
+                        if !protocols.is_empty() {
+                            protocol_updater.index_protocols(u32::try_from(i)?, tx)?;
+                        }
+                        if let Some(sat_updater) = &mut sat_updater {
+                            sat_updater.index_sats(tx)?;
+                        }
+                    }
+                    anyhow::Ok(())
                 }
-                runes_db.height_to_block_header_put(block_height, &block.header);
+                    .instrument(block_span.clone())
+                    .await?;
+                // the guard is not Send, it's left before the sqlite submit is awaited
+                let (events, runestones, update_elapsed) = {
+                    let _block_span = block_span.enter();
+                    rune_updater.update()?;
+                    let runes_num_total = rune_updater.runes_num();
+                    let events = std::mem::take(&mut rune_updater.events);
+                    let runestones = rune_updater.runestones;
 
-                runes_db.height_outpoint_to_rune_ids_batch_put_and_del(block_height, &outpoint_to_rune_ids);
+                    let changed_count = runes_num_total - runes_num_before;
+                    if changed_count > 0 {
+                        info!("Runes added: {}, total: {}", changed_count, rune_updater.runes_num());
+                        block_writer.height_to_statistic_count_put(&Statistic::Runes, block_height, changed_count);
+                    }
+                    if let Some(sat_updater) = &mut sat_updater {
+                        sat_updater.update()?;
+                    }
+                    if let Some(inscription_updater) = inscription_updater {
+                        inscription_updater.update()?;
+                        rune_balance_temp.inscriptions = inscription_updater.inscriptions;
+                        rune_balance_temp.inscription_transfers = inscription_updater.transfers;
+                    }
+                    // the header marks the block as committed in rocksdb, block_commit in sqlite
+                    block_writer.commit(block_height, &block.header, &outpoint_to_rune_ids)?;
+                    (events, runestones, updater_timestamp.elapsed())
+                };
 
-                runes_db.to_sqlite(rune_entry_temp, rune_balance_temp)?;
+                // the next block is indexed while the writer commits this one to sqlite
+                let (webhooks, watches, cache, reindex_status) = (Arc::clone(&webhooks), Arc::clone(&watches), Arc::clone(&cache), Arc::clone(&reindex_status));
+                let block_hash = block.block_hash();
+                let txs = block.txdata.len() as u32;
+                sqlite_writer.submit(move |runes_db| {
+                    let sqlite_timestamp = Instant::now();
+                    let queued = runes_db.to_sqlite(block_height, &block_hash, rune_entry_temp, rune_balance_temp, &events)?;
+                    match runes_db.refresh_mintable(latest_height) {
+                        Ok(0) => {}
+                        Ok(changed) => info!("Refreshed mintable runes: {}", changed),
+                        Err(e) => warn!("Failed to refresh mintable runes at height {}: {}", latest_height, e),
+                    }
+                    if let Some(after_blocks) = prune_spent_after_blocks {
+                        match runes_db.prune_spent(block_height, after_blocks) {
+                            Ok(0) => {}
+                            Ok(pruned) => info!("Pruned spent rune balances: {}", pruned),
+                            Err(e) => warn!("Failed to prune spent rune balances at height {}: {}", block_height, e),
+                        }
+                    }
+                    let sqlite_elapsed = sqlite_timestamp.elapsed();
+                    if queued > 0 {
+                        webhooks.queued(queued, Some(block_height));
+                    }
+                    if let Err(e) = watches.notify_block(block_height) {
+                        warn!("Failed to notify watches for height {}: {}", block_height, e);
+                    }
 
-                // Clear cache
-                cache.invalidate_all();
+                    // Clear cache
+                    let cache_timestamp = Instant::now();
+                    cache.invalidate_all();
+                    let cache_elapsed = cache_timestamp.elapsed();
+                    reindex_status.update(block_height, latest_height);
+                    let block_stats = BlockStats {
+                        height: block_height,
+                        txs,
+                        runestones,
+                        fetch_us: fetch_elapsed.as_micros() as u64,
+                        update_us: update_elapsed.as_micros() as u64,
+                        sqlite_us: sqlite_elapsed.as_micros() as u64,
+                        cache_us: cache_elapsed.as_micros() as u64,
+                        total_us: index_timestamp.elapsed().as_micros() as u64,
+                        ts: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                    };
+                    if let Err(e) = runes_db.sqlite_block_stats_insert(&block_stats) {
+                        warn!("Failed to record stats for height {}: {}", block_height, e);
+                    }
+                    Ok(())
+                }).await?;
 
-                let remaining_height = latest_height - block_height;
-                if remaining_height <= 3 {
-                    info!("{}-{}({})={}({:.5}%), {:?}/{:?}", latest_height, block_height, block.txdata.len(), remaining_height, 100f64-(block_height as f64) * 100f64 / (latest_height as f64), updater_timestamp.elapsed(), index_timestamp.elapsed());
+                let remaining_height = latest_height.saturating_sub(block_height);
+                let remaining_percent = format!("{:.5}%", 100f64 - (block_height as f64) * 100f64 / (latest_height as f64));
+                let eta = if remaining_height <= 3 {
+                    String::new()
                 } else {
-                    let remaining = start_timestamp.elapsed() / (block_height - started_height + 1) * (remaining_height);
-                    info!("{}-{}({})={}({:.5}%), {:?}/{:?}, {}", latest_height, block_height, block.txdata.len(), remaining_height, 100f64-(block_height as f64) * 100f64 / (latest_height as f64), updater_timestamp.elapsed(), index_timestamp.elapsed(), format_duration(remaining));
-                }
+                    format_duration(start_timestamp.elapsed() / (block_height.saturating_sub(started_height) + 1) * (remaining_height))
+                };
+                let _block_span = block_span.enter();
+                tracing::info!(
+                    latest_height,
+                    height = block_height,
+                    txs = block.txdata.len(),
+                    remaining = remaining_height,
+                    remaining_percent,
+                    updater_elapsed = ?updater_timestamp.elapsed(),
+                    index_elapsed = ?index_timestamp.elapsed(),
+                    eta,
+                    "Block indexed"
+                );
                 index_height.store(block_height + 1, Ordering::Relaxed);
             }
             _ => {
@@ -234,10 +691,54 @@ async fn main() -> anyhow::Result<()> {
             }
         }
     }
-    warn!("Shutting down...");
     Ok(())
 }
 
+/// Serves the API over a rocksdb secondary of the indexer, following its
+/// blocks instead of indexing. Webhooks and reindex requests are left to the
+/// indexer; without `rpc_client` mempool tracking and fee estimates are off.
+#[allow(clippy::too_many_arguments)]
+async fn serve_replica(settings: Arc<Settings>, chain: Chain, db_path: PathBuf, runes_db: Arc<RunesDB>, rpc_client: Option<AsyncClient>, shutdown: Arc<AtomicBool>, server: Arc<Server>, api: Api) -> anyhow::Result<()> {
+    let cache = Arc::new(create_cache(&settings));
+    let reindex_status = Arc::new(ReindexStatus::load(&db_path));
+    let server_shutdown_rx = server.subscribe();
+    let sqlite_writer = Arc::new(SqliteWriter::new(Arc::clone(&runes_db)));
+    let watches = Arc::new(Watches::new(Arc::clone(&runes_db), chain));
+    let mempool = Arc::new(MempoolTracker::new(&settings, rpc_client.clone(), Arc::clone(&runes_db), None, Arc::clone(&watches)));
+    tokio::spawn(Arc::clone(&mempool).run(server_shutdown_rx.clone()));
+    let fee_estimator = Arc::new(FeeEstimator::new(&settings, rpc_client.clone()));
+    let indexer_control = Arc::new(IndexerControl::read_only());
+    let app = create_router(Arc::clone(&settings), chain, Arc::clone(&runes_db), sqlite_writer, Arc::clone(&cache), rpc_client, fee_estimator, mempool, watches, indexer_control, Arc::clone(&reindex_status))?;
+    api.start(&server, Arc::clone(&settings), chain, app).await?;
+
+    let catch_up_interval = Duration::from_millis(settings.rocksdb_catch_up_interval_ms);
+    let mut indexed_height = runes_db.latest_indexed_height();
+    info!("Serving as replica from height: {:?}", indexed_height);
+    while !shutdown.load(Ordering::Relaxed) {
+        tokio::time::sleep(catch_up_interval).await;
+        if let Err(e) = runes_db.catch_up_with_primary() {
+            warn!("Failed to catch up with the indexer: {}", e);
+            continue;
+        }
+        let height = runes_db.latest_indexed_height();
+        if height != indexed_height {
+            info!("Caught up to height: {:?}", height);
+            indexed_height = height;
+            cache.invalidate_all();
+            reindex_status.refresh();
+        }
+    }
+    server.stop(&settings).await;
+    Ok(())
+}
+
+async fn get_block_hash(rpc_client: &AsyncClient, p2p: &Option<P2pBlockSource>, height: u32) -> anyhow::Result<BlockHash> {
+    match p2p {
+        Some(p2p) => p2p.get_block_hash(height).await,
+        None => rpc_client.get_block_hash(height.into()).await,
+    }
+}
+
 fn format_duration(duration: Duration) -> String {
     let total_seconds = duration.as_secs();
     let hours = total_seconds / 3600;
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{info, warn};
+use tokio::sync::watch;
+
+use crate::api::auth::unix_timestamp;
+use crate::db::writer::SqliteWriter;
+use crate::db::{RunesDB, Store};
+use crate::entry::Statistic;
+use crate::settings::Settings;
+
+/// Freelist pages returned to the filesystem per run, 40MB with 4KB pages.
+const VACUUM_PAGES: u32 = 10_000;
+/// Blocks behind the chain tip the indexer may be for a run to go ahead.
+const QUIET_BLOCKS: u32 = 1;
+/// How often the compaction window is checked when flushes are further apart.
+const WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Periodically vacuums and analyzes the sqlite database on the writer, while
+/// the indexer follows the tip instead of catching up.
+pub struct Maintenance {
+    runes_db: Arc<RunesDB>,
+    writer: Arc<SqliteWriter>,
+    interval: Duration,
+}
+
+impl Maintenance {
+    pub fn new(settings: &Settings, runes_db: Arc<RunesDB>, writer: Arc<SqliteWriter>) -> Self {
+        Maintenance {
+            runes_db,
+            writer,
+            interval: Duration::from_secs(settings.sqlite_maintenance_interval_secs),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.interval.is_zero()
+    }
+
+    /// Whether the indexer is at the tip, runs are skipped while it catches up.
+    fn quiet(&self) -> bool {
+        let Some(latest) = self.runes_db.statistic_to_value_get(&Statistic::LatestHeight) else {
+            return false;
+        };
+        self.runes_db.latest_indexed_height().is_some_and(|x| x + QUIET_BLOCKS >= latest)
+    }
+
+    pub async fn run(self: Arc<Self>, mut shutdown: watch::Receiver<bool>) {
+        if !self.enabled() {
+            return;
+        }
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                _ = shutdown.changed() => {}
+            }
+            if *shutdown.borrow() {
+                break;
+            }
+            if !self.quiet() {
+                info!("Indexer is catching up, skipping sqlite maintenance");
+                continue;
+            }
+            let t = Instant::now();
+            match self.writer.call(|db| db.sqlite_maintenance(VACUUM_PAGES)).await {
+                Ok(freed) => info!("SQLite maintenance done, freed pages: {}, {:?}", freed, t.elapsed()),
+                Err(e) => warn!("SQLite maintenance failed: {}", e),
+            }
+        }
+    }
+}
+
+/// A daily UTC time range in minutes of the day, wrapping past midnight when
+/// it ends before it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionWindow {
+    start: u32,
+    end: u32,
+}
+
+impl CompactionWindow {
+    /// Parses `HH:MM-HH:MM`.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let minutes = |x: &str| -> anyhow::Result<u32> {
+            let (h, m) = x.trim().split_once(':').context("expected HH:MM")?;
+            let (h, m) = (h.parse::<u32>()?, m.parse::<u32>()?);
+            if h > 23 || m > 59 {
+                anyhow::bail!("{} is not a time of day", x.trim());
+            }
+            Ok(h * 60 + m)
+        };
+        let (start, end) = s.split_once('-').with_context(|| format!("invalid compaction window `{s}`"))?;
+        let window = CompactionWindow { start: minutes(start)?, end: minutes(end)? };
+        if window.start == window.end {
+            anyhow::bail!("compaction window `{s}` is empty");
+        }
+        Ok(window)
+    }
+
+    fn contains(&self, secs: u64) -> bool {
+        let minute = (secs % SECS_PER_DAY / 60) as u32;
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+
+    /// The day the window that `secs` falls in opened, a window past midnight
+    /// belongs to the day it started on.
+    fn day(&self, secs: u64) -> u64 {
+        (secs - self.start as u64 * 60) / SECS_PER_DAY
+    }
+}
+
+/// Periodically flushes every rocksdb memtable and syncs the WAL, so a killed
+/// process replays little of it, and starts a full compaction once a day in
+/// the compaction window to keep read amplification bounded.
+pub struct RocksdbMaintenance {
+    runes_db: Arc<RunesDB>,
+    flush_interval: Duration,
+    compaction_window: Option<CompactionWindow>,
+}
+
+impl RocksdbMaintenance {
+    pub fn new(settings: &Settings, runes_db: Arc<RunesDB>) -> anyhow::Result<Self> {
+        Ok(RocksdbMaintenance {
+            runes_db,
+            flush_interval: Duration::from_secs(settings.rocksdb_flush_interval_secs),
+            compaction_window: settings.rocksdb_compaction_window.as_deref().map(CompactionWindow::parse).transpose()?,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.flush_interval.is_zero() || self.compaction_window.is_some()
+    }
+
+    pub async fn run(self: Arc<Self>, mut shutdown: watch::Receiver<bool>) {
+        if !self.enabled() {
+            return;
+        }
+        let tick = match (self.flush_interval.is_zero(), self.compaction_window.is_some()) {
+            (true, _) => WINDOW_CHECK_INTERVAL,
+            (false, true) => self.flush_interval.min(WINDOW_CHECK_INTERVAL),
+            (false, false) => self.flush_interval,
+        };
+        let mut flushed = Instant::now();
+        let mut compacted_day = None;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(tick) => {}
+                _ = shutdown.changed() => {}
+            }
+            if *shutdown.borrow() {
+                break;
+            }
+            if !self.flush_interval.is_zero() && flushed.elapsed() >= self.flush_interval {
+                flushed = Instant::now();
+                let db = Arc::clone(&self.runes_db);
+                match tokio::task::spawn_blocking(move || db.flush_rocksdb()).await.map_err(anyhow::Error::from) {
+                    Ok(Ok(())) => info!("RocksDB flushed, {:?}", flushed.elapsed()),
+                    Ok(Err(e)) | Err(e) => warn!("RocksDB flush failed: {}", e),
+                }
+            }
+            let Some(window) = self.compaction_window else {
+                continue;
+            };
+            let now = unix_timestamp();
+            if !window.contains(now) || compacted_day == Some(window.day(now)) {
+                continue;
+            }
+            compacted_day = Some(window.day(now));
+            let t = Instant::now();
+            let db = Arc::clone(&self.runes_db);
+            match tokio::task::spawn_blocking(move || db.compact_rocksdb(None)).await.map_err(anyhow::Error::from) {
+                Ok(Ok(cfs)) => info!("Compacted {} column families, {:?}", cfs.len(), t.elapsed()),
+                Ok(Err(e)) | Err(e) => warn!("RocksDB compaction failed: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compaction_window() {
+        let at = |h: u64, m: u64| 19_000 * SECS_PER_DAY + h * 3600 + m * 60;
+
+        let window = CompactionWindow::parse("02:00-04:30").unwrap();
+        assert!(!window.contains(at(1, 59)));
+        assert!(window.contains(at(2, 0)));
+        assert!(window.contains(at(4, 29)));
+        assert!(!window.contains(at(4, 30)));
+        assert_eq!(window.day(at(2, 0)), 19_000);
+
+        let window = CompactionWindow::parse(" 23:00 - 01:00 ").unwrap();
+        assert!(window.contains(at(23, 30)));
+        assert!(window.contains(at(0, 30)));
+        assert!(!window.contains(at(1, 0)));
+        assert_eq!(window.day(at(23, 30)), window.day(at(24, 30)));
+
+        assert!(CompactionWindow::parse("02:00").is_err());
+        assert!(CompactionWindow::parse("24:00-01:00").is_err());
+        assert!(CompactionWindow::parse("02:00-02:00").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use bitcoin::{OutPoint, Script, Transaction, Txid};
+use ordinals::Runestone;
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::db::{RunesDB, Store};
+use crate::rpc::AsyncClient;
+use crate::settings::Settings;
+use crate::watch::Watches;
+use crate::webhook::{RuneEvent, Webhooks};
+
+/// How many replaced txids are remembered.
+const MAX_REPLACED: usize = 10_000;
+/// Transactions fetched before they are slimmed down to [`MempoolTx`].
+const FETCH_CHUNK_SIZE: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum MempoolTxStatus {
+    Pending,
+    Replaced { replaced_by: Txid },
+    Unknown,
+}
+
+/// A mempool transaction as far as the tracker needs it: the outpoints it
+/// spends, and the whole transaction when it moves runes.
+#[derive(Debug, Clone)]
+pub struct MempoolTx {
+    pub txid: Txid,
+    pub inputs: Vec<OutPoint>,
+    pub runes: Option<Transaction>,
+}
+
+impl MempoolTx {
+    fn new(tx: Transaction, runes: bool) -> Self {
+        MempoolTx {
+            txid: tx.txid(),
+            inputs: tx.input.iter().map(|x| x.previous_output).collect(),
+            runes: runes.then_some(tx),
+        }
+    }
+}
+
+/// Unconfirmed transactions and the outpoints they spend.
+#[derive(Debug, Default)]
+pub struct MempoolState {
+    /// Outpoints spent by each transaction.
+    pub txs: HashMap<Txid, Vec<OutPoint>>,
+    pub spends: HashMap<OutPoint, Txid>,
+    /// Transactions that move runes, the only ones kept whole and reported
+    /// when they leave the mempool.
+    pub runes: HashMap<Txid, Transaction>,
+    pub replaced: HashMap<Txid, Txid>,
+    replaced_order: VecDeque<Txid>,
+    /// Set after the first sync, which loads the whole mempool and is not
+    /// reported to watches.
+    synced: bool,
+}
+
+impl MempoolState {
+    /// Drops transactions that left the mempool, either mined, replaced or
+    /// evicted, and returns them.
+    fn retain(&mut self, mempool: &HashSet<Txid>) -> Vec<MempoolTx> {
+        let removed = self.txs.keys().filter(|x| !mempool.contains(*x)).copied().collect::<Vec<_>>();
+        let mut txs = Vec::with_capacity(removed.len());
+        for txid in removed {
+            let inputs = self.txs.remove(&txid).unwrap();
+            for input in &inputs {
+                if self.spends.get(input) == Some(&txid) {
+                    self.spends.remove(input);
+                }
+            }
+            let runes = self.runes.remove(&txid);
+            txs.push(MempoolTx { txid, inputs, runes });
+        }
+        txs
+    }
+
+    fn insert(&mut self, tx: MempoolTx) {
+        for input in &tx.inputs {
+            self.spends.insert(*input, tx.txid);
+        }
+        if let Some(runes) = tx.runes {
+            self.runes.insert(tx.txid, runes);
+        }
+        self.replaced.remove(&tx.txid);
+        self.txs.insert(tx.txid, tx.inputs);
+    }
+
+    fn mark_replaced(&mut self, txid: Txid, replaced_by: Txid) {
+        if self.replaced.insert(txid, replaced_by).is_none() {
+            self.replaced_order.push_back(txid);
+        }
+        while self.replaced_order.len() > MAX_REPLACED {
+            let oldest = self.replaced_order.pop_front().unwrap();
+            self.replaced.remove(&oldest);
+        }
+    }
+
+    pub fn status(&self, txid: &Txid) -> MempoolTxStatus {
+        if self.txs.contains_key(txid) {
+            MempoolTxStatus::Pending
+        } else if let Some(replaced_by) = self.replaced.get(txid) {
+            MempoolTxStatus::Replaced { replaced_by: *replaced_by }
+        } else {
+            MempoolTxStatus::Unknown
+        }
+    }
+}
+
+/// Pairs removed transactions with the added ones spending any of the same
+/// outpoints, as (replaced, replacement).
+fn replacements(removed: &[MempoolTx], added: &[MempoolTx]) -> Vec<(Txid, Txid)> {
+    let mut spent_by_removed = HashMap::new();
+    for tx in removed {
+        for input in &tx.inputs {
+            spent_by_removed.insert(*input, tx.txid);
+        }
+    }
+    let mut pairs = vec![];
+    for tx in added {
+        for input in &tx.inputs {
+            if let Some(replaced) = spent_by_removed.remove(input) {
+                pairs.push((replaced, tx.txid));
+            }
+        }
+    }
+    pairs.sort();
+    pairs.dedup();
+    pairs
+}
+
+/// Added transactions that spend an output of an unconfirmed transaction moving
+/// runes, directly or down a chain, without being known to move runes. Their
+/// txids are added to `runes`.
+fn chained_runes(added: &[MempoolTx], runes: &mut HashSet<Txid>) -> Vec<Txid> {
+    let mut chained = vec![];
+    loop {
+        let found = added.iter()
+            .filter(|x| !runes.contains(&x.txid) && x.inputs.iter().any(|input| runes.contains(&input.txid)))
+            .map(|x| x.txid)
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            return chained;
+        }
+        runes.extend(&found);
+        chained.extend(found);
+    }
+}
+
+/// Polls bitcoind's mempool so the API can tell which confirmed outputs are
+/// already being spent, and reports rune transactions that get RBF replaced or
+/// evicted.
+pub struct MempoolTracker {
+    // none when serving without bitcoind, tracking is disabled then
+    client: Option<AsyncClient>,
+    runes_db: Arc<RunesDB>,
+    // none on API replicas, the indexer already reports these
+    webhooks: Option<Arc<Webhooks>>,
+    watches: Arc<Watches>,
+    state: RwLock<MempoolState>,
+    poll_interval: Duration,
+    txindex: bool,
+}
+
+impl MempoolTracker {
+    pub fn new(settings: &Settings, client: Option<AsyncClient>, runes_db: Arc<RunesDB>, webhooks: Option<Arc<Webhooks>>, watches: Arc<Watches>) -> Self {
+        MempoolTracker {
+            client,
+            runes_db,
+            webhooks,
+            watches,
+            state: RwLock::new(MempoolState::default()),
+            poll_interval: Duration::from_secs(settings.mempool_poll_interval_secs),
+            txindex: settings.bitcoin_rpc_txindex,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.client.is_some() && !self.poll_interval.is_zero()
+    }
+
+    /// The unconfirmed transaction spending `outpoint`, if any.
+    pub fn spent_by(&self, outpoint: &OutPoint) -> Option<Txid> {
+        self.state.read().unwrap().spends.get(outpoint).copied()
+    }
+
+    pub fn status(&self, txid: &Txid) -> MempoolTxStatus {
+        self.state.read().unwrap().status(txid)
+    }
+
+    /// Unconfirmed rune transactions with an output to `script_pubkey`, the
+    /// others can't send it runes.
+    pub fn txs_paying_to(&self, script_pubkey: &Script) -> Vec<Transaction> {
+        self.state.read().unwrap().runes.values()
+            .filter(|tx| tx.output.iter().any(|x| x.script_pubkey.as_script() == script_pubkey))
+            .cloned()
+            .collect()
+    }
+
+    pub async fn run(self: Arc<Self>, mut shutdown: watch::Receiver<bool>) {
+        if !self.enabled() {
+            return;
+        }
+        loop {
+            if let Err(e) = self.sync().await {
+                warn!("Mempool sync failed: {}", e);
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.poll_interval) => {}
+                _ = shutdown.changed() => {}
+            }
+            if *shutdown.borrow() {
+                break;
+            }
+        }
+    }
+
+    /// A transaction moves runes when it carries a runestone, spends an output
+    /// holding runes or spends an output of an unconfirmed transaction in
+    /// `unconfirmed` moving runes, which is not indexed yet.
+    fn moves_runes(&self, tx: &Transaction, unconfirmed: &HashSet<Txid>) -> bool {
+        Runestone::decipher(tx).is_some()
+            || tx.input.iter().any(|x| unconfirmed.contains(&x.previous_output.txid)
+                || self.runes_db.outpoint_to_rune_balances_get(&x.previous_output).is_some())
+    }
+
+    /// Whether a transaction that left the mempool was mined. Without txindex
+    /// the node can't look it up, but a mined transaction spent its inputs.
+    async fn mined(&self, client: &AsyncClient, tx: &MempoolTx) -> anyhow::Result<bool> {
+        if self.txindex {
+            let info = client.get_raw_transaction_info(tx.txid).await?;
+            return Ok(info.is_some_and(|x| x.blockhash.is_some()));
+        }
+        for input in &tx.inputs {
+            if client.is_unspent(*input).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn sync(&self) -> anyhow::Result<()> {
+        let Some(client) = &self.client else {
+            return Ok(());
+        };
+        let mempool: HashSet<Txid> = client.get_raw_mempool().await?.into_iter().collect();
+        // the state is only changed once everything is fetched, a failed sync
+        // leaves it as it was and is retried whole
+        let (missing, mut runes, synced) = {
+            let state = self.state.read().unwrap();
+            let missing = mempool.iter().filter(|x| !state.txs.contains_key(*x)).copied().collect::<Vec<_>>();
+            (missing, state.runes.keys().copied().collect::<HashSet<_>>(), state.synced)
+        };
+
+        // fetched in batches and slimmed down right away, the first sync loads
+        // the whole mempool
+        let mut added = Vec::with_capacity(missing.len());
+        for chunk in missing.chunks(FETCH_CHUNK_SIZE) {
+            // the tx may have been mined or evicted since getrawmempool
+            for tx in client.get_raw_transactions(chunk).await?.into_iter().flatten() {
+                let moves_runes = self.moves_runes(&tx, &runes);
+                if moves_runes {
+                    runes.insert(tx.txid());
+                }
+                added.push(MempoolTx::new(tx, moves_runes));
+            }
+        }
+        // children fetched before their parent were slimmed down, get them again
+        let chained = chained_runes(&added, &mut runes);
+        if !chained.is_empty() {
+            let mut txs: HashMap<Txid, Transaction> = client.get_raw_transactions(&chained).await?.into_iter().flatten().map(|x| (x.txid(), x)).collect();
+            for tx in &mut added {
+                if let Some(whole) = txs.remove(&tx.txid) {
+                    tx.runes = Some(whole);
+                }
+            }
+        }
+        let mut events = if synced {
+            let moving = added.iter().filter_map(|x| x.runes.as_ref()).collect::<Vec<_>>();
+            self.watches.unconfirmed_events(&moving)?
+        } else {
+            vec![]
+        };
+        let (removed, pairs) = {
+            let mut state = self.state.write().unwrap();
+            let removed = state.retain(&mempool);
+            let pairs = replacements(&removed, &added);
+            for tx in added {
+                state.insert(tx);
+            }
+            for (txid, replaced_by) in &pairs {
+                state.mark_replaced(*txid, *replaced_by);
+            }
+            state.synced = true;
+            if !removed.is_empty() || !pairs.is_empty() {
+                info!("Mempool synced, {} transactions, {} replaced", state.txs.len(), pairs.len());
+            }
+            (removed, pairs)
+        };
+
+        let replaced: HashMap<Txid, Txid> = pairs.into_iter().collect();
+        for tx in removed {
+            if tx.runes.is_none() {
+                continue;
+            }
+            let txid = tx.txid;
+            if let Some(replaced_by) = replaced.get(&txid) {
+                events.push(RuneEvent::Replaced { txid: txid.to_string(), replaced_by: replaced_by.to_string() });
+                continue;
+            }
+            // left the mempool without a replacement: mined, or evicted
+            match self.mined(client, &tx).await {
+                Ok(true) => {}
+                Ok(false) => events.push(RuneEvent::Evicted { txid: txid.to_string() }),
+                Err(e) => warn!("Failed to look up {} after it left the mempool: {}", txid, e),
+            }
+        }
+        self.watches.notify_mempool(&events);
+        if let Some(webhooks) = self.webhooks.as_ref().filter(|_| !events.is_empty()) {
+            webhooks.publish_mempool(events).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{ScriptBuf, Sequence, TxIn, Witness};
+
+    use super::*;
+
+    fn tx(spends: &[OutPoint], lock_time: u32) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: spends.iter().map(|x| TxIn {
+                previous_output: *x,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }).collect(),
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn tracks_spends() {
+        let a = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+        let b = OutPoint { txid: Txid::all_zeros(), vout: 1 };
+        let mut state = MempoolState::default();
+        let tx1 = tx(&[a, b], 1);
+        let txid1 = tx1.txid();
+        state.insert(MempoolTx::new(tx1, true));
+        assert_eq!(state.spends.get(&a), Some(&txid1));
+        assert_eq!(state.spends.get(&b), Some(&txid1));
+        assert_eq!(state.status(&txid1), MempoolTxStatus::Pending);
+
+        let removed = state.retain(&HashSet::new());
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].runes.is_some());
+        assert!(state.txs.is_empty());
+        assert!(state.spends.is_empty());
+        assert!(state.runes.is_empty());
+        assert_eq!(state.status(&txid1), MempoolTxStatus::Unknown);
+    }
+
+    #[test]
+    fn replacement() {
+        let a = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+        let b = OutPoint { txid: Txid::all_zeros(), vout: 1 };
+        let original = tx(&[a], 1);
+        let unrelated = tx(&[b], 1);
+        let bumped = tx(&[a], 2);
+
+        let mut state = MempoolState::default();
+        state.insert(MempoolTx::new(original.clone(), true));
+        state.insert(MempoolTx::new(unrelated.clone(), false));
+
+        let removed = state.retain(&HashSet::from([bumped.txid()]));
+        let pairs = replacements(&removed, &[MempoolTx::new(bumped.clone(), true)]);
+        assert_eq!(pairs, vec![(original.txid(), bumped.txid())]);
+
+        state.insert(MempoolTx::new(bumped.clone(), true));
+        for (txid, replaced_by) in pairs {
+            state.mark_replaced(txid, replaced_by);
+        }
+        assert_eq!(state.spends.get(&a), Some(&bumped.txid()));
+        assert_eq!(state.status(&original.txid()), MempoolTxStatus::Replaced { replaced_by: bumped.txid() });
+        assert_eq!(state.status(&unrelated.txid()), MempoolTxStatus::Unknown);
+    }
+
+    #[test]
+    fn chained_spends_move_runes() {
+        let parent = tx(&[OutPoint { txid: Txid::all_zeros(), vout: 0 }], 0);
+        let child = tx(&[OutPoint { txid: parent.txid(), vout: 0 }], 0);
+        let grandchild = tx(&[OutPoint { txid: child.txid(), vout: 1 }], 0);
+        let unrelated = tx(&[OutPoint { txid: Txid::all_zeros(), vout: 1 }], 0);
+
+        // the grandchild comes first, as getrawmempool doesn't sort by ancestry
+        let added = [grandchild.clone(), unrelated, child.clone()].map(|x| MempoolTx::new(x, false));
+        let mut runes = HashSet::from([parent.txid()]);
+        let mut chained = chained_runes(&added, &mut runes);
+        chained.sort();
+        let mut expected = vec![child.txid(), grandchild.txid()];
+        expected.sort();
+        assert_eq!(chained, expected);
+        assert_eq!(runes.len(), 3);
+        assert!(chained_runes(&added, &mut runes).is_empty());
+    }
+
+    #[test]
+    fn replaced_is_bounded() {
+        let mut state = MempoolState::default();
+        for vout in 0..MAX_REPLACED as u32 + 5 {
+            let txid = tx(&[OutPoint { txid: Txid::all_zeros(), vout }], 0).txid();
+            state.mark_replaced(txid, Txid::all_zeros());
+        }
+        assert_eq!(state.replaced.len(), MAX_REPLACED);
+        assert_eq!(state.replaced_order.len(), MAX_REPLACED);
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use bitcoin::block::Header;
+use bitcoin::consensus::{encode, Decodable};
+use bitcoin::hashes::Hash;
+use bitcoin::p2p::message::{NetworkMessage, RawNetworkMessage};
+use bitcoin::p2p::message_blockdata::{GetHeadersMessage, Inventory};
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::{Address, Magic, ServiceFlags};
+use bitcoin::{Block, BlockHash};
+use log::{info, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::api::auth::unix_timestamp;
+use crate::chain::Chain;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(60);
+const HEADERS_TIMEOUT: Duration = Duration::from_secs(60);
+/// Headers synced while the indexer is behind the tip are used this long.
+const HEADERS_REFRESH: Duration = Duration::from_secs(30);
+/// A `headers` message has at most 2000 headers, fewer when the peer has no more.
+const MAX_HEADERS: usize = 2000;
+/// Blocks requested ahead of the one being indexed, so that the peer streams
+/// them instead of answering a `getdata` per block.
+const PIPELINE_DEPTH: u32 = 16;
+/// magic, command, payload length and checksum
+const HEADER_LEN: usize = 24;
+/// A serialized block is at most 4MB, the rest is headroom.
+const MAX_PAYLOAD_LEN: usize = 8 * 1024 * 1024;
+
+/// A connection to a peer after the version handshake.
+struct Peer {
+    stream: TcpStream,
+    magic: Magic,
+    // requested blocks, and those received before they were asked for
+    in_flight: HashSet<BlockHash>,
+    blocks: HashMap<BlockHash, Block>,
+}
+
+impl Peer {
+    fn new(stream: TcpStream, magic: Magic) -> Peer {
+        Peer { stream, magic, in_flight: HashSet::new(), blocks: HashMap::new() }
+    }
+
+    async fn connect(addr: &str, magic: Magic) -> anyhow::Result<Peer> {
+        let stream = TcpStream::connect(addr).await?;
+        let mut peer = Peer::new(stream, magic);
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, peer.handshake()).await
+            .with_context(|| format!("handshake with {} timed out", addr))??;
+        Ok(peer)
+    }
+
+    async fn handshake(&mut self) -> anyhow::Result<()> {
+        let receiver = Address::new(&self.stream.peer_addr()?, ServiceFlags::NONE);
+        let sender = Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE);
+        let version = VersionMessage::new(
+            ServiceFlags::NONE,
+            unix_timestamp() as i64,
+            receiver,
+            sender,
+            rand::random(),
+            format!("/ordx:{}/", env!("CARGO_PKG_VERSION")),
+            0,
+        );
+        self.send(NetworkMessage::Version(version)).await?;
+        let (mut version, mut verack) = (false, false);
+        while !(version && verack) {
+            match self.receive().await? {
+                NetworkMessage::Version(x) => {
+                    if !x.services.has(ServiceFlags::WITNESS) {
+                        bail!("peer does not serve witness data");
+                    }
+                    // pruned peers only advertise NETWORK_LIMITED and can't serve old blocks
+                    if !x.services.has(ServiceFlags::NETWORK) {
+                        bail!("peer does not serve the full chain");
+                    }
+                    version = true;
+                    self.send(NetworkMessage::Verack).await?;
+                }
+                NetworkMessage::Verack => verack = true,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, payload: NetworkMessage) -> anyhow::Result<()> {
+        let message = RawNetworkMessage::new(self.magic, payload);
+        self.stream.write_all(&encode::serialize(&message)).await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> anyhow::Result<NetworkMessage> {
+        let mut buffer = vec![0; HEADER_LEN];
+        self.stream.read_exact(&mut buffer).await?;
+        if buffer[..4] != self.magic.to_bytes() {
+            bail!("unexpected network magic");
+        }
+        let len = u32::from_le_bytes(buffer[16..20].try_into().unwrap()) as usize;
+        if len > MAX_PAYLOAD_LEN {
+            bail!("message of {} bytes is too large", len);
+        }
+        buffer.resize(HEADER_LEN + len, 0);
+        self.stream.read_exact(&mut buffer[HEADER_LEN..]).await?;
+        let message = RawNetworkMessage::consensus_decode_from_finite_reader(&mut buffer.as_slice())?;
+        Ok(message.payload().clone())
+    }
+
+    /// Reads a message, pings are answered and requested blocks kept for
+    /// [`Peer::get_block`], other messages are returned.
+    async fn next_message(&mut self) -> anyhow::Result<Option<NetworkMessage>> {
+        match self.receive().await? {
+            NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce)).await?,
+            NetworkMessage::Block(block) if self.in_flight.remove(&block.block_hash()) => {
+                if !block.check_merkle_root() || !block.check_witness_commitment() {
+                    bail!("block {} from p2p peer does not match its header", block.block_hash());
+                }
+                self.blocks.insert(block.block_hash(), block);
+            }
+            NetworkMessage::NotFound(inventory) => {
+                if let Some(Inventory::WitnessBlock(hash)) = inventory.iter().find(|x| matches!(x, Inventory::WitnessBlock(hash) if self.in_flight.contains(hash))) {
+                    bail!("peer does not have block {}", hash);
+                }
+            }
+            message => return Ok(Some(message)),
+        }
+        Ok(None)
+    }
+
+    async fn get_headers(&mut self, locator_hashes: Vec<BlockHash>) -> anyhow::Result<Vec<Header>> {
+        self.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(locator_hashes, BlockHash::all_zeros()))).await?;
+        loop {
+            if let Some(NetworkMessage::Headers(headers)) = self.next_message().await? {
+                return Ok(headers);
+            }
+        }
+    }
+
+    /// Requests `hash` and the blocks `ahead` of it in one `getdata`, skipping
+    /// those already requested, and waits for `hash`.
+    async fn get_block(&mut self, hash: BlockHash, ahead: &[BlockHash]) -> anyhow::Result<Block> {
+        let request = iter::once(hash).chain(ahead.iter().copied())
+            .filter(|x| !self.in_flight.contains(x) && !self.blocks.contains_key(x))
+            .collect::<Vec<_>>();
+        if !request.is_empty() {
+            self.send(NetworkMessage::GetData(request.iter().map(|x| Inventory::WitnessBlock(*x)).collect())).await?;
+            self.in_flight.extend(request);
+        }
+        // blocks of a chain that was reorged away are not asked for again
+        self.blocks.retain(|x, _| *x == hash || ahead.contains(x));
+        loop {
+            if let Some(block) = self.blocks.remove(&hash) {
+                return Ok(block);
+            }
+            self.next_message().await?;
+        }
+    }
+}
+
+/// Hashes of the best chain of the peers from `base` on.
+struct HeaderChain {
+    base: u32,
+    hashes: Vec<BlockHash>,
+}
+
+impl HeaderChain {
+    fn tip(&self) -> u32 {
+        self.base + self.hashes.len() as u32 - 1
+    }
+
+    fn hash(&self, height: u32) -> Option<BlockHash> {
+        self.hashes.get(height.checked_sub(self.base)? as usize).copied()
+    }
+
+    /// The blocks `getheaders` starts after, the last ten then exponentially
+    /// sparser down to the base.
+    fn locator(&self) -> Vec<(u32, BlockHash)> {
+        let mut locator = vec![];
+        let (mut height, mut step) = (self.tip(), 1);
+        loop {
+            locator.push((height, self.hashes[(height - self.base) as usize]));
+            if height == self.base {
+                return locator;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step).max(self.base);
+        }
+    }
+
+    /// Replaces the blocks above `height` with `headers`. Only their proof of
+    /// work is checked, not the difficulty, the peers are trusted nodes.
+    fn connect(&mut self, height: u32, headers: &[Header]) -> anyhow::Result<()> {
+        let mut prev = self.hash(height).with_context(|| format!("no header at {}", height))?;
+        for header in headers {
+            if header.prev_blockhash != prev {
+                bail!("headers from p2p peer don't connect");
+            }
+            prev = header.validate_pow(header.target())
+                .map_err(|_| anyhow!("header {} from p2p peer has too little work", header.block_hash()))?;
+        }
+        self.hashes.truncate((height - self.base) as usize + 1);
+        self.hashes.extend(headers.iter().map(|x| x.block_hash()));
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    peer: Option<Peer>,
+    chain: Option<HeaderChain>,
+    synced: Option<Instant>,
+}
+
+/// Follows the chain of bitcoin peers with `getheaders` and downloads its
+/// blocks with pipelined `getdata`, instead of `getblockcount`, `getblockhash`
+/// and `getblock` over RPC. One connection is kept open and the next peer is
+/// tried when it fails.
+pub struct P2pBlockSource {
+    peers: Vec<String>,
+    magic: Magic,
+    genesis: BlockHash,
+    next: AtomicUsize,
+    state: Mutex<State>,
+}
+
+impl P2pBlockSource {
+    /// `peers` are comma separated `host:port` addresses.
+    pub fn new(chain: Chain, peers: &str) -> anyhow::Result<Self> {
+        let peers = peers.split(',').map(str::trim).filter(|x| !x.is_empty()).map(String::from).collect::<Vec<_>>();
+        if peers.is_empty() {
+            bail!("no p2p peers");
+        }
+        Ok(P2pBlockSource {
+            peers,
+            magic: chain.magic(),
+            genesis: chain.genesis_block().block_hash(),
+            next: AtomicUsize::new(0),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Syncs headers from `hash` at `height`, e.g. a block the database has,
+    /// instead of from genesis.
+    pub async fn anchor(&self, height: u32, hash: BlockHash) {
+        self.state.lock().await.chain = Some(HeaderChain { base: height, hashes: vec![hash] });
+    }
+
+    /// Height of the peer's best chain. Headers are synced again when `next`
+    /// is past its tip, else every [`HEADERS_REFRESH`].
+    pub async fn get_block_count(&self, next: u32) -> anyhow::Result<u32> {
+        let mut state = self.state.lock().await;
+        let stale = state.synced.map_or(true, |x| x.elapsed() >= HEADERS_REFRESH);
+        if stale || state.chain.as_ref().map_or(true, |x| next > x.tip()) {
+            self.sync_headers(&mut state).await?;
+        }
+        Ok(state.chain.as_ref().unwrap().tip())
+    }
+
+    pub async fn get_block_hash(&self, height: u32) -> anyhow::Result<BlockHash> {
+        let mut state = self.state.lock().await;
+        if let Some(hash) = state.chain.as_ref().and_then(|x| x.hash(height)) {
+            return Ok(hash);
+        }
+        // below the anchor after a reindex request, the headers are synced from genesis
+        if state.chain.as_ref().is_some_and(|x| height < x.base) {
+            state.chain = None;
+        }
+        self.sync_headers(&mut state).await?;
+        state.chain.as_ref().and_then(|x| x.hash(height)).with_context(|| format!("p2p peer has no block at {}", height))
+    }
+
+    /// Downloads the block `hash` at `height`, requesting the next ones of the
+    /// synced chain along with it.
+    pub async fn get_block(&self, height: u32, hash: BlockHash) -> anyhow::Result<Block> {
+        let mut state = self.state.lock().await;
+        let ahead = match &state.chain {
+            Some(chain) if chain.hash(height) == Some(hash) => (height + 1..=height + PIPELINE_DEPTH).map_while(|x| chain.hash(x)).collect(),
+            _ => vec![],
+        };
+        let peer = self.connected(&mut state.peer).await?;
+        let result = tokio::time::timeout(BLOCK_TIMEOUT, peer.get_block(hash, &ahead)).await
+            .unwrap_or_else(|_| Err(anyhow!("timed out waiting for block {}", hash)));
+        if let Err(e) = &result {
+            warn!("Dropping p2p peer: {}", e);
+            state.peer = None;
+        }
+        result
+    }
+
+    async fn connected<'a>(&self, peer: &'a mut Option<Peer>) -> anyhow::Result<&'a mut Peer> {
+        if peer.is_none() {
+            let addr = &self.peers[self.next.fetch_add(1, Ordering::Relaxed) % self.peers.len()];
+            *peer = Some(Peer::connect(addr, self.magic).await?);
+            info!("Connected to p2p peer {}", addr);
+        }
+        Ok(peer.as_mut().unwrap())
+    }
+
+    async fn sync_headers(&self, state: &mut State) -> anyhow::Result<()> {
+        let result = self.try_sync_headers(state).await;
+        if let Err(e) = &result {
+            warn!("Dropping p2p peer: {}", e);
+            state.peer = None;
+        }
+        result
+    }
+
+    async fn try_sync_headers(&self, state: &mut State) -> anyhow::Result<()> {
+        let genesis = self.genesis;
+        let mut synced = 0;
+        loop {
+            let chain = state.chain.get_or_insert_with(|| HeaderChain { base: 0, hashes: vec![genesis] });
+            let mut locator = chain.locator();
+            if chain.base > 0 {
+                locator.push((0, genesis));
+            }
+            let peer = self.connected(&mut state.peer).await?;
+            let headers = tokio::time::timeout(HEADERS_TIMEOUT, peer.get_headers(locator.iter().map(|x| x.1).collect())).await
+                .unwrap_or_else(|_| Err(anyhow!("timed out waiting for headers")))?;
+            let Some(first) = headers.first() else {
+                break;
+            };
+            let (height, _) = *locator.iter().find(|x| x.1 == first.prev_blockhash)
+                .context("headers from p2p peer don't connect to the synced chain")?;
+            let chain = state.chain.as_mut().unwrap();
+            if height < chain.base {
+                *chain = HeaderChain { base: 0, hashes: vec![genesis] };
+            }
+            chain.connect(height, &headers)?;
+            synced += headers.len();
+            if headers.len() < MAX_HEADERS {
+                break;
+            }
+        }
+        if synced > 0 {
+            info!("Synced {} headers from p2p peer, tip {}", synced, state.chain.as_ref().unwrap().tip());
+        }
+        state.synced = Some(Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// A node accepting one connection on `listener`, after the handshake.
+    async fn node(listener: TcpListener) -> Peer {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut node = Peer::new(stream, Chain::Regtest.magic());
+        let NetworkMessage::Version(mut version) = node.receive().await.unwrap() else {
+            panic!("expected version");
+        };
+        version.services = ServiceFlags::NETWORK | ServiceFlags::WITNESS;
+        node.send(NetworkMessage::Version(version)).await.unwrap();
+        node.send(NetworkMessage::Verack).await.unwrap();
+        assert_eq!(node.receive().await.unwrap(), NetworkMessage::Verack);
+        node
+    }
+
+    fn mine(prev: &Block, time: u32) -> Block {
+        let mut block = Block {
+            header: Header { prev_blockhash: prev.block_hash(), time, ..prev.header },
+            txdata: prev.txdata.clone(),
+        };
+        while block.header.validate_pow(block.header.target()).is_err() {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    #[tokio::test]
+    async fn handshake_and_get_block() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let block = genesis_block(Network::Regtest);
+        let served = block.clone();
+        tokio::spawn(async move {
+            let mut node = node(listener).await;
+            let NetworkMessage::GetData(inventory) = node.receive().await.unwrap() else {
+                panic!("expected getdata");
+            };
+            assert_eq!(inventory, vec![Inventory::WitnessBlock(served.block_hash())]);
+            node.send(NetworkMessage::Ping(1)).await.unwrap();
+            node.send(NetworkMessage::Block(served)).await.unwrap();
+            assert_eq!(node.receive().await.unwrap(), NetworkMessage::Pong(1));
+        });
+
+        let source = P2pBlockSource::new(Chain::Regtest, &addr).unwrap();
+        assert_eq!(source.get_block(0, block.block_hash()).await.unwrap(), block);
+    }
+
+    #[tokio::test]
+    async fn syncs_headers_and_pipelines_blocks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let genesis = genesis_block(Network::Regtest);
+        let mut blocks = vec![genesis.clone()];
+        for i in 1..=3 {
+            blocks.push(mine(&blocks[i - 1], genesis.header.time + i as u32));
+        }
+        let served = blocks.clone();
+        tokio::spawn(async move {
+            let mut node = node(listener).await;
+            let NetworkMessage::GetHeaders(request) = node.receive().await.unwrap() else {
+                panic!("expected getheaders");
+            };
+            assert_eq!(request.locator_hashes, vec![served[0].block_hash()]);
+            node.send(NetworkMessage::Headers(served[1..].iter().map(|x| x.header).collect())).await.unwrap();
+            let NetworkMessage::GetData(inventory) = node.receive().await.unwrap() else {
+                panic!("expected getdata");
+            };
+            assert_eq!(inventory, served[1..].iter().map(|x| Inventory::WitnessBlock(x.block_hash())).collect::<Vec<_>>());
+            for block in &served[1..] {
+                node.send(NetworkMessage::Block(block.clone())).await.unwrap();
+            }
+        });
+
+        let source = P2pBlockSource::new(Chain::Regtest, &addr).unwrap();
+        assert_eq!(source.get_block_count(1).await.unwrap(), 3);
+        for (height, block) in blocks.iter().enumerate().skip(1) {
+            let hash = source.get_block_hash(height as u32).await.unwrap();
+            assert_eq!(hash, block.block_hash());
+            assert_eq!(&source.get_block(height as u32, hash).await.unwrap(), block);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_pruned_peers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut node = Peer::new(stream, Chain::Regtest.magic());
+            let NetworkMessage::Version(mut version) = node.receive().await.unwrap() else {
+                panic!("expected version");
+            };
+            version.services = ServiceFlags::NETWORK_LIMITED | ServiceFlags::WITNESS;
+            node.send(NetworkMessage::Version(version)).await.unwrap();
+            node.send(NetworkMessage::Verack).await.unwrap();
+        });
+
+        let e = Peer::connect(&addr, Chain::Regtest.magic()).await.err().unwrap();
+        assert_eq!(e.to_string(), "peer does not serve the full chain");
+    }
+
+    #[test]
+    fn header_chain_reorgs() {
+        let genesis = genesis_block(Network::Regtest);
+        let a1 = mine(&genesis, 1);
+        let a2 = mine(&a1, 2);
+        let b2 = mine(&a1, 3);
+        let b3 = mine(&b2, 4);
+        let mut chain = HeaderChain { base: 0, hashes: vec![genesis.block_hash()] };
+        chain.connect(0, &[a1.header, a2.header]).unwrap();
+        assert_eq!(chain.locator().iter().map(|x| x.0).collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert!(chain.connect(2, &[b3.header]).is_err());
+        chain.connect(1, &[b2.header, b3.header]).unwrap();
+        assert_eq!(chain.tip(), 3);
+        assert_eq!(chain.hash(2), Some(b2.block_hash()));
+        assert_eq!(chain.hash(3), Some(b3.block_hash()));
+    }
+}
@@ -0,0 +1,254 @@
+//! Sub-indexers of protorune style protocols, whose messages ride in the
+//! OP_RETURN outputs next to a runestone: `OP_RETURN` and pushes of varint
+//! integers, the first one the tag of the protocol.
+//!
+//! A protocol writes to its own column family, undone on reorgs through the
+//! shared HEIGHT_PROTOCOL_KEY_UNDO log, and answers `/protocol/:name/:key`.
+//! Adding one takes an implementation of [`Protocol`], an entry in
+//! [`PROTOCOLS`] and its column family in `COLUMN_FAMILIES`.
+
+use bitcoin::opcodes::all::OP_RETURN;
+use bitcoin::script::Instruction;
+use bitcoin::{Script, Transaction, Txid};
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use ordinals::{varint, Artifact, Runestone};
+
+use crate::db::{BlockWriter, RunesDB, Store, PROTOCOL_ALKANES};
+use crate::updater::Result;
+
+pub trait Protocol: Sync {
+    /// Name in the settings and the API namespace.
+    fn name(&self) -> &'static str;
+
+    fn tag(&self) -> u128;
+
+    fn column_family(&self) -> &'static str;
+
+    fn index(&self, store: &ProtocolStore, message: &ProtocolMessage) -> Result;
+
+    /// The answer to `/protocol/:name/:key`, none for keys without data.
+    fn query(&self, db: &RunesDB, key: &str) -> Result<Option<Value>>;
+}
+
+/// Protocols the indexer knows, the `PROTOCOLS` setting picks the indexed ones.
+pub static PROTOCOLS: [&dyn Protocol; 1] = [
+    &MessageLog { name: "alkanes", tag: 1, column_family: PROTOCOL_ALKANES },
+];
+
+pub fn find_protocol(name: &str) -> Option<&'static dyn Protocol> {
+    PROTOCOLS.iter().copied().find(|x| x.name() == name)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ProtocolMessage {
+    pub txid: Txid,
+    pub tx_index: u32,
+    pub vout: u32,
+    pub tag: u128,
+    pub payload: Vec<u128>,
+}
+
+impl ProtocolMessage {
+    /// The messages of a transaction with a runestone, cenotaphs carry none.
+    pub fn from_transaction(tx_index: u32, tx: &Transaction) -> Vec<ProtocolMessage> {
+        let messages = tx.output.iter()
+            .enumerate()
+            .filter_map(|(vout, output)| {
+                let integers = Self::integers(&output.script_pubkey)?;
+                let (tag, payload) = integers.split_first()?;
+                Some(ProtocolMessage {
+                    txid: tx.txid(),
+                    tx_index,
+                    vout: vout as u32,
+                    tag: *tag,
+                    payload: payload.to_vec(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if messages.is_empty() || !matches!(Runestone::decipher(tx), Some(Artifact::Runestone(_))) {
+            return Vec::new();
+        }
+        messages
+    }
+
+    /// The integers of an OP_RETURN output with nothing but pushes after the
+    /// OP_RETURN, which rules out the runestone itself.
+    fn integers(script: &Script) -> Option<Vec<u128>> {
+        let mut instructions = script.instructions();
+        if instructions.next() != Some(Ok(Instruction::Op(OP_RETURN))) {
+            return None;
+        }
+        let mut payload = Vec::new();
+        for instruction in instructions {
+            let Ok(Instruction::PushBytes(push)) = instruction else {
+                return None;
+            };
+            payload.extend_from_slice(push.as_bytes());
+        }
+        let mut integers = Vec::new();
+        let mut i = 0;
+        while i < payload.len() {
+            let (integer, length) = varint::decode(&payload[i..]).ok()?;
+            integers.push(integer);
+            i += length;
+        }
+        Some(integers)
+    }
+}
+
+/// The column family of a protocol while a block is indexed, writes go to the
+/// block's batch and the undo log.
+pub struct ProtocolStore<'a> {
+    pub writer: &'a BlockWriter<'a>,
+    pub protocol: &'a dyn Protocol,
+    pub height: u32,
+}
+
+impl<'a> ProtocolStore<'a> {
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.writer.get(self.protocol.column_family(), key).unwrap()
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) {
+        self.writer.protocol_put(self.height, self.protocol, key, Some(value))
+    }
+
+    pub fn del(&self, key: &[u8]) {
+        self.writer.protocol_put(self.height, self.protocol, key, None)
+    }
+}
+
+/// Runs the enabled protocols over the messages of a block.
+pub struct ProtocolUpdater<'a> {
+    pub height: u32,
+    pub protocols: &'a [&'static dyn Protocol],
+    pub writer: &'a BlockWriter<'a>,
+}
+
+impl<'a> ProtocolUpdater<'a> {
+    #[instrument(level = "trace", skip(self, tx), fields(txid = %tx.txid()))]
+    pub fn index_protocols(&self, tx_index: u32, tx: &Transaction) -> Result {
+        for message in ProtocolMessage::from_transaction(tx_index, tx) {
+            let Some(protocol) = self.protocols.iter().find(|x| x.tag() == message.tag) else {
+                continue;
+            };
+            let store = ProtocolStore { writer: self.writer, protocol: *protocol, height: self.height };
+            protocol.index(&store, &message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the messages of a protocol as they are, for protocols whose state
+/// isn't evaluated here: alkanes run their contracts in a wasm VM of their own.
+/// Keyed by txid (32) + vout (4), varint payload values.
+pub struct MessageLog {
+    pub name: &'static str,
+    pub tag: u128,
+    pub column_family: &'static str,
+}
+
+impl Protocol for MessageLog {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn tag(&self) -> u128 {
+        self.tag
+    }
+
+    fn column_family(&self) -> &'static str {
+        self.column_family
+    }
+
+    fn index(&self, store: &ProtocolStore, message: &ProtocolMessage) -> Result {
+        let key = [&message.txid[..], &message.vout.to_be_bytes()].concat();
+        let mut value = Vec::new();
+        for integer in &message.payload {
+            varint::encode_to_vec(*integer, &mut value);
+        }
+        store.put(&key, &value);
+        Ok(())
+    }
+
+    /// The messages of a transaction, by txid.
+    fn query(&self, db: &RunesDB, key: &str) -> Result<Option<Value>> {
+        let txid: Txid = key.parse()?;
+        let mut messages = Vec::new();
+        for (k, v) in db.protocol_scan(self.column_family, &txid[..])? {
+            let vout = u32::from_be_bytes(k[32..36].try_into()?);
+            let mut payload = Vec::new();
+            let mut i = 0;
+            while i < v.len() {
+                let (integer, length) = varint::decode(&v[i..])?;
+                payload.push(integer.to_string());
+                i += length;
+            }
+            messages.push(json!({ "vout": vout, "payload": payload }));
+        }
+        Ok((!messages.is_empty()).then_some(Value::Array(messages)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::script::{Builder, PushBytesBuf};
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, ScriptBuf, TxOut};
+
+    use crate::db::{test_header, COLUMN_FAMILIES};
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn message_script(integers: &[u128]) -> ScriptBuf {
+        let mut payload = Vec::new();
+        for integer in integers {
+            varint::encode_to_vec(*integer, &mut payload);
+        }
+        Builder::new().push_opcode(OP_RETURN).push_slice(PushBytesBuf::try_from(payload).unwrap()).into_script()
+    }
+
+    fn tx(scripts: Vec<ScriptBuf>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: scripts.into_iter().map(|script_pubkey| TxOut { value: Amount::ZERO, script_pubkey }).collect(),
+        }
+    }
+
+    #[test]
+    fn messages_next_to_runestones() {
+        for protocol in PROTOCOLS {
+            assert!(COLUMN_FAMILIES.contains(&protocol.column_family()));
+        }
+
+        let runestone = Runestone::default().encipher();
+        let tx = tx(vec![runestone.clone(), message_script(&[1, 2, 300]), ScriptBuf::new(), message_script(&[])]);
+        assert_eq!(ProtocolMessage::from_transaction(3, &tx), vec![ProtocolMessage { txid: tx.txid(), tx_index: 3, vout: 1, tag: 1, payload: vec![2, 300] }]);
+        assert!(ProtocolMessage::from_transaction(0, &self::tx(vec![message_script(&[1, 2])])).is_empty());
+        // a truncated varint
+        let script = Builder::new().push_opcode(OP_RETURN).push_slice([0x80]).into_script();
+        assert!(ProtocolMessage::from_transaction(0, &self::tx(vec![runestone, script])).is_empty());
+
+        let path = TempDir::new("protocol");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let alkanes = find_protocol("alkanes").unwrap();
+        let writer = db.block_writer();
+        let updater = ProtocolUpdater { height: 101, protocols: &[alkanes], writer: &writer };
+        updater.index_protocols(3, &tx).unwrap();
+        writer.commit(101, &test_header(101), &HashMap::new()).unwrap();
+        let expected = json!([{ "vout": 1, "payload": ["2", "300"] }]);
+        assert_eq!(alkanes.query(&db, &tx.txid().to_string()).unwrap(), Some(expected));
+
+        db.reorg_to_height(101, 101).unwrap();
+        assert_eq!(alkanes.query(&db, &tx.txid().to_string()).unwrap(), None);
+    }
+}
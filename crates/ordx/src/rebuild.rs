@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::bail;
+use bitcoin::{Block, OutPoint};
+use bitcoincore_rpc::{Client, RpcApi};
+use log::info;
+use rocksdb::IteratorMode;
+
+use ordinals::{RuneId, Runestone};
+
+use crate::api::auth::unix_timestamp;
+use crate::chain::Chain;
+use crate::db::key::Key;
+use crate::db::model::{RuneBalanceForInsert, RuneBalanceForUpdate, RuneEntryForQueryInsert};
+use crate::db::{RunesDB, Store, HEIGHT_TO_BLOCK_HEADER, RUNE_ID_TO_RUNE_ENTRY};
+use crate::entry::{EntryBytes, RuneEntry, Statistic};
+use crate::updater::{script_address, RuneUpdater};
+
+pub const USAGE: &str = "Usage: ordx rebuild-sqlite [--fresh]";
+
+/// Options of the `rebuild-sqlite` subcommand, which regenerates `rune_entry`
+/// and `rune_balance` from rocksdb. Must not run while the indexer is running.
+#[derive(Debug, Default, PartialEq)]
+pub struct RebuildOptions {
+    /// move the sqlite files aside first instead of emptying the rune tables,
+    /// for a file too corrupt to open
+    pub fresh: bool,
+}
+
+impl RebuildOptions {
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut options = RebuildOptions::default();
+        for arg in args {
+            match arg.as_str() {
+                "--fresh" => options.fresh = true,
+                _ => bail!("unknown argument `{arg}`\n{USAGE}"),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Renames the sqlite files under `db_path` to `<name>.<unix time>.bak`.
+pub fn set_aside(db_path: &Path) -> anyhow::Result<()> {
+    let ts = unix_timestamp();
+    for name in ["sqlite.db", "sqlite.db-wal", "sqlite.db-shm"] {
+        let path = db_path.join(name);
+        if path.exists() {
+            let bak = db_path.join(format!("{name}.{ts}.bak"));
+            fs::rename(&path, &bak)?;
+            info!("Moved {:?} to {:?}", path, bak);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RebuildReport {
+    pub entries: usize,
+    pub balances: usize,
+    pub spent: usize,
+}
+
+/// Rune entries come from `RUNE_ID_TO_RUNE_ENTRY`. Balance rows come from
+/// the outputs `OUTPOINT_TO_RUNE_BALANCES` still has, with the address, value
+/// and spender read from the blocks they were created and spent in, so every
+/// indexed block is downloaded again but no rune is recomputed.
+///
+/// Outputs without a script hash entry had no rows, light mode or pruning
+/// left them out. Etchers need the commit transactions and are left empty,
+/// a burned or cenotaph flag never reaches a row with a balance, and a mint
+/// is flagged when the outputs hold more of the minted rune than the inputs.
+pub fn run(runes_db: &RunesDB, client: &Client, chain: Chain) -> anyhow::Result<RebuildReport> {
+    let Some(indexed_height) = runes_db.latest_indexed_height() else {
+        bail!("nothing is indexed yet");
+    };
+    if runes_db.statistic_to_value_get(&Statistic::ScriptHashIndex).is_none() {
+        bail!("the script hash index is not built yet, start the indexer once first");
+    }
+    let t = Instant::now();
+    let mut report = RebuildReport::default();
+    runes_db.sqlite_rebuild_clear()?;
+
+    let latest_height = runes_db.latest_height().unwrap_or(indexed_height).max(indexed_height);
+    let mut entries = vec![];
+    for x in runes_db.rocksdb.iterator_cf(runes_db.get_cf(RUNE_ID_TO_RUNE_ENTRY), IteratorMode::Start) {
+        let (k, v) = x?;
+        entries.push(RuneEntryForQueryInsert::new(&RuneId::decode(&k)?, &RuneEntry::load_bytes(&v), latest_height, None, None));
+    }
+    runes_db.sqlite_rune_entry_insert_all(&entries)?;
+    report.entries = entries.len();
+    info!("Rebuilt {} rune entries", report.entries);
+
+    let first_height = match runes_db.rocksdb.iterator_cf(runes_db.get_cf(HEIGHT_TO_BLOCK_HEADER), IteratorMode::Start).next() {
+        Some(x) => u32::decode(&x?.0)?,
+        None => indexed_height,
+    };
+    for height in first_height..=indexed_height {
+        let Some(header) = runes_db.height_to_block_header_get(height) else {
+            bail!("no header at {}", height);
+        };
+        let block = client.get_block(&header.block_hash())?;
+        let (inserts, spends) = block_rows(runes_db, &block, height, chain)?;
+        runes_db.sqlite_rune_balance_rebuild_block(height, &header.block_hash(), &inserts, &spends)?;
+        report.balances += inserts.len();
+        report.spent += spends.len();
+        if height % 1000 == 0 {
+            info!("Rebuilt balances to {} of {}, {:?}", height, indexed_height, t.elapsed());
+        }
+    }
+
+    let rune_ids = entries.into_iter().map(|x| x.rune_id).collect::<Vec<_>>();
+    runes_db.sqlite_rune_entry_recount(&rune_ids)?;
+    runes_db.statistic_to_value_put(&Statistic::Holders, runes_db.sqlite_rune_entry_holders_sum()?);
+    // no block has holders and transactions deltas, reorgs below here recount
+    runes_db.statistic_to_value_put(&Statistic::CounterUndoHeight, indexed_height + 1);
+    info!("Rebuilt sqlite to {}, {:?}, {:?}", indexed_height, report, t.elapsed());
+    Ok(report)
+}
+
+/// The balance rows `block` created and the spends of earlier ones.
+fn block_rows(runes_db: &RunesDB, block: &Block, height: u32, chain: Chain) -> anyhow::Result<(Vec<RuneBalanceForInsert>, Vec<RuneBalanceForUpdate>)> {
+    let (mut inserts, mut spends) = (vec![], vec![]);
+    for (idx, tx) in block.txdata.iter().enumerate() {
+        let txid = tx.txid();
+        let mut inputs: HashMap<RuneId, u128> = HashMap::new();
+        for (vin, input) in tx.input.iter().enumerate() {
+            let Some((_, spent_height, buffer)) = runes_db.outpoint_to_rune_balances_get(&input.previous_output) else {
+                continue;
+            };
+            if spent_height != height {
+                continue;
+            }
+            for (rune_id, amount) in RuneUpdater::decode_rune_balances(&buffer)? {
+                *inputs.entry(rune_id).or_default() += amount;
+                spends.push(RuneBalanceForUpdate {
+                    txid: input.previous_output.txid.to_string(),
+                    vout: input.previous_output.vout,
+                    rune_id: rune_id.to_string(),
+                    spent_height: height,
+                    spent_txid: txid.to_string(),
+                    spent_vin: vin as _,
+                    spent_ts: block.header.time,
+                });
+            }
+        }
+
+        let first = inserts.len();
+        let mut outputs: HashMap<RuneId, u128> = HashMap::new();
+        for (vout, output) in tx.output.iter().enumerate() {
+            let outpoint = OutPoint { txid, vout: vout as _ };
+            let Some((confirmed_height, _, buffer)) = runes_db.outpoint_to_rune_balances_get(&outpoint) else {
+                continue;
+            };
+            if confirmed_height != height || runes_db.outpoint_to_script_hash_get(&outpoint).is_none() {
+                continue;
+            }
+            let address = script_address(&output.script_pubkey, chain);
+            for (rune_id, amount) in RuneUpdater::decode_rune_balances(&buffer)? {
+                *outputs.entry(rune_id).or_default() += amount;
+                inserts.push(RuneBalanceForInsert {
+                    txid: txid.to_string(),
+                    vout: vout as _,
+                    value: output.value.to_sat(),
+                    rune_id: rune_id.to_string(),
+                    rune_amount: amount.to_string(),
+                    address: address.clone(),
+                    premine: false,
+                    mint: false,
+                    burn: false,
+                    cenotaph: false,
+                    transfer: !inputs.is_empty(),
+                    height,
+                    idx: idx as _,
+                    ts: block.header.time,
+                    spent_height: 0,
+                    spent_txid: None,
+                    spent_vin: None,
+                    spent_ts: None,
+                });
+            }
+        }
+        if first == inserts.len() {
+            continue;
+        }
+        let minted = Runestone::decipher(tx).and_then(|x| x.mint())
+            .is_some_and(|id| outputs.get(&id).copied().unwrap_or_default() > inputs.get(&id).copied().unwrap_or_default());
+        let premine = runes_db.rune_id_to_rune_entry_get(&RuneId { block: height as _, tx: idx as _ }).is_some_and(|x| x.premine > 0);
+        for x in &mut inserts[first..] {
+            x.mint = minted;
+            x.premine = premine;
+        }
+    }
+    Ok((inserts, spends))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::absolute::LockTime;
+    use bitcoin::block::{Header, Version};
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction;
+    use bitcoin::{Amount, BlockHash, CompactTarget, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Witness};
+
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn args(x: &str) -> Vec<String> {
+        x.split_whitespace().map(String::from).collect()
+    }
+
+    fn tx(inputs: &[OutPoint], outputs: usize) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs.iter().map(|x| TxIn { previous_output: *x, script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }).collect(),
+            output: (0..outputs).map(|x| TxOut { value: Amount::from_sat(546 + x as u64), script_pubkey: ScriptBuf::new_op_return([x as u8]) }).collect(),
+        }
+    }
+
+    fn block(txdata: Vec<Transaction>) -> Block {
+        let header = Header {
+            version: Version::ONE,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        };
+        Block { header, txdata }
+    }
+
+    fn balance(rune_id: RuneId, amount: u128) -> Vec<u8> {
+        let mut buffer = vec![];
+        RuneUpdater::encode_rune_balance(rune_id, amount, &mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(RebuildOptions::parse(&args("")).unwrap(), RebuildOptions { fresh: false });
+        assert_eq!(RebuildOptions::parse(&args("--fresh")).unwrap(), RebuildOptions { fresh: true });
+        assert!(RebuildOptions::parse(&args("--height 1")).is_err());
+    }
+
+    #[test]
+    fn rows_of_created_and_spent_outputs() {
+        let path = TempDir::new("rebuild");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        let rune_id = RuneId { block: 100, tx: 1 };
+
+        let created = tx(&[], 2);
+        let indexed = OutPoint { txid: created.txid(), vout: 0 };
+        let light = OutPoint { txid: created.txid(), vout: 1 };
+        db.outpoint_to_rune_balances_put(&indexed, (100, 101, balance(rune_id, 5)));
+        db.spk_to_outpoints_put(&created.output[0].script_pubkey, &indexed, 100, 546);
+        db.outpoint_to_rune_balances_put(&light, (100, 0, balance(rune_id, 7)));
+        let (inserts, spends) = block_rows(&db, &block(vec![tx(&[], 1), created]), 100, Chain::Regtest).unwrap();
+        assert!(spends.is_empty());
+        assert_eq!(inserts.len(), 1);
+        assert_eq!((inserts[0].vout, inserts[0].idx, inserts[0].rune_amount.as_str(), inserts[0].transfer), (0, 1, "5", false));
+        db.sqlite_rune_balance_rebuild_block(100, &BlockHash::all_zeros(), &inserts, &spends).unwrap();
+
+        let spending = tx(&[indexed], 1);
+        let (inserts, spends) = block_rows(&db, &block(vec![spending.clone()]), 101, Chain::Regtest).unwrap();
+        assert!(inserts.is_empty());
+        assert_eq!(spends.len(), 1);
+        assert_eq!((spends[0].spent_txid.clone(), spends[0].spent_vin, spends[0].rune_id.clone()), (spending.txid().to_string(), 0, rune_id.to_string()));
+        db.sqlite_rune_balance_rebuild_block(101, &BlockHash::all_zeros(), &inserts, &spends).unwrap();
+        let spent: (u32, String) = db.sqlite.get().unwrap()
+            .query_row("SELECT spent_height, spent_txid FROM rune_balance WHERE txid = ? AND vout = 0", [indexed.txid.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(spent, (101, spending.txid().to_string()));
+        assert_eq!(db.sqlite_block_commit_latest().unwrap().map(|x| x.0), Some(101));
+    }
+}
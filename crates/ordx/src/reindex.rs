@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+use log::{info, warn};
+use rocksdb::{Options, DB};
+use serde::{Deserialize, Serialize};
+
+pub const USAGE: &str = "Usage: ordx reindex";
+
+const STATUS_FILE: &str = "reindex.json";
+
+/// Deletes the rocksdb and sqlite files under `db_path`, anything else there
+/// (settings, other chains) is kept. Fails while another process has the
+/// index open.
+pub fn wipe(db_path: &Path) -> anyhow::Result<()> {
+    let rocksdb_path = db_path.join("rocksdb");
+    if rocksdb_path.exists() {
+        // takes the rocksdb lock first, so a running indexer is left alone
+        DB::destroy(&Options::default(), &rocksdb_path)?;
+        if rocksdb_path.exists() {
+            fs::remove_dir_all(&rocksdb_path)?;
+        }
+        info!("Deleted {:?}", rocksdb_path);
+    }
+    for name in ["sqlite.db", "sqlite.db-wal", "sqlite.db-shm", STATUS_FILE] {
+        let path = db_path.join(name);
+        if path.exists() {
+            fs::remove_file(&path)?;
+            info!("Deleted {:?}", path);
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_args(args: &[String]) -> anyhow::Result<()> {
+    if let Some(arg) = args.first() {
+        bail!("unknown argument `{arg}`\n{USAGE}");
+    }
+    Ok(())
+}
+
+/// Progress of a full reindex, written to `reindex.json` in the data dir and
+/// shown by `/stats`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReindexProgress {
+    pub started_ts: u64,
+    pub from_height: u32,
+    pub indexed_height: u32,
+    pub latest_height: u32,
+    pub eta_secs: Option<u64>,
+    pub done: bool,
+}
+
+#[derive(Debug)]
+pub struct ReindexStatus {
+    path: PathBuf,
+    // first height indexed by this process and when, for the ETA
+    run_from: Mutex<Option<(u32, Instant)>>,
+    progress: Mutex<Option<ReindexProgress>>,
+}
+
+impl ReindexStatus {
+    /// Starts tracking a reindex from `from_height`.
+    pub fn start(db_path: &Path, from_height: u32) -> Self {
+        let started_ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        ReindexStatus {
+            path: db_path.join(STATUS_FILE),
+            run_from: Mutex::new(None),
+            progress: Mutex::new(Some(ReindexProgress {
+                started_ts,
+                from_height,
+                indexed_height: from_height.saturating_sub(1),
+                latest_height: 0,
+                eta_secs: None,
+                done: false,
+            })),
+        }
+    }
+
+    /// Picks up the status file of a reindex interrupted by a restart, if any.
+    pub fn load(db_path: &Path) -> Self {
+        let path = db_path.join(STATUS_FILE);
+        let progress = Self::read(&path);
+        ReindexStatus {
+            path,
+            run_from: Mutex::new(None),
+            progress: Mutex::new(progress),
+        }
+    }
+
+    /// Re-reads the status file, for processes following another one's reindex.
+    pub fn refresh(&self) {
+        *self.progress.lock().unwrap() = Self::read(&self.path);
+    }
+
+    fn read(path: &Path) -> Option<ReindexProgress> {
+        fs::read(path).ok()
+            .and_then(|x| serde_json::from_slice::<ReindexProgress>(&x).ok())
+            .filter(|x| !x.done)
+    }
+
+    pub fn progress(&self) -> Option<ReindexProgress> {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Records an indexed block, a no-op when no reindex is running.
+    pub fn update(&self, indexed_height: u32, latest_height: u32) {
+        let (run_from_height, run_started) = *self.run_from.lock().unwrap().get_or_insert((indexed_height, Instant::now()));
+        let mut progress = self.progress.lock().unwrap();
+        let Some(progress) = progress.as_mut() else {
+            return;
+        };
+        progress.indexed_height = indexed_height;
+        progress.latest_height = latest_height;
+        progress.done = indexed_height >= latest_height;
+        progress.eta_secs = eta(run_started.elapsed(), indexed_height.saturating_sub(run_from_height) + 1, latest_height.saturating_sub(indexed_height))
+            .map(|x| x.as_secs());
+        if let Err(e) = write_atomic(&self.path, &serde_json::to_vec_pretty(progress).unwrap()) {
+            warn!("Failed to write {:?}: {}", self.path, e);
+        }
+        if progress.done {
+            info!("Reindex done, from {} to {}", progress.from_height, indexed_height);
+        }
+    }
+}
+
+fn eta(elapsed: Duration, blocks: u32, remaining: u32) -> Option<Duration> {
+    (blocks > 0 && remaining > 0).then(|| elapsed / blocks * remaining)
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::RunesDB;
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn wipe_keeps_other_files() {
+        let path = TempDir::new("reindex-wipe");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        drop(db);
+        fs::write(path.join(".env"), "RPC_USER=ordx").unwrap();
+
+        wipe(&path).unwrap();
+        assert!(!path.join("rocksdb").exists());
+        assert!(!path.join("sqlite.db").exists());
+        assert!(path.join(".env").exists());
+    }
+
+    #[test]
+    fn progress_round_trip() {
+        let path = TempDir::new("reindex-status");
+        fs::create_dir_all(&path).unwrap();
+
+        assert_eq!(ReindexStatus::load(&path).progress(), None);
+        let status = ReindexStatus::start(&path, 100);
+        status.update(100, 110);
+        let progress = ReindexStatus::load(&path).progress().unwrap();
+        assert_eq!((progress.from_height, progress.indexed_height, progress.latest_height, progress.done), (100, 100, 110, false));
+
+        status.update(110, 110);
+        assert!(status.progress().unwrap().done);
+        assert_eq!(ReindexStatus::load(&path).progress(), None);
+    }
+
+    #[test]
+    fn eta_from_rate() {
+        assert_eq!(eta(Duration::from_secs(10), 10, 30), Some(Duration::from_secs(30)));
+        assert_eq!(eta(Duration::from_secs(10), 10, 0), None);
+    }
+}
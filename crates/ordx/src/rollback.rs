@@ -0,0 +1,106 @@
+use anyhow::bail;
+use log::info;
+
+use crate::db::model::ReorgPlan;
+use crate::db::RunesDB;
+
+pub const USAGE: &str = "Usage: ordx rollback --height <height> [--dry-run]";
+
+/// Options of the `rollback` subcommand, which deletes everything indexed at
+/// `height` and above so the indexer resumes from `height`. Must not run while
+/// the indexer is running.
+#[derive(Debug, PartialEq)]
+pub struct RollbackOptions {
+    pub height: u32,
+    pub dry_run: bool,
+}
+
+impl RollbackOptions {
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut height = None;
+        let mut dry_run = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--dry-run" => dry_run = true,
+                "--height" => {
+                    let Some(value) = args.next() else {
+                        bail!("--height requires a value\n{USAGE}");
+                    };
+                    height = Some(value.parse()?);
+                }
+                _ => bail!("unknown argument `{arg}`\n{USAGE}"),
+            }
+        }
+        let Some(height) = height else {
+            bail!("--height is required\n{USAGE}");
+        };
+        Ok(RollbackOptions { height, dry_run })
+    }
+}
+
+/// With `prune_spent_after_blocks` set, the balances spent that many blocks
+/// below the indexed height are gone, so no rollback reaches past them.
+pub fn run(runes_db: &RunesDB, options: &RollbackOptions, prune_spent_after_blocks: Option<u32>) -> anyhow::Result<ReorgPlan> {
+    let Some(indexed_height) = runes_db.latest_indexed_height() else {
+        bail!("nothing is indexed yet");
+    };
+    if options.height == 0 || options.height > indexed_height {
+        bail!("rollback height must be between 1 and the indexed height {}", indexed_height);
+    }
+    if let Some(after_blocks) = prune_spent_after_blocks {
+        if indexed_height - options.height >= after_blocks {
+            bail!("height {} is {} or more blocks below the indexed height {}, the balances spent there are pruned, a full reindex is required", options.height, after_blocks, indexed_height);
+        }
+    }
+    let plan = runes_db.reorg_plan(options.height)?;
+    info!("{}", plan);
+    if options.dry_run {
+        info!("Dry run, nothing deleted");
+        return Ok(plan);
+    }
+    let latest_height = runes_db.latest_height().unwrap_or(indexed_height).max(indexed_height);
+    runes_db.reorg_to_height(options.height, latest_height)?;
+    info!("Rolled back, indexed height: {:?}", runes_db.latest_indexed_height());
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::db::test_header;
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn args(x: &str) -> Vec<String> {
+        x.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(
+            RollbackOptions::parse(&args("--height 840100 --dry-run")).unwrap(),
+            RollbackOptions { height: 840100, dry_run: true }
+        );
+        assert!(!RollbackOptions::parse(&args("--height 840100")).unwrap().dry_run);
+        assert!(RollbackOptions::parse(&args("--dry-run")).is_err());
+        assert!(RollbackOptions::parse(&args("--height")).is_err());
+        assert!(RollbackOptions::parse(&args("--height 1 --force")).is_err());
+    }
+
+    #[test]
+    fn pruned_heights_are_rejected() {
+        let path = TempDir::new("rollback-pruned");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        for height in [100, 130] {
+            db.block_writer().commit(height, &test_header(height), &HashMap::new()).unwrap();
+        }
+        let options = RollbackOptions { height: 101, dry_run: true };
+        assert!(run(&db, &options, Some(20)).is_err());
+        assert!(run(&db, &options, Some(30)).is_ok());
+        assert!(run(&db, &options, None).is_ok());
+    }
+}
@@ -1,15 +1,277 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
 use std::time::Duration;
 
 use anyhow::{bail, Context};
+use bitcoin::block::{Header, Version};
+use bitcoin::consensus::deserialize;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Transaction, TxMerkleNode, Txid};
+use bitcoincore_rpc::json::{GetBlockHeaderResult, GetRawTransactionResult};
 use bitcoincore_rpc::{Auth, Client, RpcApi};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
 use log::{error, info};
+use serde::Deserialize;
+use serde_json::value::to_raw_value;
 use tokio::time::sleep;
 
 use crate::chain::Chain;
+use crate::entry::BitcoinCoreRpcResultExt;
 use crate::settings::Settings;
 
-pub fn create_bitcoincore_rpc_client(settings: Arc<Settings>) -> anyhow::Result<(Client, Chain)> {
+/// Transactions per `getrawtransaction` JSON-RPC batch.
+const RAW_TRANSACTION_BATCH_SIZE: usize = 100;
+
+/// An output spent in a block, as far as the commitment check and the
+/// inscription offsets need it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prevout {
+    pub height: u32,
+    pub script_pubkey: ScriptBuf,
+    pub value: u64,
+}
+
+pub type Prevouts = HashMap<OutPoint, Prevout>;
+
+/// `getblock` with verbosity 3, only the fields needed to rebuild the block.
+#[derive(Deserialize)]
+struct VerboseBlock {
+    hash: BlockHash,
+    version: i32,
+    // unset for the genesis block
+    previousblockhash: Option<BlockHash>,
+    merkleroot: TxMerkleNode,
+    time: u32,
+    bits: String,
+    nonce: u32,
+    tx: Vec<VerboseTx>,
+}
+
+#[derive(Deserialize)]
+struct VerboseTx {
+    hex: String,
+    vin: Vec<VerboseTxIn>,
+}
+
+#[derive(Deserialize)]
+struct VerboseTxIn {
+    // unset for coinbase inputs
+    prevout: Option<VerbosePrevout>,
+}
+
+#[derive(Deserialize)]
+struct VerbosePrevout {
+    height: u32,
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    value: Amount,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: VerboseScript,
+}
+
+#[derive(Deserialize)]
+struct VerboseScript {
+    hex: String,
+}
+
+impl VerboseBlock {
+    fn into_block(self) -> anyhow::Result<(Block, Prevouts)> {
+        let header = Header {
+            version: Version::from_consensus(self.version),
+            prev_blockhash: self.previousblockhash.unwrap_or_else(BlockHash::all_zeros),
+            merkle_root: self.merkleroot,
+            time: self.time,
+            bits: CompactTarget::from_consensus(u32::from_str_radix(&self.bits, 16)?),
+            nonce: self.nonce,
+        };
+        if header.block_hash() != self.hash {
+            bail!("header of block {} does not match its hash", self.hash);
+        }
+        let mut txdata = Vec::with_capacity(self.tx.len());
+        let mut prevouts = Prevouts::new();
+        for verbose in self.tx {
+            let tx: Transaction = deserialize(&hex::decode(&verbose.hex)?)?;
+            for (input, vin) in tx.input.iter().zip(verbose.vin) {
+                if let Some(prevout) = vin.prevout {
+                    prevouts.insert(input.previous_output, Prevout {
+                        height: prevout.height,
+                        script_pubkey: ScriptBuf::from_hex(&prevout.script_pub_key.hex)?,
+                        value: prevout.value.to_sat(),
+                    });
+                }
+            }
+            txdata.push(tx);
+        }
+        Ok((Block { header, txdata }, prevouts))
+    }
+}
+
+/// Clients with their own keep-alive connection. A `Client` holds its socket
+/// under a lock for the whole request, so one client serializes every call.
+struct ClientPool {
+    clients: Vec<Client>,
+    idle: Mutex<Vec<usize>>,
+    available: Condvar,
+}
+
+impl ClientPool {
+    /// Waits for an idle client.
+    fn checkout(self: &Arc<Self>) -> PooledClient {
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(index) = idle.pop() {
+                return PooledClient { pool: Arc::clone(self), index };
+            }
+            idle = self.available.wait(idle).unwrap();
+        }
+    }
+}
+
+/// A client checked out of the pool, returned when dropped.
+pub struct PooledClient {
+    pool: Arc<ClientPool>,
+    index: usize,
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.pool.clients[self.index]
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        self.pool.idle.lock().unwrap().push(self.index);
+        self.pool.available.notify_one();
+    }
+}
+
+/// bitcoind RPC for async callers. `bitcoincore_rpc::Client` is blocking, so every
+/// call runs on tokio's blocking pool with a client of the pool, and up to pool
+/// size calls are in flight at once. Clones share the pool.
+#[derive(Clone)]
+pub struct AsyncClient {
+    pool: Arc<ClientPool>,
+}
+
+impl AsyncClient {
+    pub fn new(clients: Vec<Client>) -> Self {
+        assert!(!clients.is_empty(), "the rpc client pool is empty");
+        let idle = (0..clients.len()).collect();
+        AsyncClient { pool: Arc::new(ClientPool { clients, idle: Mutex::new(idle), available: Condvar::new() }) }
+    }
+
+    /// A client of the pool, for code that already runs off the runtime.
+    pub fn blocking(&self) -> PooledClient {
+        self.pool.checkout()
+    }
+
+    pub async fn call<F, T>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&Client) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = Arc::clone(&self.pool);
+        tokio::task::spawn_blocking(move || f(&pool.checkout())).await?
+    }
+
+    pub async fn get_block_count(&self) -> anyhow::Result<u64> {
+        self.call(|client| Ok(client.get_block_count()?)).await
+    }
+
+    pub async fn get_block_hash(&self, height: u64) -> anyhow::Result<BlockHash> {
+        self.call(move |client| Ok(client.get_block_hash(height)?)).await
+    }
+
+    pub async fn get_block(&self, hash: BlockHash) -> anyhow::Result<Block> {
+        self.call(move |client| Ok(client.get_block(&hash)?)).await
+    }
+
+    /// The block and the outputs it spends, without needing `-txindex`.
+    /// Verbosity 3 needs Bitcoin Core 23 or later.
+    pub async fn get_block_with_prevouts(&self, hash: BlockHash) -> anyhow::Result<(Block, Prevouts)> {
+        self.call(move |client| {
+            let block: VerboseBlock = client.call("getblock", &[serde_json::to_value(hash)?, 3.into()])?;
+            block.into_block()
+        }).await
+    }
+
+    pub async fn get_raw_transaction_info(&self, txid: Txid) -> anyhow::Result<Option<GetRawTransactionResult>> {
+        self.call(move |client| client.get_raw_transaction_info(&txid, None).into_option()).await
+    }
+
+    /// Fee rate in sat/vB for confirmation within `conf_target` blocks, `None`
+    /// when the node does not have enough data yet.
+    pub async fn estimate_smart_fee(&self, conf_target: u16) -> anyhow::Result<Option<f64>> {
+        self.call(move |client| {
+            let result = client.estimate_smart_fee(conf_target, None)?;
+            Ok(result.fee_rate.map(|x| x.to_sat() as f64 / 1000.0))
+        }).await
+    }
+
+    pub async fn get_raw_mempool(&self) -> anyhow::Result<Vec<Txid>> {
+        self.call(|client| Ok(client.get_raw_mempool()?)).await
+    }
+
+    /// A mempool transaction, or a confirmed one with `-txindex` or its `block_hash`.
+    pub async fn get_raw_transaction(&self, txid: Txid, block_hash: Option<BlockHash>) -> anyhow::Result<Option<Transaction>> {
+        self.call(move |client| client.get_raw_transaction(&txid, block_hash.as_ref()).into_option()).await
+    }
+
+    /// `get_raw_transaction` of many mempool transactions, sent as JSON-RPC
+    /// batches spread over the pool. Transactions the node no longer has are `None`.
+    pub async fn get_raw_transactions(&self, txids: &[Txid]) -> anyhow::Result<Vec<Option<Transaction>>> {
+        let batches = txids.chunks(RAW_TRANSACTION_BATCH_SIZE).map(<[Txid]>::to_vec).collect::<Vec<_>>();
+        let txs: Vec<Vec<_>> = stream::iter(batches)
+            .map(|batch| self.call(move |client| get_raw_transaction_batch(client, &batch)))
+            .buffered(self.pool.clients.len())
+            .try_collect()
+            .await?;
+        Ok(txs.into_iter().flatten().collect())
+    }
+
+    pub async fn get_block_header(&self, hash: BlockHash) -> anyhow::Result<Header> {
+        self.call(move |client| Ok(client.get_block_header(&hash)?)).await
+    }
+
+    /// Whether the BIP-158 basic filter of a block matches any of `scripts`,
+    /// i.e. the block may pay or spend one of them. Needs `-blockfilterindex`.
+    pub async fn block_filter_matches(&self, hash: BlockHash, scripts: &HashSet<ScriptBuf>) -> anyhow::Result<bool> {
+        let filter = self.call(move |client| Ok(client.get_block_filter(&hash)?.into_filter())).await?;
+        Ok(filter.match_any(&hash, scripts.iter().map(|x| x.as_bytes()))?)
+    }
+
+    pub async fn get_block_header_info(&self, hash: BlockHash) -> anyhow::Result<Option<GetBlockHeaderResult>> {
+        self.call(move |client| client.get_block_header_info(&hash).into_option()).await
+    }
+
+    /// Whether a confirmed output is still unspent in the node's UTXO set.
+    pub async fn is_unspent(&self, outpoint: OutPoint) -> anyhow::Result<bool> {
+        self.call(move |client| Ok(client.get_tx_out(&outpoint.txid, outpoint.vout, Some(false))?.is_some())).await
+    }
+}
+
+fn get_raw_transaction_batch(client: &Client, txids: &[Txid]) -> anyhow::Result<Vec<Option<Transaction>>> {
+    let params = txids.iter().map(|txid| Ok(vec![to_raw_value(txid)?])).collect::<serde_json::Result<Vec<_>>>()?;
+    let jsonrpc = client.get_jsonrpc_client();
+    let requests = params.iter().map(|x| jsonrpc.build_request("getrawtransaction", x)).collect::<Vec<_>>();
+    let mut txs = Vec::with_capacity(txids.len());
+    for (txid, response) in txids.iter().zip(jsonrpc.send_batch(&requests)?) {
+        let response = response.with_context(|| format!("no getrawtransaction response for {}", txid))?;
+        let hex = response.result::<String>().map_err(bitcoincore_rpc::Error::JsonRpc).into_option()?;
+        let tx = match hex {
+            Some(hex) => Some(deserialize(&hex::decode(hex)?)?),
+            None => None,
+        };
+        txs.push(tx);
+    }
+    Ok(txs)
+}
+
+pub fn create_bitcoincore_rpc_client(settings: Arc<Settings>) -> anyhow::Result<(AsyncClient, Chain)> {
     let bitcoin_rpc_url = settings.bitcoin_rpc_url.as_ref().expect("BITCOIN_RPC_URL is required");
 
     info!("Connecting to Bitcoin Core RPC at {}", bitcoin_rpc_url);
@@ -20,11 +282,12 @@ pub fn create_bitcoincore_rpc_client(settings: Arc<Settings>) -> anyhow::Result<
         Auth::UserPass(settings.bitcoin_rpc_username.clone().unwrap(), settings.bitcoin_rpc_password.clone().unwrap())
     };
 
-    let client = Client::new(bitcoin_rpc_url, auth)
+    let clients = (0..settings.bitcoin_rpc_pool_size.max(1))
+        .map(|_| Client::new(bitcoin_rpc_url, auth.clone()))
+        .collect::<Result<Vec<_>, _>>()
         .with_context(|| format!("Failed to connect to Bitcoin Core RPC at {}", bitcoin_rpc_url)).unwrap();
 
-
-    let result: bitcoincore_rpc::Result<serde_json::Value> = client.call("getblockchaininfo", &[]);
+    let result: bitcoincore_rpc::Result<serde_json::Value> = clients[0].call("getblockchaininfo", &[]);
 
     info!("Got blockchain info: {:?}", &result);
 
@@ -33,20 +296,22 @@ pub fn create_bitcoincore_rpc_client(settings: Arc<Settings>) -> anyhow::Result<
     let rpc_chain = chain_str.parse::<Chain>().unwrap();
     let ord_chain = settings.network.as_ref().expect("network is required").parse::<Chain>().unwrap();
 
-    if rpc_chain != ord_chain {
+    // bitcoind knows a custom chain as the signet or regtest it runs
+    if ord_chain != Chain::Custom && rpc_chain != ord_chain {
         bail!("Bitcoin RPC server is on {rpc_chain} but ord is on {ord_chain}");
     }
 
-    Ok((client, ord_chain))
+    Ok((AsyncClient::new(clients), ord_chain))
 }
 
-pub async fn with_retry<F, T>(mut call: F, attempts: u8, delay: Duration) -> anyhow::Result<T>
+pub async fn with_retry<F, Fut, T>(mut call: F, attempts: u8, delay: Duration) -> anyhow::Result<T>
 where
-    F: FnMut() -> anyhow::Result<T>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output=anyhow::Result<T>>,
 {
     let mut attempt: u8 = 0;
     loop {
-        let ret = call();
+        let ret = call().await;
         match ret {
             Ok(result) => return Ok(result),
             Err(e) if attempt < attempts - 1 => {
@@ -58,4 +323,67 @@ where
             Err(e) => return Err(e),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_checkout_and_return() {
+        let clients = (0..2).map(|_| Client::new("http://127.0.0.1:1", Auth::None).unwrap()).collect();
+        let client = AsyncClient::new(clients);
+        let a = client.blocking();
+        let b = client.blocking();
+        assert_ne!(a.index, b.index);
+        assert!(client.pool.idle.lock().unwrap().is_empty());
+        drop(a);
+        let c = client.blocking();
+        drop((b, c));
+        assert_eq!(client.pool.idle.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn verbose_block_with_prevouts() {
+        use bitcoin::absolute::LockTime;
+        use bitcoin::consensus::encode::serialize_hex;
+        use bitcoin::transaction::Version as TxVersion;
+        use bitcoin::{Sequence, TxIn, TxOut, Witness};
+
+        let spent = OutPoint { txid: Txid::all_zeros(), vout: 1 };
+        let tx = Transaction {
+            version: TxVersion::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn { previous_output: spent, script_sig: ScriptBuf::new(), sequence: Sequence::MAX, witness: Witness::new() }],
+            output: vec![TxOut { value: Amount::from_sat(1000), script_pubkey: ScriptBuf::from_hex("51").unwrap() }],
+        };
+        let header = Header {
+            version: Version::from_consensus(0x20000000),
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 7,
+        };
+        let json = serde_json::json!({
+            "hash": header.block_hash(),
+            "version": 0x20000000,
+            "merkleroot": TxMerkleNode::all_zeros(),
+            "time": 1_700_000_000,
+            "bits": "207fffff",
+            "nonce": 7,
+            "tx": [{
+                "hex": serialize_hex(&tx),
+                "vin": [{"txid": spent.txid, "vout": 1, "prevout": {"generated": false, "height": 100, "value": 0.0001, "scriptPubKey": {"hex": "5120aa"}}}],
+            }],
+        });
+
+        let (block, prevouts) = serde_json::from_value::<VerboseBlock>(json.clone()).unwrap().into_block().unwrap();
+        assert_eq!(block.header, header);
+        assert_eq!(block.txdata, vec![tx]);
+        assert_eq!(prevouts[&spent], Prevout { height: 100, script_pubkey: ScriptBuf::from_hex("5120aa").unwrap(), value: 10_000 });
+
+        let mut tampered = json;
+        tampered["nonce"] = 8.into();
+        assert!(serde_json::from_value::<VerboseBlock>(tampered).unwrap().into_block().is_err());
+    }
+}
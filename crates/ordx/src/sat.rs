@@ -0,0 +1,195 @@
+//! Opt-in sat range index: the ranges of sats in every unspent output, handed
+//! from inputs to outputs in order, fees to the coinbase. Ranges start at the
+//! subsidies, so every block from genesis has to be indexed.
+
+use std::collections::VecDeque;
+
+use anyhow::bail;
+use bitcoin::{OutPoint, Transaction, TxOut, Txid};
+use tracing::instrument;
+
+use crate::chain::Chain;
+use crate::db::{BlockWriter, RunesDB, Store};
+use crate::entry::{SatRange, Statistic};
+use crate::updater::Result;
+
+/// Marks a fresh database indexed from genesis as having the sat index, and
+/// refuses to turn the index on or off for a database indexed without or with
+/// it.
+pub fn check_index(runes_db: &RunesDB, enabled: bool, first_height: u32) -> Result {
+    let indexed = runes_db.statistic_to_value_get(&Statistic::IndexSats).is_some();
+    match (enabled, indexed) {
+        (true, false) if first_height > 0 || runes_db.latest_indexed_height().is_some() => {
+            bail!("the sat index needs every block from genesis, reindex with FIRST_RUNE_HEIGHT=0")
+        }
+        (true, false) => runes_db.statistic_to_value_put(&Statistic::IndexSats, 1),
+        (false, true) => bail!("the database has a sat index, turning INDEX_SATS off needs a reindex"),
+        _ => {}
+    }
+    Ok(())
+}
+
+pub struct SatUpdater<'a> {
+    pub height: u32,
+    pub writer: &'a BlockWriter<'a>,
+    // the subsidy and the fees, claimed by the coinbase after the other transactions
+    coinbase_inputs: VecDeque<SatRange>,
+    coinbase: Option<&'a Transaction>,
+    // ranges written in this block
+    ranges: u32,
+}
+
+impl<'a> SatUpdater<'a> {
+    pub fn new(chain: Chain, height: u32, writer: &'a BlockWriter<'a>) -> Self {
+        let start = chain.starting_sat(height);
+        let subsidy = chain.subsidy(height);
+        SatUpdater {
+            height,
+            writer,
+            coinbase_inputs: (subsidy > 0).then_some((start, start + subsidy)).into_iter().collect(),
+            coinbase: None,
+            ranges: 0,
+        }
+    }
+
+    #[instrument(level = "trace", skip(self, tx), fields(txid = %tx.txid()))]
+    pub fn index_sats(&mut self, tx: &'a Transaction) -> Result {
+        if tx.is_coinbase() {
+            self.coinbase = Some(tx);
+            return Ok(());
+        }
+        let mut input_ranges = VecDeque::new();
+        for input in &tx.input {
+            let ranges = self.writer.outpoint_to_sat_ranges_get(&input.previous_output);
+            if !ranges.is_empty() {
+                self.writer.outpoint_to_sat_ranges_put(self.height, &input.previous_output, &[]);
+            }
+            input_ranges.extend(ranges);
+        }
+        self.assign(tx.txid(), &tx.output, &mut input_ranges);
+        self.coinbase_inputs.extend(input_ranges);
+        Ok(())
+    }
+
+    /// Assigns the subsidy and fees to the coinbase, what it doesn't claim is
+    /// lost.
+    pub fn update(&mut self) -> Result {
+        if let Some(coinbase) = self.coinbase {
+            let mut ranges = std::mem::take(&mut self.coinbase_inputs);
+            self.assign(coinbase.txid(), &coinbase.output, &mut ranges);
+        }
+        if self.ranges > 0 {
+            self.writer.height_to_statistic_count_put(&Statistic::SatRanges, self.height, self.ranges);
+            let ranges = self.writer.statistic_to_total_get(&Statistic::SatRanges).unwrap_or_default() + self.ranges as u128;
+            self.writer.statistic_to_total_put(&Statistic::SatRanges, ranges);
+        }
+        Ok(())
+    }
+
+    /// Fills the outputs from the front of `ranges` by value, splitting the
+    /// range an output ends in. OP_RETURN outputs can't be spent and keep none.
+    fn assign(&mut self, txid: Txid, outputs: &[TxOut], ranges: &mut VecDeque<SatRange>) {
+        for (vout, output) in outputs.iter().enumerate() {
+            let mut remaining = output.value.to_sat();
+            let mut assigned = Vec::new();
+            while remaining > 0 {
+                let Some((start, end)) = ranges.pop_front() else {
+                    break;
+                };
+                if end - start > remaining {
+                    ranges.push_front((start + remaining, end));
+                    assigned.push((start, start + remaining));
+                    remaining = 0;
+                } else {
+                    assigned.push((start, end));
+                    remaining -= end - start;
+                }
+            }
+            if assigned.is_empty() || output.script_pubkey.is_op_return() {
+                continue;
+            }
+            self.ranges += assigned.len() as u32;
+            self.writer.outpoint_to_sat_ranges_put(self.height, &OutPoint { txid, vout: vout as u32 }, &assigned);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, ScriptBuf, Sequence, TxIn, Witness};
+
+    use crate::db::test_header;
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn tx(input: Vec<OutPoint>, values: &[u64]) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: input.into_iter().map(|previous_output| TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }).collect(),
+            output: values.iter().map(|x| TxOut { value: Amount::from_sat(*x), script_pubkey: ScriptBuf::new() }).collect(),
+        }
+    }
+
+    #[test]
+    fn ranges_follow_the_sats() {
+        let path = TempDir::new("sat");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        check_index(&db, true, 0).unwrap();
+        assert!(check_index(&db, false, 0).is_err());
+
+        // block 0 pays the subsidy to two outputs
+        let coinbase0 = tx(vec![OutPoint::null()], &[30 * 100_000_000, 20 * 100_000_000]);
+        let writer = db.block_writer();
+        let mut updater = SatUpdater::new(Chain::Mainnet, 0, &writer);
+        updater.index_sats(&coinbase0).unwrap();
+        updater.update().unwrap();
+        writer.commit(0, &test_header(0), &HashMap::new()).unwrap();
+        let first = OutPoint { txid: coinbase0.txid(), vout: 0 };
+        let second = OutPoint { txid: coinbase0.txid(), vout: 1 };
+        assert_eq!(db.outpoint_to_sat_ranges_get(&first), vec![(0, 30 * 100_000_000)]);
+        assert_eq!(db.outpoint_to_sat_ranges_get(&second), vec![(30 * 100_000_000, 50 * 100_000_000)]);
+
+        // block 1 spends both into one output, the fee goes to a coinbase claiming less than it may
+        let spend = tx(vec![second, first], &[10 * 100_000_000, 39 * 100_000_000]);
+        let coinbase1 = tx(vec![OutPoint::null()], &[50 * 100_000_000]);
+        let writer = db.block_writer();
+        let mut updater = SatUpdater::new(Chain::Mainnet, 1, &writer);
+        updater.index_sats(&coinbase1).unwrap();
+        updater.index_sats(&spend).unwrap();
+        updater.update().unwrap();
+        writer.commit(1, &test_header(1), &HashMap::new()).unwrap();
+        assert_eq!(db.outpoint_to_sat_ranges_get(&first), vec![]);
+        assert_eq!(db.outpoint_to_sat_ranges_get(&OutPoint { txid: spend.txid(), vout: 0 }), vec![(30 * 100_000_000, 40 * 100_000_000)]);
+        assert_eq!(
+            db.outpoint_to_sat_ranges_get(&OutPoint { txid: spend.txid(), vout: 1 }),
+            vec![(40 * 100_000_000, 50 * 100_000_000), (0, 29 * 100_000_000)],
+        );
+        assert_eq!(
+            db.outpoint_to_sat_ranges_get(&OutPoint { txid: coinbase1.txid(), vout: 0 }),
+            vec![(50 * 100_000_000, 100 * 100_000_000)],
+        );
+        assert_eq!(db.statistic_to_total_get(&Statistic::SatRanges), Some(6));
+
+        db.reorg_to_height(1, 1).unwrap();
+        assert_eq!(db.outpoint_to_sat_ranges_get(&first), vec![(0, 30 * 100_000_000)]);
+        assert_eq!(db.outpoint_to_sat_ranges_get(&OutPoint { txid: spend.txid(), vout: 1 }), vec![]);
+        assert_eq!(db.statistic_to_total_get(&Statistic::SatRanges), Some(2));
+
+        let other = TempDir::new("sat-late");
+        let late = RunesDB::new(&other);
+        assert!(check_index(&late, true, 840000).is_err());
+        check_index(&late, false, 840000).unwrap();
+    }
+}
@@ -1,32 +1,310 @@
 use std::{env, fmt};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 use config::Config;
 use dotenv::dotenv;
+use bitcoin::bech32::Hrp;
+use bitcoin::blockdata::constants::SUBSIDY_HALVING_INTERVAL;
+use bitcoin::p2p::Magic;
+use bitcoin::ScriptBuf;
 use serde::{Deserialize, Serialize};
 
+use crate::cache::CacheMethod;
+use crate::chain::{Chain, CustomChain};
+use crate::db::{RocksdbOptions, SqliteOptions};
+use crate::log_file::LogRotation;
+use crate::protocol::{find_protocol, Protocol};
+use crate::updater::REORG_DEPTH;
+
+/// What the binary runs.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Index blocks from bitcoind and serve the API.
+    #[default]
+    Index,
+    /// Only serve the API over an existing data dir, following the indexer as
+    /// a rocksdb secondary. No bitcoind connection.
+    Serve,
+}
+
+/// A `[networks.<name>]` section of `CONFIG_FILE`, the name is the chain, e.g.
+/// testnet4. Unset values are taken from the top level settings.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NetworkSettings {
+    pub data_dir: Option<String>,
+    pub bitcoin_rpc_url: Option<String>,
+    pub bitcoin_rpc_username: Option<String>,
+    pub bitcoin_rpc_password: Option<String>,
+    pub p2p_peers: Option<String>,
+    pub first_rune_height: Option<u32>,
+}
+
+/// A `[cache_methods.<method>]` section of `CONFIG_FILE`, the method as in
+/// `/admin/cache/purge`, e.g. handler_rune_by_id. Unset values are taken from
+/// the top level cache settings.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CacheMethodSettings {
+    pub time_to_live_secs: Option<u64>,
+    pub time_to_idle_secs: Option<u64>,
+    pub max_entries: Option<u64>,
+    // keep the responses in rocksdb as well until the next block, so they
+    // survive restarts, e.g. for top holders; not on replicas
+    #[serde(default)]
+    pub persistent: bool,
+}
+
 #[derive(Default, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Settings {
+    #[serde(default)]
+    pub mode: Mode,
     pub network: Option<String>,
+    // several networks in one process, from the `[networks.<name>]` sections of
+    // CONFIG_FILE: each has its own database and indexer, its API under `/<name>`
+    #[serde(default)]
+    pub networks: BTreeMap<String, NetworkSettings>,
+    // NETWORK=custom, a private signet or test harness: its network magic in
+    // hex, e.g. 0a03cf40, the bech32 HRP of its addresses, the height runes
+    // unlock at and the blocks between subsidy halvings
+    pub custom_chain_magic: Option<String>,
+    pub custom_chain_bech32_hrp: Option<String>,
+    #[serde(default)]
+    pub custom_chain_first_rune_height: u32,
+    #[serde(default = "default_custom_chain_subsidy_halving_interval")]
+    pub custom_chain_subsidy_halving_interval: u32,
     pub data_dir: Option<String>,
     pub bitcoin_rpc_url: Option<String>,
     pub bitcoin_rpc_username: Option<String>,
     pub bitcoin_rpc_password: Option<String>,
+    // concurrent bitcoind RPC connections
+    #[serde(default = "default_bitcoin_rpc_pool_size")]
+    pub bitcoin_rpc_pool_size: usize,
+    // false for nodes without -txindex, blocks are then fetched with their prevouts
+    #[serde(default = "default_bitcoin_rpc_txindex")]
+    pub bitcoin_rpc_txindex: bool,
+    // comma separated `host:port` bitcoin peers to sync headers and download
+    // blocks from, RPC is still used for commit transactions
+    pub p2p_peers: Option<String>,
     pub max_block_queue_size: Option<u8>,
-    // server
+    // height to start indexing from on an empty database, e.g. for custom signets and regtest
+    pub first_rune_height: Option<u32>,
+    // light mode, comma separated addresses or hex scripts: only their outputs get
+    // balance rows in sqlite, rune entries and the outpoint balances stay complete
+    pub light_mode_scripts: Option<String>,
+    // light mode: skip blocks whose BIP-158 filter matches none of the scripts, needs
+    // bitcoind's -blockfilterindex. Filters leave out OP_RETURN outputs, so the
+    // etchings and mints of skipped blocks are missed and runes only reach the
+    // scripts from outputs of indexed blocks
+    #[serde(default)]
+    pub light_mode_block_filters: bool,
+    // delete balances spent this many blocks ago from sqlite and rocksdb, at least
+    // the reorg depth, 0 keeps the history. Recounted holders and transactions
+    // then only see what is left
+    #[serde(default)]
+    pub prune_spent_after_blocks: u32,
+    // index inscriptions and their owners, only inscriptions revealed from the
+    // indexed heights on are seen, earlier ones stay untracked when moved
+    #[serde(default)]
+    pub index_inscriptions: bool,
+    // index the sat ranges of the outputs, needs every block from genesis, i.e.
+    // FIRST_RUNE_HEIGHT=0, and can't be turned on or off without a reindex
+    #[serde(default)]
+    pub index_sats: bool,
+    // comma separated protocols to index next to the runes, e.g. alkanes, see crate::protocol
+    pub protocols: Option<String>,
+    // read-only API replica: open rocksdb as a secondary of the indexer's, with its own files here
+    pub rocksdb_secondary_path: Option<String>,
+    #[serde(default = "default_rocksdb_catch_up_interval_ms")]
+    pub rocksdb_catch_up_interval_ms: u64,
+    // rocksdb tuning, a block cache of 0 bytes keeps one default cache per column family
+    #[serde(default)]
+    pub rocksdb_block_cache_size: usize,
+    #[serde(default = "default_rocksdb_write_buffer_size")]
+    pub rocksdb_write_buffer_size: usize,
+    #[serde(default = "default_rocksdb_max_background_jobs")]
+    pub rocksdb_max_background_jobs: i32,
+    // none, snappy, zlib, bz2, lz4, lz4hc or zstd
+    #[serde(default = "default_rocksdb_compression")]
+    pub rocksdb_compression: String,
+    // comma separated `CF=compression` overrides, e.g. OUTPOINT_TO_RUNE_BALANCES=zstd
+    pub rocksdb_cf_compression: Option<String>,
+    // bytes of WAL before the oldest memtables are flushed, 0 lets rocksdb decide
+    #[serde(default)]
+    pub rocksdb_max_total_wal_size: u64,
+    // seconds between flushes of every memtable and WAL syncs, 0 leaves them to rocksdb
+    #[serde(default)]
+    pub rocksdb_flush_interval_secs: u64,
+    // UTC `HH:MM-HH:MM` window, e.g. 02:00-04:00, a full compaction starts in once a day
+    pub rocksdb_compaction_window: Option<String>,
+    // sqlite connection pool and pragmas, applied to every pooled connection
+    #[serde(default = "default_sqlite_pool_max_size")]
+    pub sqlite_pool_max_size: u32,
+    #[serde(default = "default_sqlite_pool_min_idle")]
+    pub sqlite_pool_min_idle: u32,
+    #[serde(default = "default_sqlite_busy_timeout_ms")]
+    pub sqlite_busy_timeout_ms: u64,
+    // DELETE, TRUNCATE, PERSIST, MEMORY, WAL or OFF
+    #[serde(default = "default_sqlite_journal_mode")]
+    pub sqlite_journal_mode: String,
+    // OFF, NORMAL, FULL or EXTRA
+    #[serde(default = "default_sqlite_synchronous")]
+    pub sqlite_synchronous: String,
+    // bytes of the database file to memory map, 0 disables it
+    #[serde(default = "default_sqlite_mmap_size")]
+    pub sqlite_mmap_size: u64,
+    // seconds between incremental vacuum and ANALYZE runs while the indexer is
+    // at the tip, 0 disables them
+    #[serde(default)]
+    pub sqlite_maintenance_interval_secs: u64,
+    // server, comma separated `host:port` and `unix:/path.sock` addresses
     pub api_host: String,
+    // limit of anonymous requests per client IP, API keys have their tier's
     pub ip_limit_per_mills: u64,
     pub ip_limit_burst_size: u32,
+    // comma separated origins CORS allows, any when unset
+    pub cors_allowed_origins: Option<String>,
     pub concurrency_limit: usize,
-    // cache
+    #[serde(default)]
+    pub api_key_required: bool,
+    pub admin_api_key: Option<String>,
+    #[serde(default = "default_api_shutdown_timeout_secs")]
+    pub api_shutdown_timeout_secs: u64,
+    // responses smaller than this many bytes are sent uncompressed
+    #[serde(default = "default_compression_min_size")]
+    pub compression_min_size: u16,
+    // Cache-Control max-age, capped for tip data and fixed for data buried under a few blocks
+    #[serde(default = "default_cache_control_tip_max_age_secs")]
+    pub cache_control_tip_max_age_secs: u64,
+    #[serde(default = "default_cache_control_immutable_max_age_secs")]
+    pub cache_control_immutable_max_age_secs: u64,
+    // webhooks
+    #[serde(default = "default_webhook_max_attempts")]
+    pub webhook_max_attempts: u32,
+    #[serde(default = "default_webhook_retry_base_secs")]
+    pub webhook_retry_base_secs: u64,
+    // fees, sat/vB used by the PSBT builders when bitcoind has no estimate
+    #[serde(default = "default_fallback_fee_rate")]
+    pub fallback_fee_rate: f64,
+    #[serde(default = "default_fee_conf_target")]
+    pub fee_conf_target: u16,
+    // mempool, 0 disables tracking
+    #[serde(default = "default_mempool_poll_interval_secs")]
+    pub mempool_poll_interval_secs: u64,
+    // tracing
+    pub otlp_endpoint: Option<String>,
+    #[serde(default = "default_otlp_service_name")]
+    pub otlp_service_name: String,
+    // logs, also written to files in log_dir rotated by log_rotation (never, hourly,
+    // daily) or at log_max_file_size_mb (0 = unlimited), keeping log_max_files old
+    // ones; the API (ordx::api, tower_http) and the rest, the indexer, have their own levels
+    pub log_dir: Option<String>,
+    #[serde(default)]
+    pub log_rotation: LogRotation,
+    #[serde(default = "default_log_max_file_size_mb")]
+    pub log_max_file_size_mb: u64,
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    #[serde(default = "default_log_level")]
+    pub log_indexer_level: String,
+    #[serde(default = "default_log_level")]
+    pub log_api_level: String,
+    // cache, each method has its own of cache_max_entries
     #[serde(default = "default_cache_time_to_live_secs")]
     pub cache_time_to_live_secs: u64,
     #[serde(default = "default_cache_time_to_idle_secs")]
     pub cache_time_to_idle_secs: u64,
     #[serde(default = "default_cache_max_entries")]
     pub cache_max_entries: u64,
+    // overrides of the above by method, e.g. long for runes, short for address utxos
+    #[serde(default)]
+    pub cache_methods: BTreeMap<CacheMethod, CacheMethodSettings>,
+    // unknown rune lookups, 0 disables
+    #[serde(default = "default_cache_not_found_time_to_live_secs")]
+    pub cache_not_found_time_to_live_secs: u64,
+    #[serde(default = "default_commit_tx_cache_max_entries")]
+    pub commit_tx_cache_max_entries: u64,
 }
 
+fn default_custom_chain_subsidy_halving_interval() -> u32 {
+    SUBSIDY_HALVING_INTERVAL
+}
+fn default_bitcoin_rpc_pool_size() -> usize {
+    4
+}
+fn default_bitcoin_rpc_txindex() -> bool {
+    true
+}
+fn default_rocksdb_catch_up_interval_ms() -> u64 {
+    1000
+}
+fn default_rocksdb_write_buffer_size() -> usize {
+    RocksdbOptions::default().write_buffer_size
+}
+fn default_rocksdb_max_background_jobs() -> i32 {
+    RocksdbOptions::default().max_background_jobs
+}
+fn default_rocksdb_compression() -> String {
+    "snappy".to_string()
+}
+fn default_sqlite_pool_max_size() -> u32 {
+    SqliteOptions::default().pool_max_size
+}
+fn default_sqlite_pool_min_idle() -> u32 {
+    SqliteOptions::default().pool_min_idle
+}
+fn default_sqlite_busy_timeout_ms() -> u64 {
+    SqliteOptions::default().busy_timeout_ms
+}
+fn default_sqlite_journal_mode() -> String {
+    SqliteOptions::default().journal_mode
+}
+fn default_sqlite_synchronous() -> String {
+    SqliteOptions::default().synchronous
+}
+fn default_sqlite_mmap_size() -> u64 {
+    SqliteOptions::default().mmap_size
+}
+fn default_api_shutdown_timeout_secs() -> u64 {
+    10
+}
+fn default_compression_min_size() -> u16 {
+    1024
+}
+fn default_cache_control_tip_max_age_secs() -> u64 {
+    60
+}
+fn default_cache_control_immutable_max_age_secs() -> u64 {
+    24 * 60 * 60
+}
+fn default_webhook_max_attempts() -> u32 {
+    8
+}
+fn default_webhook_retry_base_secs() -> u64 {
+    10
+}
+fn default_fallback_fee_rate() -> f64 {
+    10.0
+}
+fn default_fee_conf_target() -> u16 {
+    6
+}
+fn default_mempool_poll_interval_secs() -> u64 {
+    5
+}
+fn default_otlp_service_name() -> String {
+    "ordx".to_string()
+}
+fn default_log_max_file_size_mb() -> u64 {
+    100
+}
+fn default_log_max_files() -> usize {
+    7
+}
+fn default_log_level() -> String {
+    "info".to_string()
+}
 fn default_cache_time_to_live_secs() -> u64 {
     10 * 60
 }
@@ -36,42 +314,160 @@ fn default_cache_time_to_idle_secs() -> u64 {
 fn default_cache_max_entries() -> u64 {
     8 * 1024
 }
+fn default_cache_not_found_time_to_live_secs() -> u64 {
+    30
+}
+fn default_commit_tx_cache_max_entries() -> u64 {
+    16 * 1024
+}
 
 impl Display for Settings {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "Settings from env: \n\
         ========================================\n\
+        mode: {:?}\n\
         network: {}\n\
+        networks: {}\n\
+        custom_chain_magic: {}\n\
+        custom_chain_bech32_hrp: {}\n\
+        custom_chain_first_rune_height: {}\n\
+        custom_chain_subsidy_halving_interval: {}\n\
         data_dir: {}\n\
         bitcoin_rpc_url: {}\n\
         bitcoin_rpc_username: {}\n\
         bitcoin_rpc_password: {} \n\
+        bitcoin_rpc_pool_size: {}\n\
+        bitcoin_rpc_txindex: {}\n\
+        p2p_peers: {}\n\
         max_block_queue_size: {}\n\
+        first_rune_height: {}\n\
+        light_mode_scripts: {}\n\
+        light_mode_block_filters: {}\n\
+        prune_spent_after_blocks: {}\n\
+        index_inscriptions: {}\n\
+        index_sats: {}\n\
+        protocols: {}\n\
+        rocksdb_secondary_path: {}\n\
+        rocksdb_catch_up_interval_ms: {}\n\
+        rocksdb_block_cache_size: {}\n\
+        rocksdb_write_buffer_size: {}\n\
+        rocksdb_max_background_jobs: {}\n\
+        rocksdb_compression: {}\n\
+        rocksdb_cf_compression: {}\n\
+        rocksdb_max_total_wal_size: {}\n\
+        rocksdb_flush_interval_secs: {}\n\
+        rocksdb_compaction_window: {}\n\
+        sqlite_pool_max_size: {}\n\
+        sqlite_pool_min_idle: {}\n\
+        sqlite_busy_timeout_ms: {}\n\
+        sqlite_journal_mode: {}\n\
+        sqlite_synchronous: {}\n\
+        sqlite_mmap_size: {}\n\
+        sqlite_maintenance_interval_secs: {}\n\
         api_host: {}\n\
         ip_limit_per_mills: {}\n\
         ip_limit_burst_size: {}\n\
+        cors_allowed_origins: {}\n\
         concurrency_limit: {}\n\
+        api_key_required: {}\n\
+        admin_api_key: {}\n\
+        api_shutdown_timeout_secs: {}\n\
+        compression_min_size: {}\n\
+        cache_control_tip_max_age_secs: {}\n\
+        cache_control_immutable_max_age_secs: {}\n\
+        webhook_max_attempts: {}\n\
+        webhook_retry_base_secs: {}\n\
+        fallback_fee_rate: {}\n\
+        fee_conf_target: {}\n\
+        mempool_poll_interval_secs: {}\n\
+        otlp_endpoint: {}\n\
+        otlp_service_name: {}\n\
+        log_dir: {}\n\
+        log_rotation: {:?}\n\
+        log_max_file_size_mb: {}\n\
+        log_max_files: {}\n\
+        log_indexer_level: {}\n\
+        log_api_level: {}\n\
         cache_time_to_live_secs: {}\n\
         cache_time_to_idle_secs: {}\n\
         cache_max_entries: {}\n\
+        cache_methods: {:?}\n\
+        cache_not_found_time_to_live_secs: {}\n\
+        commit_tx_cache_max_entries: {}\n\
         build_version: {}\n\
         build_timestamp: {}\n\
         target_triple: {}\n\
         rustc_semver: {}\n\
         ========================================",
+               self.mode,
                self.network.clone().unwrap_or_default(),
+               self.networks.keys().cloned().collect::<Vec<_>>().join(","),
+               self.custom_chain_magic.clone().unwrap_or_default(),
+               self.custom_chain_bech32_hrp.clone().unwrap_or_default(),
+               self.custom_chain_first_rune_height,
+               self.custom_chain_subsidy_halving_interval,
                self.data_dir.clone().unwrap_or_default(),
                self.bitcoin_rpc_url.clone().unwrap_or_default(),
                self.bitcoin_rpc_username.as_ref().map(|_| "***").unwrap_or_default(),
                self.bitcoin_rpc_password.as_ref().map(|_| "********").unwrap_or_default(),
+               self.bitcoin_rpc_pool_size,
+               self.bitcoin_rpc_txindex,
+               self.p2p_peers.clone().unwrap_or_default(),
                self.max_block_queue_size.map(|x| x.to_string()).unwrap_or_default(),
+               self.first_rune_height.map(|x| x.to_string()).unwrap_or_default(),
+               self.light_mode_scripts.clone().unwrap_or_default(),
+               self.light_mode_block_filters,
+               self.prune_spent_after_blocks,
+               self.index_inscriptions,
+               self.index_sats,
+               self.protocols.clone().unwrap_or_default(),
+               self.rocksdb_secondary_path.clone().unwrap_or_default(),
+               self.rocksdb_catch_up_interval_ms,
+               self.rocksdb_block_cache_size,
+               self.rocksdb_write_buffer_size,
+               self.rocksdb_max_background_jobs,
+               self.rocksdb_compression,
+               self.rocksdb_cf_compression.clone().unwrap_or_default(),
+               self.rocksdb_max_total_wal_size,
+               self.rocksdb_flush_interval_secs,
+               self.rocksdb_compaction_window.clone().unwrap_or_default(),
+               self.sqlite_pool_max_size,
+               self.sqlite_pool_min_idle,
+               self.sqlite_busy_timeout_ms,
+               self.sqlite_journal_mode,
+               self.sqlite_synchronous,
+               self.sqlite_mmap_size,
+               self.sqlite_maintenance_interval_secs,
                self.api_host,
                self.ip_limit_per_mills,
                self.ip_limit_burst_size,
+               self.cors_allowed_origins.clone().unwrap_or_default(),
                self.concurrency_limit,
+               self.api_key_required,
+               self.admin_api_key.as_ref().map(|_| "********").unwrap_or_default(),
+               self.api_shutdown_timeout_secs,
+               self.compression_min_size,
+               self.cache_control_tip_max_age_secs,
+               self.cache_control_immutable_max_age_secs,
+               self.webhook_max_attempts,
+               self.webhook_retry_base_secs,
+               self.fallback_fee_rate,
+               self.fee_conf_target,
+               self.mempool_poll_interval_secs,
+               self.otlp_endpoint.clone().unwrap_or_default(),
+               self.otlp_service_name,
+               self.log_dir.clone().unwrap_or_default(),
+               self.log_rotation,
+               self.log_max_file_size_mb,
+               self.log_max_files,
+               self.log_indexer_level,
+               self.log_api_level,
                self.cache_time_to_live_secs,
                self.cache_time_to_idle_secs,
                self.cache_max_entries,
+               self.cache_methods,
+               self.cache_not_found_time_to_live_secs,
+               self.commit_tx_cache_max_entries,
                env!("CARGO_PKG_VERSION"),
                env!("VERGEN_BUILD_TIMESTAMP"),
                env!("VERGEN_CARGO_TARGET_TRIPLE"),
@@ -81,14 +477,333 @@ impl Display for Settings {
 }
 
 impl Settings {
+    /// Settings from the environment, over those of the file at CONFIG_FILE
+    /// when set, e.g. a TOML file with `[networks.<name>]` sections.
     pub fn load() -> Self {
+        Self::try_load().unwrap()
+    }
+
+    /// [`Settings::load`] without panicking, for reloads of a running process.
+    pub fn try_load() -> anyhow::Result<Self> {
         dotenv().ok();
-        let config = Config::builder()
+        let mut builder = Config::builder();
+        if let Ok(path) = env::var("CONFIG_FILE") {
+            builder = builder.add_source(config::File::with_name(&path));
+        }
+        let config = builder
             .add_source(
                 config::Environment::default()
             )
-            .build()
-            .unwrap();
-        config.try_deserialize().unwrap()
+            .build()?;
+        Ok(config.try_deserialize()?)
+    }
+
+    pub fn persistent_cache_methods(&self) -> HashSet<CacheMethod> {
+        self.cache_methods.iter().filter(|(_, x)| x.persistent).map(|(method, _)| *method).collect()
+    }
+
+    /// The settings of every network the process runs: these alone without
+    /// `networks`, else one per section over these.
+    pub fn network_settings(&self) -> anyhow::Result<Vec<Settings>> {
+        if self.networks.is_empty() {
+            return Ok(vec![self.clone()]);
+        }
+        // the secondaries of several networks can't share a path, replicas
+        // default to one in each data dir
+        if self.rocksdb_secondary_path.is_some() {
+            anyhow::bail!("rocksdb secondary path can't be set with several networks");
+        }
+        let mut chains = Vec::new();
+        let mut settings = Vec::new();
+        for (name, network) in &self.networks {
+            // aliases, e.g. test4 and testnet4, name the same chain
+            let chain: Chain = name.parse()?;
+            if chains.contains(&chain) {
+                anyhow::bail!("network {} is configured twice", chain);
+            }
+            chains.push(chain);
+            settings.push(Settings {
+                network: Some(name.clone()),
+                networks: BTreeMap::new(),
+                data_dir: network.data_dir.clone().or_else(|| self.data_dir.clone()),
+                bitcoin_rpc_url: network.bitcoin_rpc_url.clone().or_else(|| self.bitcoin_rpc_url.clone()),
+                bitcoin_rpc_username: network.bitcoin_rpc_username.clone().or_else(|| self.bitcoin_rpc_username.clone()),
+                bitcoin_rpc_password: network.bitcoin_rpc_password.clone().or_else(|| self.bitcoin_rpc_password.clone()),
+                p2p_peers: network.p2p_peers.clone().or_else(|| self.p2p_peers.clone()),
+                first_rune_height: network.first_rune_height.or(self.first_rune_height),
+                ..self.clone()
+            });
+        }
+        Ok(settings)
     }
-}
\ No newline at end of file
+
+    /// The parameters of NETWORK=custom.
+    pub fn custom_chain(&self) -> anyhow::Result<CustomChain> {
+        let magic = self.custom_chain_magic.as_deref().ok_or_else(|| anyhow::anyhow!("custom chain magic is required"))?;
+        let magic = Magic::from_str(magic).map_err(|_| anyhow::anyhow!("invalid custom chain magic `{magic}`, expected 8 hex characters"))?;
+        let hrp = self.custom_chain_bech32_hrp.as_deref().ok_or_else(|| anyhow::anyhow!("custom chain bech32 HRP is required"))?;
+        let bech32_hrp = Hrp::parse(hrp).map_err(|_| anyhow::anyhow!("invalid custom chain bech32 HRP `{hrp}`"))?;
+        if self.custom_chain_subsidy_halving_interval == 0 {
+            anyhow::bail!("custom chain subsidy halving interval must be at least 1");
+        }
+        Ok(CustomChain {
+            magic,
+            bech32_hrp,
+            first_rune_height: self.custom_chain_first_rune_height,
+            subsidy_halving_interval: self.custom_chain_subsidy_halving_interval,
+        })
+    }
+
+    pub fn first_rune_height(&self, chain: Chain) -> u32 {
+        self.first_rune_height.unwrap_or(chain.default_start_height())
+    }
+
+    pub fn rocksdb_options(&self) -> anyhow::Result<RocksdbOptions> {
+        if self.rocksdb_max_background_jobs < 1 {
+            anyhow::bail!("rocksdb max background jobs must be at least 1");
+        }
+        Ok(RocksdbOptions {
+            block_cache_size: self.rocksdb_block_cache_size,
+            write_buffer_size: self.rocksdb_write_buffer_size,
+            max_background_jobs: self.rocksdb_max_background_jobs,
+            compression: RocksdbOptions::parse_compression(&self.rocksdb_compression)?,
+            cf_compression: self.rocksdb_cf_compression.as_deref().map(RocksdbOptions::parse_cf_compression).transpose()?.unwrap_or_default(),
+            max_total_wal_size: self.rocksdb_max_total_wal_size,
+        })
+    }
+
+    /// Blocks after which spent balances are pruned, none when the history is kept.
+    pub fn prune_spent_after_blocks(&self) -> anyhow::Result<Option<u32>> {
+        match self.prune_spent_after_blocks {
+            0 => Ok(None),
+            // a reorg puts the balances it unspends back from these
+            x if x < REORG_DEPTH => anyhow::bail!("prune spent after blocks must be 0 or at least the reorg depth {}", REORG_DEPTH),
+            x => Ok(Some(x)),
+        }
+    }
+
+    pub fn sqlite_options(&self) -> anyhow::Result<SqliteOptions> {
+        let options = SqliteOptions {
+            pool_max_size: self.sqlite_pool_max_size,
+            pool_min_idle: self.sqlite_pool_min_idle,
+            busy_timeout_ms: self.sqlite_busy_timeout_ms,
+            journal_mode: self.sqlite_journal_mode.clone(),
+            synchronous: self.sqlite_synchronous.clone(),
+            mmap_size: self.sqlite_mmap_size,
+        };
+        options.validate()?;
+        Ok(options)
+    }
+
+    pub fn protocols(&self) -> anyhow::Result<Vec<&'static dyn Protocol>> {
+        let Some(protocols) = &self.protocols else {
+            return Ok(Vec::new());
+        };
+        protocols.split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|x| find_protocol(x).ok_or_else(|| anyhow::anyhow!("unknown protocol `{x}`")))
+            .collect()
+    }
+
+    /// The scripts of light mode, none when every output is indexed.
+    pub fn light_mode_scripts(&self, chain: Chain) -> anyhow::Result<Option<HashSet<ScriptBuf>>> {
+        let Some(scripts) = &self.light_mode_scripts else {
+            return Ok(None);
+        };
+        let mut set = HashSet::new();
+        for x in scripts.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let script = match chain.parse_address(x) {
+                Ok(address) => address.require_network(chain.network())?.script_pubkey(),
+                Err(_) => ScriptBuf::from_hex(x).map_err(|_| anyhow::anyhow!("invalid light mode script `{x}`"))?,
+            };
+            set.insert(script);
+        }
+        Ok(Some(set))
+    }
+
+    /// The scripts blocks are skipped by, when light mode uses block filters.
+    pub fn light_mode_block_filter_scripts(&self, chain: Chain) -> anyhow::Result<Option<HashSet<ScriptBuf>>> {
+        if !self.light_mode_block_filters {
+            return Ok(None);
+        }
+        if self.index_sats {
+            anyhow::bail!("light mode block filters can't be used with the sat index, it needs every block");
+        }
+        match self.light_mode_scripts(chain)? {
+            Some(scripts) => Ok(Some(scripts)),
+            None => anyhow::bail!("light mode block filters need light mode scripts"),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use rocksdb::DBCompressionType;
+
+    use super::*;
+
+    #[test]
+    fn first_rune_height_override() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.first_rune_height(Chain::Mainnet), 840000);
+        assert_eq!(settings.first_rune_height(Chain::Testnet), 2583205);
+        assert_eq!(settings.first_rune_height(Chain::Regtest), 0);
+
+        settings.first_rune_height = Some(120);
+        assert_eq!(settings.first_rune_height(Chain::Signet), 120);
+        assert_eq!(settings.first_rune_height(Chain::Regtest), 120);
+    }
+
+    #[test]
+    fn sqlite_options() {
+        let mut settings = Settings {
+            sqlite_pool_max_size: 8,
+            sqlite_pool_min_idle: 2,
+            sqlite_busy_timeout_ms: 100,
+            sqlite_journal_mode: "wal".into(),
+            sqlite_synchronous: "full".into(),
+            sqlite_mmap_size: 0,
+            ..Default::default()
+        };
+        let options = settings.sqlite_options().unwrap();
+        assert_eq!((options.pool_max_size, options.pool_min_idle, options.synchronous.as_str()), (8, 2, "full"));
+
+        settings.sqlite_journal_mode = "wal; DROP TABLE rune_entry".into();
+        assert!(settings.sqlite_options().is_err());
+        settings.sqlite_journal_mode = "truncate".into();
+        settings.sqlite_pool_min_idle = 9;
+        assert!(settings.sqlite_options().is_err());
+        settings.sqlite_pool_max_size = 0;
+        settings.sqlite_pool_min_idle = 0;
+        assert!(settings.sqlite_options().is_err());
+    }
+
+    #[test]
+    fn rocksdb_options() {
+        let mut settings = Settings {
+            rocksdb_max_background_jobs: 4,
+            rocksdb_compression: "LZ4".into(),
+            rocksdb_cf_compression: Some("OUTPOINT_TO_RUNE_BALANCES=zstd, RUNE_TO_RUNE_ID = none".into()),
+            ..Default::default()
+        };
+        let options = settings.rocksdb_options().unwrap();
+        assert_eq!(options.compression, DBCompressionType::Lz4);
+        assert_eq!(options.cf_compression.get("OUTPOINT_TO_RUNE_BALANCES"), Some(&DBCompressionType::Zstd));
+        assert_eq!(options.cf_compression.get("RUNE_TO_RUNE_ID"), Some(&DBCompressionType::None));
+
+        settings.rocksdb_cf_compression = Some("NO_SUCH_CF=zstd".into());
+        assert!(settings.rocksdb_options().is_err());
+        settings.rocksdb_cf_compression = Some("RUNE_TO_RUNE_ID=brotli".into());
+        assert!(settings.rocksdb_options().is_err());
+        settings.rocksdb_cf_compression = None;
+        settings.rocksdb_max_background_jobs = 0;
+        assert!(settings.rocksdb_options().is_err());
+    }
+
+    #[test]
+    fn prune_spent_after_blocks() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.prune_spent_after_blocks().unwrap(), None);
+        settings.prune_spent_after_blocks = REORG_DEPTH - 1;
+        assert!(settings.prune_spent_after_blocks().is_err());
+        settings.prune_spent_after_blocks = 1000;
+        assert_eq!(settings.prune_spent_after_blocks().unwrap(), Some(1000));
+    }
+
+    #[test]
+    fn protocols() {
+        let mut settings = Settings::default();
+        assert!(settings.protocols().unwrap().is_empty());
+        settings.protocols = Some("alkanes, ".into());
+        assert_eq!(settings.protocols().unwrap().iter().map(|x| x.name()).collect::<Vec<_>>(), vec!["alkanes"]);
+        settings.protocols = Some("alkanes,brc20".into());
+        assert!(settings.protocols().is_err());
+    }
+
+    #[test]
+    fn network_settings() {
+        let mut settings = Settings {
+            network: Some("mainnet".into()),
+            bitcoin_rpc_url: Some("http://127.0.0.1:8332".into()),
+            first_rune_height: Some(100),
+            ..Default::default()
+        };
+        // Settings has no Debug, it would print the passwords
+        assert!(settings.network_settings().unwrap() == vec![settings.clone()]);
+
+        settings.networks.insert("mainnet".into(), NetworkSettings::default());
+        settings.networks.insert("testnet4".into(), NetworkSettings {
+            bitcoin_rpc_url: Some("http://127.0.0.1:48332".into()),
+            first_rune_height: Some(0),
+            ..Default::default()
+        });
+        let networks = settings.network_settings().unwrap();
+        assert_eq!(networks.len(), 2);
+        assert_eq!((networks[0].network.as_deref(), networks[0].bitcoin_rpc_url.as_deref(), networks[0].first_rune_height), (Some("mainnet"), Some("http://127.0.0.1:8332"), Some(100)));
+        assert_eq!((networks[1].network.as_deref(), networks[1].bitcoin_rpc_url.as_deref(), networks[1].first_rune_height), (Some("testnet4"), Some("http://127.0.0.1:48332"), Some(0)));
+        assert!(networks.iter().all(|x| x.networks.is_empty()));
+
+        settings.networks.insert("test4".into(), NetworkSettings::default());
+        assert!(settings.network_settings().is_err());
+        settings.networks.remove("test4");
+        settings.networks.insert("litecoin".into(), NetworkSettings::default());
+        assert!(settings.network_settings().is_err());
+        settings.networks.remove("litecoin");
+        settings.rocksdb_secondary_path = Some("/tmp/secondary".into());
+        assert!(settings.network_settings().is_err());
+    }
+
+    #[test]
+    fn custom_chain() {
+        let mut settings = Settings {
+            custom_chain_magic: Some("0a03cf40".into()),
+            custom_chain_bech32_hrp: Some("sb".into()),
+            custom_chain_first_rune_height: 10,
+            custom_chain_subsidy_halving_interval: 150,
+            ..Default::default()
+        };
+        let custom = settings.custom_chain().unwrap();
+        assert_eq!(custom.magic.to_bytes(), [0x0a, 0x03, 0xcf, 0x40]);
+        assert_eq!(custom.bech32_hrp.to_lowercase(), "sb");
+        assert_eq!((custom.first_rune_height, custom.subsidy_halving_interval), (10, 150));
+
+        settings.custom_chain_subsidy_halving_interval = 0;
+        assert!(settings.custom_chain().is_err());
+        settings.custom_chain_subsidy_halving_interval = 150;
+        settings.custom_chain_magic = Some("0a03cf".into());
+        assert!(settings.custom_chain().is_err());
+        settings.custom_chain_magic = None;
+        assert!(settings.custom_chain().is_err());
+        settings.custom_chain_magic = Some("0a03cf40".into());
+        settings.custom_chain_bech32_hrp = Some("s b".into());
+        assert!(settings.custom_chain().is_err());
+    }
+
+    #[test]
+    fn light_mode_scripts() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.light_mode_scripts(Chain::Regtest).unwrap(), None);
+
+        settings.light_mode_scripts = Some("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080, 51,".into());
+        let scripts = settings.light_mode_scripts(Chain::Regtest).unwrap().unwrap();
+        assert_eq!(scripts.len(), 2);
+        assert!(scripts.contains(&ScriptBuf::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap()));
+        assert!(scripts.contains(&ScriptBuf::from_hex("51").unwrap()));
+
+        assert!(settings.light_mode_scripts(Chain::Mainnet).is_err());
+        settings.light_mode_scripts = Some("not a script".into());
+        assert!(settings.light_mode_scripts(Chain::Regtest).is_err());
+    }
+
+    #[test]
+    fn light_mode_block_filter_scripts() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.light_mode_block_filter_scripts(Chain::Regtest).unwrap(), None);
+        settings.light_mode_block_filters = true;
+        assert!(settings.light_mode_block_filter_scripts(Chain::Regtest).is_err());
+        settings.light_mode_scripts = Some("51".into());
+        assert_eq!(settings.light_mode_block_filter_scripts(Chain::Regtest).unwrap().unwrap().len(), 1);
+        settings.index_sats = true;
+        assert!(settings.light_mode_block_filter_scripts(Chain::Regtest).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::Write;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use log::{LevelFilter, Log, Metadata, Record, warn};
+
+use crate::log_file::RotatingFile;
+use crate::settings::Settings;
+
+/// Installs the log/tracing output. Without `otlp_endpoint` spans and events are
+/// forwarded to env_logger through tracing's `log` feature, same output as before.
+pub fn init(settings: &Settings) -> anyhow::Result<()> {
+    match &settings.otlp_endpoint {
+        #[cfg(feature = "otlp")]
+        Some(endpoint) => {
+            otlp::init(endpoint, &settings.otlp_service_name)?;
+            if let Some(dir) = &settings.log_dir {
+                warn!("Log dir {} ignored, logs are exported to {}", dir, endpoint);
+            }
+            Ok(())
+        }
+        #[cfg(not(feature = "otlp"))]
+        Some(endpoint) => {
+            init_logger(settings)?;
+            warn!("OTLP endpoint {} ignored, ordx was built without the `otlp` feature", endpoint);
+            Ok(())
+        }
+        None => init_logger(settings),
+    }
+}
+
+/// env_logger on stderr, plus the rotating file of `log_dir` when set.
+fn init_logger(settings: &Settings) -> anyhow::Result<()> {
+    let stderr = env_logger::Builder::from_default_env().build();
+    let file = settings.log_dir.as_ref().map(|dir| FileLogger::new(dir, settings)).transpose()?;
+    let max_level = file.as_ref().map_or(LevelFilter::Off, |x| x.api_level.max(x.indexer_level)).max(stderr.filter());
+    log::set_boxed_logger(Box::new(Logger { stderr, file }))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+struct Logger {
+    stderr: env_logger::Logger,
+    file: Option<FileLogger>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata) || self.file.as_ref().is_some_and(|x| x.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.stderr.matches(record) {
+            self.stderr.log(record);
+        }
+        if let Some(file) = &self.file {
+            file.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = &self.file {
+            file.flush();
+        }
+    }
+}
+
+// targets logged at the API level, the rest is the indexer's
+const API_TARGETS: [&str; 2] = ["ordx::api", "tower_http"];
+
+struct FileLogger {
+    file: Mutex<RotatingFile>,
+    api_level: LevelFilter,
+    indexer_level: LevelFilter,
+}
+
+impl FileLogger {
+    fn new(dir: &str, settings: &Settings) -> anyhow::Result<Self> {
+        let level = |x: &str| x.parse::<LevelFilter>().with_context(|| format!("invalid log level: {}", x));
+        let file = RotatingFile::open(dir, "ordx.log", settings.log_rotation, settings.log_max_file_size_mb * 1024 * 1024, settings.log_max_files)
+            .with_context(|| format!("failed to open the log file in {}", dir))?;
+        Ok(Self {
+            file: Mutex::new(file),
+            api_level: level(&settings.log_api_level)?,
+            indexer_level: level(&settings.log_indexer_level)?,
+        })
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        let api = API_TARGETS.iter().any(|x| target.strip_prefix(x).is_some_and(|x| x.is_empty() || x.starts_with("::")));
+        if api { self.api_level } else { self.indexer_level }
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // one write per record, so that rotation never splits it
+        let line = format!("[{} {:<5} {}] {}\n",
+                           chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"), record.level(), record.target(), record.args());
+        let _ = self.file.lock().unwrap().write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {
+        let _ = self.file.lock().unwrap().flush();
+    }
+}
+
+/// Flushes spans that are still buffered in the exporter.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::EnvFilter;
+
+    pub fn init(endpoint: &str, service_name: &str) -> anyhow::Result<()> {
+        let provider = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(
+                trace::Config::default()
+                    .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name.to_string())])),
+            )
+            .install_batch(runtime::Tokio)?;
+        let tracer = provider.tracer("ordx");
+        opentelemetry::global::set_tracer_provider(provider);
+
+        // RUST_LOG keeps working as the filter, log records are bridged into tracing
+        tracing_subscriber::registry()
+            .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+            .with(tracing_subscriber::fmt::layer())
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .try_init()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn file_levels() {
+        let dir = TempDir::new("telemetry");
+        let settings = Settings { log_api_level: "warn".into(), log_indexer_level: "debug".into(), ..Default::default() };
+        let logger = FileLogger::new(dir.to_str().unwrap(), &settings).unwrap();
+        let enabled = |target, level| logger.enabled(&Metadata::builder().target(target).level(level).build());
+
+        assert!(!enabled("ordx::api::handler", Level::Info));
+        assert!(!enabled("tower_http::trace", Level::Info));
+        assert!(enabled("ordx::api", Level::Warn));
+        assert!(enabled("ordx::apis", Level::Debug));
+        assert!(enabled("ordx::updater", Level::Debug));
+        assert!(!enabled("ordx::updater", Level::Trace));
+        assert!(FileLogger::new(dir.to_str().unwrap(), &Settings { log_api_level: "loud".into(), ..settings }).is_err());
+    }
+}
@@ -1,19 +1,24 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::time::Duration;
 
-use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
-use bitcoincore_rpc::{Client, RpcApi};
+use anyhow::{anyhow, bail, Context};
+use bitcoin::{OutPoint, Script, ScriptBuf, Transaction, TxIn, Txid};
+use futures_util::future::join_all;
 use hex::ToHex;
-use log::info;
+use tracing::{info, instrument};
 
 use ordinals::*;
 
-use crate::db::model::{RuneBalanceForInsert, RuneBalanceForTemp, RuneBalanceForUpdate, RuneBalanceKey, RuneEntryForQueryInsert, RuneEntryForTemp, RuneEntryForUpdate, RuneOpType};
-use crate::db::RunesDB;
+use crate::cache::{CommitTx, CommitTxCache};
+use crate::chain::Chain;
+use crate::db::key::script_hash;
+use crate::db::model::{CenotaphForQueryInsert, RuneBalanceForInsert, RuneBalanceForTemp, RuneBalanceForUpdate, RuneEdictForInsert, RuneBalanceKey, RuneEntryForQueryInsert, RuneEntryForTemp, RuneEntryForUpdate, RuneOpType};
+use crate::db::{BlockWriter, Store};
 use crate::entry::*;
 use crate::into_usize::IntoUsize;
 use crate::lot::*;
-use crate::rpc::with_retry;
+use crate::rpc::{with_retry, AsyncClient, Prevouts};
+use crate::webhook::RuneEvent;
 
 pub type Result<T = (), E = anyhow::Error> = std::result::Result<T, E>;
 
@@ -22,19 +27,33 @@ pub const REORG_DEPTH: u32 = 10;
 pub struct RuneUpdater<'a, > {
     pub block_time: u32,
     pub burned: HashMap<RuneId, Lot>,
-    pub client: &'a Client,
+    pub events: Vec<RuneEvent>,
+    pub client: &'a AsyncClient,
+    pub commit_tx_cache: &'a CommitTxCache,
+    // outputs spent in the block, commit txs are looked up here instead of
+    // with getrawtransaction when set
+    pub prevouts: Option<&'a Prevouts>,
+    // light mode, only outputs to these scripts get balance rows in sqlite and
+    // are indexed by rune and height
+    pub balance_scripts: Option<&'a HashSet<ScriptBuf>>,
     pub height: u32,
     pub latest_height: u32,
-    pub network: Network,
+    pub chain: Chain,
     pub minimum: Rune,
     pub runes: u32,
-    pub runes_db: &'a RunesDB,
+    // transactions with a runestone or cenotaph in this block
+    pub runestones: u32,
+    // transactions with a runestone or rune inputs in this block
+    pub transactions: u32,
+    pub mints: u32,
+    pub writer: &'a BlockWriter<'a>,
     pub outpoint_to_rune_ids: &'a mut HashMap<OutPoint, HashSet<RuneId>>,
     pub rune_entry_temp: &'a mut RuneEntryForTemp,
     pub rune_balance_temp: &'a mut RuneBalanceForTemp,
 }
 
 impl<'a> RuneUpdater<'a> {
+    #[instrument(level = "trace", skip(self, tx), fields(txid = %tx.txid()))]
     pub async fn index_runes(
         &mut self,
         tx_index: u32,
@@ -42,8 +61,14 @@ impl<'a> RuneUpdater<'a> {
     ) -> Result<()> {
         let txid = tx.txid();
         let artifact = Runestone::decipher(tx);
+        if artifact.is_some() {
+            self.runestones += 1;
+        }
 
         let mut unallocated = self.unallocated(&txid, tx)?;
+        if artifact.is_some() || !unallocated.is_empty() {
+            self.transactions += 1;
+        }
 
         let mut allocated: Vec<HashMap<RuneId, Lot>> = vec![HashMap::new(); tx.output.len()];
 
@@ -65,7 +90,7 @@ impl<'a> RuneUpdater<'a> {
                     }
                 }
 
-                for Edict { id, amount, output } in runestone.edicts.iter().copied() {
+                for (edict, Edict { id, amount, output }) in runestone.edicts.iter().copied().enumerate() {
                     let amount = Lot(amount);
 
                     // edicts with output values greater than the number of outputs
@@ -75,6 +100,7 @@ impl<'a> RuneUpdater<'a> {
 
                     let id = if id == RuneId::default() {
                         let Some((id, ..)) = etched else {
+                            self.record_edict(txid, tx_index, edict, id, amount, output, Lot(0));
                             continue;
                         };
 
@@ -84,13 +110,16 @@ impl<'a> RuneUpdater<'a> {
                     };
 
                     let Some(balance) = unallocated.get_mut(&id) else {
+                        self.record_edict(txid, tx_index, edict, id, amount, output, Lot(0));
                         continue;
                     };
 
+                    let mut moved = Lot(0);
                     let mut allocate = |balance: &mut Lot, amount: Lot, output: usize| {
                         if amount > 0 {
                             *balance -= amount;
                             *allocated[output].entry(id).or_default() += amount;
+                            moved += amount;
                         }
                     };
 
@@ -135,27 +164,40 @@ impl<'a> RuneUpdater<'a> {
 
                         allocate(balance, amount, output);
                     }
+
+                    self.record_edict(txid, tx_index, edict, id, amount, output, moved);
                 }
             }
 
-            if let Some((id, rune)) = etched {
-                self.create_rune_entry(txid, artifact, id, rune)?;
+            if let Some((id, rune, commitment)) = etched {
+                self.create_rune_entry(txid, artifact, id, rune, commitment)?;
             }
         }
 
         let mut burned: HashMap<RuneId, Lot> = HashMap::new();
 
-        if let Some(Artifact::Cenotaph(_)) = artifact {
-            let mut cenotaph = false;
+        if let Some(Artifact::Cenotaph(cenotaph)) = &artifact {
+            let mut cenotaph_burned = BTreeMap::new();
             for (id, balance) in unallocated {
                 *burned.entry(id).or_default() += balance;
                 if balance > 0 {
-                    cenotaph = true;
+                    cenotaph_burned.insert(id.to_string(), balance.n().to_string());
                 }
             }
-            if cenotaph {
+            if !cenotaph_burned.is_empty() {
                 self.rune_balance_temp.insert_tx_op(txid.to_string(), RuneOpType::Cenotaph);
             }
+            self.rune_balance_temp.cenotaphs.push(CenotaphForQueryInsert {
+                txid: txid.to_string(),
+                flaw: cenotaph.flaw.map(|x| serde_json::to_string(&x)).transpose()?.map(|x| x.trim_matches('"').to_string()),
+                description: cenotaph.flaw.map(|x| x.to_string()),
+                etching: cenotaph.etching.map(|x| x.to_string()),
+                mint: cenotaph.mint.map(|x| x.to_string()),
+                burned: cenotaph_burned,
+                height: self.height,
+                idx: tx_index,
+                ts: self.block_time,
+            });
         } else {
             let pointer = artifact
                 .map(|artifact| match artifact {
@@ -223,13 +265,24 @@ impl<'a> RuneUpdater<'a> {
                 vout: vout.try_into().unwrap(),
             };
 
-            let address = match Address::from_script(&tx.output[vout].script_pubkey, self.network) {
-                Ok(v) => v.to_string(),
-                Err(_) => tx.output[vout].script_pubkey.to_bytes().encode_hex(),
+            let address = self.script_address(&tx.output[vout].script_pubkey);
+            let rows = match self.balance_scripts {
+                Some(scripts) => scripts.contains(&tx.output[vout].script_pubkey),
+                None => true,
             };
 
-            let rune_ids = self.outpoint_to_rune_ids.entry(outpoint).or_default();
+            let mut amounts = Vec::with_capacity(balances.len());
             for (id, balance) in balances {
+                Self::encode_rune_balance(id, balance.n(), &mut buffer);
+                amounts.push((id, balance.n()));
+                // light mode only keeps the outpoint balance of other outputs, for
+                // transfers to the scripts. One left by a reorg is overwritten if
+                // its transaction is mined again.
+                if !rows {
+                    continue;
+                }
+                self.outpoint_to_rune_ids.entry(outpoint).or_default().insert(id);
+                self.writer.rune_id_to_outpoints_put(&id, &outpoint, balance.n());
                 let key = RuneBalanceKey {
                     txid: txid.to_string(),
                     vout: vout as _,
@@ -255,28 +308,51 @@ impl<'a> RuneUpdater<'a> {
                     spent_vin: None,
                     spent_ts: None,
                 });
-                Self::encode_rune_balance(id, balance.n(), &mut buffer);
-                rune_ids.insert(id);
             }
 
             let balance: RuneBalanceEntry = (self.height, 0, buffer.clone());
-            self.runes_db.outpoint_to_rune_balances_put(&outpoint, balance);
+            self.writer.outpoint_to_rune_balances_put(&outpoint, balance);
+            if rows {
+                self.writer.spk_to_outpoints_put(&tx.output[vout].script_pubkey, &outpoint, self.height, tx.output[vout].value.to_sat());
+                self.writer.script_hash_rune_id_to_balance_add(&script_hash(&tx.output[vout].script_pubkey), &amounts);
+            }
         }
 
         // increment entries with burned runes
         for (id, amount) in burned {
+            if amount > 0 {
+                self.events.push(RuneEvent::Burn {
+                    rune_id: id.to_string(),
+                    txid: txid.to_string(),
+                    amount: amount.n().to_string(),
+                });
+            }
             *self.burned.entry(id).or_default() += amount;
         }
 
         Ok(())
     }
 
+    #[instrument(skip_all, fields(height = self.height))]
     pub fn update(&self) -> Result {
         for (rune_id, burned) in &self.burned {
-            let mut entry = self.runes_db.rune_id_to_rune_entry_get(rune_id).unwrap();
-            self.runes_db.rune_id_height_to_burned_put(rune_id, self.height, burned.n());
-            entry.burned = self.runes_db.rune_id_to_burned_inc(rune_id);
-            self.runes_db.rune_id_to_rune_entry_put(rune_id, &entry);
+            let mut entry = self.writer.rune_id_to_rune_entry_get(rune_id).unwrap();
+            self.writer.height_rune_id_to_burned_put(self.height, rune_id, burned.n());
+            entry.burned = self.writer.rune_id_to_burned_add(rune_id, burned.n());
+            self.writer.rune_id_to_rune_entry_put(rune_id, &entry);
+        }
+
+        let burned = self.burned.values().map(|x| x.n()).sum::<u128>();
+        if self.mints > 0 || burned > 0 {
+            let mints = self.writer.statistic_to_total_get(&Statistic::Mints).unwrap_or_default() + self.mints as u128;
+            self.writer.statistic_to_total_put(&Statistic::Mints, mints);
+            let burned = self.writer.statistic_to_total_get(&Statistic::Burned).unwrap_or_default() + burned;
+            self.writer.statistic_to_total_put(&Statistic::Burned, burned);
+        }
+        if self.transactions > 0 {
+            self.writer.height_to_statistic_count_put(&Statistic::RuneTransactions, self.height, self.transactions);
+            let transactions = self.writer.statistic_to_value_get(&Statistic::RuneTransactions).unwrap_or_default() + self.transactions;
+            self.writer.statistic_to_value_put(&Statistic::RuneTransactions, transactions);
         }
         Ok(())
     }
@@ -291,13 +367,14 @@ impl<'a> RuneUpdater<'a> {
         artifact: &Artifact,
         id: RuneId,
         rune: Rune,
+        commitment: Option<(OutPoint, ScriptBuf)>,
     ) -> Result {
-        self.runes_db.rune_to_rune_id_put(&rune, &id);
+        self.writer.rune_to_rune_id_put(&rune, &id);
 
         let number: u64 = self.runes as _;
         self.runes += 1;
 
-        self.runes_db.statistic_to_value_put(&Statistic::Runes, self.runes);
+        self.writer.statistic_to_value_put(&Statistic::Runes, self.runes);
 
         let entry = match artifact {
             Artifact::Cenotaph(_) => RuneEntry {
@@ -345,35 +422,19 @@ impl<'a> RuneUpdater<'a> {
             }
         };
 
-        self.runes_db.rune_id_to_rune_entry_put(&id, &entry);
-        info!("New RUNE: {}({}, {})", entry.spaced_rune, &id, number);
+        self.writer.rune_id_to_rune_entry_put(&id, &entry);
+        info!(rune = %entry.spaced_rune, id = %id, number, "New RUNE");
 
-        self.rune_entry_temp.insert(&id, RuneEntryForQueryInsert {
+        self.events.push(RuneEvent::Etching {
             rune_id: id.to_string(),
-            etching: entry.etching.to_string(),
-            number: entry.number,
-            rune: entry.spaced_rune.rune.to_string(),
             spaced_rune: entry.spaced_rune.to_string(),
-            symbol: entry.symbol.map(|s| s.to_string()),
-            divisibility: entry.divisibility,
-            premine: entry.premine.to_string(),
-            amount: entry.terms.and_then(|t| t.amount).map(|a| a.to_string()),
-            cap: entry.terms.and_then(|t| t.cap).map(|c| c.to_string()),
-            start_height: entry.terms.and_then(|t| t.height.0).map(|s| s as _),
-            end_height: entry.terms.and_then(|t| t.height.1).map(|e| e as _),
-            start_offset: entry.terms.and_then(|t| t.offset.0).map(|s| s as _),
-            end_offset: entry.terms.and_then(|t| t.offset.1).map(|e| e as _),
-            mints: entry.mints.to_string(),
-            turbo: entry.turbo,
-            burned: entry.burned.to_string(),
-            mintable: entry.mintable(self.latest_height as _).unwrap_or(0) > 0,
-            fairmint: entry.fairmint(),
-            holders: 0,
-            transactions: 0,
-            height: self.height,
-            ts: self.block_time,
+            txid: txid.to_string(),
         });
 
+        let etcher = commitment.as_ref().map(|(_, script)| self.script_address(script));
+        let commit_txid = commitment.map(|(outpoint, _)| outpoint.txid.to_string());
+        self.rune_entry_temp.insert(&id, RuneEntryForQueryInsert::new(&id, &entry, self.latest_height, etcher, commit_txid));
+
         Ok(())
     }
 
@@ -382,7 +443,7 @@ impl<'a> RuneUpdater<'a> {
         tx_index: u32,
         tx: &Transaction,
         artifact: &Artifact,
-    ) -> Result<Option<(RuneId, Rune)>> {
+    ) -> Result<Option<(RuneId, Rune, Option<(OutPoint, ScriptBuf)>)>> {
         let rune = match artifact {
             Artifact::Runestone(runestone) => match runestone.etching {
                 Some(etching) => etching.rune,
@@ -394,20 +455,21 @@ impl<'a> RuneUpdater<'a> {
             },
         };
 
-        let rune = if let Some(rune) = rune {
+        let (rune, commitment) = if let Some(rune) = rune {
             if rune < self.minimum
                 || rune.is_reserved()
-                || self.runes_db.rune_to_rune_id_get(&rune).is_some()
-                || !self.tx_commits_to_rune(tx, rune).await?
+                || self.writer.rune_to_rune_id_get(&rune).is_some()
             {
                 return Ok(None);
             }
-            rune
+            let Some(commitment) = self.rune_commitment(tx, rune).await? else {
+                return Ok(None);
+            };
+            (rune, Some(commitment))
         } else {
-            self
-                .runes_db.height_to_statistic_count_inc(&Statistic::ReservedRunes, self.height);
-            self.runes_db.statistic_to_value_inc(&Statistic::ReservedRunes);
-            Rune::reserved(self.height.into(), tx_index)
+            self.writer.height_to_statistic_count_inc(&Statistic::ReservedRunes, self.height);
+            self.writer.statistic_to_value_inc(&Statistic::ReservedRunes);
+            (Rune::reserved(self.height.into(), tx_index), None)
         };
 
         Ok(Some((
@@ -416,11 +478,12 @@ impl<'a> RuneUpdater<'a> {
                 tx: tx_index,
             },
             rune,
+            commitment,
         )))
     }
 
     fn mint(&mut self, txid: &Txid, id: RuneId) -> Result<Option<Lot>> {
-        let Some(entry) = self.runes_db.rune_id_to_rune_entry_get(&id) else {
+        let Some(entry) = self.writer.rune_id_to_rune_entry_get(&id) else {
             return Ok(None);
         };
 
@@ -430,14 +493,21 @@ impl<'a> RuneUpdater<'a> {
             return Ok(None);
         };
 
-        self.runes_db.rune_id_height_to_mints_inc(&id, self.height);
+        self.writer.height_rune_id_to_mints_inc(self.height, &id);
+        self.mints += 1;
 
-        rune_entry.mints = self.runes_db.rune_id_to_mints_inc(&id);
+        rune_entry.mints = self.writer.rune_id_to_mints_inc(&id);
 
-        self.runes_db.rune_id_to_rune_entry_put(&id, &rune_entry);
+        self.writer.rune_id_to_rune_entry_put(&id, &rune_entry);
 
         self.rune_balance_temp.insert_tx_op(txid.to_string(), RuneOpType::Mint);
 
+        self.events.push(RuneEvent::Mint {
+            rune_id: id.to_string(),
+            txid: txid.to_string(),
+            amount: amount.to_string(),
+        });
+
         self.rune_entry_temp.try_update(id, RuneEntryForUpdate {
             rune_id: id.to_string(),
             mints: rune_entry.mints.to_string(),
@@ -448,76 +518,122 @@ impl<'a> RuneUpdater<'a> {
         Ok(Some(Lot(amount)))
     }
 
-    async fn tx_commits_to_rune(&self, tx: &Transaction, rune: Rune) -> Result<bool> {
+    /// The matured taproot output `tx` spends to commit to `rune` and its
+    /// script, if any.
+    async fn rune_commitment(&self, tx: &Transaction, rune: Rune) -> Result<Option<(OutPoint, ScriptBuf)>> {
         let commitment = rune.commitment();
 
-        for input in &tx.input {
-            // extracting a tapscript does not indicate that the input being spent
-            // was actually a taproot output. this is checked below, when we load the
-            // output's entry from the database
-            let Some(tapscript) = input.witness.tapscript() else {
-                continue;
-            };
+        let candidates = tx.input.iter()
+            .filter(|input| Self::pushes_commitment(input, &commitment))
+            .map(|input| input.previous_output)
+            .collect::<Vec<_>>();
+        // fetched concurrently, but checked in input order
+        let commit_outputs = join_all(candidates.iter().map(|x| self.commit_output(*x))).await;
 
-            for instruction in tapscript.instructions() {
-                // ignore errors, since the extracted script may not be valid
-                let Ok(instruction) = instruction else {
-                    break;
-                };
+        for (outpoint, commit_output) in candidates.into_iter().zip(commit_outputs) {
+            let (height, script) = commit_output?;
 
-                let Some(pushbytes) = instruction.push_bytes() else {
-                    continue;
-                };
+            let taproot = script.is_p2tr();
 
-                if pushbytes.as_bytes() != commitment {
-                    continue;
-                }
-
-                let previus_txid = input.previous_output.txid;
-                let Some(tx_info) = with_retry(|| match self
-                    .client
-                    .get_raw_transaction_info(&previus_txid, None)
-                    .into_option() {
-                    Ok(v) => Ok(v),
-                    Err(e) => Err(e)
-                }, 5, Duration::from_millis(100)).await.unwrap()
-                else {
-                    panic!(
-                        "can't get input transaction: {}",
-                        previus_txid
-                    );
-                };
+            if !taproot {
+                continue;
+            }
 
+            let confirmations = self
+                .height
+                .checked_sub(height)
+                .unwrap()
+                + 1;
 
-                let taproot = tx_info.vout[input.previous_output.vout.into_usize()]
-                    .script_pub_key
-                    .script()?
-                    .is_p2tr();
+            if confirmations >= Runestone::COMMIT_CONFIRMATIONS.into() {
+                return Ok(Some((outpoint, script)));
+            }
+        }
 
-                if !taproot {
-                    continue;
-                }
+        Ok(None)
+    }
 
-                let commit_tx_height = self
-                    .client
-                    .get_block_header_info(&tx_info.blockhash.unwrap())
-                    .into_option()?
-                    .unwrap()
-                    .height;
+    fn pushes_commitment(input: &TxIn, commitment: &[u8]) -> bool {
+        // extracting a tapscript does not indicate that the input being spent
+        // was actually a taproot output. this is checked in rune_commitment,
+        // when we load the output's transaction
+        let Some(tapscript) = input.witness.tapscript() else {
+            return false;
+        };
 
-                let confirmations = self
-                    .height
-                    .checked_sub(commit_tx_height.try_into().unwrap())
-                    .unwrap()
-                    + 1;
+        for instruction in tapscript.instructions() {
+            // ignore errors, since the extracted script may not be valid
+            let Ok(instruction) = instruction else {
+                break;
+            };
 
-                if confirmations >= Runestone::COMMIT_CONFIRMATIONS.into() {
-                    return Ok(true);
-                }
+            if instruction.push_bytes().is_some_and(|x| x.as_bytes() == commitment) {
+                return true;
             }
         }
 
-        Ok(false)
+        false
+    }
+
+    fn script_address(&self, script: &Script) -> String {
+        script_address(script, self.chain)
+    }
+
+    /// Height and script of the output an etching input spends.
+    async fn commit_output(&self, outpoint: OutPoint) -> Result<(u32, ScriptBuf)> {
+        if let Some(prevouts) = self.prevouts {
+            let Some(prevout) = prevouts.get(&outpoint) else {
+                bail!("no prevout for input: {}", outpoint);
+            };
+            return Ok((prevout.height, prevout.script_pubkey.clone()));
+        }
+        let commit_tx = self.commit_tx(outpoint.txid).await?;
+        Ok((commit_tx.height, commit_tx.scripts[outpoint.vout.into_usize()].clone()))
+    }
+
+    async fn commit_tx(&self, txid: Txid) -> Result<CommitTx> {
+        let client = self.client;
+        self.commit_tx_cache
+            .try_get_with(txid, async move {
+                let Some(tx_info) = with_retry(
+                    || client.get_raw_transaction_info(txid),
+                    5,
+                    Duration::from_millis(100),
+                ).await?
+                else {
+                    bail!("can't get input transaction: {}", txid);
+                };
+
+                let blockhash = tx_info.blockhash
+                    .with_context(|| format!("input transaction {} is unconfirmed", txid))?;
+                let height = client
+                    .get_block_header_info(blockhash)
+                    .await?
+                    .with_context(|| format!("can't get block header: {}", blockhash))?
+                    .height;
+
+                Ok(CommitTx {
+                    height: height.try_into()?,
+                    scripts: tx_info.vout.iter().map(|x| x.script_pub_key.script()).collect::<Result<_, _>>()?,
+                })
+            })
+            .await
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record_edict(&mut self, txid: Txid, tx_index: u32, edict: usize, id: RuneId, amount: Lot, output: usize, allocated: Lot) {
+        self.rune_balance_temp.edicts.push(RuneEdictForInsert {
+            txid: txid.to_string(),
+            edict: edict as u32,
+            rune_id: id.to_string(),
+            amount: amount.n().to_string(),
+            allocated: allocated.n().to_string(),
+            output: output as u32,
+            height: self.height,
+            idx: tx_index,
+            ts: self.block_time,
+        });
     }
 
     fn unallocated(&mut self, txid: &Txid, tx: &Transaction) -> Result<HashMap<RuneId, Lot>> {
@@ -525,17 +641,26 @@ impl<'a> RuneUpdater<'a> {
         let mut unallocated: HashMap<RuneId, Lot> = HashMap::new();
 
         // increment unallocated runes with the runes in tx inputs
-        for (index, input) in tx.input.iter().enumerate() {
-            if let Some(mut entry) = self
-                .runes_db.outpoint_to_rune_balances_get(&input.previous_output)
-            {
+        let prevouts: Vec<OutPoint> = tx.input.iter().map(|input| input.previous_output).collect();
+        let entries = self.writer.outpoint_to_rune_balances_multi_get(&prevouts);
+        for ((index, input), entry) in tx.input.iter().enumerate().zip(entries) {
+            if let Some(mut entry) = entry {
                 let buffer = &entry.2;
-                let mut rune_ids = self.outpoint_to_rune_ids.entry(input.previous_output).or_default();
+                let script_hash = self.writer.outpoint_to_script_hash_get(&input.previous_output);
+                // outputs light mode doesn't index have no script hash, see above
+                let indexed = self.balance_scripts.is_none() || script_hash.is_some();
+                let mut spent = vec![];
                 let mut i = 0;
                 while i < buffer.len() {
                     let ((id, balance), len) = Self::decode_rune_balance(&buffer[i..]).unwrap();
                     i += len;
+                    spent.push((id, balance));
                     *unallocated.entry(id).or_default() += balance;
+                    if !indexed {
+                        continue;
+                    }
+                    self.outpoint_to_rune_ids.entry(input.previous_output).or_default().insert(id);
+                    self.writer.rune_id_to_outpoints_del(&id, &input.previous_output);
                     let key = RuneBalanceKey {
                         txid: input.previous_output.txid.to_string(),
                         vout: input.previous_output.vout,
@@ -550,12 +675,15 @@ impl<'a> RuneUpdater<'a> {
                         spent_height: self.height,
                         spent_ts: self.block_time,
                     });
-                    rune_ids.insert(id);
                 }
 
 
                 entry.1 = self.height;
-                self.runes_db.outpoint_to_rune_balances_put(&input.previous_output, entry);
+                self.writer.outpoint_to_rune_balances_put(&input.previous_output, entry);
+                self.writer.spk_to_outpoints_del(&input.previous_output);
+                if let Some((script_hash, _)) = script_hash {
+                    self.writer.script_hash_rune_id_to_balance_sub(&script_hash, &spent);
+                }
 
                 self.rune_balance_temp.insert_tx_op(txid.to_string(), RuneOpType::Transfer);
             }
@@ -571,6 +699,17 @@ impl<'a> RuneUpdater<'a> {
         varint::encode_to_vec(balance, buffer);
     }
 
+    pub fn decode_rune_balances(buffer: &[u8]) -> Result<Vec<(RuneId, u128)>> {
+        let mut balances = vec![];
+        let mut i = 0;
+        while i < buffer.len() {
+            let (balance, len) = Self::decode_rune_balance(&buffer[i..])?;
+            balances.push(balance);
+            i += len;
+        }
+        Ok(balances)
+    }
+
     pub fn decode_rune_balance(buffer: &[u8]) -> Result<((RuneId, u128), usize)> {
         let mut len = 0;
         let (block, block_len) = varint::decode(&buffer[len..])?;
@@ -587,10 +726,110 @@ impl<'a> RuneUpdater<'a> {
     }
 }
 
+/// The address of `script`, or its hex when it has none.
+pub fn script_address(script: &Script, chain: Chain) -> String {
+    match chain.address_from_script(script) {
+        Ok(v) => chain.format_address(&v),
+        Err(_) => script.to_bytes().encode_hex(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, TxOut};
+    use bitcoincore_rpc::{Auth, Client};
+    use rocksdb::IteratorMode;
+
+    use crate::cache::create_commit_tx_cache;
+    use crate::db::{test_header, RunesDB, HEIGHT_OUTPOINT_TO_RUNE_IDS};
+    use crate::settings::Settings;
+    use crate::test_util::TempDir;
     use crate::updater::RuneUpdater;
 
+    use super::*;
+
+    fn transfer(input: OutPoint, script_pubkey: &Script) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn { previous_output: input, ..Default::default() }],
+            output: vec![TxOut { value: Amount::from_sat(546), script_pubkey: script_pubkey.into() }],
+        }
+    }
+
+    #[tokio::test]
+    async fn light_mode_indexes_only_the_scripts() {
+        let path = TempDir::new("light-mode-indexes");
+        let db = RunesDB::new(&path);
+        let rune_id = RuneId { block: 100, tx: 1 };
+        let watched = ScriptBuf::from_hex("51").unwrap();
+        let held = OutPoint { txid: Txid::all_zeros(), vout: 0 };
+        {
+            let writer = db.block_writer();
+            let mut buffer = vec![];
+            RuneUpdater::encode_rune_balance(rune_id, 100, &mut buffer);
+            writer.outpoint_to_rune_balances_put(&held, (100, 0, buffer));
+            writer.commit(100, &test_header(100), &HashMap::new()).unwrap();
+        }
+
+        let client = AsyncClient::new(vec![Client::new("http://127.0.0.1:1", Auth::None).unwrap()]);
+        let commit_tx_cache = create_commit_tx_cache(&Settings::default());
+        let scripts = HashSet::from([watched.clone()]);
+        let index = |height: u32, tx: Transaction| {
+            let (db, client, commit_tx_cache, scripts) = (&db, &client, &commit_tx_cache, &scripts);
+            async move {
+                let writer = db.block_writer();
+                let mut outpoint_to_rune_ids = HashMap::new();
+                let mut rune_entry_temp = RuneEntryForTemp::default();
+                let mut rune_balance_temp = RuneBalanceForTemp::default();
+                let mut updater = RuneUpdater {
+                    block_time: 0,
+                    burned: HashMap::new(),
+                    events: vec![],
+                    client,
+                    commit_tx_cache,
+                    prevouts: None,
+                    balance_scripts: Some(scripts),
+                    height,
+                    latest_height: height,
+                    chain: Chain::Regtest,
+                    minimum: Rune(0),
+                    runes: 0,
+                    runestones: 0,
+                    transactions: 0,
+                    mints: 0,
+                    writer: &writer,
+                    outpoint_to_rune_ids: &mut outpoint_to_rune_ids,
+                    rune_entry_temp: &mut rune_entry_temp,
+                    rune_balance_temp: &mut rune_balance_temp,
+                };
+                updater.index_runes(1, &tx).await.unwrap();
+                writer.commit(height, &test_header(height), &outpoint_to_rune_ids).unwrap();
+                (outpoint_to_rune_ids, rune_balance_temp.inserts.len())
+            }
+        };
+
+        // from an output of another script to another one, only the balance is kept
+        let other = transfer(held, &ScriptBuf::from_hex("52").unwrap());
+        let unmatched = OutPoint { txid: other.txid(), vout: 0 };
+        let (outpoint_to_rune_ids, rows) = index(101, other).await;
+        assert!(outpoint_to_rune_ids.is_empty());
+        assert_eq!(rows, 0);
+        assert_eq!(db.outpoint_to_rune_balances_get(&unmatched).map(|x| x.0), Some(101));
+        assert!(db.rune_id_to_outpoints_list(&rune_id).unwrap().is_empty());
+        assert!(db.rocksdb.iterator_cf(db.get_cf(HEIGHT_OUTPOINT_TO_RUNE_IDS), IteratorMode::Start).next().is_none());
+
+        let to_watched = transfer(unmatched, &watched);
+        let matched = OutPoint { txid: to_watched.txid(), vout: 0 };
+        let (outpoint_to_rune_ids, rows) = index(102, to_watched).await;
+        assert_eq!(outpoint_to_rune_ids, HashMap::from([(matched, HashSet::from([rune_id]))]));
+        assert_eq!(rows, 1);
+        assert_eq!(db.rune_id_to_outpoints_list(&rune_id).unwrap(), vec![(matched, 100)]);
+    }
+
     #[test]
     fn test_combine_vec() {
         let original_vec: Vec<u8> = vec![1, 2, 3, 4];
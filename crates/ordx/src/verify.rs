@@ -0,0 +1,344 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use anyhow::bail;
+use bitcoin::{BlockHash, OutPoint};
+use bitcoincore_rpc::{Client, RpcApi};
+use log::{info, warn};
+use rocksdb::IteratorMode;
+use rusqlite::params;
+
+use ordinals::RuneId;
+
+use crate::db::key::Key;
+use crate::db::{RunesDB, Store, HEIGHT_TO_BLOCK_HEADER, OUTPOINT_TO_RUNE_BALANCES, RUNE_ID_TO_RUNE_ENTRY};
+use crate::entry::{EntryBytes, RuneBalanceEntry, RuneEntry, Statistic};
+use crate::updater::{RuneUpdater, REORG_DEPTH};
+
+pub const USAGE: &str = "Usage: ordx verify [--repair] [--skip-balances] [--skip-entries] [--skip-blocks] [--depth <blocks>]";
+
+/// Options of the `verify` subcommand. Must not run while the indexer is running,
+/// rocksdb refuses a second process anyway.
+#[derive(Debug, Default, PartialEq)]
+pub struct VerifyOptions {
+    pub repair: bool,
+    pub skip_balances: bool,
+    pub skip_entries: bool,
+    pub skip_blocks: bool,
+    /// only compare the latest `depth` block hashes, all indexed blocks if none
+    pub depth: Option<u32>,
+}
+
+impl VerifyOptions {
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut options = VerifyOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--repair" => options.repair = true,
+                "--skip-balances" => options.skip_balances = true,
+                "--skip-entries" => options.skip_entries = true,
+                "--skip-blocks" => options.skip_blocks = true,
+                "--depth" => {
+                    let Some(depth) = args.next() else {
+                        bail!("--depth requires a value\n{USAGE}");
+                    };
+                    options.depth = Some(depth.parse()?);
+                }
+                _ => bail!("unknown argument `{arg}`\n{USAGE}"),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BalanceState {
+    pub amount: u128,
+    pub height: u32,
+    pub spent_height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    BalanceMissingInSqlite { outpoint: OutPoint, rune_id: RuneId },
+    BalanceMissingInRocksdb { outpoint: String, rune_id: String },
+    Balance { outpoint: OutPoint, rune_id: RuneId, rocksdb: BalanceState, sqlite: BalanceState },
+    EntryMissingInSqlite { rune_id: RuneId },
+    EntryMissingInRocksdb { rune_id: String },
+    EntryCounter { rune_id: RuneId, field: &'static str, rocksdb: u128, other: String },
+    BlockHash { height: u32, indexed: BlockHash, bitcoind: BlockHash },
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Divergence::BalanceMissingInSqlite { outpoint, rune_id } =>
+                write!(f, "balance {} {} missing in sqlite", outpoint, rune_id),
+            Divergence::BalanceMissingInRocksdb { outpoint, rune_id } =>
+                write!(f, "balance {} {} missing in rocksdb", outpoint, rune_id),
+            Divergence::Balance { outpoint, rune_id, rocksdb, sqlite } =>
+                write!(f, "balance {} {} differs, rocksdb: {:?}, sqlite: {:?}", outpoint, rune_id, rocksdb, sqlite),
+            Divergence::EntryMissingInSqlite { rune_id } =>
+                write!(f, "rune entry {} missing in sqlite", rune_id),
+            Divergence::EntryMissingInRocksdb { rune_id } =>
+                write!(f, "rune entry {} missing in rocksdb", rune_id),
+            Divergence::EntryCounter { rune_id, field, rocksdb, other } =>
+                write!(f, "rune entry {} {} differs, rocksdb: {}, {}", rune_id, field, rocksdb, other),
+            Divergence::BlockHash { height, indexed, bitcoind } =>
+                write!(f, "block {} hash differs, indexed: {}, bitcoind: {}", height, indexed, bitcoind),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub divergences: Vec<Divergence>,
+    pub repaired: usize,
+}
+
+impl VerifyReport {
+    /// Balances or entries missing in sqlite lack the address and value and
+    /// can't be repaired without reindexing, same for blocks beyond the reorg window.
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.len() == self.repaired
+    }
+}
+
+pub fn run(runes_db: &RunesDB, client: &Client, options: &VerifyOptions) -> anyhow::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    if !options.skip_blocks {
+        verify_block_hashes(runes_db, client, options, &mut report)?;
+    }
+    if !options.skip_entries {
+        verify_rune_entries(runes_db, options, &mut report)?;
+    }
+    if !options.skip_balances {
+        verify_rune_balances(runes_db, options, &mut report)?;
+    }
+    for divergence in &report.divergences {
+        warn!("{}", divergence);
+    }
+    info!("Verify done, divergences: {}, repaired: {}", report.divergences.len(), report.repaired);
+    Ok(report)
+}
+
+fn verify_block_hashes(runes_db: &RunesDB, client: &Client, options: &VerifyOptions, report: &mut VerifyReport) -> anyhow::Result<()> {
+    info!("Verifying block hashes against bitcoind ...");
+    let cf = runes_db.get_cf(HEIGHT_TO_BLOCK_HEADER);
+    let mut lowest_mismatch = None;
+    for (checked, x) in runes_db.rocksdb.iterator_cf(cf, IteratorMode::End).enumerate() {
+        if options.depth.is_some_and(|depth| checked as u32 >= depth) {
+            break;
+        }
+        let (k, v) = x?;
+        let height = u32::decode(&k)?;
+        let indexed = bitcoin::block::Header::load_bytes(&v).block_hash();
+        let bitcoind = client.get_block_hash(height.into())?;
+        if indexed != bitcoind {
+            lowest_mismatch = Some(height);
+            report.divergences.push(Divergence::BlockHash { height, indexed, bitcoind });
+        }
+    }
+    let (Some(height), true) = (lowest_mismatch, options.repair) else {
+        return Ok(());
+    };
+    let indexed_height = runes_db.latest_indexed_height().unwrap_or_default();
+    // spent outpoints can only be restored within the reorg window
+    if indexed_height - height >= REORG_DEPTH {
+        warn!("Block {} is deeper than the reorg window, reindex required", height);
+        return Ok(());
+    }
+    let latest_height = runes_db.latest_height().unwrap_or(indexed_height);
+    runes_db.reorg_to_height(height, latest_height)?;
+    report.repaired += report.divergences.iter().filter(|x| matches!(x, Divergence::BlockHash { .. })).count();
+    Ok(())
+}
+
+fn verify_rune_entries(runes_db: &RunesDB, options: &VerifyOptions, report: &mut VerifyReport) -> anyhow::Result<()> {
+    info!("Verifying rune entries ...");
+    let mut conn = runes_db.sqlite.get()?;
+    let mut sqlite_entries = HashMap::new();
+    {
+        let mut stmt = conn.prepare("SELECT rune_id, mints, burned FROM rune_entry")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, (row.get::<_, String>(1)?, row.get::<_, String>(2)?)))
+        })?;
+        for row in rows {
+            let (rune_id, counters) = row?;
+            sqlite_entries.insert(rune_id, counters);
+        }
+    }
+
+    let mut divergences = Vec::new();
+    let cf = runes_db.get_cf(RUNE_ID_TO_RUNE_ENTRY);
+    for x in runes_db.rocksdb.iterator_cf(cf, IteratorMode::Start) {
+        let (k, v) = x?;
+        let rune_id = RuneId::decode(&k)?;
+        let entry = RuneEntry::load_bytes(&v);
+        let mints = runes_db.rune_id_to_mints_get(&rune_id).unwrap_or_default();
+        if mints != entry.mints {
+            divergences.push(Divergence::EntryCounter { rune_id, field: "mints", rocksdb: entry.mints, other: format!("RUNE_ID_TO_MINTS: {}", mints) });
+        }
+        let burned = runes_db.rune_id_to_burned_get(&rune_id).unwrap_or_default();
+        if burned != entry.burned {
+            divergences.push(Divergence::EntryCounter { rune_id, field: "burned", rocksdb: entry.burned, other: format!("RUNE_ID_TO_BURNED: {}", burned) });
+        }
+        // every minted rune is either burned or held by an unspent output
+        let circulating = entry.supply().saturating_sub(entry.burned);
+        let unspent = runes_db.rune_id_to_outpoints_supply(&rune_id)?;
+        if unspent != circulating {
+            divergences.push(Divergence::EntryCounter { rune_id, field: "supply", rocksdb: circulating, other: format!("RUNE_ID_TO_OUTPOINTS: {}", unspent) });
+        }
+        let Some((sqlite_mints, sqlite_burned)) = sqlite_entries.remove(&rune_id.to_string()) else {
+            divergences.push(Divergence::EntryMissingInSqlite { rune_id });
+            continue;
+        };
+        if sqlite_mints != entry.mints.to_string() {
+            divergences.push(Divergence::EntryCounter { rune_id, field: "mints", rocksdb: entry.mints, other: format!("sqlite: {}", sqlite_mints) });
+        }
+        if sqlite_burned != entry.burned.to_string() {
+            divergences.push(Divergence::EntryCounter { rune_id, field: "burned", rocksdb: entry.burned, other: format!("sqlite: {}", sqlite_burned) });
+        }
+    }
+    divergences.extend(sqlite_entries.into_keys().map(|rune_id| Divergence::EntryMissingInRocksdb { rune_id }));
+
+    if options.repair {
+        let tx = conn.transaction()?;
+        for divergence in &divergences {
+            match divergence {
+                Divergence::EntryCounter { rune_id, field: "mints", rocksdb, .. } => {
+                    runes_db.rune_id_to_mints_put(rune_id, *rocksdb);
+                    tx.execute("UPDATE rune_entry SET mints = ? WHERE rune_id = ?", params![rocksdb.to_string(), rune_id.to_string()])?;
+                }
+                Divergence::EntryCounter { rune_id, field: "burned", rocksdb, .. } => {
+                    runes_db.rune_id_to_burned_put(rune_id, *rocksdb);
+                    tx.execute("UPDATE rune_entry SET burned = ? WHERE rune_id = ?", params![rocksdb.to_string(), rune_id.to_string()])?;
+                }
+                Divergence::EntryMissingInRocksdb { rune_id } => {
+                    tx.execute("DELETE FROM rune_entry WHERE rune_id = ?", params![rune_id])?;
+                }
+                _ => continue,
+            }
+            report.repaired += 1;
+        }
+        tx.commit()?;
+    }
+    report.divergences.extend(divergences);
+    Ok(())
+}
+
+fn verify_rune_balances(runes_db: &RunesDB, options: &VerifyOptions, report: &mut VerifyReport) -> anyhow::Result<()> {
+    info!("Verifying rune balances ...");
+    let mut conn = runes_db.sqlite.get()?;
+    let mut sqlite_balances = HashMap::new();
+    {
+        let mut stmt = conn.prepare("SELECT txid, vout, rune_id, rune_amount, height, spent_height FROM rune_balance")?;
+        let rows = stmt.query_map([], |row| {
+            let key = (row.get::<_, String>(0)?, row.get::<_, u32>(1)?, row.get::<_, String>(2)?);
+            Ok((key, (row.get::<_, String>(3)?, row.get::<_, u32>(4)?, row.get::<_, u32>(5)?)))
+        })?;
+        for row in rows {
+            let (key, (amount, height, spent_height)) = row?;
+            let state = BalanceState { amount: amount.parse()?, height, spent_height };
+            sqlite_balances.insert(key, state);
+        }
+    }
+    info!("Loaded {} rune balances from sqlite", sqlite_balances.len());
+
+    let mut divergences = Vec::new();
+    let cf = runes_db.get_cf(OUTPOINT_TO_RUNE_BALANCES);
+    for x in runes_db.rocksdb.iterator_cf(cf, IteratorMode::Start) {
+        let (k, v) = x?;
+        let outpoint = OutPoint::decode(&k)?;
+        let (height, spent_height, buffer) = RuneBalanceEntry::load_bytes(&v);
+        for (rune_id, amount) in decode_balances(&buffer)? {
+            let rocksdb = BalanceState { amount, height, spent_height };
+            let key = (outpoint.txid.to_string(), outpoint.vout, rune_id.to_string());
+            match sqlite_balances.remove(&key) {
+                None => divergences.push(Divergence::BalanceMissingInSqlite { outpoint, rune_id }),
+                Some(sqlite) if sqlite != rocksdb => divergences.push(Divergence::Balance { outpoint, rune_id, rocksdb, sqlite }),
+                Some(_) => {}
+            }
+        }
+    }
+    divergences.extend(sqlite_balances.into_keys().map(|(txid, vout, rune_id)| Divergence::BalanceMissingInRocksdb {
+        outpoint: format!("{}:{}", txid, vout),
+        rune_id,
+    }));
+
+    if options.repair {
+        let mut repaired_rune_ids = HashSet::new();
+        let tx = conn.transaction()?;
+        for divergence in &divergences {
+            match divergence {
+                Divergence::Balance { outpoint, rune_id, rocksdb, .. } => {
+                    repaired_rune_ids.insert(rune_id.to_string());
+                    let params = params![rocksdb.amount.to_string(), rocksdb.height, rocksdb.spent_height, outpoint.txid.to_string(), outpoint.vout, rune_id.to_string()];
+                    if rocksdb.spent_height == 0 {
+                        tx.execute("UPDATE rune_balance SET rune_amount = ?, height = ?, spent_height = ?, spent_txid = null, spent_vin = null, spent_ts = null WHERE txid = ? AND vout = ? AND rune_id = ?", params)?;
+                    } else {
+                        tx.execute("UPDATE rune_balance SET rune_amount = ?, height = ?, spent_height = ? WHERE txid = ? AND vout = ? AND rune_id = ?", params)?;
+                    }
+                }
+                Divergence::BalanceMissingInRocksdb { outpoint, rune_id } => {
+                    repaired_rune_ids.insert(rune_id.clone());
+                    let (txid, vout) = outpoint.split_once(':').unwrap();
+                    tx.execute("DELETE FROM rune_balance WHERE txid = ? AND vout = ? AND rune_id = ?", params![txid, vout.parse::<u32>()?, rune_id])?;
+                }
+                _ => continue,
+            }
+            report.repaired += 1;
+        }
+        tx.commit()?;
+        // the incremental holders and transactions of these runes followed the diverged rows
+        if !repaired_rune_ids.is_empty() {
+            runes_db.sqlite_rune_entry_recount(&repaired_rune_ids.into_iter().collect::<Vec<_>>())?;
+            runes_db.statistic_to_value_put(&Statistic::Holders, runes_db.sqlite_rune_entry_holders_sum()?);
+        }
+    }
+    report.divergences.extend(divergences);
+    Ok(())
+}
+
+fn decode_balances(buffer: &[u8]) -> anyhow::Result<Vec<(RuneId, u128)>> {
+    let mut balances = Vec::new();
+    let mut i = 0;
+    while i < buffer.len() {
+        let (balance, len) = RuneUpdater::decode_rune_balance(&buffer[i..])?;
+        balances.push(balance);
+        i += len;
+    }
+    Ok(balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(x: &str) -> Vec<String> {
+        x.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(VerifyOptions::parse(&[]).unwrap(), VerifyOptions::default());
+        assert_eq!(
+            VerifyOptions::parse(&args("--repair --skip-balances --depth 100")).unwrap(),
+            VerifyOptions { repair: true, skip_balances: true, depth: Some(100), ..Default::default() }
+        );
+        assert!(VerifyOptions::parse(&args("--depth")).is_err());
+        assert!(VerifyOptions::parse(&args("--fix")).is_err());
+    }
+
+    #[test]
+    fn decode_multiple_balances() {
+        let mut buffer = Vec::new();
+        RuneUpdater::encode_rune_balance(RuneId { block: 840000, tx: 1 }, 21, &mut buffer);
+        RuneUpdater::encode_rune_balance(RuneId { block: 1, tx: 0 }, u128::MAX, &mut buffer);
+        assert_eq!(
+            decode_balances(&buffer).unwrap(),
+            vec![(RuneId { block: 840000, tx: 1 }, 21), (RuneId { block: 1, tx: 0 }, u128::MAX)]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use bitcoin::{ScriptBuf, Transaction};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::api::handler::decode_runes_tx;
+use crate::chain::Chain;
+use crate::db::{RunesDB, Store};
+use crate::webhook::{transfer_events, RuneEvent, TransferDirection};
+
+/// Notifications a slow stream subscriber may fall behind by before it skips
+/// ahead.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A balance change of a watched address, sent to the push streams.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchNotification {
+    /// Unset for mempool transactions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    pub event: RuneEvent,
+}
+
+impl WatchNotification {
+    pub fn address(&self) -> Option<&str> {
+        match &self.event {
+            RuneEvent::Transfer { address, .. } | RuneEvent::Unconfirmed { address, .. } => Some(address),
+            _ => None,
+        }
+    }
+}
+
+/// Fans balance changes of addresses on the watch list out to the push
+/// streams. Webhooks of a watch are queued by [`crate::webhook::Webhooks`].
+pub struct Watches {
+    runes_db: Arc<RunesDB>,
+    chain: Chain,
+    sender: broadcast::Sender<WatchNotification>,
+}
+
+impl Watches {
+    pub fn new(runes_db: Arc<RunesDB>, chain: Chain) -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Watches { runes_db, chain, sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WatchNotification> {
+        self.sender.subscribe()
+    }
+
+    /// Watched addresses by their script pubkey.
+    fn watched(&self) -> anyhow::Result<HashMap<ScriptBuf, String>> {
+        let addresses = self.runes_db.sqlite_watch_addresses(None)?;
+        Ok(addresses.into_iter()
+            .filter_map(|x| Some((self.chain.parse_address(&x).ok()?.assume_checked().script_pubkey(), x)))
+            .collect())
+    }
+
+    /// Sends the transfers of watched addresses in a block whose sqlite rows
+    /// are committed.
+    pub fn notify_block(&self, height: u32) -> anyhow::Result<()> {
+        if self.sender.receiver_count() == 0 {
+            return Ok(());
+        }
+        let watched = self.watched()?;
+        if watched.is_empty() {
+            return Ok(());
+        }
+        let addresses = watched.values().collect::<HashSet<_>>();
+        for event in transfer_events(&self.runes_db, height)? {
+            if let RuneEvent::Transfer { address, .. } = &event {
+                if addresses.contains(address) {
+                    let _ = self.sender.send(WatchNotification { height: Some(height), event });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rune balances new mempool transactions move to or from watched
+    /// addresses.
+    pub fn unconfirmed_events(&self, txs: &[&Transaction]) -> anyhow::Result<Vec<RuneEvent>> {
+        let watched = self.watched()?;
+        if watched.is_empty() || txs.is_empty() {
+            return Ok(vec![]);
+        }
+        let addresses = watched.values().collect::<HashSet<_>>();
+        let mut events = vec![];
+        for tx in txs {
+            let txid = tx.txid().to_string();
+            for input in &tx.input {
+                let prevout = input.previous_output;
+                if self.runes_db.outpoint_to_rune_balances_get(&prevout).is_none() {
+                    continue;
+                }
+                for x in self.runes_db.sqlite_rune_balance_list_by_txid(&prevout.txid.to_string())? {
+                    if x.txid != prevout.txid.to_string() || x.vout != prevout.vout || !addresses.contains(&x.address) {
+                        continue;
+                    }
+                    events.push(RuneEvent::Unconfirmed {
+                        address: x.address,
+                        direction: TransferDirection::Sent,
+                        rune_id: x.rune_id,
+                        txid: txid.clone(),
+                        amount: x.rune_amount,
+                    });
+                }
+            }
+            if !tx.output.iter().any(|x| watched.contains_key(&x.script_pubkey)) {
+                continue;
+            }
+            let decoded = decode_runes_tx(&self.runes_db, (*tx).clone())?;
+            for (vout, balances) in decoded.outputs {
+                let Some(address) = tx.output.get(vout).and_then(|x| watched.get(&x.script_pubkey)) else {
+                    continue;
+                };
+                for (id, lot) in balances {
+                    events.push(RuneEvent::Unconfirmed {
+                        address: address.clone(),
+                        direction: TransferDirection::Received,
+                        rune_id: id.to_string(),
+                        txid: txid.clone(),
+                        amount: lot.n().to_string(),
+                    });
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn notify_mempool(&self, events: &[RuneEvent]) {
+        for event in events.iter().filter(|x| matches!(x, RuneEvent::Unconfirmed { .. })) {
+            let _ = self.sender.send(WatchNotification { height: None, event: event.clone() });
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::bail;
+use axum::body::Bytes;
+use axum::http::{header, Request, StatusCode, Uri};
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use bitcoin::BlockHash;
+use futures_util::stream::{self, TryStreamExt};
+use http_body_util::Full;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use itertools::Itertools;
+use log::{info, warn};
+use rusqlite::Connection;
+use serde::Serialize;
+use tokio::net::lookup_host;
+use tokio::sync::{watch, Notify};
+
+use crate::api::auth::unix_timestamp;
+use crate::db::model::{WebhookDeliveryForInsert, WebhookDeliveryForQuery, WebhookForQuery};
+use crate::db::writer::SqliteWriter;
+use crate::db::RunesDB;
+use crate::settings::Settings;
+
+pub const X_ORDX_SIGNATURE: &str = "x-ordx-signature";
+pub const X_ORDX_DELIVERY: &str = "x-ordx-delivery";
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RETRY_DELAY_SECS: u64 = 60 * 60;
+const DELIVERY_BATCH_SIZE: u32 = 100;
+/// Endpoints sent to at the same time.
+const DELIVERY_CONCURRENCY: usize = 16;
+
+type HttpClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// What a webhook subscribes to, stored as text in `webhook.event`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookFilter {
+    Etching,
+    Mint,
+    Burn,
+    Transfer(String),
+    /// Pending rune transactions replaced or evicted from the mempool.
+    Mempool,
+    /// Confirmed and unconfirmed balance changes of an address on the watch
+    /// list.
+    Watch(String),
+}
+
+impl Display for WebhookFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Etching => write!(f, "etching"),
+            Self::Mint => write!(f, "mint"),
+            Self::Burn => write!(f, "burn"),
+            Self::Transfer(address) => write!(f, "transfer:{}", address),
+            Self::Mempool => write!(f, "mempool"),
+            Self::Watch(address) => write!(f, "watch:{}", address),
+        }
+    }
+}
+
+impl FromStr for WebhookFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "etching" => Ok(Self::Etching),
+            "mint" => Ok(Self::Mint),
+            "burn" => Ok(Self::Burn),
+            "mempool" => Ok(Self::Mempool),
+            _ => match s.split_once(':') {
+                Some(("transfer", address)) if !address.is_empty() => Ok(Self::Transfer(address.to_string())),
+                Some(("watch", address)) if !address.is_empty() => Ok(Self::Watch(address.to_string())),
+                _ => bail!("invalid webhook event `{s}`"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    Received,
+    Sent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RuneEvent {
+    Etching {
+        rune_id: String,
+        spaced_rune: String,
+        txid: String,
+    },
+    Mint {
+        rune_id: String,
+        txid: String,
+        amount: String,
+    },
+    Burn {
+        rune_id: String,
+        txid: String,
+        amount: String,
+    },
+    Transfer {
+        address: String,
+        direction: TransferDirection,
+        rune_id: String,
+        txid: String,
+        vout: u32,
+        amount: String,
+    },
+    Replaced {
+        txid: String,
+        replaced_by: String,
+    },
+    Evicted {
+        txid: String,
+    },
+    /// A mempool transaction paying runes to, or spending runes of, a watched
+    /// address.
+    Unconfirmed {
+        address: String,
+        direction: TransferDirection,
+        rune_id: String,
+        txid: String,
+        amount: String,
+    },
+    /// Undoes every earlier event of the same block, only in the event log.
+    Revert,
+}
+
+impl RuneEvent {
+    pub fn matches(&self, filter: &WebhookFilter) -> bool {
+        match (self, filter) {
+            (Self::Etching { .. }, WebhookFilter::Etching) => true,
+            (Self::Mint { .. }, WebhookFilter::Mint) => true,
+            (Self::Burn { .. }, WebhookFilter::Burn) => true,
+            (Self::Transfer { address, .. }, WebhookFilter::Transfer(subscribed)) => address == subscribed,
+            (Self::Replaced { .. } | Self::Evicted { .. }, WebhookFilter::Mempool) => true,
+            (Self::Transfer { address, .. } | Self::Unconfirmed { address, .. }, WebhookFilter::Watch(watched)) => address == watched,
+            _ => false,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Etching { .. } => "etching",
+            Self::Mint { .. } => "mint",
+            Self::Burn { .. } => "burn",
+            Self::Transfer { .. } => "transfer",
+            Self::Replaced { .. } => "replaced",
+            Self::Evicted { .. } => "evicted",
+            Self::Unconfirmed { .. } => "unconfirmed",
+            Self::Revert => "revert",
+        }
+    }
+}
+
+/// Rune outputs created and spent in a block whose sqlite rows are committed.
+pub fn transfer_events(runes_db: &RunesDB, height: u32) -> anyhow::Result<Vec<RuneEvent>> {
+    let conn = runes_db.sqlite.get()?;
+    transfer_events_in(runes_db, &conn, height)
+}
+
+/// [`transfer_events`] as seen by `conn`, e.g. the transaction writing the block.
+pub fn transfer_events_in(runes_db: &RunesDB, conn: &Connection, height: u32) -> anyhow::Result<Vec<RuneEvent>> {
+    let mut events = vec![];
+    for (direction, spent) in [(TransferDirection::Received, false), (TransferDirection::Sent, true)] {
+        for x in runes_db.sqlite_address_transfers_at_height(conn, height, spent)? {
+            events.push(RuneEvent::Transfer {
+                address: x.address,
+                direction,
+                rune_id: x.rune_id,
+                txid: if spent { x.spent_txid.unwrap_or_default() } else { x.txid },
+                vout: x.vout,
+                amount: x.rune_amount,
+            });
+        }
+    }
+    Ok(events)
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    webhook_id: u32,
+    event: &'a str,
+    /// Unset for mempool events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_hash: Option<String>,
+    events: Vec<&'a RuneEvent>,
+}
+
+/// `sha256=<hex>` HMAC of the request body with the webhook secret.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(body);
+    let mac = hmac::Hmac::<sha256::Hash>::from_engine(engine);
+    format!("sha256={}", hex::encode(mac.to_byte_array()))
+}
+
+/// Webhooks are delivered over https, or plain http e.g. to a local receiver.
+pub fn validate_url(url: &str) -> anyhow::Result<()> {
+    let uri = url.parse::<Uri>()?;
+    if !matches!(uri.scheme_str(), Some("https" | "http")) || uri.host().is_none() {
+        bail!("webhook url must be an absolute https:// or http:// url");
+    }
+    Ok(())
+}
+
+/// [`validate_url`] for webhooks registered without an admin key, which
+/// mustn't reach the loopback, private or link-local addresses around the
+/// indexer. A host name is checked against every address it resolves to.
+pub async fn validate_public_url(url: &str) -> anyhow::Result<()> {
+    validate_url(url)?;
+    let uri = url.parse::<Uri>()?;
+    let host = uri.host().unwrap_or_default().trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+    if host == "localhost" || host.ends_with(".localhost") {
+        bail!("webhook url must not point to a local address");
+    }
+    let addresses = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![ip],
+        Err(_) => {
+            let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+            lookup_host((host.as_str(), port)).await?.map(|x| x.ip()).collect()
+        }
+    };
+    if addresses.is_empty() || !addresses.iter().all(is_public_ip) {
+        bail!("webhook url must not point to a local address");
+    }
+    Ok(())
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            // 100.64.0.0/10 is shared address space, e.g. carrier-grade NAT
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()
+                || ip.is_multicast() || ip.is_documentation() || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(&IpAddr::V4(ip)),
+            // fc00::/7 is unique local, fe80::/10 link-local
+            None => !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+                || ip.segments()[0] & 0xfe00 == 0xfc00 || ip.segments()[0] & 0xffc0 == 0xfe80),
+        },
+    }
+}
+
+/// One delivery per webhook with matching events, `block` is unset for mempool
+/// events.
+pub fn deliveries(webhooks: &[WebhookForQuery], block: Option<(u32, &BlockHash)>, events: &[RuneEvent]) -> anyhow::Result<Vec<WebhookDeliveryForInsert>> {
+    let height = block.map(|x| x.0);
+    let now = unix_timestamp();
+    let mut deliveries = vec![];
+    for webhook in webhooks {
+        let Ok(filter) = webhook.event.parse::<WebhookFilter>() else {
+            continue;
+        };
+        let matched: Vec<&RuneEvent> = events.iter().filter(|x| x.matches(&filter)).collect();
+        if matched.is_empty() {
+            continue;
+        }
+        let payload = WebhookPayload {
+            webhook_id: webhook.id,
+            event: &webhook.event,
+            height,
+            block_hash: block.map(|x| x.1.to_string()),
+            events: matched,
+        };
+        deliveries.push(WebhookDeliveryForInsert {
+            webhook_id: webhook.id,
+            height: height.unwrap_or_default(),
+            payload: serde_json::to_string(&payload)?,
+            created_ts: now,
+        });
+    }
+    Ok(deliveries)
+}
+
+fn retry_delay_secs(base: u64, attempts: u32) -> u64 {
+    base.saturating_mul(1 << attempts.saturating_sub(1).min(16)).min(MAX_RETRY_DELAY_SECS)
+}
+
+pub struct Webhooks {
+    runes_db: Arc<RunesDB>,
+    writer: Arc<SqliteWriter>,
+    notify: Notify,
+    max_attempts: u32,
+    retry_base_secs: u64,
+}
+
+impl Webhooks {
+    pub fn new(settings: &Settings, runes_db: Arc<RunesDB>, writer: Arc<SqliteWriter>) -> Self {
+        Webhooks {
+            runes_db,
+            writer,
+            notify: Notify::new(),
+            max_attempts: settings.webhook_max_attempts,
+            retry_base_secs: settings.webhook_retry_base_secs,
+        }
+    }
+
+    /// Queues deliveries for mempool events, which belong to no block.
+    pub async fn publish_mempool(&self, events: Vec<RuneEvent>) -> anyhow::Result<()> {
+        let webhooks = self.runes_db.sqlite_webhook_list()?;
+        if webhooks.is_empty() {
+            return Ok(());
+        }
+        let deliveries = deliveries(&webhooks, None, &events)?;
+        if !deliveries.is_empty() {
+            let count = deliveries.len();
+            self.writer.call(move |db| db.sqlite_webhook_delivery_insert_batch(&deliveries)).await?;
+            self.queued(count, None);
+        }
+        Ok(())
+    }
+
+    /// Wakes the sender for deliveries queued in sqlite, block deliveries are
+    /// queued by [`RunesDB::to_sqlite`] with the block.
+    pub fn queued(&self, count: usize, height: Option<u32>) {
+        info!("Queued {} webhook deliveries for height {:?}", count, height);
+        self.notify.notify_one();
+    }
+
+    /// Sends due deliveries until shutdown. Pending deliveries live in sqlite and
+    /// are picked up again after a restart.
+    pub async fn run(self: Arc<Self>, mut shutdown: watch::Receiver<bool>) {
+        // server certificates are checked against the bundled Mozilla roots
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let client: HttpClient = Client::builder(TokioExecutor::new()).build(connector);
+        loop {
+            if let Err(e) = self.deliver_due(&client).await {
+                warn!("Webhook delivery failed: {}", e);
+            }
+            tokio::select! {
+                _ = self.notify.notified() => {}
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                _ = shutdown.changed() => {}
+            }
+            if *shutdown.borrow() {
+                break;
+            }
+        }
+    }
+
+    async fn deliver_due(&self, client: &HttpClient) -> anyhow::Result<()> {
+        let due = self.runes_db.sqlite_webhook_delivery_due(unix_timestamp(), DELIVERY_BATCH_SIZE)?;
+        if due.is_empty() {
+            return Ok(());
+        }
+        let webhooks: HashMap<u32, WebhookForQuery> = self.runes_db.sqlite_webhook_list()?
+            .into_iter()
+            .map(|x| (x.id, x))
+            .collect();
+        // endpoints are sent to concurrently, each one gets its deliveries in order
+        let by_webhook = due.into_iter().into_group_map_by(|x| x.webhook_id);
+        stream::iter(by_webhook.into_values().map(anyhow::Ok))
+            .try_for_each_concurrent(DELIVERY_CONCURRENCY, |deliveries| async {
+                for mut delivery in deliveries {
+                    match webhooks.get(&delivery.webhook_id) {
+                        Some(webhook) => {
+                            let result = send(client, webhook, &delivery).await;
+                            self.record_attempt(&mut delivery, result);
+                        }
+                        None => {
+                            delivery.failed = true;
+                            delivery.error = Some("webhook deleted".to_string());
+                            delivery.updated_ts = unix_timestamp();
+                        }
+                    }
+                    self.writer.call(move |db| db.sqlite_webhook_delivery_update(&delivery)).await?;
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    fn record_attempt(&self, delivery: &mut WebhookDeliveryForQuery, result: anyhow::Result<StatusCode>) {
+        let now = unix_timestamp();
+        delivery.attempts += 1;
+        delivery.updated_ts = now;
+        match result {
+            Ok(status) => {
+                delivery.status_code = Some(status.as_u16());
+                delivery.delivered = status.is_success();
+                delivery.error = (!delivery.delivered).then(|| format!("HTTP {}", status));
+            }
+            Err(e) => {
+                delivery.status_code = None;
+                delivery.error = Some(e.to_string());
+            }
+        }
+        if delivery.delivered {
+            return;
+        }
+        if delivery.attempts >= self.max_attempts {
+            delivery.failed = true;
+            warn!("Webhook delivery {} failed after {} attempts: {:?}", delivery.id, delivery.attempts, delivery.error);
+        } else {
+            delivery.next_attempt_ts = now + retry_delay_secs(self.retry_base_secs, delivery.attempts);
+        }
+    }
+}
+
+async fn send(client: &HttpClient, webhook: &WebhookForQuery, delivery: &WebhookDeliveryForQuery) -> anyhow::Result<StatusCode> {
+    let request = Request::post(&webhook.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(X_ORDX_SIGNATURE, sign(&webhook.secret, delivery.payload.as_bytes()))
+        .header(X_ORDX_DELIVERY, delivery.id)
+        .body(Full::new(Bytes::from(delivery.payload.clone())))?;
+    let response = tokio::time::timeout(SEND_TIMEOUT, client.request(request)).await??;
+    Ok(response.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn filter_round_trip() {
+        for s in ["etching", "mint", "burn", "transfer:bc1qxyz", "mempool", "watch:bc1qxyz"] {
+            assert_eq!(s.parse::<WebhookFilter>().unwrap().to_string(), s);
+        }
+        assert!("transfer:".parse::<WebhookFilter>().is_err());
+        assert!("watch:".parse::<WebhookFilter>().is_err());
+        assert!("etch".parse::<WebhookFilter>().is_err());
+    }
+
+    #[test]
+    fn transfer_matches_address() {
+        let event = RuneEvent::Transfer {
+            address: "bc1qxyz".into(),
+            direction: TransferDirection::Received,
+            rune_id: "840000:1".into(),
+            txid: "00".into(),
+            vout: 0,
+            amount: "1".into(),
+        };
+        assert!(event.matches(&WebhookFilter::Transfer("bc1qxyz".into())));
+        assert!(!event.matches(&WebhookFilter::Transfer("bc1qabc".into())));
+        assert!(!event.matches(&WebhookFilter::Mint));
+        assert!(event.matches(&WebhookFilter::Watch("bc1qxyz".into())));
+
+        let pending = RuneEvent::Unconfirmed {
+            address: "bc1qxyz".into(),
+            direction: TransferDirection::Sent,
+            rune_id: "840000:1".into(),
+            txid: "00".into(),
+            amount: "1".into(),
+        };
+        assert!(pending.matches(&WebhookFilter::Watch("bc1qxyz".into())));
+        assert!(!pending.matches(&WebhookFilter::Transfer("bc1qxyz".into())));
+        assert!(!pending.matches(&WebhookFilter::Mempool));
+    }
+
+    #[test]
+    fn hmac_signature() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn retry_backoff() {
+        assert_eq!(retry_delay_secs(10, 1), 10);
+        assert_eq!(retry_delay_secs(10, 2), 20);
+        assert_eq!(retry_delay_secs(10, 4), 80);
+        assert_eq!(retry_delay_secs(10, 30), MAX_RETRY_DELAY_SECS);
+    }
+
+    #[test]
+    fn block_queues_matching_deliveries() {
+        use crate::db::model::{RuneBalanceForTemp, RuneEntryForTemp, WebhookForInsert};
+
+        let path = TempDir::new("webhook-block");
+        let db = RunesDB::new(&path);
+        db.init_sqlite().unwrap();
+        for event in ["mint", "etching"] {
+            db.sqlite_webhook_insert(&WebhookForInsert {
+                url: "http://localhost:1/hook".into(),
+                event: event.into(),
+                secret: "secret".into(),
+                created_ts: 0,
+            }).unwrap();
+        }
+
+        let mint = RuneEvent::Mint { rune_id: "840000:1".into(), txid: "00".into(), amount: "1".into() };
+        let queued = db.to_sqlite(840001, &BlockHash::all_zeros(), RuneEntryForTemp::default(), RuneBalanceForTemp::default(), &[mint.clone(), mint]).unwrap();
+        assert_eq!(queued, 1);
+
+        let due = db.sqlite_webhook_delivery_due(unix_timestamp(), 10).unwrap();
+        assert_eq!(due.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&due[0].payload).unwrap();
+        assert_eq!(payload["event"], "mint");
+        assert_eq!(payload["height"], 840001);
+        assert_eq!(payload["events"].as_array().unwrap().len(), 2);
+        assert_eq!(payload["events"][0]["type"], "mint");
+    }
+
+    #[test]
+    fn url_validation() {
+        assert!(validate_url("http://localhost:8080/hook").is_ok());
+        assert!(validate_url("https://example.com/hook").is_ok());
+        assert!(validate_url("ftp://example.com/hook").is_err());
+        assert!(validate_url("/hook").is_err());
+    }
+
+    #[tokio::test]
+    async fn public_url_validation() {
+        for url in ["http://localhost:8080/hook", "http://api.localhost/hook", "http://127.0.0.1/hook", "http://10.0.0.1/hook",
+            "http://192.168.1.1/hook", "http://169.254.169.254/latest", "http://100.64.0.1/hook", "http://0.0.0.0/hook",
+            "http://[::1]/hook", "http://[fd00::1]/hook", "http://[fe80::1]/hook", "http://[::ffff:127.0.0.1]/hook"] {
+            assert!(validate_public_url(url).await.is_err(), "{}", url);
+        }
+        assert!(validate_public_url("https://1.1.1.1/hook").await.is_ok());
+        assert!(validate_public_url("https://[2606:4700::1111]/hook").await.is_ok());
+        assert!(validate_public_url("ftp://1.1.1.1/hook").await.is_err());
+    }
+}